@@ -360,13 +360,47 @@ mod tests {
         commands.trigger(request);
     }
 
+    /// Checks that the optional sections of the test assets made it through deserialization.
+    fn assert_test_asset_contents(actionset: &ActionSet) {
+        use cranium_core::behavior_trees::BehaviorTreeNode;
+        use cranium_core::state_machines::StateTransitionTrigger;
+
+        assert_eq!(actionset.actions[0].timeouts.max_run_secs, Some(30.));
+        assert_eq!(actionset.actions[0].timeouts.max_secs_since_tick, Some(5.));
+        assert_eq!(actionset.actions[0].timeouts.max_queued_secs, None);
+
+        let machine = &actionset.state_machines[0];
+        assert!(machine.redecide_on_transition);
+        assert!(machine.allows("Patrol", "test_actionset"));
+        assert!(matches!(
+            &machine.get_state("Combat").unwrap().transitions[0].trigger,
+            StateTransitionTrigger::Consideration { below: true, .. }
+        ));
+
+        let chores = actionset.htn_domains[0].get_task("DoChores").unwrap();
+        assert_eq!(chores.methods[0].priority, 2.);
+        assert!(!chores.methods[1].preconditions[0].value);
+        assert_eq!(chores.methods[1].subtasks, ["demo::go_shopping", "DoChores"]);
+
+        let tree = &actionset.behavior_trees[0];
+        assert!(matches!(&tree.root, BehaviorTreeNode::Sequence(children) if children.len() == 4));
+        assert_eq!(tree.root.node_count(), 9);
+
+        let goal = &actionset.goals[0];
+        assert_eq!(goal.priority, 1.5);
+        assert_eq!(goal.actionsets, ["test_actionset"]);
+        assert!(goal.completed_when[0].value);
+    }
+
     fn succeed_on_loaded(
         trigger: On<ActionSetLoaded>,
+        assets: Res<Assets<ActionSet>>,
         mut exit: MessageWriter<AppExit>,
     ) {
         let _evt = trigger.event();
         #[cfg(feature = "logging")]
         bevy::log::info!("ActionSet loaded successfully from {:?} as {:?}", _evt.filename, _evt.asset_handle);
+        assert_test_asset_contents(assets.get(&_evt.asset_handle).unwrap());
         exit.write(AppExit::Success);
     }
 
@@ -6,12 +6,29 @@ You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use bevy::prelude::*;
 use cranium_core::actions;
+use cranium_core::blackboard;
+use cranium_core::action_queue;
 use cranium_core::action_runtime;
 use cranium_core::action_state;
+use cranium_core::action_timing;
 use cranium_core::considerations;
 use cranium_core::context_fetchers;
+use cranium_core::crowds;
+use cranium_core::curves;
 use cranium_core::decision_loop;
+use cranium_core::goals;
+use cranium_core::goap;
+use cranium_core::htn;
+use cranium_core::behavior_trees;
+use cranium_core::knowledge_sharing;
+use cranium_core::layers;
+use cranium_core::lods;
+use cranium_core::memories;
+use cranium_core::orders;
+use cranium_core::response_surfaces;
+use cranium_core::senses;
 use cranium_core::smart_object;
+use cranium_core::state_machines;
 
 #[cfg(feature = "include_actionset_loader")]
 use cortex_actionset_loader::ActionSetAssetPlugin;
@@ -29,12 +46,35 @@ impl Plugin for CraniumPlugin {
         app
         .add_plugins((
             actions::ActionHandlerPlugin,
-            action_state::ActionStateUpdatesPlugin,
+            // Plugin tuples are limited in size, so the Action lifecycle plugins are grouped together.
+            (
+                action_state::ActionStateUpdatesPlugin,
+                action_queue::ActionQueuePlugin,
+                action_timing::ActionTimingPlugin,
+            ),
             context_fetchers::ContextFetcherPlugin, 
             considerations::ConsiderationPlugin,
+            memories::MemoriesPlugin,
+            senses::SensesPlugin,
+            knowledge_sharing::KnowledgeSharingPlugin,
+            orders::OrdersPlugin,
+            blackboard::BlackboardPlugin,
+            state_machines::StateMachinePlugin,
+            goals::GoalPlugin,
+            // Plugin tuples are limited in size, so the planners are grouped together.
+            (
+                goap::GoapPlugin,
+                htn::HtnPlugin,
+                behavior_trees::BehaviorTreePlugin,
+            ),
+            crowds::CrowdPlugin,
+            layers::LayersPlugin,
         ))
         .init_resource::<action_runtime::UserDefaultActionTrackerSpawnConfig>()
         .init_resource::<smart_object::ActionSetStore>()
+        .init_resource::<curves::UtilityCurveRegistry>()
+        .init_resource::<response_surfaces::UtilityResponseSurfaceRegistry>()
+        .init_resource::<lods::LodUpdateFrequency>()
         .add_message::<cranium_core::events::AiActionDispatchToUserCode>()
         .add_observer(action_runtime::create_tracker_for_picked_action)
         .add_observer(action_runtime::actiontracker_triggered_spawner)
@@ -42,6 +82,14 @@ impl Plugin for CraniumPlugin {
         .add_observer(decision_loop::prepare_ai)
         .add_observer(decision_loop::decision_engine)
         // .add_observer(decision_loop::trigger_dispatch_to_user_actions)
+        .add_systems(
+            First, 
+            (
+                curves::register_actionset_curve_definitions,
+                response_surfaces::register_actionset_surface_definitions,
+            ).run_if(resource_changed::<smart_object::ActionSetStore>)
+        )
+        .add_systems(PreUpdate, decision_loop::release_deferred_decisions)
         .add_systems(
             FixedPostUpdate, 
             (
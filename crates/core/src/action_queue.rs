@@ -0,0 +1,361 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+If a copy of the MPL was not distributed with this file,
+You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+//! Action Queues - running several Actions of an AI one after another.
+//!
+//! Each queued Action gets its own ActionTracker Entity, owned by the AI. Entries start out in the
+//! `ActionState::Queued` state and get promoted to Ready once all the entries they depend on have
+//! Succeeded. By default, an entry depends on the entry queued right before it, which makes for a
+//! plain sequence; you can specify the dependencies explicitly instead to run entries side by side.
+//!
+//! If any entry Fails or gets Cancelled, the whole queue is cleared: all remaining entries are
+//! Cancelled and an `ActionQueueCleared` event is triggered for the AI. Once the last entry
+//! Succeeds, an `ActionQueueCompleted` event is triggered instead.
+//!
+//! The planners (see the `plans` module) run their plans through the queue as well.
+
+use bevy::platform::prelude::{ToOwned, vec};
+use bevy::prelude::*;
+
+use crate::action_runtime::{ActionTrackerState, UserDefaultActionTrackerSpawnConfig, insert_action_tracker};
+use crate::action_state::{ActionState, AiActionStateChange, AiActionStateChangeRequest};
+use crate::actions::ScoredAction;
+use crate::types::{ActionKey, AiEntity, CraniumList};
+
+/// A single entry of an ActionQueue.
+#[derive(Debug, Clone)]
+pub struct QueuedAction {
+    /// The ActionTracker Entity of the entry.
+    pub tracker: Entity,
+    pub action_key: ActionKey,
+    /// The ActionTracker Entities of the entries that must Succeed before this one can start.
+    pub depends_on: CraniumList<Entity>,
+}
+
+/// The unfinished queued Actions of an AI, in the order they were queued.
+#[derive(Component, Debug, Default)]
+pub struct ActionQueue {
+    entries: CraniumList<QueuedAction>,
+}
+
+impl ActionQueue {
+    pub fn entries(&self) -> &[QueuedAction] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, tracker: Entity) -> bool {
+        self.entries.iter().any(|entry| entry.tracker == tracker)
+    }
+
+    /// True if any of the dependencies are still waiting to finish.
+    pub fn is_blocked(&self, depends_on: &[Entity]) -> bool {
+        depends_on.iter().any(|dependency| self.contains(*dependency))
+    }
+}
+
+/// Links an ActionTracker to the ActionQueue of the AI.
+#[derive(Component, Debug, Clone)]
+pub struct ActionQueueEntry {
+    pub ai: AiEntity,
+}
+
+/// Signals that the last entry of the AI's ActionQueue has Succeeded.
+#[derive(EntityEvent, Debug, Clone)]
+pub struct ActionQueueCompleted {
+    pub entity: AiEntity,
+}
+
+/// Signals that the AI's ActionQueue was cleared because one of its entries Failed or got Cancelled.
+#[derive(EntityEvent, Debug, Clone)]
+pub struct ActionQueueCleared {
+    pub entity: AiEntity,
+    /// The Action that did not Succeed.
+    pub action_key: ActionKey,
+    pub state: ActionState,
+}
+
+/// Sets up the ActionTracker for a reserved Entity and adds it to the AI's ActionQueue.
+///
+/// With no explicit dependencies, the entry depends on the last entry in the queue (if any).
+fn push_queue_entry(
+    world: &mut World,
+    ai: AiEntity,
+    tracker: Entity,
+    action: ScoredAction,
+    depends_on: Option<CraniumList<Entity>>,
+) {
+    let action_key = action.action.action_key.to_owned();
+
+    let blocked = {
+        let Ok(mut ai_entity) = world.get_entity_mut(ai) else {
+            #[cfg(feature = "logging")]
+            bevy::log::warn!("ActionQueue: AI {:?} does not exist, dropping queued Action {:?}", ai, &action_key);
+
+            if let Ok(tracker_entity) = world.get_entity_mut(tracker) {
+                tracker_entity.despawn();
+            }
+            return;
+        };
+
+        if !ai_entity.contains::<ActionQueue>() {
+            ai_entity.insert(ActionQueue::default());
+        }
+        let mut queue = ai_entity.get_mut::<ActionQueue>().unwrap();
+
+        let depends_on = depends_on.unwrap_or_else(|| {
+            queue.entries.last().map(|last| vec![last.tracker]).unwrap_or_default()
+        });
+        let blocked = queue.is_blocked(&depends_on);
+        queue.entries.push(QueuedAction { tracker, action_key, depends_on });
+        blocked
+    };
+
+    let state = match blocked {
+        true => ActionTrackerState::queued(),
+        false => ActionTrackerState::ready(),
+    };
+
+    let config = world
+        .get_resource::<UserDefaultActionTrackerSpawnConfig>()
+        .map(|defaults| defaults.ticking_config())
+        .unwrap_or_else(|| UserDefaultActionTrackerSpawnConfig::default().ticking_config());
+    let spawn_time = (
+        world.get_resource::<Time>().map(|time| time.elapsed()).unwrap_or_default(),
+        world.get_resource::<Time<Real>>().map(|time| time.elapsed()).unwrap_or_default(),
+    );
+
+    let mut commands = world.commands();
+    insert_action_tracker(
+        commands.entity(tracker), ai, action, Some(&config), spawn_time, state, ActionQueueEntry { ai },
+    );
+    world.flush();
+}
+
+/// Queues an Action for the AI, returning its ActionTracker Entity.
+///
+/// With `depends_on` set to None, the Action runs after the last entry already in the queue.
+pub fn enqueue_action(
+    world: &mut World,
+    ai: AiEntity,
+    action: ScoredAction,
+    depends_on: Option<CraniumList<Entity>>,
+) -> Entity {
+    let tracker = world.spawn_empty().id();
+    push_queue_entry(world, ai, tracker, action, depends_on);
+    tracker
+}
+
+/// Cancels all the entries in the AI's ActionQueue, without triggering `ActionQueueCleared`.
+pub fn clear_action_queue(world: &mut World, ai: AiEntity) {
+    let Some(mut queue) = world.get_mut::<ActionQueue>(ai) else {
+        return;
+    };
+    let entries = core::mem::take(&mut queue.entries);
+
+    for entry in entries {
+        world.write_message(AiActionStateChangeRequest {
+            entity: entry.tracker,
+            action: entry.action_key,
+            to_state: ActionState::Cancelled,
+        });
+    }
+}
+
+/// Convenience methods for managing an AI's ActionQueue from Commands.
+pub trait ActionQueueCommandsExt {
+    /// Queues an Action to run after the last entry in the AI's queue; returns its ActionTracker Entity.
+    fn enqueue_action(&mut self, action: ScoredAction) -> Entity;
+
+    /// Queues an Action to run once all of the specified entries Succeed (or right away, if there
+    /// are none); returns its ActionTracker Entity.
+    fn enqueue_action_after(&mut self, action: ScoredAction, depends_on: CraniumList<Entity>) -> Entity;
+
+    /// Cancels all the entries in the AI's queue.
+    fn clear_action_queue(&mut self) -> &mut Self;
+}
+
+impl ActionQueueCommandsExt for EntityCommands<'_> {
+    fn enqueue_action(&mut self, action: ScoredAction) -> Entity {
+        let tracker = self.commands().spawn_empty().id();
+        self.queue(move |entity: EntityWorldMut| {
+            let ai = entity.id();
+            push_queue_entry(entity.into_world_mut(), ai, tracker, action, None);
+        });
+        tracker
+    }
+
+    fn enqueue_action_after(&mut self, action: ScoredAction, depends_on: CraniumList<Entity>) -> Entity {
+        let tracker = self.commands().spawn_empty().id();
+        self.queue(move |entity: EntityWorldMut| {
+            let ai = entity.id();
+            push_queue_entry(entity.into_world_mut(), ai, tracker, action, Some(depends_on));
+        });
+        tracker
+    }
+
+    fn clear_action_queue(&mut self) -> &mut Self {
+        self.queue(|entity: EntityWorldMut| {
+            let ai = entity.id();
+            clear_action_queue(entity.into_world_mut(), ai);
+        })
+    }
+}
+
+/// An Observer promoting queued Actions as the entries they depend on Succeed, and clearing the
+/// queue if any entry does not.
+pub fn advance_action_queues(
+    event: On<AiActionStateChange>,
+    entry_query: Query<&ActionQueueEntry>,
+    mut queue_query: Query<&mut ActionQueue>,
+    mut state_writer: MessageWriter<AiActionStateChangeRequest>,
+    mut commands: Commands,
+) {
+    if !event.to_state.is_terminal() {
+        return;
+    }
+    let Ok(entry) = entry_query.get(event.entity) else {
+        return;
+    };
+    let ai = entry.ai;
+    let Ok(mut queue) = queue_query.get_mut(ai) else {
+        return;
+    };
+    let Some(position) = queue.entries.iter().position(|queued| queued.tracker == event.entity) else {
+        return;
+    };
+    let finished = queue.entries.remove(position);
+
+    match event.to_state {
+        ActionState::Succeeded => {
+            for queued in queue.entries.iter() {
+                if queued.depends_on.contains(&finished.tracker) && !queue.is_blocked(&queued.depends_on) {
+                    state_writer.write(AiActionStateChangeRequest {
+                        entity: queued.tracker,
+                        action: queued.action_key.to_owned(),
+                        to_state: ActionState::Ready,
+                    });
+                }
+            }
+
+            if queue.is_empty() {
+                commands.trigger(ActionQueueCompleted { entity: ai });
+            }
+        },
+        state => {
+            #[cfg(feature = "logging")]
+            bevy::log::debug!(
+                "advance_action_queues: AI {:?} - queued Action {:?} ended as {:?}, clearing the queue.",
+                ai, &finished.action_key, state
+            );
+
+            for queued in core::mem::take(&mut queue.entries) {
+                state_writer.write(AiActionStateChangeRequest {
+                    entity: queued.tracker,
+                    action: queued.action_key,
+                    to_state: ActionState::Cancelled,
+                });
+            }
+            commands.trigger(ActionQueueCleared { entity: ai, action_key: finished.action_key, state });
+        },
+    }
+}
+
+/// Sets up ActionQueues (see the module docs).
+pub struct ActionQueuePlugin;
+
+impl Plugin for ActionQueuePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_observer(advance_action_queues);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action_runtime::{ActionTrackerCreationTimer, ActionTrackerRuntimeTimer, ActionTrackerTickTimer, ActionTrackerTicks};
+    use crate::action_state::ActionStateUpdatesPlugin;
+    use crate::action_timing::ActionTimingPlugin;
+    use crate::actions::Action;
+
+    fn scored(key: &str) -> ScoredAction {
+        ScoredAction {
+            action: Action { name: key.into(), action_key: key.into(), context: Entity::PLACEHOLDER },
+            score: 1.,
+        }
+    }
+
+    fn state_of(app: &App, tracker: Entity) -> ActionState {
+        *app.world().get::<ActionTrackerState>(tracker).unwrap().get_state()
+    }
+
+    fn set_state(app: &mut App, tracker: Entity, key: &str, to_state: ActionState) {
+        app.world_mut().write_message(AiActionStateChangeRequest { entity: tracker, action: key.into(), to_state });
+        app.world_mut().run_system_cached(crate::action_state::action_state_update_handler).unwrap();
+        app.world_mut().flush();
+        app.world_mut().run_system_cached(crate::action_state::action_state_update_handler).unwrap();
+    }
+
+    #[test]
+    fn test_queue_promotes_and_clears() {
+        let mut app = App::new();
+        app.add_plugins((ActionStateUpdatesPlugin, ActionQueuePlugin));
+        let ai = app.world_mut().spawn_empty().id();
+
+        let first = enqueue_action(app.world_mut(), ai, scored("Approach"), None);
+        let second = enqueue_action(app.world_mut(), ai, scored("Grab"), None);
+        let side = enqueue_action(app.world_mut(), ai, scored("Shout"), Some(CraniumList::new()));
+        let third = enqueue_action(app.world_mut(), ai, scored("Leave"), None);
+
+        assert_eq!(state_of(&app, first), ActionState::Ready);
+        assert_eq!(state_of(&app, second), ActionState::Queued);
+        assert_eq!(state_of(&app, side), ActionState::Ready);
+
+        set_state(&mut app, first, "Approach", ActionState::Succeeded);
+        assert_eq!(state_of(&app, second), ActionState::Ready);
+        assert_eq!(state_of(&app, third), ActionState::Queued);
+
+        set_state(&mut app, second, "Grab", ActionState::Failed);
+        assert_eq!(state_of(&app, third), ActionState::Cancelled);
+        assert_eq!(state_of(&app, side), ActionState::Cancelled);
+        assert!(app.world().get::<ActionQueue>(ai).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_queued_trackers_use_the_spawn_config() {
+        let mut app = App::new();
+        app.add_plugins((ActionStateUpdatesPlugin, ActionQueuePlugin, ActionTimingPlugin))
+            .init_resource::<Time>()
+            .init_resource::<Time<Real>>()
+            .init_resource::<UserDefaultActionTrackerSpawnConfig>();
+        app.world_mut().resource_mut::<UserDefaultActionTrackerSpawnConfig>()
+            .with_config_builder(|builder| builder.set_use_timers(true));
+        let ai = app.world_mut().spawn_empty().id();
+
+        let first = enqueue_action(app.world_mut(), ai, scored("Approach"), None);
+        let second = enqueue_action(app.world_mut(), ai, scored("Grab"), None);
+        assert_eq!(state_of(&app, second), ActionState::Queued);
+
+        let world = app.world();
+        assert!(world.get::<ActionTrackerTicks>(second).is_some());
+        assert!(world.get::<ActionTrackerCreationTimer>(second).is_some());
+        assert!(world.get::<ActionTrackerTickTimer>(second).is_some());
+
+        set_state(&mut app, first, "Approach", ActionState::Running);
+        set_state(&mut app, first, "Approach", ActionState::Succeeded);
+        let runtime = app.world().get::<ActionTrackerRuntimeTimer>(first).unwrap();
+        assert!(runtime.start_time.is_some());
+        assert!(runtime.end_time.is_some());
+    }
+}
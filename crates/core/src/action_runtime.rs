@@ -11,6 +11,7 @@ use crate::actions::{Action, ScoredAction};
 use crate::action_state::ActionState;
 use crate::types;
 use crate::events;
+use crate::lods::{AiLevelOfDetail, LodUpdateFrequency};
 
 
 // Action Execution
@@ -25,6 +26,29 @@ use crate::events;
 pub struct ActionTracker(pub ScoredAction);
 
 
+/// Links an ActionTracker Entity to the AI whose Action it tracks.
+/// 
+/// Every ActionTracker spawned by the library gets this, so a single AI can have several 
+/// live Actions at once (e.g. a queue, a Behavior Tree, or just a new pick replacing an old one).
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+#[relationship(relationship_target = TrackedBy)]
+pub struct Tracks(pub types::AiEntity);
+
+/// All ActionTrackers of an AI, oldest first; maintained automatically based on `Tracks`.
+/// 
+/// Despawning the AI despawns its ActionTrackers as well.
+#[derive(Component, Debug, Default)]
+#[relationship_target(relationship = Tracks, linked_spawn)]
+pub struct TrackedBy(bevy::platform::prelude::Vec<Entity>);
+
+
+/// Marks ActionTrackers spawned for Actions picked by the decision engine.
+/// 
+/// An AI only runs one picked Action at a time; picking a new one Cancels the previous one.
+#[derive(Component, Debug, Default)]
+pub struct PickedActionTracker;
+
+
 /// An 'extension' Component for ActionTracker Bundles.
 /// 
 /// Adds tracking of the AI Entity that 'owns' the tracked Action. 
@@ -75,13 +99,24 @@ impl ActionTrackerState {
 }
 
 /// Helper; wraps how we store time for tracking Action runtime timining.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum TimeInstantActionTracker {
     Virtual(core::time::Duration),
     Real(core::time::Duration),
     VirtualAndReal((core::time::Duration, core::time::Duration)),
 }
 
+impl TimeInstantActionTracker {
+    /// The game (virtual) time of this instant, if it was recorded.
+    pub fn game_time(&self) -> Option<core::time::Duration> {
+        match self {
+            Self::Virtual(time) => Some(*time),
+            Self::Real(_) => None,
+            Self::VirtualAndReal((time, _)) => Some(*time),
+        }
+    }
+}
+
 /// An 'extension' Component for ActionTracker Bundles.
 /// Adds Action time metadata tracking to the ActionTracker for creation time.
 /// 
@@ -167,6 +202,19 @@ pub struct ActionTrackerTickTimer {
 pub struct ActionTrackerTicks;
 
 
+/// An 'extension' Component for ActionTracker Bundles.
+/// 
+/// Records when a ticked ActionTracker was last dispatched to user code, 
+/// so that ticks can be throttled based on the owning AI's LOD.
+/// 
+/// This is added to ticked trackers spawned by the library automatically; 
+/// ticked trackers without it are never throttled.
+#[derive(Component, Debug, Default)]
+pub struct ActionTrackerLodThrottle {
+    pub last_dispatch: Option<core::time::Duration>,
+}
+
+
 #[derive(Debug, Clone)]
 pub struct ActionTrackerSpawnConfig {
     track_owner_ai: bool, 
@@ -333,80 +381,122 @@ pub struct ActionTrackerSpawnedForTargetAI {
     pub action_tracker: Entity,
 }
 
-/// Event handler for spawning ActionTrackers for Actions, 
-/// triggered by an ActionTrackerSpawnRequested Event.
-pub fn actiontracker_triggered_spawner(
-    trigger: On<ActionTrackerSpawnRequested>,
-    mut commands: Commands,
-    game_timer: Res<Time>,
-    real_timer: Res<Time<Real>>,
-) {
-    let event = trigger.event();
-    let owner_ai = event.entity;
+/// Spawns a new ActionTracker Entity for the AI's Action, returning the Entity.
+/// 
+/// The tracker starts out in `initial_state` (usually Ready, or Queued if it has to wait for 
+/// something) and gets the `extra` Components on top of the ones required by its config.
+/// 
+/// Returns None if the AI no longer exists.
+pub fn spawn_action_tracker<B: Bundle>(
+    commands: &mut Commands,
+    owner_ai: types::AiEntity,
+    action: ScoredAction,
+    tracker_config: Option<&ActionTrackerSpawnConfig>,
+    spawn_time: (core::time::Duration, core::time::Duration),
+    initial_state: ActionTrackerState,
+    extra: B,
+) -> Option<Entity> {
+    if let Err(_err) = commands.get_entity(owner_ai) {
+        #[cfg(feature = "logging")]
+        bevy::log::warn!(
+            "Attempted to spawn an ActionTracker for an AI Entity ({:?}) that no longer exists - {:?}",
+            owner_ai, _err
+        );
+        return None;
+    }
 
-    match commands.get_entity(owner_ai) {
-        Err(_err) => {
-            #[cfg(feature = "logging")]
-            bevy::log::warn!(
-                "Attempted to spawn an ActionTracker for an AI Entity ({:?}) that no longer exists - {:?}",
-                owner_ai, _err
-            )
-        }
+    let tracker_cmds = commands.spawn_empty();
+    let tracker = tracker_cmds.id();
+    insert_action_tracker(tracker_cmds, owner_ai, action, tracker_config, spawn_time, initial_state, extra);
+    Some(tracker)
+}
 
-        Ok(mut ai_cmds) => {
-            ai_cmds.insert((
-                ActionTracker(event.action.clone()),
-                ActionTrackerState::ready(),
-            ));
+/// Sets up an ActionTracker for the AI's Action on an existing (e.g. reserved) Entity.
+/// 
+/// Same as `spawn_action_tracker()` otherwise, except that the AI is assumed to exist.
+pub fn insert_action_tracker<B: Bundle>(
+    mut tracker_cmds: EntityCommands,
+    owner_ai: types::AiEntity,
+    action: ScoredAction,
+    tracker_config: Option<&ActionTrackerSpawnConfig>,
+    spawn_time: (core::time::Duration, core::time::Duration),
+    initial_state: ActionTrackerState,
+    extra: B,
+) {
+    tracker_cmds.insert((
+        ActionTracker(action),
+        initial_state,
+        Tracks(owner_ai),
+    ));
+
+    let spawn_config = match tracker_config {
+        Some(config) => config,
+        None => &ActionTrackerSpawnConfig::builder().build()
+    };
 
-            let spawn_config = match &event.tracker_config {
-                Some(config) => config,
-                None => &ActionTrackerSpawnConfig::builder().build()
-            };
+    if spawn_config.track_owner_ai {
+        tracker_cmds.insert(ActionTrackerOwningAI {
+            owner_ai: owner_ai.into()
+        });
+    }
 
-            if spawn_config.track_owner_ai {
-                ai_cmds.insert(ActionTrackerOwningAI {
-                    owner_ai: event.entity.into()
-                });
-            }
+    if spawn_config.use_ticker {
+        // Add ticking to this ActionTracker.
+        // The Component for this is just a marker, pretty trivial.
+        tracker_cmds.insert((ActionTrackerTicks, ActionTrackerLodThrottle::default()));
+    }
 
-            if spawn_config.use_ticker {
-                // Add ticking to this ActionTracker.
-                // The Component for this is just a marker, pretty trivial.
-                ai_cmds.insert(ActionTrackerTicks);
-            }
+    // Add timing components.
+    // 
+    // For now we'll use unwrapped elapsed Durations for this as a standard.
+    // Real time in particular may span DAYS for reloads, so wrapping it may cause serious artifacts.
+    // Duration is u64-based; you may get issues if you leave your game running for 585 billion years.
+    if spawn_config.use_create_timer {
+        tracker_cmds.insert(ActionTrackerCreationTimer {
+            creation_time: TimeInstantActionTracker::VirtualAndReal(spawn_time)
+        });
+    }
 
-            // Add timing components.
-            // 
-            // For now we'll use unwrapped elapsed Durations for this as a standard.
-            // Real time in particular may span DAYS for reloads, so wrapping it may cause serious artifacts.
-            // Duration is u64-based; you may get issues if you leave your game running for 585 billion years.
-            if spawn_config.use_create_timer {
-                let virtual_spawn_time = game_timer.elapsed();
-                let real_spawn_time = real_timer.elapsed();
-
-                ai_cmds.insert(ActionTrackerCreationTimer {
-                    creation_time: TimeInstantActionTracker::VirtualAndReal((virtual_spawn_time, real_spawn_time))
-                });
-            }
+    if spawn_config.use_runtime_timer {
+        // The Action hasn't started yet, so they will both be None for now.
+        tracker_cmds.insert(ActionTrackerRuntimeTimer::default());
+    }
 
-            if spawn_config.use_runtime_timer {
-                // The Action hasn't started yet, so they will both be None for now.
-                ai_cmds.insert(ActionTrackerRuntimeTimer::default());
-            }
+    if spawn_config.use_tick_timer {
+        // The Action hasn't been ticked, so starts as None.
+        tracker_cmds.insert(ActionTrackerTickTimer::default());
+    }
 
-            if spawn_config.use_tick_timer {
-                // The Action hasn't been ticked, so starts as None.
-                ai_cmds.insert(ActionTrackerTickTimer::default());
-            }
+    tracker_cmds.insert(extra);
+    let tracker = tracker_cmds.id();
 
-            // Send a friendly PSA that we have created this Entity for downstream users to hook into.
-            ai_cmds.trigger(|atracker| ActionTrackerSpawnedForTargetAI { 
-                entity: owner_ai,
-                action_tracker: atracker,
-            });
-        }
-    }
+    // Send a friendly PSA that we have created this Entity for downstream users to hook into.
+    tracker_cmds.commands().trigger(ActionTrackerSpawnedForTargetAI { 
+        entity: owner_ai,
+        action_tracker: tracker,
+    });
+}
+
+/// Event handler for spawning ActionTrackers for Actions, 
+/// triggered by an ActionTrackerSpawnRequested Event.
+/// 
+/// Each ActionTracker is spawned as its own Entity, linked to the AI with `Tracks`.
+pub fn actiontracker_triggered_spawner(
+    trigger: On<ActionTrackerSpawnRequested>,
+    mut commands: Commands,
+    game_timer: Res<Time>,
+    real_timer: Res<Time<Real>>,
+) {
+    let event = trigger.event();
+    spawn_action_tracker(
+        &mut commands,
+        event.entity,
+        event.action.clone(),
+        event.tracker_config.as_ref(),
+        (game_timer.elapsed(), real_timer.elapsed()),
+        ActionTrackerState::ready(),
+        (),
+    );
 }
 
 
@@ -415,6 +505,7 @@ pub fn actiontracker_triggered_spawner(
 /// You could DIY it, but using this Event should cover typical usecases for ya.
 #[derive(EntityEvent)]
 pub struct ActionTrackerDespawnRequested {
+    /// The ActionTracker Entity.
     entity: Entity, 
 }
 
@@ -450,7 +541,7 @@ pub fn actiontracker_done_cleanup_system(
         if is_done {
             #[cfg(feature = "logging")]
             bevy::log::info!(
-                "ActionTrackerCleanup: Action {:?} of AI {:?} finished, cleaning up the Tracker", 
+                "ActionTrackerCleanup: Action {:?} of Tracker {:?} finished, cleaning it up", 
                 _tracker.0.action.name, entity
             );
             commands.trigger(ActionTrackerDespawnRequested {
@@ -496,17 +587,66 @@ impl UserDefaultActionTrackerSpawnConfig {
         self.config = Some(built);
         self
     }
+
+    /// The configured defaults (or the library defaults, if none set up), but always ticking. 
+    /// 
+    /// Used for the ActionTrackers Cranium runs by itself, like ActionQueue entries or Behavior Tree leaves. 
+    /// Configs that time Actions get the tick timer as well, same as if they were ticking all along.
+    pub fn ticking_config(&self) -> ActionTrackerSpawnConfig {
+        let config_builder = match &self.config {
+            None => ActionTrackerSpawnConfigBuilder::new(),
+            Some(preexisting) => ActionTrackerSpawnConfigBuilder::from_reference_config(preexisting)
+                .set_use_tick_timer(preexisting.use_tick_timer || preexisting.use_runtime_timer),
+        };
+        config_builder.set_use_ticker(true).build()
+    }
 }
 
 /// A batteries-included solution for creating ActionTrackers for your Actions.
 /// 
-/// Event-driven; responds to AiActionPicked events
+/// Event-driven; responds to AiActionPicked events. The AI's previously picked Action 
+/// (if it is still live) gets Cancelled, as the new pick replaces it - unless the AI 
+/// simply picked the same Action (key and context) again, in which case it keeps running.
 pub fn create_tracker_for_picked_action(
     trigger: On<crate::events::AiActionPicked>,
     mut commands: Commands,
     user_default_config_resource: Res<UserDefaultActionTrackerSpawnConfig>,
+    tracked_by_query: Query<&TrackedBy>,
+    picked_query: Query<(&ActionTracker, &ActionTrackerState), With<PickedActionTracker>>,
+    mut state_writer: MessageWriter<crate::action_state::AiActionStateChangeRequest>,
+    (game_timer, real_timer): (Res<Time>, Res<Time<Real>>),
 ) {
     let event = trigger.event();
+    let mut repicked = false;
+
+    for tracker in tracked_by_query.get(event.entity).into_iter().flat_map(|trackers| trackers.iter()) {
+        let Ok((previous, state)) = picked_query.get(tracker) else {
+            continue;
+        };
+        if state.0.is_terminal() {
+            continue;
+        }
+        if !repicked && previous.0.action.action_key == event.action_key && previous.0.action.context == event.action_context {
+            repicked = true;
+            continue;
+        }
+
+        #[cfg(feature = "logging")]
+        bevy::log::debug!(
+            "create_tracker_for_picked_action: AI {:?} picked {:?}, Cancelling the previous pick {:?}", 
+            event.entity, &event.action_name, &previous.0.action.name
+        );
+
+        state_writer.write(crate::action_state::AiActionStateChangeRequest {
+            entity: tracker,
+            action: previous.0.action.action_key.to_owned(),
+            to_state: ActionState::Cancelled,
+        });
+    }
+
+    if repicked {
+        return;
+    }
 
     let action = Action {
         name: event.action_name.clone(),
@@ -521,25 +661,34 @@ pub fn create_tracker_for_picked_action(
 
     let user_config = user_default_config_resource.config.clone();
 
-    commands.trigger(
-        ActionTrackerSpawnRequested::new(
-            event.entity,
-            scored_action, 
-            user_config,
-        )
+    spawn_action_tracker(
+        &mut commands,
+        event.entity,
+        scored_action, 
+        user_config.as_ref(),
+        (game_timer.elapsed(), real_timer.elapsed()),
+        ActionTrackerState::ready(),
+        PickedActionTracker,
     );
 }
 
 /// A System that processes and updates `ActionTrackers` to trigger `Actions`.
 /// 
 /// This particular implementation uses tick-based [`Action`] processing.
+/// 
+/// If the `LodUpdateFrequency` Resource is present, trackers are only ticked as often 
+/// as the owning AI's LOD allows (and not at all for inactive AIs).
 fn tick_based_action_tracker_handler(
     mut query: Query<(
         Entity,
         &ActionTracker, 
         Option<&mut ActionTrackerState>, 
-        Option<&mut ActionTrackerTickTimer>
+        Option<&mut ActionTrackerTickTimer>,
+        Option<&Tracks>,
     ), With<ActionTrackerTicks>>,
+    mut throttle_query: Query<(Option<&Tracks>, &mut ActionTrackerLodThrottle)>,
+    lod_query: Query<&AiLevelOfDetail>,
+    lod_frequency: Option<Res<LodUpdateFrequency>>,
     mut dispatch_writer: MessageWriter<events::AiActionDispatchToUserCode>,
     game_timer: Res<Time>,
     real_timer: Res<Time<Real>>,
@@ -549,22 +698,41 @@ fn tick_based_action_tracker_handler(
         "tick_based_action_tracker_handler - Running...", 
     );
 
-    for (ai, tracker, maybe_state, tick_timer) in query.iter_mut() {
+    for (tracker_entity, tracker, maybe_state, tick_timer, maybe_tracker_owner) in query.iter_mut() {
         let should_process = maybe_state.as_ref().map(|state| state.0.should_process()).unwrap_or(true);
         
         if !should_process {
             #[cfg(feature = "logging")]
             bevy::log::debug!(
-                "tick_based_action_tracker_handler - AI {:?}: Skipping processing for Action(Tracker) {:?} - {:?}", 
-                ai, tracker.0.action.name, maybe_state
+                "tick_based_action_tracker_handler - Tracker {:?}: Skipping processing for Action(Tracker) {:?} - {:?}", 
+                tracker_entity, tracker.0.action.name, maybe_state
             );
             continue;
         }
 
+        if let Some(lod_frequency) = lod_frequency.as_ref() 
+            && let Ok((maybe_owner, mut throttle)) = throttle_query.get_mut(tracker_entity) 
+        {
+            let owner = maybe_owner.map(|owner| owner.0).unwrap_or(tracker_entity);
+            let lod = lod_query.get(owner).map(|lod| lod.get_current_lod()).unwrap_or_default();
+            let now = game_timer.elapsed();
+
+            if !lod_frequency.ticks.is_due(lod, throttle.last_dispatch, now) {
+                #[cfg(feature = "logging")]
+                bevy::log::debug!(
+                    "tick_based_action_tracker_handler - AI {:?}: Action(Tracker) {:?} throttled by LOD {:?}", 
+                    owner, tracker.0.action.name, lod
+                );
+                continue;
+            }
+
+            throttle.last_dispatch = Some(now);
+        }
+
         #[cfg(feature = "logging")]
         bevy::log::debug!(
             "tick_based_action_tracker_handler: processing Action(Tracker) {:?} for {:?} - {:?}", 
-            tracker.0.action.name, ai, maybe_state
+            tracker.0.action.name, tracker_entity, maybe_state
         );
 
         if let Some(mut tick_timer_included) = tick_timer {
@@ -578,13 +746,16 @@ fn tick_based_action_tracker_handler(
             tick_timer_included.last_tick_time = Some(new_value);
         }
 
+        // Trackers are separate Entities from the AI; ActionHandlers always get the AI.
+        let owner = maybe_tracker_owner.map(|owner| owner.0).unwrap_or(tracker_entity);
+
         let message = events::AiActionDispatchToUserCode::new(
-            ai, 
+            owner, 
             tracker.0.action.action_key.to_owned(), 
             tracker.0.action.name.to_owned(), 
             tracker.0.action.context, 
             tracker.0.score
-        );
+        ).with_action_tracker(tracker_entity);
 
         dispatch_writer.write(message);
     }
@@ -619,8 +790,86 @@ impl Plugin for TickBasedActionTrackerPlugin {
 }
 
 
-// #[cfg(test)]
-// mod tests {
-//     #[test]
-//     fn test_run_action() {}
-// }
+#[cfg(test)]
+mod tests {
+    use bevy::platform::prelude::Vec;
+    use crate::action_state::{action_state_update_handler, AiActionStateChangeRequest};
+    use crate::actions::{AcceptsActionHandlerRegistrations, ActionHandlerInputs};
+    use crate::decision_loop::handle_dispatch_to_user_actions;
+    use super::*;
+
+    #[derive(Component)]
+    struct Dispatched(ActionHandlerInputs);
+
+    fn trackers_of(world: &World, ai: Entity) -> Vec<Entity> {
+        world.get::<TrackedBy>(ai).map(|trackers| trackers.iter().collect()).unwrap_or_default()
+    }
+
+    fn pick(world: &mut World, ai: Entity, action_key: &str) {
+        world.trigger(events::AiActionPicked::new(ai, action_key.into(), action_key.into(), ai, 1.));
+        world.flush();
+    }
+
+    #[test]
+    fn test_picks_spawn_separate_trackers() {
+        let mut world = World::new();
+        world.init_resource::<UserDefaultActionTrackerSpawnConfig>();
+        world.init_resource::<Time>();
+        world.init_resource::<Time<Real>>();
+        world.init_resource::<Messages<AiActionStateChangeRequest>>();
+        world.add_observer(create_tracker_for_picked_action);
+        let ai = world.spawn_empty().id();
+
+        pick(&mut world, ai, "Eat");
+        pick(&mut world, ai, "Eat");
+        assert_eq!(trackers_of(&world, ai).len(), 1);
+
+        pick(&mut world, ai, "Sleep");
+        let trackers = trackers_of(&world, ai);
+        assert_eq!(trackers.len(), 2);
+
+        // The new pick supersedes the old one, addressed by its own tracker Entity.
+        world.run_system_cached(action_state_update_handler).unwrap();
+        assert_eq!(world.get::<ActionTrackerState>(trackers[0]).unwrap().0, ActionState::Cancelled);
+        assert_eq!(world.get::<ActionTrackerState>(trackers[1]).unwrap().0, ActionState::Ready);
+
+        world.add_observer(actiontracker_triggered_despawner);
+        world.run_system_cached(actiontracker_done_cleanup_system).unwrap();
+        assert_eq!(trackers_of(&world, ai), [trackers[1]]);
+        assert!(world.get_entity(ai).is_ok());
+
+        world.despawn(ai);
+        assert!(world.get_entity(trackers[1]).is_err());
+    }
+
+    #[test]
+    fn test_ticks_dispatch_with_their_tracker() {
+        let mut world = World::new();
+        world.init_resource::<UserDefaultActionTrackerSpawnConfig>();
+        world.init_resource::<Time>();
+        world.init_resource::<Time<Real>>();
+        world.init_resource::<Messages<AiActionStateChangeRequest>>();
+        world.init_resource::<Messages<events::AiActionDispatchToUserCode>>();
+        world.resource_mut::<UserDefaultActionTrackerSpawnConfig>()
+            .with_config_builder(|builder| builder.set_use_ticker(true));
+        world.add_observer(create_tracker_for_picked_action);
+        world.register_action_handler(
+            |inputs: ActionHandlerInputs, mut commands: Commands| { commands.spawn(Dispatched(inputs)); },
+            "Eat",
+        );
+        let ai = world.spawn_empty().id();
+
+        pick(&mut world, ai, "Eat");
+        let tracker = trackers_of(&world, ai)[0];
+
+        world.run_system_cached(tick_based_action_tracker_handler).unwrap();
+        world.run_system_cached(handle_dispatch_to_user_actions).unwrap();
+
+        let dispatched: Vec<ActionHandlerInputs> = world
+            .query::<&Dispatched>()
+            .iter(&world)
+            .map(|dispatched| dispatched.0)
+            .collect();
+        assert_eq!(dispatched, [(ai, None, ai, Some(tracker))]);
+    }
+}
@@ -9,7 +9,7 @@ You can obtain one at https://mozilla.org/MPL/2.0/.
 use bevy::prelude::*;
 use bevy::{platform::collections::Equivalent, reflect::Reflect};
 
-use crate::{types, action_runtime::ActionTrackerState};
+use crate::{types, action_runtime::{ActionTracker, ActionTrackerState, TrackedBy}};
 
 #[cfg(any(feature = "actionset_loader"))]
 use serde::{Deserialize, Serialize};
@@ -122,15 +122,52 @@ pub struct AiActionStateChange {
     pub to_state: crate::action_state::ActionState,
 }
 
+/// Convenience type-alias for the Query used to find the ActionTracker a state change request is meant for.
+pub type TrackerLookupQuery<'w, 's> = Query<'w, 's, (Option<&'static ActionTracker>, Option<&'static TrackedBy>)>;
+
+/// Resolves the target of a state change request to an ActionTracker Entity.
+/// 
+/// Requests should address the ActionTracker itself, but may also address the AI owning it (as 
+/// ActionHandlers only know the latter); in the latter case, we look for the most recent unfinished 
+/// ActionTracker of the AI (see `TrackedBy`) tracking the requested Action. If there is none, the 
+/// Entity is used as-is.
+fn resolve_tracker_entity(
+    entity: Entity,
+    action: &types::ActionKey,
+    tracker_qry: &TrackerLookupQuery,
+    tracker_state_qry: &Query<&mut ActionTrackerState>,
+) -> Entity {
+    let Ok((maybe_tracker, maybe_tracked_by)) = tracker_qry.get(entity) else {
+        return entity;
+    };
+
+    if maybe_tracker.is_some_and(|tracker| tracker.0.action.action_key == *action) {
+        return entity;
+    }
+
+    maybe_tracked_by
+        .and_then(|trackers| {
+            trackers.iter().rev().find(|tracker_entity| {
+                tracker_qry
+                    .get(*tracker_entity)
+                    .is_ok_and(|(tracker, _)| tracker.is_some_and(|tracker| tracker.0.action.action_key == *action))
+                && tracker_state_qry.get(*tracker_entity).is_ok_and(|state| !state.0.is_terminal())
+            })
+        })
+        .unwrap_or(entity)
+}
+
 /// A System that processes all pending AiActionStateChangeRequest and applies them. 
 /// Can be scheduled as a System or (via `action_state_update_handler_observer()`) as an Observer.
 pub fn action_state_update_handler(
     mut request_reader: MessageReader<AiActionStateChangeRequest>,
     mut tracker_state_qry: Query<&mut ActionTrackerState>,
+    tracker_qry: TrackerLookupQuery,
     mut commands: Commands,
 ) {
     request_reader.read().for_each(|msg| {
-        let maybe_tracker_state = tracker_state_qry.get_mut(msg.entity);
+        let target = resolve_tracker_entity(msg.entity, &msg.action, &tracker_qry, &tracker_state_qry);
+        let maybe_tracker_state = tracker_state_qry.get_mut(target);
 
         match maybe_tracker_state {
             Err(err) => {
@@ -152,11 +189,17 @@ pub fn action_state_update_handler(
                 }
             }
             Ok(mut state) => { 
-                bevy::log::debug!("example_action for AI {:?}: Updating the state to new value {:?}", msg.entity, msg.to_state);
                 let current = state.get_state().clone();
+                if current.is_terminal() {
+                    // Terminal states are final; this is most likely a late report for a Cancelled Action.
+                    bevy::log::debug!("{:?}: ActionTracker {:?} already finished as {:?}, ignoring {:?}", &msg.action, target, current, msg.to_state);
+                    return;
+                }
+
+                bevy::log::debug!("{:?}: Updating the state of ActionTracker {:?} to new value {:?}", &msg.action, target, msg.to_state);
                 commands.trigger(AiActionStateChange {
                     action: msg.action.clone(),
-                    entity: msg.entity,
+                    entity: target,
                     from_state: Some(current), 
                     to_state: msg.to_state.clone(),
                 });
@@ -179,9 +222,10 @@ pub fn action_state_update_handler_observer(
     _trigger: On<ProcessActionStateUpdatesSignal>,
     request_reader: MessageReader<AiActionStateChangeRequest>,
     tracker_state_qry: Query<&mut ActionTrackerState>,
+    tracker_qry: TrackerLookupQuery,
     commands: Commands,
 ) {
-    action_state_update_handler(request_reader, tracker_state_qry, commands);
+    action_state_update_handler(request_reader, tracker_state_qry, tracker_qry, commands);
 }
 
 
@@ -0,0 +1,392 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+If a copy of the MPL was not distributed with this file,
+You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+//! Automatic Action timing and timeouts.
+//!
+//! The `ActionTimingPlugin` stamps the `ActionTrackerRuntimeTimer` of ActionTrackers as their
+//! Actions change state: the start time once the Action starts Running, and the end time once it
+//! reaches a terminal state.
+//!
+//! ActionTemplates may also declare `ActionTimeouts` in their ActionSet data:
+//! - `max_queued_secs` - how long the Action may wait (Queued or Ready) before it starts Running,
+//! - `max_run_secs` - how long the Action may take once it started, and
+//! - `max_secs_since_tick` - how long a Running Action may go without being ticked (counting from
+//!   its start if it was never ticked at all).
+//!
+//! ActionTrackers announced with `ActionTrackerSpawnedForTargetAI` for such templates get an
+//! `ActionTrackerTimeouts` Component, along with any timers they need for it. Once an Action
+//! exceeds any of its limits, it is moved to Failed and an `ActionTimedOut` event with the reason
+//! is triggered for its ActionTracker.
+//!
+//! Timeouts are measured in game time, same as the other ActionTracker timers.
+
+use core::time::Duration;
+
+use bevy::prelude::*;
+
+#[cfg(feature = "actionset_loader")]
+use serde::{Deserialize, Serialize};
+
+use crate::action_runtime::{
+    ActionTracker, ActionTrackerCreationTimer, ActionTrackerRuntimeTimer, ActionTrackerSpawnedForTargetAI,
+    ActionTrackerState, ActionTrackerTickTimer, TimeInstantActionTracker,
+};
+use crate::action_state::{ActionState, AiActionStateChange, AiActionStateChangeRequest};
+use crate::smart_object::{ActionSetStore, SmartObjects};
+use crate::types::ActionKey;
+
+/// Time limits for an Action, in seconds of game time. None means no limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Reflect)]
+#[cfg_attr(feature = "actionset_loader", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "actionset_loader", serde(default))]
+pub struct ActionTimeouts {
+    /// How long the Action may wait (Queued or Ready) before it starts Running.
+    pub max_queued_secs: Option<f32>,
+    /// How long the Action may take once it started.
+    pub max_run_secs: Option<f32>,
+    /// How long a Running Action may go without being ticked.
+    pub max_secs_since_tick: Option<f32>,
+}
+
+impl ActionTimeouts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_queued_secs(mut self, secs: f32) -> Self {
+        self.max_queued_secs = Some(secs);
+        self
+    }
+
+    pub fn with_max_run_secs(mut self, secs: f32) -> Self {
+        self.max_run_secs = Some(secs);
+        self
+    }
+
+    pub fn with_max_secs_since_tick(mut self, secs: f32) -> Self {
+        self.max_secs_since_tick = Some(secs);
+        self
+    }
+
+    /// True if there are no limits at all.
+    pub fn is_empty(&self) -> bool {
+        self.max_queued_secs.is_none() && self.max_run_secs.is_none() && self.max_secs_since_tick.is_none()
+    }
+
+    /// Checks an Action in the given state against the limits at time `now`.
+    ///
+    /// The other times are when the ActionTracker was created, when the Action started and when it
+    /// was last ticked; limits whose reference time is unknown are not checked.
+    pub fn check(
+        &self,
+        state: &ActionState,
+        now: Duration,
+        created: Option<Duration>,
+        started: Option<Duration>,
+        last_tick: Option<Duration>,
+    ) -> Option<ActionTimeoutReason> {
+        let exceeded = |limit: Option<f32>, since: Option<Duration>| match (limit, since) {
+            (Some(limit), Some(since)) => now.saturating_sub(since).as_secs_f32() > limit,
+            _ => false,
+        };
+
+        if state.is_terminal() {
+            return None;
+        }
+
+        if state.is_initial() {
+            return exceeded(self.max_queued_secs, created).then_some(ActionTimeoutReason::QueuedTooLong);
+        }
+
+        if exceeded(self.max_run_secs, started) {
+            return Some(ActionTimeoutReason::RanTooLong);
+        }
+
+        let is_running = *state == ActionState::Running;
+        (is_running && exceeded(self.max_secs_since_tick, last_tick.or(started)))
+            .then_some(ActionTimeoutReason::NotTicked)
+    }
+}
+
+/// Why an Action timed out (see `ActionTimeouts`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+pub enum ActionTimeoutReason {
+    /// The Action did not start Running within `max_queued_secs`.
+    QueuedTooLong,
+    /// The Action did not finish within `max_run_secs` of starting.
+    RanTooLong,
+    /// The Running Action was not ticked for `max_secs_since_tick`.
+    NotTicked,
+}
+
+/// An 'extension' Component for ActionTracker Bundles.
+///
+/// The time limits of the tracked Action, copied over from its ActionTemplate.
+/// Removed once the Action times out.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct ActionTrackerTimeouts(pub ActionTimeouts);
+
+/// Signals that an Action exceeded one of its `ActionTimeouts` and is being moved to Failed.
+#[derive(EntityEvent, Debug)]
+pub struct ActionTimedOut {
+    /// The ActionTracker Entity.
+    pub entity: Entity,
+    pub action_key: ActionKey,
+    pub reason: ActionTimeoutReason,
+}
+
+/// An Observer copying the timeouts of the ActionTemplate onto newly spawned ActionTrackers.
+pub fn attach_action_timeouts(
+    event: On<ActionTrackerSpawnedForTargetAI>,
+    store: Option<Res<ActionSetStore>>,
+    smart_objects_query: Query<&SmartObjects>,
+    tracker_query: Query<(&ActionTracker, Has<ActionTrackerCreationTimer>, Has<ActionTrackerRuntimeTimer>)>,
+    (game_timer, real_timer): (Res<Time>, Res<Time<Real>>),
+    mut commands: Commands,
+) {
+    let Some(store) = store else {
+        return;
+    };
+    let Ok((tracker, has_creation_timer, has_runtime_timer)) = tracker_query.get(event.action_tracker) else {
+        return;
+    };
+    let Ok(smart_objects) = smart_objects_query.get(event.entity) else {
+        return;
+    };
+    let action = &tracker.0.action;
+    let Some(timeouts) = store
+        .find_template(smart_objects, &action.action_key, &action.name)
+        .map(|template| template.timeouts)
+        .filter(|timeouts| !timeouts.is_empty())
+    else {
+        return;
+    };
+
+    #[cfg(feature = "logging")]
+    bevy::log::debug!(
+        "attach_action_timeouts: ActionTracker {:?} of {:?} gets timeouts {:?}",
+        event.action_tracker, &action.name, timeouts
+    );
+
+    let mut tracker_cmds = commands.entity(event.action_tracker);
+    tracker_cmds.insert(ActionTrackerTimeouts(timeouts));
+
+    if timeouts.max_queued_secs.is_some() && !has_creation_timer {
+        tracker_cmds.insert(ActionTrackerCreationTimer {
+            creation_time: TimeInstantActionTracker::VirtualAndReal((game_timer.elapsed(), real_timer.elapsed())),
+        });
+    }
+
+    let needs_runtime_timer = timeouts.max_run_secs.is_some() || timeouts.max_secs_since_tick.is_some();
+    if needs_runtime_timer && !has_runtime_timer {
+        tracker_cmds.insert(ActionTrackerRuntimeTimer::default());
+    }
+
+    if timeouts.max_secs_since_tick.is_some() {
+        tracker_cmds.insert_if_new(ActionTrackerTickTimer::default());
+    }
+}
+
+/// An Observer stamping the start and end times of Actions on their ActionTrackers.
+pub fn stamp_action_runtime(
+    event: On<AiActionStateChange>,
+    mut query: Query<&mut ActionTrackerRuntimeTimer>,
+    (game_timer, real_timer): (Res<Time>, Res<Time<Real>>),
+) {
+    let Ok(mut timer) = query.get_mut(event.entity) else {
+        return;
+    };
+    let now = TimeInstantActionTracker::VirtualAndReal((game_timer.elapsed(), real_timer.elapsed()));
+
+    if event.to_state == ActionState::Running && timer.start_time.is_none() {
+        timer.start_time = Some(now);
+    } else if event.to_state.is_terminal() && timer.end_time.is_none() {
+        timer.end_time = Some(now);
+    }
+}
+
+/// Convenience type-alias for the Query used to check ActionTrackers for timeouts.
+pub type TimeoutCheckQuery<'w, 's> = Query<'w, 's, (
+    Entity,
+    &'static ActionTracker,
+    &'static ActionTrackerState,
+    &'static ActionTrackerTimeouts,
+    Option<&'static ActionTrackerCreationTimer>,
+    Option<&'static ActionTrackerRuntimeTimer>,
+    Option<&'static ActionTrackerTickTimer>,
+)>;
+
+/// A System moving Actions that exceeded their `ActionTimeouts` to Failed.
+pub fn fail_timed_out_actions(
+    query: TimeoutCheckQuery,
+    game_timer: Res<Time>,
+    mut state_writer: MessageWriter<AiActionStateChangeRequest>,
+    mut commands: Commands,
+) {
+    let now = game_timer.elapsed();
+
+    for (tracker_entity, tracker, state, timeouts, creation_timer, runtime_timer, tick_timer) in query.iter() {
+        let created = creation_timer.and_then(|timer| timer.creation_time.game_time());
+        let started = runtime_timer
+            .and_then(|timer| timer.start_time.as_ref())
+            .and_then(|time| time.game_time());
+        let last_tick = tick_timer
+            .and_then(|timer| timer.last_tick_time.as_ref())
+            .and_then(|time| time.game_time());
+
+        let Some(reason) = timeouts.0.check(&state.0, now, created, started, last_tick) else {
+            continue;
+        };
+
+        #[cfg(feature = "logging")]
+        bevy::log::warn!(
+            "fail_timed_out_actions: Action {:?} of ActionTracker {:?} timed out ({:?}), failing it",
+            &tracker.0.action.name, tracker_entity, reason
+        );
+
+        state_writer.write(AiActionStateChangeRequest {
+            entity: tracker_entity,
+            action: tracker.0.action.action_key.clone(),
+            to_state: ActionState::Failed,
+        });
+
+        // The request is processed later, so make sure we only time out once.
+        commands.entity(tracker_entity).remove::<ActionTrackerTimeouts>();
+        commands.trigger(ActionTimedOut {
+            entity: tracker_entity,
+            action_key: tracker.0.action.action_key.clone(),
+            reason,
+        });
+    }
+}
+
+/// Sets up automatic Action timing and timeouts (see the module docs).
+pub struct ActionTimingPlugin;
+
+impl Plugin for ActionTimingPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_observer(attach_action_timeouts)
+            .add_observer(stamp_action_runtime)
+            .add_systems(FixedPostUpdate, fail_timed_out_actions)
+        ;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::platform::prelude::{Vec, vec};
+    use bevy::time::{TimePlugin, TimeUpdateStrategy};
+    use crate::action_runtime::spawn_action_tracker;
+    use crate::action_state::ActionStateUpdatesPlugin;
+    use crate::actions::{Action, ActionTemplate, ScoredAction};
+    use crate::actionset::ActionSet;
+    use crate::types::ThreadSafeRef;
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct TimedOut(Vec<(Entity, ActionTimeoutReason, Duration)>);
+
+    fn spawn_tracker(world: &mut World, ai: Entity, action_key: &str) -> Entity {
+        let action = ScoredAction {
+            action: Action { name: action_key.into(), context: ai, action_key: action_key.into() },
+            score: 1.,
+        };
+        let tracker = spawn_action_tracker(&mut world.commands(), ai, action, None, Default::default(), ActionTrackerState::ready(), ()).unwrap();
+        world.flush();
+        tracker
+    }
+
+    #[test]
+    fn test_timeout_checks() {
+        let timeouts = ActionTimeouts::new()
+            .with_max_queued_secs(5.)
+            .with_max_run_secs(30.)
+            .with_max_secs_since_tick(2.);
+        let at = Duration::from_secs;
+
+        assert_eq!(timeouts.check(&ActionState::Queued, at(4), Some(at(0)), None, None), None);
+        assert_eq!(
+            timeouts.check(&ActionState::Ready, at(6), Some(at(0)), None, None),
+            Some(ActionTimeoutReason::QueuedTooLong),
+        );
+
+        assert_eq!(timeouts.check(&ActionState::Running, at(11), Some(at(0)), Some(at(10)), None), None);
+        assert_eq!(
+            timeouts.check(&ActionState::Running, at(13), Some(at(0)), Some(at(10)), None),
+            Some(ActionTimeoutReason::NotTicked),
+        );
+        assert_eq!(timeouts.check(&ActionState::Running, at(13), Some(at(0)), Some(at(10)), Some(at(12))), None);
+        assert_eq!(timeouts.check(&ActionState::Paused, at(20), Some(at(0)), Some(at(10)), None), None);
+        assert_eq!(
+            timeouts.check(&ActionState::Running, at(41), Some(at(0)), Some(at(10)), Some(at(40))),
+            Some(ActionTimeoutReason::RanTooLong),
+        );
+
+        assert_eq!(timeouts.check(&ActionState::Failed, at(100), Some(at(0)), Some(at(10)), None), None);
+        assert!(ActionTimeouts::default().is_empty());
+    }
+
+    #[test]
+    fn test_timing_and_timeouts_in_app() {
+        let mut app = App::new();
+        app.add_plugins((TimePlugin, ActionStateUpdatesPlugin, ActionTimingPlugin))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(250)))
+            .init_resource::<ActionSetStore>()
+            .init_resource::<TimedOut>();
+        app.add_observer(|event: On<ActionTimedOut>, time: Res<Time>, mut timed_out: ResMut<TimedOut>| {
+            timed_out.0.push((event.entity, event.reason, time.elapsed()));
+        });
+
+        let template = |key: &str| ActionTemplate::new(key, "test::Self", vec![], 1., key, None, None);
+        let actionset = ActionSet::new("Worker", vec![
+            template("Work").with_timeouts(ActionTimeouts::new().with_max_run_secs(2.)),
+            template("Chore").with_timeouts(ActionTimeouts::new().with_max_queued_secs(1.)),
+            template("Idle"),
+        ]);
+        app.world_mut().resource_mut::<ActionSetStore>().map_by_name.insert("Worker".into(), actionset);
+
+        let world = app.world_mut();
+        let ai = world.spawn(SmartObjects { actionset_refs: ThreadSafeRef::new(vec!["Worker".into()]) }).id();
+        let work = spawn_tracker(world, ai, "Work");
+        let chore = spawn_tracker(world, ai, "Chore");
+        let idle = spawn_tracker(world, ai, "Idle");
+
+        // The timeouts and the timers they need are copied over from the ActionTemplates.
+        assert_eq!(world.get::<ActionTrackerTimeouts>(work).unwrap().0.max_run_secs, Some(2.));
+        assert!(world.get::<ActionTrackerRuntimeTimer>(work).is_some());
+        assert!(world.get::<ActionTrackerCreationTimer>(work).is_none());
+        assert_eq!(world.get::<ActionTrackerTimeouts>(chore).unwrap().0.max_queued_secs, Some(1.));
+        assert!(world.get::<ActionTrackerCreationTimer>(chore).is_some());
+        assert!(world.get::<ActionTrackerTimeouts>(idle).is_none());
+
+        world.write_message(AiActionStateChangeRequest { entity: work, action: "Work".into(), to_state: ActionState::Running });
+        for _ in 0..16 {
+            app.update();
+        }
+
+        let world = app.world();
+        let timed_out = &world.resource::<TimedOut>().0;
+        assert_eq!(timed_out.len(), 2);
+        assert_eq!(timed_out[0].0, chore);
+        assert_eq!(timed_out[0].1, ActionTimeoutReason::QueuedTooLong);
+        assert!(timed_out[0].2 > Duration::from_secs(1) && timed_out[0].2 <= Duration::from_millis(1250));
+        assert_eq!(timed_out[1].0, work);
+        assert_eq!(timed_out[1].1, ActionTimeoutReason::RanTooLong);
+
+        // Both timed out Actions ended up Failed, with the runtime stamped from Running to Failed.
+        assert_eq!(world.get::<ActionTrackerState>(chore).unwrap().0, ActionState::Failed);
+        assert_eq!(world.get::<ActionTrackerState>(work).unwrap().0, ActionState::Failed);
+        assert_eq!(world.get::<ActionTrackerState>(idle).unwrap().0, ActionState::Ready);
+        assert!(world.get::<ActionTrackerTimeouts>(work).is_none());
+
+        let runtime = world.get::<ActionTrackerRuntimeTimer>(work).unwrap();
+        let started = runtime.start_time.as_ref().and_then(|time| time.game_time()).unwrap();
+        let ended = runtime.end_time.as_ref().and_then(|time| time.game_time()).unwrap();
+        assert!(ended.saturating_sub(started) > Duration::from_secs(2));
+        assert!(ended.saturating_sub(started) <= Duration::from_millis(2250));
+    }
+}
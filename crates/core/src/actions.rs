@@ -35,7 +35,9 @@ use bevy::prelude::*;
 #[cfg(any(feature = "actionset_loader"))]
 use serde::{Serialize, Deserialize};
 
+use crate::action_timing::ActionTimeouts;
 use crate::considerations::ConsiderationData;
+use crate::goap::GoapFact;
 use crate::types::{self, ActionContextRef, CraniumList, CraniumKvMap};
 use crate::identifiers::{ContextFetcherIdentifier};
 
@@ -90,6 +92,25 @@ pub struct ActionTemplate {
     // AI LODs: 
     pub lod_min: Option<types::AiLodLevelPrimitive>,
     pub lod_max: Option<types::AiLodLevelPrimitive>,
+
+    // GOAP (see the `goap` module); ignored by the plain Utility AI decision process.
+    /// Facts that need to hold for the Action to be usable in a plan.
+    #[cfg_attr(any(feature = "actionset_loader"), serde(default))]
+    pub preconditions: CraniumList<GoapFact>,
+    /// Facts that hold once the Action succeeds (or, for GOAP goals, the facts the goal wants to hold).
+    #[cfg_attr(any(feature = "actionset_loader"), serde(default))]
+    pub effects: CraniumList<GoapFact>,
+    /// The cost of using the Action in a plan; defaults to 1.0.
+    #[cfg_attr(any(feature = "actionset_loader"), serde(default))]
+    pub cost: Option<types::ActionScore>,
+
+    /// Pawn resources (e.g. "legs") the Action needs exclusive use of (see the `layers` module).
+    #[cfg_attr(any(feature = "actionset_loader"), serde(default))]
+    pub resources: CraniumList<String>,
+
+    /// Time limits for running the Action (see the `action_timing` module).
+    #[cfg_attr(any(feature = "actionset_loader"), serde(default))]
+    pub timeouts: ActionTimeouts,
 }
 
 impl ActionTemplate {
@@ -114,14 +135,43 @@ impl ActionTemplate {
             action_key: action_key.into(),
             lod_min: lod_min,
             lod_max: lod_max,
+            preconditions: CraniumList::new(),
+            effects: CraniumList::new(),
+            cost: None,
+            resources: CraniumList::new(),
+            timeouts: ActionTimeouts::default(),
         }
     }
 
+    /// Adds GOAP preconditions and effects to this ActionTemplate (see the `goap` module).
+    pub fn with_goap(mut self, preconditions: CraniumList<GoapFact>, effects: CraniumList<GoapFact>) -> Self {
+        self.preconditions.extend(preconditions);
+        self.effects.extend(effects);
+        self
+    }
+
+    pub fn with_cost(mut self, cost: types::ActionScore) -> Self {
+        self.cost = Some(cost);
+        self
+    }
+
+    /// Adds Pawn resources the Action needs exclusive use of (see the `layers` module).
+    pub fn with_resources<IS: Into<String>>(mut self, resources: impl IntoIterator<Item = IS>) -> Self {
+        self.resources.extend(resources.into_iter().map(Into::into));
+        self
+    }
+
+    /// Sets the time limits for running the Action (see the `action_timing` module).
+    pub fn with_timeouts(mut self, timeouts: ActionTimeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
     /// Checks if this template should be processed at a given LOD.
     pub fn is_within_lod_range(&self, lod: &Option<crate::lods::AiLevelOfDetailValue>) -> bool {
         let qry_lod = lod.map(|lv| lv.to_primitive()).unwrap_or(crate::lods::LOD_NORMAL);
         let min = self.lod_min.unwrap_or(crate::lods::LOD_NORMAL);
-        let max = self.lod_min.unwrap_or(crate::lods::LOD_MINIMAL);
+        let max = self.lod_max.unwrap_or(crate::lods::LOD_MINIMAL);
         qry_lod >= min && qry_lod <= max
     }
 }
@@ -148,10 +198,14 @@ impl PartialEq for ActionTemplate {
 impl Eq for ActionTemplate {}
 
 /// Convenience type-alias for the input type required from an ActionHandler. 
+/// 
+/// The last item is the ActionTracker of the dispatched Action, if the dispatch came from one; 
+/// use it to address the Action itself, e.g. to request state changes for it.
 pub type ActionHandlerInputs = (
     types::AiEntity,
     types::PawnEntityRef,
     types::ActionContextRef,
+    types::ActionTrackerEntityRef,
 );
 
 /// Convenience type-alias for the output type required from an ActionHandler. 
@@ -285,3 +339,30 @@ impl Plugin for ActionHandlerPlugin {
         ;
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lods::{AiLevelOfDetailValue, LOD_ELEVATED, LOD_INACTIVE, LOD_MINIMAL, LOD_NORMAL};
+
+    #[test]
+    fn test_template_lod_range() {
+        let at = |lod| Some(AiLevelOfDetailValue::new(lod));
+        let template = |lod_min, lod_max| ActionTemplate::new("Test", "test::CF", CraniumList::new(), 1., "Test", lod_min, lod_max);
+
+        // The max used to be read from lod_min, which made this Template unusable at NORMAL.
+        let elevated_to_minimal = template(Some(LOD_ELEVATED), Some(LOD_MINIMAL));
+        assert!(elevated_to_minimal.is_within_lod_range(&at(LOD_ELEVATED)));
+        assert!(elevated_to_minimal.is_within_lod_range(&at(LOD_NORMAL)));
+        assert!(!elevated_to_minimal.is_within_lod_range(&at(LOD_INACTIVE)));
+
+        let high_detail_only = template(Some(LOD_ELEVATED), Some(LOD_NORMAL));
+        assert!(high_detail_only.is_within_lod_range(&None));
+        assert!(!high_detail_only.is_within_lod_range(&at(LOD_NORMAL + 1)));
+
+        let defaults = template(None, None);
+        assert!(defaults.is_within_lod_range(&at(LOD_MINIMAL)));
+        assert!(!defaults.is_within_lod_range(&at(LOD_ELEVATED)));
+    }
+}
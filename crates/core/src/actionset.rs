@@ -8,6 +8,12 @@ You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use bevy::prelude::*;
 use crate::actions::{ActionTemplate};
+use crate::behavior_trees::BehaviorTreeDefinition;
+use crate::curves::UtilityCurveDefinition;
+use crate::goals::GoalTemplate;
+use crate::htn::HtnDomain;
+use crate::response_surfaces::UtilityResponseSurfaceDefinition;
+use crate::state_machines::StateMachineDefinition;
 
 #[cfg(feature = "actionset_loader")]
 use serde::{Serialize, Deserialize};
@@ -17,6 +23,35 @@ use serde::{Serialize, Deserialize};
 pub struct ActionSet {
     pub name: String,
     pub actions: crate::types::CraniumList<ActionTemplate>,
+
+    /// Utility Curves defined in data; these get registered into the `UtilityCurveRegistry` 
+    /// once the ActionSet is stored, and can then be used by any Consideration by name.
+    #[cfg_attr(any(feature = "actionset_loader"), serde(default))]
+    pub curves: crate::types::CraniumList<UtilityCurveDefinition>,
+
+    /// Response Surfaces defined in data; like `curves`, these get registered 
+    /// (into the `UtilityResponseSurfaceRegistry`) once the ActionSet is stored.
+    #[cfg_attr(any(feature = "actionset_loader"), serde(default))]
+    pub surfaces: crate::types::CraniumList<UtilityResponseSurfaceDefinition>,
+
+    /// State Machines defined in data; like `curves`, these get registered 
+    /// (into the `StateMachineRegistry`) once the ActionSet is stored.
+    #[cfg_attr(any(feature = "actionset_loader"), serde(default))]
+    pub state_machines: crate::types::CraniumList<StateMachineDefinition>,
+
+    /// HTN domains defined in data; like `curves`, these get registered 
+    /// (into the `HtnDomainRegistry`) once the ActionSet is stored.
+    #[cfg_attr(any(feature = "actionset_loader"), serde(default))]
+    pub htn_domains: crate::types::CraniumList<HtnDomain>,
+
+    /// Behavior Trees defined in data; like `curves`, these get registered 
+    /// (into the `BehaviorTreeRegistry`) once the ActionSet is stored.
+    #[cfg_attr(any(feature = "actionset_loader"), serde(default))]
+    pub behavior_trees: crate::types::CraniumList<BehaviorTreeDefinition>,
+
+    /// Goals for the goal tier (see the `goals` module), available to any AI with access to this ActionSet.
+    #[cfg_attr(any(feature = "actionset_loader"), serde(default))]
+    pub goals: crate::types::CraniumList<GoalTemplate>,
 }
 
 impl ActionSet {
@@ -24,6 +59,48 @@ impl ActionSet {
         Self {
             name: name.into(),
             actions: actions,
+            curves: crate::types::CraniumList::new(),
+            surfaces: crate::types::CraniumList::new(),
+            state_machines: crate::types::CraniumList::new(),
+            htn_domains: crate::types::CraniumList::new(),
+            behavior_trees: crate::types::CraniumList::new(),
+            goals: crate::types::CraniumList::new(),
         }
     }
+
+    /// Adds data-defined Curves to this ActionSet (see `UtilityCurveDefinition`).
+    pub fn with_curves(mut self, curves: crate::types::CraniumList<UtilityCurveDefinition>) -> Self {
+        self.curves.extend(curves);
+        self
+    }
+
+    /// Adds data-defined Response Surfaces to this ActionSet (see `UtilityResponseSurfaceDefinition`).
+    pub fn with_surfaces(mut self, surfaces: crate::types::CraniumList<UtilityResponseSurfaceDefinition>) -> Self {
+        self.surfaces.extend(surfaces);
+        self
+    }
+
+    /// Adds data-defined State Machines to this ActionSet (see `StateMachineDefinition`).
+    pub fn with_state_machines(mut self, state_machines: crate::types::CraniumList<StateMachineDefinition>) -> Self {
+        self.state_machines.extend(state_machines);
+        self
+    }
+
+    /// Adds data-defined HTN domains to this ActionSet (see `HtnDomain`).
+    pub fn with_htn_domains(mut self, htn_domains: crate::types::CraniumList<HtnDomain>) -> Self {
+        self.htn_domains.extend(htn_domains);
+        self
+    }
+
+    /// Adds data-defined Behavior Trees to this ActionSet (see `BehaviorTreeDefinition`).
+    pub fn with_behavior_trees(mut self, behavior_trees: crate::types::CraniumList<BehaviorTreeDefinition>) -> Self {
+        self.behavior_trees.extend(behavior_trees);
+        self
+    }
+
+    /// Adds goals to this ActionSet (see `GoalTemplate`).
+    pub fn with_goals(mut self, goals: crate::types::CraniumList<GoalTemplate>) -> Self {
+        self.goals.extend(goals);
+        self
+    }
 }
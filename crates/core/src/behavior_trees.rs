@@ -0,0 +1,1013 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+If a copy of the MPL was not distributed with this file,
+You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+//! Behavior Trees - composite Actions scripted in data.
+//!
+//! Some Actions are really small scripts ('approach, play animation, wait 2s, pick up'); making every
+//! step a separate decision is wasteful and brittle. Instead, an ActionTemplate with an ActionKey of
+//! `BehaviorTree::<tree name>` runs a Behavior Tree defined in an ActionSet (see `ActionSet::behavior_trees`).
+//! Utility AI picks *what* to do, the tree handles *how*.
+//!
+//! Supported nodes (see `BehaviorTreeNode`):
+//! - Leaves: `Action` (runs the ActionHandler of an ActionKey) and `Wait`.
+//! - Composites: `Sequence`, `Selector` and `Parallel`.
+//! - Decorators: `Timeout`, `Retry`, `Cooldown` and `Invert`. Decorators with several children run
+//!   them as a Sequence.
+//!
+//! Each running `Action` leaf gets its own ActionTracker Entity, owned by the AI and using the Context
+//! of the composite Action. Leaf ActionHandlers are dispatched as usual and finish their step by moving
+//! it to a terminal state with an `AiActionStateChangeRequest` for the AI and the leaf's ActionKey -
+//! Succeeded succeeds the leaf, Failed or Cancelled fails it. Leaves that are no longer needed (e.g. the
+//! other branches of a Parallel, or a timed-out subtree) get Cancelled.
+//!
+//! As leaves are told apart by their ActionKeys, the children of a Parallel node may not run leaves
+//! with the same ActionKey; trees that do are rejected on registration (see `BehaviorTreeDefinition::validate`).
+//!
+//! The composite Action itself is moved to Running once the tree starts and to Succeeded or Failed
+//! once it finishes. Picking any other Action stops the tree.
+
+use core::time::Duration;
+use bevy::platform::prelude::{String, ToOwned, vec};
+use bevy::prelude::*;
+
+use crate::action_runtime::{ActionTrackerState, UserDefaultActionTrackerSpawnConfig, spawn_action_tracker};
+use crate::action_state::{ActionState, AiActionStateChange, AiActionStateChangeRequest};
+use crate::actions::{
+    AcceptsActionHandlerRegistrations, ActionHandlerInputs, ActionHandlerKeyToSystemMap,
+    ActionPickCallback, Action, ScoredAction,
+};
+use crate::errors::BehaviorTreeError;
+use crate::events::AiActionPicked;
+use crate::smart_object::ActionSetStore;
+use crate::types::{ActionContextRef, ActionKey, AiEntity, CraniumKvMap, CraniumList};
+
+#[cfg(feature = "actionset_loader")]
+use serde::{Serialize, Deserialize};
+
+/// ActionKey prefix marking ActionTemplates as composite Actions running the Behavior Tree of the same name.
+pub const BEHAVIOR_TREE_ACTION_PREFIX: &str = "BehaviorTree::";
+
+/// A single node of a Behavior Tree.
+#[derive(Clone, Debug, Reflect)]
+#[cfg_attr(feature = "actionset_loader", derive(Serialize, Deserialize))]
+pub enum BehaviorTreeNode {
+    /// Runs the ActionHandler for the ActionKey until the step is moved to a terminal state.
+    Action(ActionKey),
+    /// Succeeds after the specified number of seconds.
+    Wait(f32),
+    /// Runs the children in order; fails as soon as any of them fails.
+    Sequence(CraniumList<BehaviorTreeNode>),
+    /// Runs the children in order until one of them succeeds; fails if none of them does.
+    Selector(CraniumList<BehaviorTreeNode>),
+    /// Runs all the children at once. The children may not share any Action leaf ActionKeys.
+    Parallel {
+        children: CraniumList<BehaviorTreeNode>,
+        /// If set, succeeds as soon as any child succeeds (and fails once all of them fail).
+        /// Otherwise, succeeds once all children succeed (and fails as soon as any of them fails).
+        #[cfg_attr(feature = "actionset_loader", serde(default))]
+        succeed_on_any: bool,
+    },
+    /// Fails (cancelling the children) if the children take longer than the specified number of seconds.
+    Timeout {
+        seconds: f32,
+        children: CraniumList<BehaviorTreeNode>,
+    },
+    /// Runs the children again from scratch if they fail, up to `attempts` times in total.
+    Retry {
+        attempts: u32,
+        children: CraniumList<BehaviorTreeNode>,
+    },
+    /// Fails right away if the children finished less than the specified number of seconds ago.
+    ///
+    /// Cooldowns are tracked per AI and persist across runs of the tree.
+    Cooldown {
+        seconds: f32,
+        children: CraniumList<BehaviorTreeNode>,
+    },
+    /// Flips the result of the children.
+    Invert(CraniumList<BehaviorTreeNode>),
+}
+
+impl BehaviorTreeNode {
+    pub fn children(&self) -> &[BehaviorTreeNode] {
+        match self {
+            Self::Action(_) | Self::Wait(_) => &[],
+            Self::Sequence(children)
+            | Self::Selector(children)
+            | Self::Invert(children)
+            | Self::Parallel { children, .. }
+            | Self::Timeout { children, .. }
+            | Self::Retry { children, .. }
+            | Self::Cooldown { children, .. } => children,
+        }
+    }
+
+    /// The number of nodes in the subtree starting at this node (including itself).
+    pub fn node_count(&self) -> usize {
+        1 + self.children().iter().map(|child| child.node_count()).sum::<usize>()
+    }
+
+    /// The ActionKeys of all Action leaves in the subtree starting at this node, without duplicates.
+    pub fn action_keys(&self) -> CraniumList<&ActionKey> {
+        let mut keys: CraniumList<&ActionKey> = match self {
+            Self::Action(action_key) => vec![action_key],
+            _ => self.children().iter().flat_map(|child| child.action_keys()).collect(),
+        };
+        keys.sort_unstable();
+        keys.dedup();
+        keys
+    }
+
+    /// The first ActionKey used by several children of a Parallel node in the subtree, if any.
+    fn find_parallel_key_conflict(&self) -> Option<&ActionKey> {
+        if let Self::Parallel { children, .. } = self {
+            let mut seen: CraniumList<&ActionKey> = CraniumList::new();
+            for child in children {
+                let keys = child.action_keys();
+                if let Some(conflict) = keys.iter().find(|key| seen.contains(key)) {
+                    return Some(conflict);
+                }
+                seen.extend(keys);
+            }
+        }
+        self.children().iter().find_map(|child| child.find_parallel_key_conflict())
+    }
+}
+
+/// A named Behavior Tree.
+#[derive(Clone, Debug, Reflect)]
+#[cfg_attr(feature = "actionset_loader", derive(Serialize, Deserialize))]
+pub struct BehaviorTreeDefinition {
+    pub name: String,
+    pub root: BehaviorTreeNode,
+}
+
+impl BehaviorTreeDefinition {
+    pub fn new<IS: Into<String>>(name: IS, root: BehaviorTreeNode) -> Self {
+        Self { name: name.into(), root }
+    }
+
+    /// Checks that the tree can run as defined.
+    ///
+    /// Leaf ActionHandlers finish their steps with state change requests for the AI and the leaf's
+    /// ActionKey, so leaves that may run at the same time (i.e. under different children of a
+    /// Parallel node) need distinct ActionKeys.
+    pub fn validate(&self) -> Result<(), BehaviorTreeError> {
+        match self.root.find_parallel_key_conflict() {
+            Some(action_key) => Err(BehaviorTreeError::ParallelActionKeyConflict(action_key.to_owned())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// All known Behavior Trees, by name.
+#[derive(Resource, Debug, Default)]
+pub struct BehaviorTreeRegistry {
+    pub trees: CraniumKvMap<String, BehaviorTreeDefinition>,
+}
+
+impl BehaviorTreeRegistry {
+    /// Adds a tree, replacing any previous one under the same name.
+    ///
+    /// Trees that fail validation (see `BehaviorTreeDefinition::validate`) are rejected with the
+    /// validation error and leave the registry unchanged.
+    pub fn register_behavior_tree(&mut self, tree: BehaviorTreeDefinition) -> Result<&mut Self, BehaviorTreeError> {
+        tree.validate()?;
+        self.trees.insert(tree.name.to_owned(), tree);
+        Ok(self)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&BehaviorTreeDefinition> {
+        self.trees.get(name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect)]
+pub enum BehaviorTreeStatus {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl BehaviorTreeStatus {
+    /// The status of a leaf whose ActionTracker moved to the state, if the state is terminal.
+    pub fn from_action_state(state: ActionState) -> Option<Self> {
+        match state {
+            ActionState::Succeeded => Some(Self::Succeeded),
+            ActionState::Failed | ActionState::Cancelled => Some(Self::Failed),
+            _ => None,
+        }
+    }
+}
+
+/// When the Cooldown nodes of the AI's trees can run again, by tree name and node index.
+#[derive(Component, Debug, Default)]
+pub struct BehaviorTreeCooldowns {
+    pub ready_at: CraniumKvMap<(String, usize), Duration>,
+}
+
+impl BehaviorTreeCooldowns {
+    pub fn is_cooling_down(&self, tree: &str, node: usize, now: Duration) -> bool {
+        self.ready_at
+            .get(&(tree.to_owned(), node))
+            .is_some_and(|ready_at| *ready_at > now)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct NodeMemory {
+    result: Option<BehaviorTreeStatus>,
+    started_at: Option<Duration>,
+    /// For Action leaves: set once the leaf's ActionTracker has been requested.
+    requested: bool,
+    tracker: Option<(Entity, ActionKey)>,
+    attempts: u32,
+}
+
+/// What a tick of a Behavior Tree wants done to the World.
+#[derive(Debug, Default)]
+pub struct BehaviorTreeTick {
+    /// Action leaves to spawn ActionTrackers for, by node index.
+    pub to_spawn: CraniumList<(usize, ActionKey)>,
+    /// Running leaf ActionTrackers to cancel.
+    pub to_cancel: CraniumList<(Entity, ActionKey)>,
+}
+
+/// The state of the Behavior Tree an AI is running.
+#[derive(Component, Debug)]
+pub struct BehaviorTreeRunner {
+    pub tree: String,
+    /// The ActionKey of the composite Action running the tree.
+    pub action_key: ActionKey,
+    pub context: ActionContextRef,
+    pub status: BehaviorTreeStatus,
+    /// Per-node state, in depth-first order.
+    memory: CraniumList<NodeMemory>,
+}
+
+impl BehaviorTreeRunner {
+    pub fn new(definition: &BehaviorTreeDefinition, action_key: ActionKey, context: ActionContextRef) -> Self {
+        Self {
+            tree: definition.name.to_owned(),
+            action_key,
+            context,
+            status: BehaviorTreeStatus::Running,
+            memory: (0..definition.root.node_count()).map(|_| NodeMemory::default()).collect(),
+        }
+    }
+
+    /// Stores the ActionTracker spawned for an Action leaf.
+    pub fn set_leaf_tracker(&mut self, node: usize, tracker: Entity, action_key: ActionKey) {
+        if let Some(memory) = self.memory.get_mut(node) {
+            memory.tracker = Some((tracker, action_key));
+        }
+    }
+
+    /// Records the result of an Action leaf; results from stale ActionTrackers are ignored.
+    pub fn record_leaf_result(&mut self, node: usize, tracker: Entity, status: BehaviorTreeStatus) {
+        let Some(memory) = self.memory.get_mut(node) else {
+            return;
+        };
+        if memory.result.is_none() && memory.tracker.as_ref().is_some_and(|(entity, _)| *entity == tracker) {
+            memory.result = Some(status);
+        }
+    }
+
+    /// All the leaf ActionTrackers that are still running.
+    pub fn running_trackers(&self) -> impl Iterator<Item = &(Entity, ActionKey)> {
+        self.memory.iter()
+            .filter(|memory| memory.result.is_none())
+            .filter_map(|memory| memory.tracker.as_ref())
+    }
+
+    /// Moves the tree forward; the caller is responsible for applying the returned changes to the World.
+    pub fn tick(
+        &mut self,
+        definition: &BehaviorTreeDefinition,
+        now: Duration,
+        cooldowns: &mut BehaviorTreeCooldowns,
+    ) -> BehaviorTreeTick {
+        let mut out = BehaviorTreeTick::default();
+        if self.status == BehaviorTreeStatus::Running {
+            self.status = self.tick_node(&definition.root, 0, now, cooldowns, &mut out);
+        }
+        out
+    }
+
+    fn tick_node(
+        &mut self,
+        node: &BehaviorTreeNode,
+        idx: usize,
+        now: Duration,
+        cooldowns: &mut BehaviorTreeCooldowns,
+        out: &mut BehaviorTreeTick,
+    ) -> BehaviorTreeStatus {
+        if let Some(result) = self.memory[idx].result {
+            return result;
+        }
+
+        let first_tick = self.memory[idx].started_at.is_none();
+        let started_at = *self.memory[idx].started_at.get_or_insert(now);
+
+        let status = match node {
+            BehaviorTreeNode::Action(action_key) => {
+                if !self.memory[idx].requested {
+                    self.memory[idx].requested = true;
+                    out.to_spawn.push((idx, action_key.to_owned()));
+                }
+                BehaviorTreeStatus::Running
+            },
+            BehaviorTreeNode::Wait(seconds) => {
+                match now.saturating_sub(started_at) >= Duration::from_secs_f32(seconds.max(0.)) {
+                    true => BehaviorTreeStatus::Succeeded,
+                    false => BehaviorTreeStatus::Running,
+                }
+            },
+            BehaviorTreeNode::Sequence(children) => {
+                self.tick_children(children, idx, BehaviorTreeStatus::Succeeded, now, cooldowns, out)
+            },
+            BehaviorTreeNode::Selector(children) => {
+                self.tick_children(children, idx, BehaviorTreeStatus::Failed, now, cooldowns, out)
+            },
+            BehaviorTreeNode::Parallel { children, succeed_on_any } => {
+                let (decisive, otherwise) = match succeed_on_any {
+                    true => (BehaviorTreeStatus::Succeeded, BehaviorTreeStatus::Failed),
+                    false => (BehaviorTreeStatus::Failed, BehaviorTreeStatus::Succeeded),
+                };
+
+                let mut child_idx = idx + 1;
+                let mut statuses: CraniumList<BehaviorTreeStatus> = CraniumList::new();
+                for child in children {
+                    statuses.push(self.tick_node(child, child_idx, now, cooldowns, out));
+                    child_idx += child.node_count();
+                }
+
+                if statuses.contains(&decisive) {
+                    decisive
+                } else if statuses.contains(&BehaviorTreeStatus::Running) {
+                    BehaviorTreeStatus::Running
+                } else {
+                    otherwise
+                }
+            },
+            BehaviorTreeNode::Timeout { seconds, children } => {
+                match now.saturating_sub(started_at) >= Duration::from_secs_f32(seconds.max(0.)) {
+                    true => BehaviorTreeStatus::Failed,
+                    false => self.tick_children(children, idx, BehaviorTreeStatus::Succeeded, now, cooldowns, out),
+                }
+            },
+            BehaviorTreeNode::Retry { attempts, children } => {
+                let status = self.tick_children(children, idx, BehaviorTreeStatus::Succeeded, now, cooldowns, out);
+                if status == BehaviorTreeStatus::Failed && self.memory[idx].attempts + 1 < *attempts {
+                    self.memory[idx].attempts += 1;
+                    self.cancel_subtree(idx, node.node_count(), true, out);
+                    // We'll start over on the next tick.
+                    BehaviorTreeStatus::Running
+                } else {
+                    status
+                }
+            },
+            BehaviorTreeNode::Cooldown { seconds, children } => {
+                if first_tick && cooldowns.is_cooling_down(&self.tree, idx, now) {
+                    BehaviorTreeStatus::Failed
+                } else {
+                    let status = self.tick_children(children, idx, BehaviorTreeStatus::Succeeded, now, cooldowns, out);
+                    if status != BehaviorTreeStatus::Running {
+                        let ready_at = now + Duration::from_secs_f32(seconds.max(0.));
+                        cooldowns.ready_at.insert((self.tree.to_owned(), idx), ready_at);
+                    }
+                    status
+                }
+            },
+            BehaviorTreeNode::Invert(children) => {
+                match self.tick_children(children, idx, BehaviorTreeStatus::Succeeded, now, cooldowns, out) {
+                    BehaviorTreeStatus::Succeeded => BehaviorTreeStatus::Failed,
+                    BehaviorTreeStatus::Failed => BehaviorTreeStatus::Succeeded,
+                    BehaviorTreeStatus::Running => BehaviorTreeStatus::Running,
+                }
+            },
+        };
+
+        if status != BehaviorTreeStatus::Running {
+            self.memory[idx].result = Some(status);
+            // Whatever is still running below a finished node is no longer needed.
+            self.cancel_subtree(idx, node.node_count(), false, out);
+        }
+        status
+    }
+
+    /// Runs the children of the node in order for as long as they finish with the `continue_on` status.
+    fn tick_children(
+        &mut self,
+        children: &[BehaviorTreeNode],
+        idx: usize,
+        continue_on: BehaviorTreeStatus,
+        now: Duration,
+        cooldowns: &mut BehaviorTreeCooldowns,
+        out: &mut BehaviorTreeTick,
+    ) -> BehaviorTreeStatus {
+        let mut child_idx = idx + 1;
+        for child in children {
+            let status = self.tick_node(child, child_idx, now, cooldowns, out);
+            if status != continue_on {
+                return status;
+            }
+            child_idx += child.node_count();
+        }
+        continue_on
+    }
+
+    /// Cancels the running leaves below the node; if `reset` is set, the subtree starts over from scratch.
+    fn cancel_subtree(&mut self, idx: usize, count: usize, reset: bool, out: &mut BehaviorTreeTick) {
+        for memory in self.memory[idx + 1..idx + count].iter_mut() {
+            if memory.result.is_none() && let Some(tracker) = memory.tracker.take() {
+                out.to_cancel.push(tracker);
+            }
+            match reset {
+                true => *memory = NodeMemory::default(),
+                false => if memory.result.is_none() {
+                    memory.result = Some(BehaviorTreeStatus::Failed);
+                },
+            }
+        }
+    }
+}
+
+/// Links an Action leaf's ActionTracker to the Behavior Tree running it.
+#[derive(Component, Debug, Clone)]
+pub struct BehaviorTreeLeaf {
+    pub ai: AiEntity,
+    pub node: usize,
+}
+
+/// Asks for the AI to start running the Behavior Tree (unless it already does); triggered by composite ActionHandlers.
+#[derive(EntityEvent, Debug, Clone)]
+pub struct BehaviorTreeStartRequested {
+    pub entity: AiEntity,
+    pub tree: String,
+    pub action_key: ActionKey,
+    pub context: ActionContextRef,
+}
+
+/// Signals that the AI's Behavior Tree has finished.
+#[derive(EntityEvent, Debug, Clone)]
+pub struct BehaviorTreeFinished {
+    pub entity: AiEntity,
+    pub tree: String,
+    pub action_key: ActionKey,
+    pub succeeded: bool,
+}
+
+/// Builds an ActionHandler running the Behavior Tree named by the `BehaviorTree::` ActionKey.
+///
+/// Registered automatically for all `BehaviorTree::` ActionKeys in stored ActionSets.
+pub fn composite_handler<IS: Into<String>>(action_key: IS) -> ActionPickCallback {
+    let action_key: String = action_key.into();
+    let tree = action_key.strip_prefix(BEHAVIOR_TREE_ACTION_PREFIX).unwrap_or(&action_key).to_owned();
+    ActionPickCallback::new(move |inputs: ActionHandlerInputs, mut commands: Commands| {
+        let (ai, _pawn, context, _tracker) = inputs;
+        commands.trigger(BehaviorTreeStartRequested {
+            entity: ai,
+            tree: tree.to_owned(),
+            action_key: action_key.to_owned(),
+            context,
+        });
+    })
+}
+
+fn cancel_leaves(runner: &BehaviorTreeRunner, state_writer: &mut MessageWriter<AiActionStateChangeRequest>) {
+    for (tracker, action_key) in runner.running_trackers() {
+        state_writer.write(AiActionStateChangeRequest {
+            entity: *tracker,
+            action: action_key.to_owned(),
+            to_state: ActionState::Cancelled,
+        });
+    }
+}
+
+/// An Observer starting Behavior Trees for composite Actions.
+pub fn start_behavior_trees(
+    event: On<BehaviorTreeStartRequested>,
+    registry: Option<Res<BehaviorTreeRegistry>>,
+    runner_query: Query<&BehaviorTreeRunner>,
+    mut state_writer: MessageWriter<AiActionStateChangeRequest>,
+    mut commands: Commands,
+) {
+    let ai = event.entity;
+
+    if let Ok(runner) = runner_query.get(ai) {
+        if runner.action_key == event.action_key && runner.context == event.context {
+            return;
+        }
+        cancel_leaves(runner, &mut state_writer);
+    }
+
+    let Some(definition) = registry.as_ref().and_then(|registry| registry.get(&event.tree)) else {
+        #[cfg(feature = "logging")]
+        bevy::log::warn!("start_behavior_trees: Unknown Behavior Tree {:?}, failing {:?}", &event.tree, &event.action_key);
+
+        state_writer.write(AiActionStateChangeRequest {
+            entity: ai,
+            action: event.action_key.to_owned(),
+            to_state: ActionState::Failed,
+        });
+        return;
+    };
+
+    #[cfg(feature = "logging")]
+    bevy::log::debug!("start_behavior_trees: AI {:?} starting Behavior Tree {:?}", ai, &event.tree);
+
+    if let Ok(mut cmds) = commands.get_entity(ai) {
+        cmds.insert(BehaviorTreeRunner::new(definition, event.action_key.to_owned(), event.context));
+    }
+    state_writer.write(AiActionStateChangeRequest {
+        entity: ai,
+        action: event.action_key.to_owned(),
+        to_state: ActionState::Running,
+    });
+}
+
+/// An Observer recording the results of Action leaves as their ActionTrackers finish.
+pub fn record_behavior_tree_leaves(
+    event: On<AiActionStateChange>,
+    leaf_query: Query<&BehaviorTreeLeaf>,
+    mut runner_query: Query<&mut BehaviorTreeRunner>,
+) {
+    let Some(status) = BehaviorTreeStatus::from_action_state(event.to_state) else {
+        return;
+    };
+    let Ok(leaf) = leaf_query.get(event.entity) else {
+        return;
+    };
+    if let Ok(mut runner) = runner_query.get_mut(leaf.ai) {
+        runner.record_leaf_result(leaf.node, event.entity, status);
+    }
+}
+
+/// An Observer stopping the AI's Behavior Tree once it picks a different Action.
+///
+/// Picking the same composite Action again after its tree has finished runs the tree again.
+pub fn stop_behavior_trees(
+    event: On<AiActionPicked>,
+    runner_query: Query<&BehaviorTreeRunner>,
+    mut state_writer: MessageWriter<AiActionStateChangeRequest>,
+    mut commands: Commands,
+) {
+    let Ok(runner) = runner_query.get(event.entity) else {
+        return;
+    };
+    if runner.action_key == event.action_key && runner.status == BehaviorTreeStatus::Running {
+        return;
+    }
+
+    cancel_leaves(runner, &mut state_writer);
+    commands.entity(event.entity).remove::<BehaviorTreeRunner>();
+}
+
+/// A System moving all running Behavior Trees forward.
+pub fn tick_behavior_trees(
+    mut runner_query: Query<(Entity, &mut BehaviorTreeRunner, Option<&mut BehaviorTreeCooldowns>)>,
+    (registry, spawn_defaults): (Option<Res<BehaviorTreeRegistry>>, Option<Res<UserDefaultActionTrackerSpawnConfig>>),
+    (time, real_time): (Res<Time>, Res<Time<Real>>),
+    mut state_writer: MessageWriter<AiActionStateChangeRequest>,
+    mut commands: Commands,
+) {
+    let Some(registry) = registry else {
+        return;
+    };
+    let now = time.elapsed();
+    let spawn_time = (now, real_time.elapsed());
+    let config = spawn_defaults.map(|defaults| defaults.ticking_config())
+        .unwrap_or_else(|| UserDefaultActionTrackerSpawnConfig::default().ticking_config());
+
+    for (ai, mut runner, maybe_cooldowns) in runner_query.iter_mut() {
+        if runner.status != BehaviorTreeStatus::Running {
+            continue;
+        }
+        let Some(definition) = registry.get(&runner.tree) else {
+            continue;
+        };
+
+        let mut new_cooldowns = BehaviorTreeCooldowns::default();
+        let had_cooldowns = maybe_cooldowns.is_some();
+        let tick = match maybe_cooldowns {
+            Some(mut cooldowns) => runner.tick(definition, now, &mut cooldowns),
+            None => runner.tick(definition, now, &mut new_cooldowns),
+        };
+        if !had_cooldowns && !new_cooldowns.ready_at.is_empty() {
+            commands.entity(ai).insert(new_cooldowns);
+        }
+
+        for (tracker, action_key) in tick.to_cancel {
+            state_writer.write(AiActionStateChangeRequest { entity: tracker, action: action_key, to_state: ActionState::Cancelled });
+        }
+
+        for (node, action_key) in tick.to_spawn {
+            let action = ScoredAction {
+                action: Action {
+                    name: action_key.to_owned(),
+                    context: runner.context,
+                    action_key: action_key.to_owned(),
+                },
+                score: 1.,
+            };
+            let Some(tracker) = spawn_action_tracker(
+                &mut commands, ai, action, Some(&config), spawn_time, ActionTrackerState::ready(), BehaviorTreeLeaf { ai, node },
+            ) else {
+                continue;
+            };
+            runner.set_leaf_tracker(node, tracker, action_key);
+        }
+
+        if runner.status != BehaviorTreeStatus::Running {
+            let succeeded = runner.status == BehaviorTreeStatus::Succeeded;
+
+            #[cfg(feature = "logging")]
+            bevy::log::debug!("tick_behavior_trees: AI {:?} finished Behavior Tree {:?} (succeeded: {:?})", ai, &runner.tree, succeeded);
+
+            state_writer.write(AiActionStateChangeRequest {
+                entity: ai,
+                action: runner.action_key.to_owned(),
+                to_state: match succeeded {
+                    true => ActionState::Succeeded,
+                    false => ActionState::Failed,
+                },
+            });
+            commands.trigger(BehaviorTreeFinished {
+                entity: ai,
+                tree: runner.tree.to_owned(),
+                action_key: runner.action_key.to_owned(),
+                succeeded,
+            });
+        }
+    }
+}
+
+/// An exclusive System that registers the Behavior Trees defined in the stored ActionSets, plus
+/// the ActionHandlers for any `BehaviorTree::` ActionKeys in them.
+///
+/// ActionSets are processed in order of their names, so if several of them define a tree with
+/// the same name, the first valid definition wins. Trees that fail validation are logged and skipped.
+pub fn register_actionset_behavior_trees(world: &mut World) {
+    let mut trees: CraniumList<BehaviorTreeDefinition> = CraniumList::new();
+    let mut composite_keys: CraniumList<ActionKey> = CraniumList::new();
+
+    {
+        let Some(store) = world.get_resource::<ActionSetStore>() else {
+            return;
+        };
+        let registered = world.get_resource::<ActionHandlerKeyToSystemMap>();
+
+        let mut actionsets: CraniumList<_> = store.map_by_name.iter().collect();
+        actionsets.sort_by_key(|(name, _)| *name);
+
+        for (_, actionset) in actionsets {
+            trees.extend(actionset.behavior_trees.iter().cloned());
+
+            for template in actionset.actions.iter() {
+                if template.action_key.starts_with(BEHAVIOR_TREE_ACTION_PREFIX)
+                    && !registered.is_some_and(|reg| reg.mapping.contains_key(&template.action_key))
+                {
+                    composite_keys.push(template.action_key.to_owned());
+                }
+            }
+        }
+    }
+
+    {
+        let mut registry = world.get_resource_or_init::<BehaviorTreeRegistry>();
+        let mut registered_names: CraniumList<String> = CraniumList::new();
+        for tree in trees {
+            if registered_names.contains(&tree.name) {
+                continue;
+            }
+            let tree_name = tree.name.to_owned();
+
+            let _res = registry.register_behavior_tree(tree);
+            if _res.is_ok() {
+                registered_names.push(tree_name.to_owned());
+            }
+
+            #[cfg(feature = "logging")]
+            match _res {
+                Ok(_) => bevy::log::debug!(
+                    "register_actionset_behavior_trees: Registered Behavior Tree {:?}", &tree_name,
+                ),
+                Err(err) => bevy::log::error!(
+                    "register_actionset_behavior_trees: Failed to register Behavior Tree {:?} - {:?}", &tree_name, err,
+                ),
+            }
+        }
+    }
+
+    for key in composite_keys {
+        world.register_action_handler(composite_handler(key.to_owned()), key);
+    }
+}
+
+/// Sets up running Behavior Trees as composite Actions (see the module docs).
+pub struct BehaviorTreePlugin;
+
+impl Plugin for BehaviorTreePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<BehaviorTreeRegistry>()
+            .add_observer(start_behavior_trees)
+            .add_observer(record_behavior_tree_leaves)
+            .add_observer(stop_behavior_trees)
+            .add_systems(
+                First,
+                register_actionset_behavior_trees.run_if(resource_exists_and_changed::<ActionSetStore>)
+            )
+            .add_systems(
+                FixedPostUpdate,
+                tick_behavior_trees.after(crate::decision_loop::handle_dispatch_to_user_actions)
+            )
+        ;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bevy::platform::prelude::vec;
+    use crate::action_runtime::ActionTracker;
+    use crate::action_state::{ActionStateUpdatesPlugin, action_state_update_handler};
+    use super::*;
+
+    fn secs(seconds: u64) -> Duration {
+        Duration::from_secs(seconds)
+    }
+
+    fn leaf(action_key: &str) -> BehaviorTreeNode {
+        BehaviorTreeNode::Action(action_key.into())
+    }
+
+    /// Ticks the runner, pretending to spawn the requested leaves with made-up ActionTracker Entities.
+    fn tick_and_spawn(
+        runner: &mut BehaviorTreeRunner,
+        tree: &BehaviorTreeDefinition,
+        now: Duration,
+        cooldowns: &mut BehaviorTreeCooldowns,
+    ) -> (CraniumList<(usize, Entity)>, BehaviorTreeTick) {
+        let tick = runner.tick(tree, now, cooldowns);
+        let spawned = tick.to_spawn.iter()
+            .map(|(node, action_key)| {
+                let tracker = Entity::from_raw_u32(100 + *node as u32).unwrap();
+                runner.set_leaf_tracker(*node, tracker, action_key.to_owned());
+                (*node, tracker)
+            })
+            .collect();
+        (spawned, tick)
+    }
+
+    #[test]
+    fn test_tick_sequence_timeout_and_retry() {
+        let tree = BehaviorTreeDefinition::new("PickUp", BehaviorTreeNode::Sequence(vec![
+            BehaviorTreeNode::Timeout { seconds: 10., children: vec![BehaviorTreeNode::Action("Approach".into())] },
+            BehaviorTreeNode::Wait(2.),
+            BehaviorTreeNode::Retry { attempts: 2, children: vec![BehaviorTreeNode::Action("Grab".into())] },
+        ]));
+        let mut cooldowns = BehaviorTreeCooldowns::default();
+        let mut runner = BehaviorTreeRunner::new(&tree, "BehaviorTree::PickUp".into(), Entity::PLACEHOLDER);
+
+        let approach = Entity::from_raw_u32(1).unwrap();
+        let tick = runner.tick(&tree, secs(0), &mut cooldowns);
+        assert_eq!(tick.to_spawn.len(), 1);
+        let (approach_node, _) = tick.to_spawn[0];
+        runner.set_leaf_tracker(approach_node, approach, "Approach".into());
+
+        // Nothing new gets spawned while the leaf runs.
+        assert!(runner.tick(&tree, secs(1), &mut cooldowns).to_spawn.is_empty());
+        runner.record_leaf_result(approach_node, approach, BehaviorTreeStatus::Succeeded);
+
+        // Waiting...
+        assert!(runner.tick(&tree, secs(2), &mut cooldowns).to_spawn.is_empty());
+        let tick = runner.tick(&tree, secs(4), &mut cooldowns);
+        assert_eq!(tick.to_spawn.len(), 1);
+        let (grab_node, _) = tick.to_spawn[0];
+
+        // The first attempt fails, the second one succeeds.
+        let first_grab = Entity::from_raw_u32(2).unwrap();
+        runner.set_leaf_tracker(grab_node, first_grab, "Grab".into());
+        runner.record_leaf_result(grab_node, first_grab, BehaviorTreeStatus::Failed);
+        assert!(runner.tick(&tree, secs(5), &mut cooldowns).to_spawn.is_empty());
+        assert_eq!(runner.status, BehaviorTreeStatus::Running);
+
+        let tick = runner.tick(&tree, secs(6), &mut cooldowns);
+        assert_eq!(tick.to_spawn.len(), 1);
+        let second_grab = Entity::from_raw_u32(3).unwrap();
+        runner.set_leaf_tracker(grab_node, second_grab, "Grab".into());
+
+        // Stale results are ignored.
+        runner.record_leaf_result(grab_node, first_grab, BehaviorTreeStatus::Failed);
+        runner.record_leaf_result(grab_node, second_grab, BehaviorTreeStatus::Succeeded);
+        runner.tick(&tree, secs(7), &mut cooldowns);
+        assert_eq!(runner.status, BehaviorTreeStatus::Succeeded);
+
+        // A leaf running past its timeout gets cancelled.
+        let mut runner = BehaviorTreeRunner::new(&tree, "BehaviorTree::PickUp".into(), Entity::PLACEHOLDER);
+        let tick = runner.tick(&tree, secs(0), &mut cooldowns);
+        runner.set_leaf_tracker(tick.to_spawn[0].0, approach, "Approach".into());
+        let tick = runner.tick(&tree, secs(11), &mut cooldowns);
+        assert_eq!(tick.to_cancel.len(), 1);
+        assert_eq!(runner.status, BehaviorTreeStatus::Failed);
+    }
+
+    #[test]
+    fn test_tick_selector_and_invert() {
+        let tree = BehaviorTreeDefinition::new("Enter", BehaviorTreeNode::Selector(vec![
+            leaf("OpenDoor"),
+            BehaviorTreeNode::Invert(vec![leaf("KickDoor")]),
+        ]));
+        let mut cooldowns = BehaviorTreeCooldowns::default();
+        let mut runner = BehaviorTreeRunner::new(&tree, "BehaviorTree::Enter".into(), Entity::PLACEHOLDER);
+
+        // Selectors try one child at a time.
+        let (spawned, _) = tick_and_spawn(&mut runner, &tree, secs(0), &mut cooldowns);
+        assert_eq!(spawned.len(), 1);
+        let (open_node, open) = spawned[0];
+        runner.record_leaf_result(open_node, open, BehaviorTreeStatus::Failed);
+
+        let (spawned, _) = tick_and_spawn(&mut runner, &tree, secs(1), &mut cooldowns);
+        assert_eq!(spawned.len(), 1);
+        let (kick_node, kick) = spawned[0];
+        assert_eq!(runner.status, BehaviorTreeStatus::Running);
+
+        // The inverted success fails the last child, and with it the whole Selector.
+        runner.record_leaf_result(kick_node, kick, BehaviorTreeStatus::Succeeded);
+        runner.tick(&tree, secs(2), &mut cooldowns);
+        assert_eq!(runner.status, BehaviorTreeStatus::Failed);
+
+        // The first child to succeed wins.
+        let mut runner = BehaviorTreeRunner::new(&tree, "BehaviorTree::Enter".into(), Entity::PLACEHOLDER);
+        let (spawned, _) = tick_and_spawn(&mut runner, &tree, secs(0), &mut cooldowns);
+        runner.record_leaf_result(spawned[0].0, spawned[0].1, BehaviorTreeStatus::Succeeded);
+        let (spawned, _) = tick_and_spawn(&mut runner, &tree, secs(1), &mut cooldowns);
+        assert!(spawned.is_empty());
+        assert_eq!(runner.status, BehaviorTreeStatus::Succeeded);
+    }
+
+    #[test]
+    fn test_tick_parallel() {
+        let parallel = |succeed_on_any| BehaviorTreeDefinition::new("Guard", BehaviorTreeNode::Parallel {
+            children: vec![leaf("Watch"), leaf("Listen")],
+            succeed_on_any,
+        });
+        let mut cooldowns = BehaviorTreeCooldowns::default();
+
+        // By default, all children need to succeed, and any failure fails the node.
+        let tree = parallel(false);
+        let mut runner = BehaviorTreeRunner::new(&tree, "BehaviorTree::Guard".into(), Entity::PLACEHOLDER);
+        let (spawned, _) = tick_and_spawn(&mut runner, &tree, secs(0), &mut cooldowns);
+        assert_eq!(spawned.len(), 2);
+        runner.record_leaf_result(spawned[0].0, spawned[0].1, BehaviorTreeStatus::Succeeded);
+        runner.tick(&tree, secs(1), &mut cooldowns);
+        assert_eq!(runner.status, BehaviorTreeStatus::Running);
+        runner.record_leaf_result(spawned[1].0, spawned[1].1, BehaviorTreeStatus::Succeeded);
+        runner.tick(&tree, secs(2), &mut cooldowns);
+        assert_eq!(runner.status, BehaviorTreeStatus::Succeeded);
+
+        let mut runner = BehaviorTreeRunner::new(&tree, "BehaviorTree::Guard".into(), Entity::PLACEHOLDER);
+        let (spawned, _) = tick_and_spawn(&mut runner, &tree, secs(0), &mut cooldowns);
+        runner.record_leaf_result(spawned[0].0, spawned[0].1, BehaviorTreeStatus::Failed);
+        let tick = runner.tick(&tree, secs(1), &mut cooldowns);
+        assert_eq!(runner.status, BehaviorTreeStatus::Failed);
+        assert_eq!(tick.to_cancel, [(spawned[1].1, "Listen".into())]);
+
+        // With succeed_on_any, it is the other way around.
+        let tree = parallel(true);
+        let mut runner = BehaviorTreeRunner::new(&tree, "BehaviorTree::Guard".into(), Entity::PLACEHOLDER);
+        let (spawned, _) = tick_and_spawn(&mut runner, &tree, secs(0), &mut cooldowns);
+        runner.record_leaf_result(spawned[0].0, spawned[0].1, BehaviorTreeStatus::Failed);
+        runner.tick(&tree, secs(1), &mut cooldowns);
+        assert_eq!(runner.status, BehaviorTreeStatus::Running);
+        runner.record_leaf_result(spawned[1].0, spawned[1].1, BehaviorTreeStatus::Failed);
+        runner.tick(&tree, secs(2), &mut cooldowns);
+        assert_eq!(runner.status, BehaviorTreeStatus::Failed);
+
+        let mut runner = BehaviorTreeRunner::new(&tree, "BehaviorTree::Guard".into(), Entity::PLACEHOLDER);
+        let (spawned, _) = tick_and_spawn(&mut runner, &tree, secs(0), &mut cooldowns);
+        runner.record_leaf_result(spawned[1].0, spawned[1].1, BehaviorTreeStatus::Succeeded);
+        let tick = runner.tick(&tree, secs(1), &mut cooldowns);
+        assert_eq!(runner.status, BehaviorTreeStatus::Succeeded);
+        assert_eq!(tick.to_cancel, [(spawned[0].1, "Watch".into())]);
+    }
+
+    #[test]
+    fn test_cooldowns_persist_across_runs() {
+        let tree = BehaviorTreeDefinition::new("Taunt", BehaviorTreeNode::Cooldown {
+            seconds: 5.,
+            children: vec![leaf("Shout")],
+        });
+        let mut cooldowns = BehaviorTreeCooldowns::default();
+
+        let mut runner = BehaviorTreeRunner::new(&tree, "BehaviorTree::Taunt".into(), Entity::PLACEHOLDER);
+        let (spawned, _) = tick_and_spawn(&mut runner, &tree, secs(0), &mut cooldowns);
+        runner.record_leaf_result(spawned[0].0, spawned[0].1, BehaviorTreeStatus::Succeeded);
+        runner.tick(&tree, secs(1), &mut cooldowns);
+        assert_eq!(runner.status, BehaviorTreeStatus::Succeeded);
+
+        // A new run of the tree is still on cooldown...
+        let mut runner = BehaviorTreeRunner::new(&tree, "BehaviorTree::Taunt".into(), Entity::PLACEHOLDER);
+        let (spawned, _) = tick_and_spawn(&mut runner, &tree, secs(3), &mut cooldowns);
+        assert!(spawned.is_empty());
+        assert_eq!(runner.status, BehaviorTreeStatus::Failed);
+
+        // ...until the cooldown runs out, counting from when the children finished.
+        let mut runner = BehaviorTreeRunner::new(&tree, "BehaviorTree::Taunt".into(), Entity::PLACEHOLDER);
+        let (spawned, _) = tick_and_spawn(&mut runner, &tree, secs(6), &mut cooldowns);
+        assert_eq!(spawned.len(), 1);
+        assert_eq!(runner.status, BehaviorTreeStatus::Running);
+    }
+
+    #[test]
+    fn test_parallel_action_key_conflicts_are_rejected() {
+        let tree = |children| BehaviorTreeDefinition::new("Guard", BehaviorTreeNode::Parallel { children, succeed_on_any: false });
+
+        // The same ActionKey within a single child runs one leaf at a time, which is fine.
+        let sequential = tree(vec![BehaviorTreeNode::Sequence(vec![leaf("Look"), leaf("Look")]), leaf("Listen")]);
+        assert_eq!(sequential.validate(), Ok(()));
+
+        let conflicting = tree(vec![leaf("Look"), BehaviorTreeNode::Invert(vec![leaf("Look")])]);
+        assert_eq!(conflicting.validate(), Err(BehaviorTreeError::ParallelActionKeyConflict("Look".into())));
+
+        let mut registry = BehaviorTreeRegistry::default();
+        registry.register_behavior_tree(sequential).unwrap();
+        assert_eq!(
+            registry.register_behavior_tree(conflicting).err(),
+            Some(BehaviorTreeError::ParallelActionKeyConflict("Look".into())),
+        );
+        assert!(registry.get("Guard").is_some_and(|tree| tree.root.node_count() == 5));
+    }
+
+    #[test]
+    fn test_leaf_trackers_in_app() {
+        let mut app = App::new();
+        app.add_plugins((ActionStateUpdatesPlugin, BehaviorTreePlugin)).init_resource::<Time>().init_resource::<Time<Real>>();
+        app.world_mut().resource_mut::<BehaviorTreeRegistry>().register_behavior_tree(
+            BehaviorTreeDefinition::new("Fetch", BehaviorTreeNode::Sequence(vec![leaf("Approach"), leaf("Grab")]))
+        ).unwrap();
+
+        let ai = app.world_mut().spawn_empty().id();
+        app.world_mut().trigger(BehaviorTreeStartRequested {
+            entity: ai,
+            tree: "Fetch".into(),
+            action_key: "BehaviorTree::Fetch".into(),
+            context: ai,
+        });
+        app.world_mut().flush();
+
+        let tick_and_finish_leaf = |app: &mut App, to_state: ActionState| -> Option<Entity> {
+            app.world_mut().run_system_cached(tick_behavior_trees).unwrap();
+            let mut leaves = app.world_mut().query::<(Entity, &BehaviorTreeLeaf, &ActionTracker, &ActionTrackerState)>();
+            let (tracker, action_key) = leaves
+                .iter(app.world())
+                .find(|(_, _, _, state)| !state.get_state().is_terminal())
+                .map(|(tracker, leaf, action, state)| {
+                    assert_eq!(leaf.ai, ai);
+                    assert_eq!(*state.get_state(), ActionState::Ready);
+                    (tracker, action.0.action.action_key.to_owned())
+                })?;
+
+            app.world_mut().write_message(AiActionStateChangeRequest { entity: tracker, action: action_key, to_state });
+            app.world_mut().run_system_cached(action_state_update_handler).unwrap();
+            app.world_mut().flush();
+            Some(tracker)
+        };
+
+        let approach = tick_and_finish_leaf(&mut app, ActionState::Succeeded).unwrap();
+        let grab = tick_and_finish_leaf(&mut app, ActionState::Succeeded).unwrap();
+        assert_ne!(approach, grab);
+        assert_eq!(app.world().get::<ActionTracker>(grab).unwrap().0.action.action_key, "Grab");
+
+        // Both leaves reported back through their state changes, so the tree is done.
+        assert!(tick_and_finish_leaf(&mut app, ActionState::Succeeded).is_none());
+        assert_eq!(app.world().get::<BehaviorTreeRunner>(ai).unwrap().status, BehaviorTreeStatus::Succeeded);
+    }
+
+    #[test]
+    fn test_actionset_behavior_trees_register_in_name_order() {
+        use crate::actionset::ActionSet;
+
+        let conflicting = BehaviorTreeNode::Parallel { children: vec![leaf("Look"), leaf("Look")], succeed_on_any: false };
+        let mut world = World::new();
+        let mut store = ActionSetStore::default();
+        for (name, root) in [("c", leaf("Sleep")), ("a", conflicting), ("b", leaf("Look")), ("d", leaf("Listen"))] {
+            let tree = BehaviorTreeDefinition::new("Guard", root);
+            store.map_by_name.insert(name.into(), ActionSet::new(name, vec![]).with_behavior_trees(vec![tree]));
+        }
+        world.insert_resource(store);
+
+        for _ in 0..3 {
+            world.run_system_cached(register_actionset_behavior_trees).unwrap();
+        }
+
+        // The ActionSets are processed by name and "a" is rejected, so "b" wins the shared name.
+        let registry = world.resource::<BehaviorTreeRegistry>();
+        assert!(matches!(&registry.get("Guard").unwrap().root, BehaviorTreeNode::Action(key) if key == "Look"));
+    }
+}
@@ -0,0 +1,545 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+If a copy of the MPL was not distributed with this file,
+You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+//! Blackboards - a standard place for per-AI scratch state.
+//!
+//! Most AIs need to keep some bits of state around between decisions - the current target, the
+//! alert level, the last waypoint visited... The `Blackboard` Component is a simple key-value store
+//! for exactly that, holding any reflectable value under a String key.
+//!
+//! In Rust code, you can use `BlackboardKey<T>` constants to get typed access without repeating
+//! the type at every call site, e.g. `const ALERT: BlackboardKey<f32> = BlackboardKey::new("alert");`.
+//!
+//! Every write that actually changes a value bumps the Blackboard's revision counter, and each entry
+//! remembers the revision it was last changed at, so Systems can check whether a specific key changed
+//! since they last looked (`Blackboard::changed_since()`). Bevy's own `Changed<Blackboard>` works too,
+//! but is coarser - it fires for any mutable access to the Component.
+//!
+//! Designers can also use the Blackboard from ActionSet data alone, without any Rust code, by using
+//! specially-prefixed keys; the `BlackboardPlugin` registers the matching Considerations and
+//! ActionHandlers automatically whenever they show up in a stored ActionSet:
+//!
+//! - Consideration `Blackboard::<key>` - the numeric value stored under the key (bools are 0.0/1.0).
+//! - Consideration `BlackboardHas::<key>` - 1.0 if there is any value under the key, 0.0 otherwise.
+//! - Consideration `BlackboardIsContext::<key>` - 1.0 if the key holds the Context Entity, 0.0 otherwise.
+//! - Action `BlackboardSet::<key>=<value>` - stores a bool, number or (failing both) a String.
+//! - Action `BlackboardSetContext::<key>` - stores the Action's Context Entity.
+//! - Action `BlackboardClear::<key>` - removes the value.
+//!
+//! The ActionHandlers only write the Blackboard; the Action's state is handled like for any other Action.
+
+use core::borrow::Borrow;
+use core::marker::PhantomData;
+
+use bevy::platform::prelude::{Box, String, ToOwned, ToString, Vec};
+use bevy::prelude::*;
+
+use crate::actions::{ActionHandlerInputs, ActionHandlerKeyToSystemMap, ActionPickCallback, AcceptsActionHandlerRegistrations};
+use crate::considerations::{
+    AcceptsConsiderationRegistrations, ConsiderationInputs, ConsiderationKeyToSystemMap,
+    ConsiderationOutputs, ensure_consideration_queries,
+};
+use crate::dynamic_bounds::reflected_as_score;
+use crate::smart_object::ActionSetStore;
+use crate::types::{ActionScore, CraniumKvMap};
+
+/// Consideration key prefix for reading numeric Blackboard values.
+pub const BLACKBOARD_VALUE_PREFIX: &str = "Blackboard::";
+/// Consideration key prefix for checking whether a Blackboard value is set.
+pub const BLACKBOARD_HAS_PREFIX: &str = "BlackboardHas::";
+/// Consideration key prefix for checking whether a Blackboard value is the current Context.
+pub const BLACKBOARD_IS_CONTEXT_PREFIX: &str = "BlackboardIsContext::";
+/// ActionHandler key prefix for storing a literal value; the key and value are separated by a `=`.
+pub const BLACKBOARD_SET_PREFIX: &str = "BlackboardSet::";
+/// ActionHandler key prefix for storing the Action's Context.
+pub const BLACKBOARD_SET_CONTEXT_PREFIX: &str = "BlackboardSetContext::";
+/// ActionHandler key prefix for removing a value.
+pub const BLACKBOARD_CLEAR_PREFIX: &str = "BlackboardClear::";
+
+/// A typed name of a Blackboard entry.
+pub struct BlackboardKey<T> {
+    name: &'static str,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> BlackboardKey<T> {
+    pub const fn new(name: &'static str) -> Self {
+        Self { name, _marker: PhantomData }
+    }
+
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl<T> Clone for BlackboardKey<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for BlackboardKey<T> {}
+
+impl<T> core::fmt::Debug for BlackboardKey<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("BlackboardKey").field(&self.name).finish()
+    }
+}
+
+/// A single value on a Blackboard, along with the revision it was last changed at.
+#[derive(Debug)]
+pub struct BlackboardEntry {
+    value: Box<dyn Reflect>,
+    changed_at: u64,
+}
+
+impl BlackboardEntry {
+    pub fn value(&self) -> &dyn Reflect {
+        self.value.as_ref()
+    }
+
+    pub fn changed_at(&self) -> u64 {
+        self.changed_at
+    }
+}
+
+/// Per-AI scratch state - any reflectable values, keyed by name.
+#[derive(Component, Debug, Default)]
+pub struct Blackboard {
+    entries: CraniumKvMap<String, BlackboardEntry>,
+    revision: u64,
+}
+
+impl Blackboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The current revision; increases with every change made to the Blackboard.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Stores a value under the key.
+    ///
+    /// Returns false (and does not bump the revision) if the key already held an equal value.
+    pub fn set_value<T: Reflect, IS: Into<String>>(&mut self, key: IS, value: T) -> bool {
+        self.set_boxed(key, Box::new(value))
+    }
+
+    /// Stores an already-boxed value; see `set_value()`.
+    pub fn set_boxed<IS: Into<String>>(&mut self, key: IS, value: Box<dyn Reflect>) -> bool {
+        let key = key.into();
+        let unchanged = self.entries
+            .get(&key)
+            .and_then(|entry| entry.value.reflect_partial_eq(value.as_partial_reflect()))
+            .unwrap_or(false);
+
+        if unchanged {
+            return false;
+        }
+
+        self.revision += 1;
+        self.entries.insert(key, BlackboardEntry { value, changed_at: self.revision });
+        true
+    }
+
+    /// Typed version of `set_value()`.
+    pub fn set<T: Reflect>(&mut self, key: &BlackboardKey<T>, value: T) -> bool {
+        self.set_value(key.name, value)
+    }
+
+    /// Retrieves the value under the key; None if unset or not of the requested type.
+    pub fn get_value<T: Reflect>(&self, key: &str) -> Option<&T> {
+        self.entries.get(key).and_then(|entry| entry.value.downcast_ref::<T>())
+    }
+
+    /// Typed version of `get_value()`.
+    pub fn get<T: Reflect>(&self, key: &BlackboardKey<T>) -> Option<&T> {
+        self.get_value(key.name)
+    }
+
+    pub fn get_mut<T: Reflect>(&mut self, key: &BlackboardKey<T>) -> Option<&mut T> {
+        // We cannot tell if the caller will actually change anything, so assume they will.
+        let entry = self.entries.get_mut(key.name)?;
+        let value = entry.value.downcast_mut::<T>()?;
+        self.revision += 1;
+        entry.changed_at = self.revision;
+        Some(value)
+    }
+
+    /// Retrieves the value under the key without knowing its type.
+    pub fn get_reflect(&self, key: &str) -> Option<&dyn Reflect> {
+        self.entries.get(key).map(|entry| entry.value.as_ref())
+    }
+
+    /// Retrieves the value under the key as a number, if it is one (bools count as 0.0 or 1.0).
+    pub fn get_number(&self, key: &str) -> Option<ActionScore> {
+        let value = self.get_reflect(key)?;
+        match value.downcast_ref::<bool>() {
+            Some(flag) => Some(if *flag { 1. } else { 0. }),
+            None => reflected_as_score(value.as_partial_reflect()),
+        }
+    }
+
+    /// Retrieves the value under the key as an Entity, if it is one.
+    pub fn get_entity(&self, key: &str) -> Option<Entity> {
+        self.get_value::<Entity>(key).copied()
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Box<dyn Reflect>> {
+        let removed = self.entries.remove(key)?;
+        self.revision += 1;
+        Some(removed.value)
+    }
+
+    pub fn clear(&mut self) {
+        if !self.entries.is_empty() {
+            self.revision += 1;
+            self.entries.clear();
+        }
+    }
+
+    /// The revision the key was last changed at; None if it is not set.
+    pub fn changed_at(&self, key: &str) -> Option<u64> {
+        self.entries.get(key).map(|entry| entry.changed_at)
+    }
+
+    /// True if the key was set to a new value after the specified revision.
+    ///
+    /// Note that removals are not tracked per-key; use `contains()` for those.
+    pub fn changed_since(&self, key: &str, revision: u64) -> bool {
+        self.changed_at(key).map(|changed| changed > revision).unwrap_or(false)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &BlackboardEntry)> {
+        self.entries.iter().map(|(key, entry)| (key.as_str(), entry))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Convenience methods for writing to an Entity's Blackboard from Commands.
+///
+/// These insert an empty Blackboard first if the Entity does not have one yet.
+pub trait BlackboardCommandsExt {
+    fn set_blackboard_value<T: Reflect, IS: Into<String>>(&mut self, key: IS, value: T) -> &mut Self;
+    fn set_blackboard_boxed<IS: Into<String>>(&mut self, key: IS, value: Box<dyn Reflect>) -> &mut Self;
+    fn remove_blackboard_value<IS: Into<String>>(&mut self, key: IS) -> &mut Self;
+}
+
+impl BlackboardCommandsExt for EntityCommands<'_> {
+    fn set_blackboard_value<T: Reflect, IS: Into<String>>(&mut self, key: IS, value: T) -> &mut Self {
+        self.set_blackboard_boxed(key, Box::new(value))
+    }
+
+    fn set_blackboard_boxed<IS: Into<String>>(&mut self, key: IS, value: Box<dyn Reflect>) -> &mut Self {
+        let key = key.into();
+        self.queue(move |mut entity: EntityWorldMut| {
+            match entity.get_mut::<Blackboard>() {
+                Some(mut blackboard) => { blackboard.set_boxed(key, value); },
+                None => {
+                    let mut blackboard = Blackboard::new();
+                    blackboard.set_boxed(key, value);
+                    entity.insert(blackboard);
+                }
+            }
+        })
+    }
+
+    fn remove_blackboard_value<IS: Into<String>>(&mut self, key: IS) -> &mut Self {
+        let key = key.into();
+        self.queue(move |mut entity: EntityWorldMut| {
+            if let Some(mut blackboard) = entity.get_mut::<Blackboard>() {
+                blackboard.remove(&key);
+            }
+        })
+    }
+}
+
+/// Builds an ActionHandler that stores a (copy of the) value under the key on the AI's Blackboard.
+pub fn set_value_handler<T: Reflect + Clone, IS: Into<String>>(key: IS, value: T) -> ActionPickCallback {
+    let key: String = key.into();
+    ActionPickCallback::new(move |inputs: ActionHandlerInputs, mut commands: Commands| {
+        let (ai, _pawn, _context, _tracker) = inputs;
+        if let Ok(mut cmds) = commands.get_entity(ai) {
+            cmds.set_blackboard_value(key.clone(), value.clone());
+        }
+    })
+}
+
+/// Builds an ActionHandler that stores the Action's Context under the key on the AI's Blackboard.
+pub fn set_context_handler<IS: Into<String>>(key: IS) -> ActionPickCallback {
+    let key: String = key.into();
+    ActionPickCallback::new(move |inputs: ActionHandlerInputs, mut commands: Commands| {
+        let (ai, _pawn, context, _tracker) = inputs;
+        if let Ok(mut cmds) = commands.get_entity(ai) {
+            cmds.set_blackboard_value(key.clone(), context);
+        }
+    })
+}
+
+/// Builds an ActionHandler that removes the value under the key from the AI's Blackboard.
+pub fn clear_handler<IS: Into<String>>(key: IS) -> ActionPickCallback {
+    let key: String = key.into();
+    ActionPickCallback::new(move |inputs: ActionHandlerInputs, mut commands: Commands| {
+        let (ai, _pawn, _context, _tracker) = inputs;
+        if let Ok(mut cmds) = commands.get_entity(ai) {
+            cmds.remove_blackboard_value(key.clone());
+        }
+    })
+}
+
+/// Registers a Consideration that reads the numeric value under the Blackboard key.
+///
+/// Unset or non-numeric values discard the Context.
+pub fn register_value_consideration<IS: Into<String>>(world: &mut World, blackboard_key: IS, key: IS) {
+    let blackboard_key: String = blackboard_key.into();
+    world.register_consideration(
+        move |inputs: ConsiderationInputs, query: Query<&Blackboard>| -> ConsiderationOutputs {
+            let (ai, _pawn, _context) = inputs.0;
+            query.get(ai).ok()?.get_number(&blackboard_key)
+        },
+        key,
+    );
+}
+
+/// Registers a Consideration that returns 1.0 if there is any value under the Blackboard key and 0.0 otherwise.
+pub fn register_has_consideration<IS: Into<String>>(world: &mut World, blackboard_key: IS, key: IS) {
+    let blackboard_key: String = blackboard_key.into();
+    world.register_consideration(
+        move |inputs: ConsiderationInputs, query: Query<&Blackboard>| -> ConsiderationOutputs {
+            let (ai, _pawn, _context) = inputs.0;
+            let has = query.get(ai).map(|blackboard| blackboard.contains(&blackboard_key)).unwrap_or(false);
+            Some(if has { 1. } else { 0. })
+        },
+        key,
+    );
+}
+
+/// Registers a Consideration that returns 1.0 if the Blackboard key holds the Context Entity and 0.0 otherwise.
+pub fn register_is_context_consideration<IS: Into<String>>(world: &mut World, blackboard_key: IS, key: IS) {
+    let blackboard_key: String = blackboard_key.into();
+    world.register_consideration(
+        move |inputs: ConsiderationInputs, query: Query<&Blackboard>| -> ConsiderationOutputs {
+            let (ai, _pawn, context) = inputs.0;
+            let stored = query.get(ai).ok().and_then(|blackboard| blackboard.get_entity(&blackboard_key));
+            Some(if stored == Some(context) { 1. } else { 0. })
+        },
+        key,
+    );
+}
+
+/// Parses a literal from an ActionSet key into a reflectable value - a bool, a number, or a String.
+fn parse_literal(raw: &str) -> Box<dyn Reflect> {
+    let raw = raw.trim();
+    if let Ok(flag) = raw.parse::<bool>() {
+        return Box::new(flag)
+    }
+    if let Ok(number) = raw.parse::<ActionScore>() {
+        return Box::new(number)
+    }
+    Box::new(raw.to_string())
+}
+
+/// Resolves a prefixed ActionHandler key (see the module docs) to a Blackboard-writing handler.
+fn handler_for_action_key(action_key: &str) -> Option<ActionPickCallback> {
+    if let Some(rest) = action_key.strip_prefix(BLACKBOARD_SET_CONTEXT_PREFIX) {
+        return Some(set_context_handler(rest))
+    }
+    if let Some(rest) = action_key.strip_prefix(BLACKBOARD_CLEAR_PREFIX) {
+        return Some(clear_handler(rest))
+    }
+    if let Some(rest) = action_key.strip_prefix(BLACKBOARD_SET_PREFIX) {
+        let (key, raw_value) = rest.split_once('=')?;
+        let key = key.trim().to_owned();
+        let value = parse_literal(raw_value);
+
+        return Some(ActionPickCallback::new(move |inputs: ActionHandlerInputs, mut commands: Commands| {
+            let (ai, _pawn, _context, _tracker) = inputs;
+            let Ok(mut cmds) = commands.get_entity(ai) else {
+                return;
+            };
+            // Not every reflectable type can be cloned, but all our literals can.
+            if let Ok(value) = value.reflect_clone() {
+                cmds.set_blackboard_boxed(key.clone(), value);
+            }
+        }))
+    }
+    None
+}
+
+/// An exclusive System that registers the Considerations and ActionHandlers for all
+/// Blackboard-prefixed keys used in the stored ActionSets (see the module docs).
+///
+/// Keys that already have a registration are left alone, so you can always override them.
+pub fn register_actionset_blackboard_keys(world: &mut World) {
+    let mut consideration_keys: Vec<String> = Vec::new();
+    let mut action_keys: Vec<String> = Vec::new();
+
+    {
+        let Some(store) = world.get_resource::<ActionSetStore>() else {
+            return;
+        };
+        let registered_considerations = world.get_resource::<ConsiderationKeyToSystemMap>();
+        let registered_actions = world.get_resource::<ActionHandlerKeyToSystemMap>();
+
+        for actionset in store.map_by_name.values() {
+            for template in actionset.actions.iter() {
+                if !registered_actions.is_some_and(|reg| reg.mapping.contains_key(&template.action_key)) {
+                    action_keys.push(template.action_key.to_owned());
+                }
+
+                for consideration in template.considerations.iter() {
+                    let keys = core::iter::once(&consideration.consideration_name)
+                        .chain(consideration.lod_fallback.iter())
+                        .chain(consideration.surface.iter().map(|surface| &surface.consideration_name));
+
+                    for key in keys {
+                        let key: &str = key.borrow();
+                        if !registered_considerations.is_some_and(|reg| reg.mapping.contains_key(key)) {
+                            consideration_keys.push(key.to_owned());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut registered_any_consideration = false;
+    for key in consideration_keys {
+        if let Some(bb_key) = key.strip_prefix(BLACKBOARD_VALUE_PREFIX) {
+            register_value_consideration(world, bb_key.to_owned(), key.to_owned());
+        } else if let Some(bb_key) = key.strip_prefix(BLACKBOARD_HAS_PREFIX) {
+            register_has_consideration(world, bb_key.to_owned(), key.to_owned());
+        } else if let Some(bb_key) = key.strip_prefix(BLACKBOARD_IS_CONTEXT_PREFIX) {
+            register_is_context_consideration(world, bb_key.to_owned(), key.to_owned());
+        } else {
+            continue;
+        }
+
+        #[cfg(feature = "logging")]
+        bevy::log::debug!("register_actionset_blackboard_keys: Registered Consideration {:?}", &key);
+
+        registered_any_consideration = true;
+    }
+
+    for key in action_keys {
+        let Some(handler) = handler_for_action_key(&key) else {
+            continue;
+        };
+
+        #[cfg(feature = "logging")]
+        bevy::log::debug!("register_actionset_blackboard_keys: Registered ActionHandler {:?}", &key);
+
+        world.register_action_handler(handler, key);
+    }
+
+    // New Consideration Systems need to be initialized before their first run.
+    if registered_any_consideration {
+        ensure_consideration_queries(world);
+    }
+}
+
+/// Enables the data-driven Blackboard Considerations and ActionHandlers (see the module docs).
+pub struct BlackboardPlugin;
+
+impl Plugin for BlackboardPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            First,
+            register_actionset_blackboard_keys.run_if(resource_exists_and_changed::<ActionSetStore>)
+        );
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALERT: BlackboardKey<f32> = BlackboardKey::new("alert");
+
+    #[test]
+    fn test_blackboard_values_and_revisions() {
+        let mut blackboard = Blackboard::new();
+        assert!(blackboard.set(&ALERT, 0.5));
+        let after_first_write = blackboard.revision();
+
+        // Writing an equal value is not a change.
+        assert!(!blackboard.set(&ALERT, 0.5));
+        assert!(!blackboard.changed_since(ALERT.name(), after_first_write));
+
+        blackboard.set_value("alarmed", true);
+        assert!(blackboard.set(&ALERT, 0.75));
+        assert!(blackboard.changed_since(ALERT.name(), after_first_write));
+        assert_eq!(blackboard.get(&ALERT), Some(&0.75));
+        assert_eq!(blackboard.get_value::<u32>("alert"), None);
+        assert_eq!(blackboard.get_number("alarmed"), Some(1.));
+
+        assert!(blackboard.remove("alarmed").is_some());
+        assert!(!blackboard.contains("alarmed"));
+
+        assert!(matches!(handler_for_action_key("BlackboardSet::alert=0.25"), Some(_)));
+        assert!(handler_for_action_key("BlackboardSet::alert").is_none());
+        assert_eq!(parse_literal(" 2 ").downcast_ref::<ActionScore>(), Some(&2.));
+    }
+    #[test]
+    fn test_actionset_blackboard_keys() {
+        use bevy::platform::prelude::vec;
+        use crate::actions::ActionTemplate;
+        use crate::actionset::ActionSet;
+        use crate::considerations::ConsiderationData;
+        use crate::decision_loop::{handle_dispatch_to_user_actions, run_consideration_system};
+        use crate::events::AiActionDispatchToUserCode;
+
+        let mut app = App::new();
+        app.add_plugins(BlackboardPlugin)
+            .init_resource::<ActionSetStore>()
+            .add_message::<AiActionDispatchToUserCode>();
+
+        let data = |key: &str| ConsiderationData::new(key, "Linear", 0., 1.);
+        let template = |key: &str| ActionTemplate::new(
+            key, "test::CF", vec![data("Blackboard::alert"), data("BlackboardIsContext::target")], 1., key, None, None,
+        );
+        let actionset = ActionSet::new("Guard", vec![
+            template("BlackboardSet::alert=0.25"),
+            template("BlackboardSetContext::target"),
+        ]);
+        app.world_mut().resource_mut::<ActionSetStore>().map_by_name.insert("Guard".into(), actionset);
+        app.update();
+
+        let mut blackboard = Blackboard::new();
+        blackboard.set(&ALERT, 0.75);
+        let ai = app.world_mut().spawn(blackboard).id();
+        let intruder = app.world_mut().spawn_empty().id();
+
+        let score = |world: &World, key: &str, context: Entity| {
+            let map = world.resource::<ConsiderationKeyToSystemMap>();
+            run_consideration_system(map, &key.into(), (ai, None, context), world)
+        };
+        assert_eq!(score(app.world(), "Blackboard::alert", intruder), Some(0.75));
+        assert_eq!(score(app.world(), "BlackboardIsContext::target", intruder), Some(0.));
+
+        for action_key in ["BlackboardSet::alert=0.25", "BlackboardSetContext::target"] {
+            app.world_mut().write_message(AiActionDispatchToUserCode::new(ai, action_key.into(), action_key.into(), intruder, 1.));
+        }
+        app.world_mut().run_system_cached(handle_dispatch_to_user_actions).unwrap();
+
+        assert_eq!(score(app.world(), "Blackboard::alert", intruder), Some(0.25));
+        assert_eq!(score(app.world(), "BlackboardIsContext::target", intruder), Some(1.));
+    }
+}
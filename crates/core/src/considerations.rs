@@ -8,11 +8,15 @@ You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use bevy::platform::prelude::{String, ToOwned};
 
+use bevy::ecs::change_detection::Tick;
 use bevy::prelude::*;
 use bevy::platform::sync::Arc;
 
 use crate::types::{self, ActionContextRef, AiEntity, CraniumKvMap, CraniumRwLock, PawnEntityRef};
 use crate::identifiers::{ConsiderationIdentifier, CurveIdentifier};
+use crate::dynamic_bounds::DynamicBound;
+use crate::lods::AiLevelOfDetailValue;
+use crate::normalization::NormalizationMode;
 
 #[cfg(any(feature = "actionset_loader"))]
 use serde::{Serialize, Deserialize};
@@ -28,6 +32,39 @@ pub struct ConsiderationData {
 
     pub min: types::ActionScore,
     pub max: types::ActionScore,
+
+    /// If set, the min value is looked up from the World for each evaluation instead; 
+    /// the constant `min` is used as a fallback if the lookup fails. See `DynamicBound`.
+    #[cfg_attr(any(feature = "actionset_loader"), serde(default))]
+    pub min_from: Option<DynamicBound>,
+
+    /// If set, the max value is looked up from the World for each evaluation instead; 
+    /// the constant `max` is used as a fallback if the lookup fails. See `DynamicBound`.
+    #[cfg_attr(any(feature = "actionset_loader"), serde(default))]
+    pub max_from: Option<DynamicBound>,
+
+    /// How the raw Consideration value gets mapped onto the Curve input range; 
+    /// see `NormalizationMode` for the options. Linear min/max rescaling by default.
+    #[cfg_attr(any(feature = "actionset_loader"), serde(default))]
+    pub normalization: NormalizationMode,
+
+    /// If set, this Consideration is scored using a two-input Response Surface.
+    /// 
+    /// See `ResponseSurfaceInputData` and the `response_surfaces` module for details.
+    #[cfg_attr(any(feature = "actionset_loader"), serde(default))]
+    pub surface: Option<ResponseSurfaceInputData>,
+
+    // AI LODs - unlike for ActionTemplates, a missing min/max means 'no limit'.
+    // Outside of the range, the Consideration is either swapped for the fallback or treated as neutral.
+    #[cfg_attr(any(feature = "actionset_loader"), serde(default))]
+    pub lod_min: Option<types::AiLodLevelPrimitive>,
+    #[cfg_attr(any(feature = "actionset_loader"), serde(default))]
+    pub lod_max: Option<types::AiLodLevelPrimitive>,
+
+    /// A (presumably cheaper) Consideration to run instead of the main one outside of the LOD range.
+    /// Uses the same Curve, min/max and normalization. If None, the Consideration is skipped instead.
+    #[cfg_attr(any(feature = "actionset_loader"), serde(default))]
+    pub lod_fallback: Option<ConsiderationIdentifier>,
 }
 
 impl ConsiderationData {
@@ -42,8 +79,120 @@ impl ConsiderationData {
             curve_name: curve_name.into(),
             min: min, 
             max: max, 
+            min_from: None,
+            max_from: None,
+            normalization: NormalizationMode::default(),
+            surface: None,
+            lod_min: None,
+            lod_max: None,
+            lod_fallback: None,
+        }
+    }
+
+    /// Looks the min value up from the World for each evaluation (see `DynamicBound`); 
+    /// the constant min becomes a fallback.
+    pub fn with_dynamic_min(mut self, bound: DynamicBound) -> Self {
+        self.min_from = Some(bound);
+        self
+    }
+
+    /// Looks the max value up from the World for each evaluation (see `DynamicBound`); 
+    /// the constant max becomes a fallback.
+    pub fn with_dynamic_max(mut self, bound: DynamicBound) -> Self {
+        self.max_from = Some(bound);
+        self
+    }
+
+    /// Sets the `NormalizationMode` used to map raw values of this Consideration onto the Curve input range.
+    pub fn with_normalization(mut self, normalization: NormalizationMode) -> Self {
+        self.normalization = normalization;
+        self
+    }
+
+    /// Turns this into a two-input Consideration, scored using the Response Surface 
+    /// registered under the key in the provided `ResponseSurfaceInputData`.
+    pub fn with_surface(mut self, surface: ResponseSurfaceInputData) -> Self {
+        self.surface = Some(surface);
+        self
+    }
+
+    /// Restricts this Consideration to a range of LODs. Outside of it, the Consideration is 
+    /// treated as neutral (i.e. skipped), unless a fallback is set with `with_lod_fallback()`.
+    pub fn with_lod_range(mut self, lod_min: Option<types::AiLodLevelPrimitive>, lod_max: Option<types::AiLodLevelPrimitive>) -> Self {
+        self.lod_min = lod_min;
+        self.lod_max = lod_max;
+        self
+    }
+
+    /// Runs the specified Consideration instead of the main one outside of the LOD range.
+    pub fn with_lod_fallback<CNN: Into<ConsiderationIdentifier>>(mut self, fallback: CNN) -> Self {
+        self.lod_fallback = Some(fallback.into());
+        self
+    }
+
+    /// Checks if the main Consideration should be run at a given LOD.
+    pub fn is_within_lod_range(&self, lod: &Option<AiLevelOfDetailValue>) -> bool {
+        let qry_lod = lod.unwrap_or_default().to_primitive();
+        let min = self.lod_min.unwrap_or(types::AiLodLevelPrimitive::MIN);
+        let max = self.lod_max.unwrap_or(types::AiLodLevelPrimitive::MAX);
+        qry_lod >= min && qry_lod <= max
+    }
+
+    /// The key of the Consideration to actually run at a given LOD; 
+    /// None if it should be skipped altogether.
+    pub fn key_for_lod(&self, lod: &Option<AiLevelOfDetailValue>) -> Option<&ConsiderationIdentifier> {
+        match self.is_within_lod_range(lod) {
+            true => Some(&self.consideration_name),
+            false => self.lod_fallback.as_ref(),
+        }
+    }
+}
+
+/// The second input and the Response Surface used by a two-input Consideration.
+/// 
+/// The parent Consideration's own (normalized) value is the x input to the Surface, 
+/// the Consideration in here provides the y input (normalized using its own min/max and mode), 
+/// and the parent Consideration's Curve is applied to the Surface's output.
+#[derive(Clone, Debug, Reflect)]
+#[cfg_attr(feature = "actionset_loader", derive(Serialize, Deserialize))]
+pub struct ResponseSurfaceInputData {
+    /// The key of the Consideration providing the y input.
+    #[cfg_attr(any(feature = "actionset_loader"), serde(rename="consideration"))]
+    pub consideration_name: ConsiderationIdentifier,
+
+    /// The key of the Response Surface to sample.
+    #[cfg_attr(any(feature = "actionset_loader"), serde(rename="surface"))]
+    pub surface_name: CurveIdentifier,
+
+    pub min: types::ActionScore,
+    pub max: types::ActionScore,
+
+    /// How the raw y input gets mapped onto the Surface input range; see `NormalizationMode`.
+    #[cfg_attr(any(feature = "actionset_loader"), serde(default))]
+    pub normalization: NormalizationMode,
+}
+
+impl ResponseSurfaceInputData {
+    pub fn new<CNN: Into<ConsiderationIdentifier>, SN: Into<CurveIdentifier>>(
+        consideration_name: CNN,
+        surface_name: SN,
+        min: types::ActionScore,
+        max: types::ActionScore,
+    ) -> Self {
+        Self {
+            consideration_name: consideration_name.into(),
+            surface_name: surface_name.into(),
+            min,
+            max,
+            normalization: NormalizationMode::default(),
         }
     }
+
+    /// Sets the `NormalizationMode` used for the y input.
+    pub fn with_normalization(mut self, normalization: NormalizationMode) -> Self {
+        self.normalization = normalization;
+        self
+    }
 }
 
 /// Convenience type-alias for generic inputs piped into each Consideration. 
@@ -136,7 +285,10 @@ pub struct ConsiderationKeyToSystemMap {
     pub mapping: CraniumKvMap<
         ConsiderationIdentifier, 
         Arc<CraniumRwLock<dyn ConsiderationSystem>>
-    >
+    >,
+
+    /// The last change of the mapping that the Systems were (re)initialized for, if any.
+    initialized_for: Option<Tick>,
 }
 
 
@@ -257,7 +409,8 @@ pub fn reinit_consideration_queries(world: &mut World) {
         Some(r) => r,
     };
 
-    registry.mapping.iter_mut().for_each(|(_key, system_lock)| {
+    // Only reading the mapping here, so that reinitializing does not count as a change to it.
+    registry.mapping.iter().for_each(|(_key, system_lock)| {
         let write_state = system_lock.write();
         {
             match write_state {
@@ -273,6 +426,30 @@ pub fn reinit_consideration_queries(world: &mut World) {
             }
         }
     });
+
+    let initialized_for = registry.last_changed();
+    registry.bypass_change_detection().initialized_for = Some(initialized_for);
+}
+
+/// Makes sure the Consideration Systems are ready to run from Exclusive Systems outside of the decision loop.
+///
+/// Unlike `reinit_consideration_queries`, this ignores `ShouldReinitConsiderationQueries`; it only reinitializes if the
+/// Considerations were (re)registered since the last time they were initialized, as the Queries
+/// of already initialized Systems keep themselves up to date with the World.
+pub fn ensure_consideration_queries(world: &mut World) {
+    let is_up_to_date = world
+        .get_resource_ref::<ConsiderationKeyToSystemMap>()
+        .is_none_or(|registry| registry.initialized_for == Some(registry.last_changed()));
+
+    if is_up_to_date {
+        return;
+    }
+
+    let previous_reinit = world.get_resource_mut::<ShouldReinitConsiderationQueries>().map(|mut res| core::mem::replace(&mut res.0, true));
+    reinit_consideration_queries(world);
+    if let Some(previous) = previous_reinit {
+        world.resource_mut::<ShouldReinitConsiderationQueries>().set(previous);
+    }
 }
 
 pub struct ConsiderationPlugin;
@@ -289,3 +466,55 @@ impl Plugin for ConsiderationPlugin {
         ;
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lods::{LOD_ELEVATED, LOD_INACTIVE, LOD_NORMAL};
+
+    #[test]
+    fn test_consideration_lod_range() {
+        let at = |lod| Some(AiLevelOfDetailValue::new(lod));
+        let unbounded = ConsiderationData::new("test::Main", "Linear", 0., 1.);
+        assert!(unbounded.is_within_lod_range(&at(LOD_ELEVATED)));
+        assert!(unbounded.is_within_lod_range(&at(LOD_INACTIVE)));
+
+        let skipped = unbounded.clone().with_lod_range(None, Some(LOD_NORMAL));
+        assert_eq!(skipped.key_for_lod(&None), Some(&"test::Main".into()));
+        assert_eq!(skipped.key_for_lod(&at(LOD_NORMAL + 1)), None);
+
+        let swapped = unbounded.with_lod_range(Some(LOD_NORMAL), None).with_lod_fallback("test::Cheap");
+        assert_eq!(swapped.key_for_lod(&at(LOD_ELEVATED)), Some(&"test::Cheap".into()));
+        assert_eq!(swapped.key_for_lod(&at(LOD_INACTIVE)), Some(&"test::Main".into()));
+    }
+
+    #[test]
+    fn test_ensure_consideration_queries() {
+        fn one(_: ConsiderationInputs) -> ConsiderationOutputs {
+            Some(1.)
+        }
+
+        let initialized_for = |world: &World| world.resource::<ConsiderationKeyToSystemMap>().initialized_for;
+
+        let mut world = World::new();
+        world.insert_resource(ShouldReinitConsiderationQueries(false));
+        world.register_consideration(one, "test::One");
+        assert_eq!(initialized_for(&world), None);
+
+        ensure_consideration_queries(&mut world);
+        let first = initialized_for(&world);
+        assert!(first.is_some());
+        assert!(!world.resource::<ShouldReinitConsiderationQueries>().get());
+
+        // Nothing new registered, nothing to do - and reinitializing did not count as a change.
+        world.increment_change_tick();
+        ensure_consideration_queries(&mut world);
+        assert_eq!(initialized_for(&world), first);
+
+        world.register_consideration(one, "test::Two");
+        ensure_consideration_queries(&mut world);
+        assert_ne!(initialized_for(&world), first);
+        assert!(!world.resource::<ShouldReinitConsiderationQueries>().get());
+    }
+}
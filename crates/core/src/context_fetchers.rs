@@ -6,6 +6,7 @@ You can obtain one at https://mozilla.org/MPL/2.0/.
 
 //! ContextFetchers - registerable Systems that find inputs (Contexts) for ActionTemplates.
 
+use bevy::ecs::change_detection::Tick;
 use bevy::prelude::*;
 use bevy::platform::prelude::{String, ToOwned};
 use bevy::platform::sync::Arc;
@@ -90,7 +91,10 @@ pub struct ContextFetcherKeyToSystemMap {
     pub mapping: CraniumKvMap<
         types::ContextFetcherKey, 
         Arc<CraniumRwLock<dyn ContextFetcherSystem>>
-    >
+    >,
+
+    /// The last change of the mapping that the Systems were (re)initialized for, if any.
+    initialized_for: Option<Tick>,
 }
 
 
@@ -211,7 +215,8 @@ pub fn reinit_cf_queries(world: &mut World) {
         Some(r) => r,
     };
 
-    registry.mapping.iter_mut().for_each(|(_key, system_lock)| {
+    // Only reading the mapping here, so that reinitializing does not count as a change to it.
+    registry.mapping.iter().for_each(|(_key, system_lock)| {
         let write_state = system_lock.write();
         {
             match write_state {
@@ -227,6 +232,30 @@ pub fn reinit_cf_queries(world: &mut World) {
             }
         }
     });
+
+    let initialized_for = registry.last_changed();
+    registry.bypass_change_detection().initialized_for = Some(initialized_for);
+}
+
+/// Makes sure the ContextFetcher Systems are ready to run from Exclusive Systems outside of the decision loop.
+///
+/// Unlike `reinit_cf_queries`, this ignores `ShouldReinitCfQueries`; it only reinitializes if the
+/// ContextFetchers were (re)registered since the last time they were initialized, as the Queries
+/// of already initialized Systems keep themselves up to date with the World.
+pub fn ensure_cf_queries(world: &mut World) {
+    let is_up_to_date = world
+        .get_resource_ref::<ContextFetcherKeyToSystemMap>()
+        .is_none_or(|registry| registry.initialized_for == Some(registry.last_changed()));
+
+    if is_up_to_date {
+        return;
+    }
+
+    let previous_reinit = world.get_resource_mut::<ShouldReinitCfQueries>().map(|mut res| core::mem::replace(&mut res.0, true));
+    reinit_cf_queries(world);
+    if let Some(previous) = previous_reinit {
+        world.resource_mut::<ShouldReinitCfQueries>().set(previous);
+    }
 }
 
 pub struct ContextFetcherPlugin;
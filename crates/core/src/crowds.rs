@@ -0,0 +1,443 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+If a copy of the MPL was not distributed with this file,
+You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+//! Crowds - one AI driving many Pawns.
+//!
+//! Running a full AI per member of a big crowd (a herd, a swarm, a militia) is wasteful when they
+//! should mostly act together anyway; instead, a whole crowd can share one collective brain.
+//!
+//! Pawns join an AI's crowd with the `CrowdPawnOf` relationship. The AI decides once for the whole
+//! crowd, as usual - its own `Pawn` (if any) can be a leader or an anchor Entity for the crowd, and
+//! Considerations can look at the crowd's Pawns through the aggregate Considerations below.
+//!
+//! What happens with the picked Action depends on the AI's `CrowdDispatch`:
+//! - `Collective` (the default) - the ActionHandler runs once, as for any other AI, and reads the
+//!   Pawns from the AI's `CrowdPawns` itself.
+//! - `PerPawn` - the ActionHandler runs once for each Pawn, with the Pawn and a Context assigned to
+//!   it as the inputs (see `CrowdContextAssignment`). The Contexts are assigned once per pick, stored
+//!   in the AI's `CrowdAssignment` and reported in a `CrowdActionDispatched` event; every dispatch of
+//!   the Action (e.g. every tick) then runs the ActionHandler for each assigned Pawn that is still in
+//!   the crowd. The Action still has a single ActionTracker for the AI.
+//!
+//! The `CrowdPlugin` registers aggregate Considerations for prefixed keys whenever they show up in a
+//! stored ActionSet. Each of them runs another (registered) Consideration once for every Pawn in the
+//! crowd, with that Pawn as the input, and aggregates the raw values; Pawns for which the inner
+//! Consideration returns None are left out, and if none are left, the Context is discarded.
+//!
+//! - Consideration `CrowdAverage::<key>` - the average value of the Consideration `<key>`.
+//! - Consideration `CrowdMin::<key>` - the lowest value of the Consideration `<key>`.
+//! - Consideration `CrowdMax::<key>` - the highest value of the Consideration `<key>`.
+
+use core::borrow::Borrow;
+
+use bevy::platform::prelude::{String, ToOwned, Vec};
+use bevy::prelude::*;
+
+use crate::actions::ActionTemplate;
+use crate::considerations::{
+    AcceptsConsiderationRegistrations, ConsiderationInputs, ConsiderationKeyToSystemMap, ConsiderationOutputs,
+    ensure_consideration_queries,
+};
+use crate::context_fetchers::{ContextFetcherKeyToSystemMap, ensure_cf_queries};
+use crate::decision_loop::{run_consideration_system, score_considerations};
+use crate::events::AiActionPicked;
+use crate::identifiers::ConsiderationIdentifier;
+use crate::smart_object::{ActionSetStore, SmartObjects};
+use crate::types::{ActionContextRef, ActionKey, ActionScore, AiEntity, CraniumList, PawnEntity};
+
+/// Prefix for Consideration keys averaging another Consideration over the crowd's Pawns.
+pub const CROWD_AVERAGE_PREFIX: &str = "CrowdAverage::";
+/// Prefix for Consideration keys taking the lowest value of another Consideration over the crowd's Pawns.
+pub const CROWD_MIN_PREFIX: &str = "CrowdMin::";
+/// Prefix for Consideration keys taking the highest value of another Consideration over the crowd's Pawns.
+pub const CROWD_MAX_PREFIX: &str = "CrowdMax::";
+
+/// Makes a Pawn part of the crowd driven by an AI.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+#[relationship(relationship_target = CrowdPawns)]
+pub struct CrowdPawnOf(pub Entity);
+
+/// All Pawns in the crowd driven by an AI; maintained automatically based on `CrowdPawnOf`.
+#[derive(Component, Debug, Default)]
+#[relationship_target(relationship = CrowdPawnOf)]
+pub struct CrowdPawns(Vec<PawnEntity>);
+
+/// How a crowd AI picks the Context for each of its Pawns when dispatching `PerPawn`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Reflect)]
+pub enum CrowdContextAssignment {
+    /// Every Pawn gets the Context the AI picked.
+    #[default]
+    Shared,
+    /// Every Pawn gets the Context that scores best for it, using the picked ActionTemplate's
+    /// ContextFetcher and Considerations with the Pawn as the input.
+    BestPerPawn,
+    /// Like `BestPerPawn`, but Pawns prefer Contexts no other Pawn got yet, to spread the crowd out.
+    Distinct,
+}
+
+/// How a crowd AI dispatches the Actions it picks (see the module docs).
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq, Reflect)]
+pub enum CrowdDispatch {
+    /// The ActionHandler runs once for the whole crowd.
+    #[default]
+    Collective,
+    /// The ActionHandler runs once per Pawn in the crowd.
+    PerPawn(CrowdContextAssignment),
+}
+
+impl CrowdDispatch {
+    pub fn is_per_pawn(&self) -> bool {
+        matches!(self, Self::PerPawn(_))
+    }
+}
+
+/// The Contexts assigned to the Pawns of a `PerPawn` crowd AI for the Action it picked last.
+#[derive(Component, Clone, Debug)]
+pub struct CrowdAssignment {
+    pub action_key: ActionKey,
+    /// The Context the AI itself picked.
+    pub action_context: ActionContextRef,
+    pub assignments: CraniumList<(PawnEntity, ActionContextRef)>,
+}
+
+impl CrowdAssignment {
+    /// True if the assignment was made for the Action with the key and Context the AI picked.
+    pub fn is_for(&self, action_key: &str, action_context: ActionContextRef) -> bool {
+        self.action_key == action_key && self.action_context == action_context
+    }
+}
+
+/// Reports which Context each Pawn of a crowd AI got for an Action it dispatches `PerPawn`.
+#[derive(EntityEvent, Debug, Clone)]
+pub struct CrowdActionDispatched {
+    pub entity: AiEntity,
+    pub action_key: ActionKey,
+    pub assignments: CraniumList<(PawnEntity, ActionContextRef)>,
+}
+
+/// How an aggregate Consideration combines the values of its Pawns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Reflect)]
+pub enum CrowdAggregate {
+    Average,
+    Min,
+    Max,
+}
+
+impl CrowdAggregate {
+    pub fn aggregate<I: IntoIterator<Item = ActionScore>>(&self, values: I) -> Option<ActionScore> {
+        let mut count: usize = 0;
+        let mut acc: Option<ActionScore> = None;
+
+        for value in values {
+            count += 1;
+            acc = Some(match (acc, self) {
+                (None, _) => value,
+                (Some(acc), Self::Average) => acc + value,
+                (Some(acc), Self::Min) => acc.min(value),
+                (Some(acc), Self::Max) => acc.max(value),
+            });
+        }
+
+        match self {
+            Self::Average => acc.map(|sum| sum / count as ActionScore),
+            _ => acc,
+        }
+    }
+}
+
+/// Registers a Consideration aggregating the raw values of the inner Consideration over the crowd's Pawns.
+pub fn register_crowd_aggregate_consideration<IS: Into<String>>(
+    world: &mut World,
+    inner_key: IS,
+    aggregate: CrowdAggregate,
+    key: IS,
+) {
+    let inner_key = ConsiderationIdentifier::from(inner_key);
+    world.register_consideration(
+        move |inputs: ConsiderationInputs, world: &World| -> ConsiderationOutputs {
+            let (ai, _pawn, context) = inputs.0;
+            let pawns = world.get::<CrowdPawns>(ai)?;
+            let consideration_map = world.get_resource::<ConsiderationKeyToSystemMap>()?;
+
+            let inner: &str = inner_key.borrow();
+            if !consideration_map.mapping.contains_key(inner) {
+                #[cfg(feature = "logging")]
+                bevy::log::warn!("Crowd aggregate Consideration: Unknown Consideration {:?}, discarding.", inner);
+                return None;
+            }
+
+            aggregate.aggregate(pawns.iter().filter_map(|pawn| {
+                run_consideration_system(consideration_map, &inner_key, (ai, Some(pawn), context), world)
+            }))
+        },
+        key,
+    );
+}
+
+/// Finds the ActionTemplate behind a picked Action among the AI's ActionSets.
+fn find_picked_template<'a>(
+    world: &'a World,
+    ai: AiEntity,
+    action_key: &str,
+    action_name: &str,
+) -> Option<&'a ActionTemplate> {
+    let store = world.get_resource::<ActionSetStore>()?;
+    let smart_objects = world.get::<SmartObjects>(ai)?;
+    store.find_template(smart_objects, action_key, action_name)
+}
+
+/// Picks a Context for each Pawn of the AI's crowd (see `CrowdContextAssignment`).
+fn assign_contexts(
+    world: &World,
+    ai: AiEntity,
+    (action_key, action_name): (&str, &str),
+    picked: ActionContextRef,
+    assignment: CrowdContextAssignment,
+) -> CraniumList<(PawnEntity, ActionContextRef)> {
+    let pawns: CraniumList<PawnEntity> = world
+        .get::<CrowdPawns>(ai)
+        .map(|pawns| pawns.iter().collect())
+        .unwrap_or_default();
+
+    let scoring = match assignment {
+        CrowdContextAssignment::Shared => None,
+        _ => find_picked_template(world, ai, action_key, action_name).zip(
+            world.get_resource::<ContextFetcherKeyToSystemMap>()
+            .zip(world.get_resource::<ConsiderationKeyToSystemMap>())
+        ),
+    };
+    let Some((template, (cf_map, consideration_map))) = scoring else {
+        return pawns.into_iter().map(|pawn| (pawn, picked)).collect();
+    };
+
+    let mut assignments: CraniumList<(PawnEntity, ActionContextRef)> = CraniumList::new();
+    for pawn in pawns {
+        let contexts = cf_map.mapping
+            .get(&template.context_fetcher_name.0)
+            .and_then(|system| system.write().ok()?.run_readonly((ai, Some(pawn)), world).ok())
+            .unwrap_or_default();
+
+        let mut candidates: CraniumList<(ActionScore, ActionContextRef)> = contexts.into_iter()
+            .map(|ctx| (score_considerations(&template.considerations, consideration_map, (ai, Some(pawn), ctx), world), ctx))
+            .filter(|(score, _)| *score > 0.)
+            .collect();
+        // Stable, so ties keep the ContextFetcher's order.
+        candidates.sort_by(|(left, _), (right, _)| right.total_cmp(left));
+
+        let untaken = candidates.iter()
+            .find(|(_, ctx)| !assignments.iter().any(|(_, taken)| taken == ctx))
+            .filter(|_| assignment == CrowdContextAssignment::Distinct);
+        let context = untaken.or(candidates.first()).map(|(_, ctx)| *ctx).unwrap_or(picked);
+
+        assignments.push((pawn, context));
+    }
+    assignments
+}
+
+/// An Observer assigning Contexts to the Pawns of `PerPawn` crowd AIs for the Actions they pick.
+///
+/// The assignment is stored in the AI's `CrowdAssignment` and reported with `CrowdActionDispatched`.
+pub fn assign_crowd_contexts(
+    event: On<AiActionPicked>,
+    query: Query<&CrowdDispatch>,
+    mut commands: Commands,
+) {
+    let ai = event.entity;
+    let Ok(CrowdDispatch::PerPawn(assignment)) = query.get(ai) else {
+        return;
+    };
+    let assignment = *assignment;
+    let action_key = event.action_key.to_owned();
+    let action_name = event.action_name.to_owned();
+    let picked = event.action_context;
+
+    commands.queue(move |world: &mut World| {
+        // Per-Pawn scoring runs the ContextFetchers and Considerations, which must be initialized first.
+        if assignment != CrowdContextAssignment::Shared {
+            ensure_cf_queries(world);
+            ensure_consideration_queries(world);
+        }
+
+        let assignments = assign_contexts(world, ai, (&action_key, &action_name), picked, assignment);
+        let Ok(mut ai_entity) = world.get_entity_mut(ai) else {
+            return;
+        };
+
+        #[cfg(feature = "logging")]
+        bevy::log::debug!("assign_crowd_contexts: AI {:?} - {:?} assigned as {:?}", ai, &action_name, &assignments);
+
+        ai_entity.insert(CrowdAssignment {
+            action_key: action_key.to_owned(),
+            action_context: picked,
+            assignments: assignments.to_owned(),
+        });
+        world.trigger(CrowdActionDispatched { entity: ai, action_key, assignments });
+    });
+}
+
+/// An exclusive System that registers the aggregate Considerations for all Crowd-prefixed keys used
+/// in the stored ActionSets (see the module docs).
+///
+/// Keys that already have a registration are left alone, so you can always override them.
+pub fn register_actionset_crowd_keys(world: &mut World) {
+    let mut consideration_keys: CraniumList<String> = CraniumList::new();
+
+    {
+        let Some(store) = world.get_resource::<ActionSetStore>() else {
+            return;
+        };
+        let registered = world.get_resource::<ConsiderationKeyToSystemMap>();
+
+        for actionset in store.map_by_name.values() {
+            for template in actionset.actions.iter() {
+                for consideration in template.considerations.iter() {
+                    let key: &str = consideration.consideration_name.borrow();
+                    let is_crowd_key = [CROWD_AVERAGE_PREFIX, CROWD_MIN_PREFIX, CROWD_MAX_PREFIX]
+                        .iter()
+                        .any(|prefix| key.starts_with(prefix));
+
+                    if is_crowd_key && !registered.is_some_and(|reg| reg.mapping.contains_key(key)) {
+                        consideration_keys.push(key.to_owned());
+                    }
+                }
+            }
+        }
+    }
+
+    if consideration_keys.is_empty() {
+        return;
+    }
+
+    for key in consideration_keys {
+        let (inner, aggregate) = if let Some(inner) = key.strip_prefix(CROWD_AVERAGE_PREFIX) {
+            (inner, CrowdAggregate::Average)
+        } else if let Some(inner) = key.strip_prefix(CROWD_MIN_PREFIX) {
+            (inner, CrowdAggregate::Min)
+        } else if let Some(inner) = key.strip_prefix(CROWD_MAX_PREFIX) {
+            (inner, CrowdAggregate::Max)
+        } else {
+            continue;
+        };
+
+        #[cfg(feature = "logging")]
+        bevy::log::debug!("register_actionset_crowd_keys: Registered Consideration {:?}", &key);
+
+        register_crowd_aggregate_consideration(world, inner.to_owned(), aggregate, key.to_owned());
+    }
+
+    // New Consideration Systems need to be initialized before their first run.
+    ensure_consideration_queries(world);
+}
+
+/// Sets up crowd AIs driving many Pawns (see the module docs).
+pub struct CrowdPlugin;
+
+impl Plugin for CrowdPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_observer(assign_crowd_contexts)
+            .add_systems(
+                First,
+                register_actionset_crowd_keys.run_if(resource_exists_and_changed::<ActionSetStore>)
+            )
+        ;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bevy::platform::prelude::vec;
+    use super::*;
+    use crate::actions::{AcceptsActionHandlerRegistrations, ActionHandlerInputs};
+    use crate::decision_loop::handle_dispatch_to_user_actions;
+    use crate::events::AiActionDispatchToUserCode;
+
+    #[derive(Component)]
+    struct Health(f32);
+
+    #[derive(Component)]
+    struct Dispatched(ActionHandlerInputs);
+
+    fn dispatched(world: &mut World) -> CraniumList<ActionHandlerInputs> {
+        let mut dispatched: CraniumList<ActionHandlerInputs> = world
+            .query::<&Dispatched>()
+            .iter(world)
+            .map(|dispatched| dispatched.0)
+            .collect();
+        dispatched.sort();
+        dispatched
+    }
+
+    #[test]
+    fn test_crowd_aggregates_and_per_pawn_dispatch() {
+        let mut app = App::new();
+        app.add_plugins(CrowdPlugin).add_message::<AiActionDispatchToUserCode>();
+        app.register_consideration(
+            |inputs: ConsiderationInputs, query: Query<&Health>| -> ConsiderationOutputs {
+                let (_ai, pawn, _context) = inputs.0;
+                query.get(pawn?).ok().map(|health| health.0)
+            },
+            "Health",
+        );
+        app.register_action_handler(
+            |inputs: ActionHandlerInputs, mut commands: Commands| { commands.spawn(Dispatched(inputs)); },
+            "Graze",
+        );
+
+        let world = app.world_mut();
+        register_crowd_aggregate_consideration(world, "Health", CrowdAggregate::Average, "CrowdAverage::Health");
+        register_crowd_aggregate_consideration(world, "Health", CrowdAggregate::Min, "CrowdMin::Health");
+        ensure_consideration_queries(world);
+
+        let ai = world.spawn(CrowdDispatch::PerPawn(CrowdContextAssignment::Shared)).id();
+        let field = world.spawn_empty().id();
+        let sheep = vec![
+            world.spawn((CrowdPawnOf(ai), Health(0.25))).id(),
+            world.spawn((CrowdPawnOf(ai), Health(0.75))).id(),
+            world.spawn(CrowdPawnOf(ai)).id(),
+        ];
+
+        let consideration_map = world.resource::<ConsiderationKeyToSystemMap>();
+        let average = run_consideration_system(consideration_map, &"CrowdAverage::Health".into(), (ai, None, field), world);
+        let min = run_consideration_system(consideration_map, &"CrowdMin::Health".into(), (ai, None, field), world);
+        assert_eq!(average, Some(0.5));
+        assert_eq!(min, Some(0.25));
+
+        world.trigger(AiActionPicked::new(ai, "Graze".into(), "Graze".into(), field, 1.));
+        world.flush();
+        assert_eq!(world.get::<CrowdAssignment>(ai).unwrap().assignments.len(), 3);
+
+        // Every dispatch of the picked Action (e.g. every tick) runs the ActionHandler for each Pawn.
+        let tracker = world.spawn_empty().id();
+        let dispatch = |world: &mut World, context: Entity| {
+            world.write_message(AiActionDispatchToUserCode::new(ai, "Graze".into(), "Graze".into(), context, 1.).with_action_tracker(tracker));
+            world.run_system_cached(handle_dispatch_to_user_actions).unwrap();
+        };
+        dispatch(world, field);
+        dispatch(world, field);
+
+        let mut expected: CraniumList<ActionHandlerInputs> = sheep.iter().map(|pawn| (ai, Some(*pawn), field, Some(tracker))).collect();
+        expected.extend(expected.to_owned());
+        expected.sort();
+        assert_eq!(dispatched(world), expected);
+
+        // A new pick gets a new assignment; Pawns that left the crowd since are skipped.
+        let meadow = world.spawn_empty().id();
+        world.trigger(AiActionPicked::new(ai, "Graze".into(), "Graze".into(), meadow, 1.));
+        world.flush();
+        world.entity_mut(sheep[2]).remove::<CrowdPawnOf>();
+        dispatch(world, meadow);
+
+        let moved: CraniumList<ActionHandlerInputs> = dispatched(world)
+            .into_iter()
+            .filter(|inputs| inputs.2 == meadow)
+            .collect();
+        let mut expected: CraniumList<ActionHandlerInputs> = sheep[..2].iter().map(|pawn| (ai, Some(*pawn), meadow, Some(tracker))).collect();
+        expected.sort();
+        assert_eq!(moved, expected);
+    }
+}
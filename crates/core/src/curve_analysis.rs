@@ -0,0 +1,374 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+If a copy of the MPL was not distributed with this file,
+You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+//! Utility Curve analysis - sampling-based checks for what a Curve actually does.
+//!
+//! Built-in Curves are well-behaved by construction, but custom Curves are just code and
+//! nothing stops a Curve from leaving the unit interval or doing something other than
+//! what its name suggests. This module lets you check that before it bites you.
+//!
+//! All analysis here is done by sampling the Curve at evenly spaced points, so it is
+//! approximate - a Curve that misbehaves between two samples will not be caught.
+//! `DEFAULT_CURVE_ANALYSIS_RESOLUTION` is fine for anything reasonably smooth.
+//!
+//! The most important items in this module are:
+//! 1) The `UtilityCurveAnalysisExt` trait, which adds the analysis methods to all Utility Curves.
+//! 2) `closest_builtin_curve()`, which finds the built-in Curve that behaves most like a given Curve.
+//! 3) `closest_builtin_resolver()`, which builds a `NoCurveMatchStrategy` fallback resolver using the above.
+
+use bevy::platform::prelude::{String, ToOwned};
+use crate::curves::{resolve_curve_from_name, SupportedUtilityCurve, UtilityCurve, BUILTIN_CURVE_KEYS, CURVE_LINEAR};
+use crate::errors::CurveResolverFn;
+use crate::types::{ActionScore, CraniumKvMap, MIN_CONSIDERATION_SCORE, MAX_CONSIDERATION_SCORE};
+
+/// A sensible default number of samples for Curve analysis.
+pub const DEFAULT_CURVE_ANALYSIS_RESOLUTION: usize = 256;
+
+/// The smallest change between two samples that counts as the Curve going up or down.
+///
+/// Anything smaller is treated as flat, so float noise does not turn a plateau into a hundred peaks.
+pub const CURVE_SHAPE_TOLERANCE: ActionScore = 1e-6;
+
+/// Evenly spaced sample points on the unit interval, both ends included.
+fn sample_points(resolution: usize) -> impl Iterator<Item = ActionScore> {
+    let resolution = resolution.max(2);
+    let last_idx = (resolution - 1) as ActionScore;
+    (0..resolution).map(move |idx| idx as ActionScore / last_idx)
+}
+
+/// The result of checking whether a Curve's raw output stays within the unit interval.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CurveRangeReport {
+    /// The lowest (non-NaN) raw value sampled.
+    pub min: ActionScore,
+    /// The highest (non-NaN) raw value sampled.
+    pub max: ActionScore,
+    /// How many samples fell outside of the unit interval.
+    pub out_of_range: usize,
+    /// How many samples were NaN.
+    pub nan: usize,
+    /// How many samples were taken in total.
+    pub samples: usize,
+}
+
+impl CurveRangeReport {
+    /// True if every sample was a number on the unit interval.
+    pub fn is_valid(&self) -> bool {
+        self.out_of_range == 0 && self.nan == 0
+    }
+}
+
+/// The broad shape of a Curve, i.e. its order relation class.
+///
+/// This is the first thing to match on when picking a replacement for a Curve;
+/// a Decreasing Curve standing in for an Increasing one will make the AI do the opposite of what you want.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CurveShape {
+    /// The output never changes (within `CURVE_SHAPE_TOLERANCE`).
+    Constant,
+    /// The output never goes down, and goes up at least once.
+    Increasing,
+    /// The output never goes up, and goes down at least once.
+    Decreasing,
+    /// The output goes both up and down.
+    ///
+    /// The peak count includes the edges of the domain, so e.g. a Triangle has one peak
+    /// and an AntiTriangle (high at both ends, low in the middle) has two.
+    Peaking { peaks: usize },
+}
+
+/// Analysis utilities for Utility Curves; implemented for all Curves, including trait objects.
+pub trait UtilityCurveAnalysisExt: UtilityCurve {
+    /// Samples the raw (unclamped) output of the Curve and reports whether it stays on the unit interval.
+    fn check_range(&self, resolution: usize) -> CurveRangeReport {
+        let mut report = CurveRangeReport {
+            min: ActionScore::INFINITY,
+            max: ActionScore::NEG_INFINITY,
+            out_of_range: 0,
+            nan: 0,
+            samples: 0,
+        };
+
+        for t in sample_points(resolution) {
+            let val = self.sample_unchecked(t);
+            report.samples += 1;
+
+            if val.is_nan() {
+                report.nan += 1;
+                continue;
+            }
+
+            report.min = report.min.min(val);
+            report.max = report.max.max(val);
+
+            if !(MIN_CONSIDERATION_SCORE..=MAX_CONSIDERATION_SCORE).contains(&val) {
+                report.out_of_range += 1;
+            }
+        }
+
+        report
+    }
+
+    /// Classifies the shape of the Curve (as sampled with `sample_safe()`); see `CurveShape`.
+    fn classify_shape(&self, resolution: usize) -> CurveShape {
+        let mut rises = false;
+        let mut falls = false;
+        let mut peaks: usize = 0;
+
+        // The direction of the last non-flat step; None until the Curve first moves.
+        let mut last_direction: Option<bool> = None;
+        let mut prev = self.sample_safe(MIN_CONSIDERATION_SCORE);
+
+        for t in sample_points(resolution).skip(1) {
+            let curr = self.sample_safe(t);
+            let delta = curr - prev;
+            prev = curr;
+
+            if delta.abs() <= CURVE_SHAPE_TOLERANCE {
+                continue;
+            }
+
+            let rising = delta > 0.;
+            rises |= rising;
+            falls |= !rising;
+
+            match (last_direction, rising) {
+                // Starts by going down, so the left edge is a peak.
+                (None, false) => peaks += 1,
+                // Went up, now goes down - a peak in between.
+                (Some(true), false) => peaks += 1,
+                _ => {},
+            }
+            last_direction = Some(rising);
+        }
+
+        // Ends by going up, so the right edge is a peak.
+        if last_direction == Some(true) {
+            peaks += 1;
+        }
+
+        match (rises, falls) {
+            (false, false) => CurveShape::Constant,
+            (true, false) => CurveShape::Increasing,
+            (false, true) => CurveShape::Decreasing,
+            (true, true) => CurveShape::Peaking { peaks },
+        }
+    }
+
+    /// How similar this Curve is to another one, from 0.0 (opposites) to 1.0 (identical).
+    ///
+    /// This is one minus the mean absolute difference between the two Curves' (safe) outputs,
+    /// so it reads as 'how much of the unit square the two Curves agree on'.
+    /// It is symmetric, i.e. `a.similarity_to(b) == b.similarity_to(a)`.
+    fn similarity_to<O: UtilityCurve + ?Sized>(&self, other: &O, resolution: usize) -> ActionScore {
+        let mut total_diff: ActionScore = 0.;
+        let mut samples: usize = 0;
+
+        for t in sample_points(resolution) {
+            total_diff += (self.sample_safe(t) - other.sample_safe(t)).abs();
+            samples += 1;
+        }
+
+        MAX_CONSIDERATION_SCORE - (total_diff / samples as ActionScore)
+    }
+}
+
+impl<C: UtilityCurve + ?Sized> UtilityCurveAnalysisExt for C {}
+
+
+/// A built-in Curve picked as the closest match for some other Curve.
+#[derive(Debug, Clone)]
+pub struct BuiltinCurveMatch {
+    /// The key of the built-in Curve.
+    pub key: &'static str,
+    /// The built-in Curve itself.
+    pub curve: SupportedUtilityCurve,
+    /// Whether the built-in Curve has the same `CurveShape` as the matched Curve.
+    pub same_shape: bool,
+    /// The `similarity_to()` score between the two Curves.
+    pub similarity: ActionScore,
+}
+
+/// Finds the built-in Curve that behaves the most like the provided Curve.
+///
+/// Built-ins with the same `CurveShape` (including the peak count) always win over
+/// built-ins with a different shape; ties within those groups go to the most similar Curve.
+/// This follows the fallback guidelines in `NoCurveMatchStrategyConfig`.
+pub fn closest_builtin_curve<C: UtilityCurve + ?Sized>(curve: &C, resolution: usize) -> BuiltinCurveMatch {
+    let shape = curve.classify_shape(resolution);
+
+    BUILTIN_CURVE_KEYS
+        .iter()
+        .filter_map(|key| resolve_curve_from_name(*key).map(|builtin| (*key, builtin)))
+        .map(|(key, builtin)| BuiltinCurveMatch {
+            key,
+            same_shape: builtin.classify_shape(resolution) == shape,
+            similarity: builtin.similarity_to(curve, resolution),
+            curve: builtin,
+        })
+        .max_by(|a, b| {
+            a.same_shape
+                .cmp(&b.same_shape)
+                .then(a.similarity.total_cmp(&b.similarity))
+        })
+        .expect("closest_builtin_curve: the built-in Curve catalogue is empty!")
+}
+
+/// Builds a Curve resolver for `NoCurveMatchStrategy::DefaultCurveWithLog` (or WithoutLog)
+/// that maps each missing Curve key to the closest built-in Curve.
+///
+/// Since a missing key has no Curve to compare against, you provide reference Curves for
+/// the keys you expect could go missing - for example custom Curves from a mod that may not
+/// be installed, or Curves you have replaced in a newer version of your game. The matching
+/// is done once, here, so the resolver itself is just a lookup.
+///
+/// Keys without a reference Curve resolve to Linear.
+pub fn closest_builtin_resolver<
+    IS: Into<String>,
+    C: UtilityCurve,
+    I: IntoIterator<Item = (IS, C)>
+>(
+    reference_curves: I,
+    resolution: usize,
+) -> impl CurveResolverFn + 'static {
+    let mut fallbacks: CraniumKvMap<String, SupportedUtilityCurve> = CraniumKvMap::default();
+
+    for (key, reference) in reference_curves {
+        let matched = closest_builtin_curve(&reference, resolution);
+        fallbacks.insert(key.into(), matched.curve);
+    }
+
+    move |key: &String| {
+        fallbacks
+            .get(key.as_str())
+            .cloned()
+            .unwrap_or(SupportedUtilityCurve::Linear(CURVE_LINEAR))
+    }
+}
+
+/// What a Curve has to satisfy to be registered using `UtilityCurveRegistry::register_validated_curve()`.
+///
+/// The range check is always performed; the shape check is opt-in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CurveValidation {
+    /// How many samples to check.
+    pub resolution: usize,
+    /// If set, the Curve must classify as this `CurveShape`.
+    pub expected_shape: Option<CurveShape>,
+}
+
+impl Default for CurveValidation {
+    fn default() -> Self {
+        Self {
+            resolution: DEFAULT_CURVE_ANALYSIS_RESOLUTION,
+            expected_shape: None,
+        }
+    }
+}
+
+impl CurveValidation {
+    /// Requires the validated Curve to have the provided shape.
+    pub fn with_expected_shape(mut self, shape: CurveShape) -> Self {
+        self.expected_shape = Some(shape);
+        self
+    }
+
+    /// Checks a Curve against this validation config.
+    pub fn validate<C: UtilityCurve + ?Sized>(&self, curve: &C) -> Result<(), crate::errors::CurveRegistrationError> {
+        let range = curve.check_range(self.resolution);
+        if !range.is_valid() {
+            return Err(crate::errors::CurveRegistrationError::OutOfRange(range))
+        }
+
+        if let Some(expected) = self.expected_shape {
+            let found = curve.classify_shape(self.resolution);
+            if found != expected {
+                return Err(crate::errors::CurveRegistrationError::UnexpectedShape { expected, found })
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Analysis results for all built-in Curves, in `BUILTIN_CURVE_KEYS` order.
+///
+/// Mainly useful for tooling and documentation - e.g. to list which built-ins are Increasing.
+pub fn analyze_builtin_curves(resolution: usize) -> impl Iterator<Item = (String, CurveShape, CurveRangeReport)> {
+    BUILTIN_CURVE_KEYS
+        .iter()
+        .filter_map(move |key| resolve_curve_from_name(*key).map(|curve| (
+            (*key).to_owned(),
+            curve.classify_shape(resolution),
+            curve.check_range(resolution),
+        )))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::curves::{UtilityCurveExt, UtilityConstantCurve, CURVE_SQUARE, CURVE_ANTILINEAR, LinearCurve, QuadraticInCurve};
+
+    #[test]
+    fn test_builtin_curves_are_valid() {
+        for (key, _shape, range) in analyze_builtin_curves(DEFAULT_CURVE_ANALYSIS_RESOLUTION) {
+            assert!(range.is_valid(), "Built-in Curve {:?} left the unit interval: {:?}", key, range);
+        }
+    }
+
+    #[test]
+    fn test_shape_classification() {
+        let res = DEFAULT_CURVE_ANALYSIS_RESOLUTION;
+        let shape_of = |key: &str| resolve_curve_from_name(key).unwrap().classify_shape(res);
+
+        assert_eq!(shape_of("ConstHalf"), CurveShape::Constant);
+        assert_eq!(shape_of("Linear"), CurveShape::Increasing);
+        assert_eq!(shape_of("ExponentialIn"), CurveShape::Increasing);
+        assert_eq!(shape_of("AntiSquare"), CurveShape::Decreasing);
+        assert_eq!(shape_of("Triangle"), CurveShape::Peaking { peaks: 1 });
+        assert_eq!(shape_of("AntiTriangle"), CurveShape::Peaking { peaks: 2 });
+        assert_eq!(shape_of("QuadGauss"), CurveShape::Peaking { peaks: 1 });
+    }
+
+    #[test]
+    fn test_similarity_and_closest_match() {
+        let res = DEFAULT_CURVE_ANALYSIS_RESOLUTION;
+        assert_eq!(CURVE_SQUARE.similarity_to(&CURVE_SQUARE, res), 1.);
+        assert!((CURVE_LINEAR.similarity_to(&CURVE_ANTILINEAR, res) - 0.5).abs() < 1e-2);
+
+        // A slightly squished Square should still match to Square rather than e.g. Linear.
+        let squished = QuadraticInCurve {}.soft_leak(0.05);
+        assert_eq!(closest_builtin_curve(&squished, res).key, "Square");
+
+        // Shape beats raw similarity - a barely rising line is closest to ConstHalf by area,
+        // but it is still an Increasing Curve, so it should get an Increasing replacement.
+        let barely_rising = LinearCurve {}.soft_leak(0.9);
+        let matched = closest_builtin_curve(&barely_rising, res);
+        assert!(matched.same_shape);
+        assert_eq!(matched.curve.classify_shape(res), CurveShape::Increasing);
+
+        let resolver = closest_builtin_resolver([("test::Squished", squished)], res);
+        assert_eq!(resolver(&"test::Squished".into()).sample_safe(0.5), 0.25);
+        assert_eq!(resolver(&"test::Unknown".into()).sample_safe(0.5), 0.5);
+    }
+
+    #[test]
+    fn test_validation() {
+        let validation = CurveValidation::default();
+        assert!(validation.validate(&CURVE_SQUARE).is_ok());
+        assert!(matches!(
+            validation.validate(&UtilityConstantCurve::new_unchecked(1.5)),
+            Err(crate::errors::CurveRegistrationError::OutOfRange(_))
+        ));
+        assert_eq!(
+            validation.with_expected_shape(CurveShape::Increasing).validate(&CURVE_ANTILINEAR),
+            Err(crate::errors::CurveRegistrationError::UnexpectedShape {
+                expected: CurveShape::Increasing,
+                found: CurveShape::Decreasing,
+            })
+        );
+    }
+}
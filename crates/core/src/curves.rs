@@ -41,8 +41,13 @@ You can obtain one at https://mozilla.org/MPL/2.0/.
 use bevy::math::{self, curve::CurveExt, Curve, curve::Interval};
 use bevy::platform::prelude::{String, ToOwned};
 use bevy::platform::sync::Arc;
+use crate::errors::CurveRegistrationError;
+use crate::identifiers::CurveIdentifier;
 use crate::types::{ActionScore, CraniumKvMap, MIN_CONSIDERATION_SCORE, MAX_CONSIDERATION_SCORE};
 
+#[cfg(feature = "actionset_loader")]
+use serde::{Serialize, Deserialize};
+
 // Reexporting some common basic Bevy Curves for easy access when building custom user Curves.
 pub use bevy::math::curve::{LinearCurve, QuadraticInCurve, QuadraticInOutCurve, ExponentialInCurve, CubicInCurve};
 
@@ -110,6 +115,13 @@ pub trait UtilityCurveExt: UtilityCurve + Sized {
     fn inverse_samples(self) -> UtilityCurveSampler<Self> {
         UtilityCurveSampler::new_inverse(self)
     }
+
+    /// Samples this Curve into a lookup table of a given resolution; see `BakedUtilityCurve`.
+    ///
+    /// The original Curve is only used while baking and is dropped afterwards.
+    fn bake(self, resolution: usize) -> BakedUtilityCurve {
+        BakedUtilityCurve::bake(&self, resolution)
+    }
 }
 
 impl<T: UtilityCurve + Sized> UtilityCurveExt for T {}
@@ -593,6 +605,151 @@ impl<U: UtilityCurve> Curve<ActionScore> for UtilityCurveSampler<U> {
 
 impl<U: UtilityCurve> UtilityCurve for UtilityCurveSampler<U> {}
 
+/// The default number of entries in a `BakedUtilityCurve` lookup table.
+pub const DEFAULT_BAKED_CURVE_RESOLUTION: usize = 256;
+
+/// The smallest number of entries a `BakedUtilityCurve` can have (i.e. the two endpoints).
+pub const MIN_BAKED_CURVE_RESOLUTION: usize = 2;
+
+/// How many points we check per lookup table segment when measuring the error of a bake.
+const BAKED_CURVE_ERROR_OVERSAMPLING: usize = 8;
+
+/// A Utility Curve that has been 'baked' into a fixed-resolution lookup table.
+///
+/// Sampling is a table lookup plus a LERP between the two nearest entries, so it costs the
+/// same no matter how expensive the original Curve was. This makes baking a good fit for
+/// `SupportedUtilityCurve::Custom` Curves (which pay for an `Arc<dyn UtilityCurve>` call on
+/// every sample) and for deep stacks of wrapper Curves (e.g. a SoftLeak of an AverageCurve
+/// of two HalfwayMirrorCurves...).
+///
+/// The price is precision. The baked Curve matches the original exactly at the table entries
+/// and approximates it linearly in between; you can check how far off it is with `error_bound()`.
+/// Smooth Curves bake very well - at the default resolution of 256 entries, the error of the
+/// built-in smooth Curves is well below anything that would change a decision.
+///
+/// Curves with hard steps (e.g. AtLeast, Equals) are a poor fit - the step gets smeared
+/// across one table segment, so the error bound will be large no matter the resolution.
+///
+/// The table is stored behind an Arc, so cloning a BakedUtilityCurve is cheap.
+#[derive(Clone)]
+pub struct BakedUtilityCurve {
+    samples: Arc<[ActionScore]>,
+    error_bound: ActionScore,
+}
+
+impl BakedUtilityCurve {
+    /// Samples the provided Curve at `resolution` evenly spaced points (including both
+    /// endpoints) and measures the error of the result against the original.
+    ///
+    /// Resolutions below `MIN_BAKED_CURVE_RESOLUTION` are bumped up to it.
+    pub fn bake<C: UtilityCurve + ?Sized>(curve: &C, resolution: usize) -> Self {
+        let resolution = resolution.max(MIN_BAKED_CURVE_RESOLUTION);
+        let last_idx = (resolution - 1) as ActionScore;
+
+        let samples: Arc<[ActionScore]> = (0..resolution)
+            .map(|idx| curve.sample_safe(idx as ActionScore / last_idx))
+            .collect()
+        ;
+
+        let mut baked = Self {
+            samples,
+            error_bound: MIN_CONSIDERATION_SCORE,
+        };
+
+        // The table is exact at the entries, so we only need to check the points between them.
+        let check_points = (resolution - 1) * BAKED_CURVE_ERROR_OVERSAMPLING;
+        let error_bound = (0..=check_points)
+            .map(|idx| {
+                let t = idx as ActionScore / check_points as ActionScore;
+                (curve.sample_safe(t) - baked.sample_safe(t)).abs()
+            })
+            .fold(MIN_CONSIDERATION_SCORE, ActionScore::max)
+        ;
+
+        baked.error_bound = error_bound;
+        baked
+    }
+
+    /// Bakes the provided Curve at `DEFAULT_BAKED_CURVE_RESOLUTION`.
+    pub fn bake_default<C: UtilityCurve + ?Sized>(curve: &C) -> Self {
+        Self::bake(curve, DEFAULT_BAKED_CURVE_RESOLUTION)
+    }
+
+    /// Builds a lookup table Curve directly from a list of evenly spaced samples, e.g. ones
+    /// authored by hand in ActionSet data. The first sample is the value at t=0.0, the last at t=1.0.
+    ///
+    /// As there is no 'original' Curve here, the table IS the Curve and the error bound is zero.
+    /// Samples are clamped to the unit interval.
+    ///
+    /// Fails if there are fewer than `MIN_BAKED_CURVE_RESOLUTION` samples or any sample is NaN.
+    pub fn from_samples<I: IntoIterator<Item = ActionScore>>(samples: I) -> Result<Self, CurveRegistrationError> {
+        let samples: Arc<[ActionScore]> = samples
+            .into_iter()
+            .map(|sample| sample.clamp(MIN_CONSIDERATION_SCORE, MAX_CONSIDERATION_SCORE))
+            .collect()
+        ;
+
+        if samples.len() < MIN_BAKED_CURVE_RESOLUTION || samples.iter().any(|sample| sample.is_nan()) {
+            return Err(CurveRegistrationError::InvalidTable)
+        }
+
+        Ok(Self {
+            samples,
+            error_bound: MIN_CONSIDERATION_SCORE,
+        })
+    }
+
+    /// The largest absolute difference between this Curve and the Curve it was baked from,
+    /// as measured by oversampling both Curves when baking.
+    ///
+    /// Note that this is a *measured* bound - in principle, an original Curve could
+    /// wiggle around between the check points without us noticing. In practice, this
+    /// would take a Curve with features much finer than any sensible Utility Curve has.
+    pub fn error_bound(&self) -> ActionScore {
+        self.error_bound
+    }
+
+    /// The number of entries in the lookup table.
+    pub fn resolution(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// The raw lookup table, evenly spaced from t=0.0 to t=1.0 (inclusive).
+    pub fn samples(&self) -> &[ActionScore] {
+        &self.samples
+    }
+}
+
+impl core::fmt::Debug for BakedUtilityCurve {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("BakedUtilityCurve")
+            .field("resolution", &self.resolution())
+            .field("error_bound", &self.error_bound)
+            .finish()
+    }
+}
+
+impl Curve<ActionScore> for BakedUtilityCurve {
+    fn domain(&self) -> Interval {
+        Interval::UNIT
+    }
+
+    fn sample_unchecked(&self, t: f32) -> ActionScore {
+        let last_idx = self.samples.len() - 1;
+        let position = Interval::UNIT.clamp(t) * last_idx as ActionScore;
+
+        // Position is never negative, so truncating is the same as flooring here.
+        let lower_idx = (position as usize).min(last_idx - 1);
+        let frac = position - lower_idx as ActionScore;
+
+        let lower = self.samples[lower_idx];
+        let upper = self.samples[lower_idx + 1];
+        lower + (upper - lower) * frac
+    }
+}
+
+impl UtilityCurve for BakedUtilityCurve {}
+
 // We're wrapping all of these in UtilityCurveSamplers even when not really necessary 
 // for the sake of more predictable, uniform typing.
 pub const CURVE_CONST_ZERO: UtilityCurveSampler<UtilityConstantCurve> = UtilityCurveSampler::new_forward(UtilityConstantCurve::new_const(0));
@@ -860,6 +1017,14 @@ pub enum SupportedUtilityCurve {
     /// reject these potential targets outright.
     AntiQuadraticQuasiGauss(UtilityCurveSampler<HalfwayMirrorCurve<QuadraticInOutCurve>>),
 
+    /// A lookup-table approximation of some other Curve (or a table defined directly in data).
+    /// 
+    /// **COST:** Cheap and constant - a table lookup and a LERP, regardless of the original Curve.
+    /// 
+    /// **USAGE:** Bake expensive Custom Curves or deep stacks of Curve wrappers. Check the 
+    /// `BakedUtilityCurve::error_bound()` to make sure the approximation is good enough for you.
+    Baked(BakedUtilityCurve),
+
     /// A user-defined Curve type registered in the UtilityCurveRegistry. 
     /// 
     /// Due to the Arc<dyn T> overhead, these will be less performant than 
@@ -890,6 +1055,7 @@ impl core::fmt::Debug for SupportedUtilityCurve {
             Self::AntiTriangle(_) => f.debug_tuple("AntiTriangle").finish(),
             Self::QuadraticQuasiGauss(_) => f.debug_tuple("QuadraticQuasiGauss").finish(),
             Self::AntiQuadraticQuasiGauss(_) => f.debug_tuple("AntiQuadraticQuasiGauss").finish(),
+            Self::Baked(c) => f.debug_tuple("Baked").field(c).finish(),
             Self::Custom(_) => f.debug_tuple("Custom").finish(),
         }
     }
@@ -921,6 +1087,7 @@ impl Curve<ActionScore> for SupportedUtilityCurve {
             Self::AntiTriangle(c) => c.sample_unchecked(t),
             Self::QuadraticQuasiGauss(c) => c.sample_unchecked(t),
             Self::AntiQuadraticQuasiGauss(c) => c.sample_unchecked(t),
+            Self::Baked(c) => c.sample_unchecked(t),
             Self::Custom(arc) => arc.sample_safe(t),
         }
     }
@@ -945,6 +1112,29 @@ impl TryFrom<&String> for SupportedUtilityCurve {
     }
 }
 
+/// The keys of all Curves included with the library, i.e. all keys `resolve_curve_from_name()` accepts.
+pub const BUILTIN_CURVE_KEYS: &[&str] = &[
+    "ConstZero",
+    "ConstMax",
+    "ConstHalf",
+    "AtLeast",
+    "LessThan",
+    "Equals",
+    "NotEquals",
+    "Linear",
+    "AntiLinear",
+    "Linear25%SoftLeak",
+    "AntiLinear25%SoftLeak",
+    "Square",
+    "AntiSquare",
+    "ExponentialIn",
+    "AntiExponentialIn",
+    "Triangle",
+    "AntiTriangle",
+    "QuadGauss",
+    "AntiQuadGauss",
+];
+
 /// Retrieves a Utility curve based on a string(-ish) key.
 /// 
 /// This will only work for curves included with the library! 
@@ -1022,6 +1212,172 @@ impl UtilityCurveRegistry {
             }
         }
     }
+
+    /// Like `register_curve()`, but checks the Curve first and rejects it if it fails validation 
+    /// (see `curve_analysis::CurveValidation`). 
+    pub fn register_validated_curve<C: UtilityCurve + 'static>(
+        &mut self, 
+        curve: C, 
+        name: String,
+        validation: &crate::curve_analysis::CurveValidation,
+    ) -> Result<SupportedUtilityCurve, CurveRegistrationError> {
+        if resolve_curve_from_name(name.as_str()).is_some() {
+            return Err(CurveRegistrationError::ConflictsWithBuiltin(name))
+        }
+        validation.validate(&curve)?;
+
+        let wrapper = SupportedUtilityCurve::Custom(Arc::new(curve));
+        self.mapping.insert(name, wrapper.clone());
+        Ok(wrapper)
+    }
+
+    /// Like `register_curve()`, but bakes the Curve into a lookup table first (see `BakedUtilityCurve`).
+    /// 
+    /// This skips the Arc<dyn T> overhead of Custom Curves entirely, as the baked 
+    /// Curve gets stored as a `SupportedUtilityCurve::Baked` instead.
+    pub fn register_baked_curve<C: UtilityCurve>(
+        &mut self, 
+        curve: &C, 
+        name: String,
+        resolution: usize,
+    ) -> Result<SupportedUtilityCurve, CurveRegistrationError> {
+        let is_static = resolve_curve_from_name(name.as_str());
+        match is_static {
+            Some(_) => Err(CurveRegistrationError::ConflictsWithBuiltin(name)),
+            None => {
+                let wrapper = SupportedUtilityCurve::Baked(BakedUtilityCurve::bake(curve, resolution));
+                self.mapping.insert(name, wrapper.clone());
+                Ok(wrapper)
+            }
+        }
+    }
+
+    /// Bakes an already available Curve (built-in or registered) and registers the result under a new key.
+    /// 
+    /// Fails if the source key cannot be resolved or the new key conflicts with a built-in Curve.
+    /// 
+    /// Note that you *can* pass the same key for both, which replaces a registered Curve with its baked version.
+    pub fn bake_curve_as<S: core::borrow::Borrow<str>>(
+        &mut self, 
+        source_name: S, 
+        name: String,
+        resolution: usize,
+    ) -> Result<SupportedUtilityCurve, CurveRegistrationError> {
+        let source = self.get_curve_by_name(source_name.borrow()).ok_or_else(
+            || CurveRegistrationError::NotInRegistry(source_name.borrow().to_owned())
+        )?;
+        self.register_baked_curve(&source, name, resolution)
+    }
+
+    /// Registers a Curve defined in ActionSet data. 
+    /// 
+    /// Fails if the definition references a Curve key that cannot be resolved, 
+    /// contains an invalid lookup table, or conflicts with a built-in Curve.
+    pub fn register_curve_definition(
+        &mut self, 
+        definition: &UtilityCurveDefinition,
+    ) -> Result<SupportedUtilityCurve, CurveRegistrationError> {
+        use core::borrow::Borrow;
+        let name: &str = definition.name.borrow();
+
+        match &definition.source {
+            UtilityCurveDefinitionSource::Baked { curve, resolution } => self.bake_curve_as(
+                curve, 
+                name.to_owned(), 
+                resolution.unwrap_or(DEFAULT_BAKED_CURVE_RESOLUTION),
+            ),
+            UtilityCurveDefinitionSource::Table { samples } => {
+                if resolve_curve_from_name(name).is_some() {
+                    return Err(CurveRegistrationError::ConflictsWithBuiltin(name.to_owned()))
+                }
+                let wrapper = SupportedUtilityCurve::Baked(
+                    BakedUtilityCurve::from_samples(samples.iter().copied())?
+                );
+                self.mapping.insert(name.to_owned(), wrapper.clone());
+                Ok(wrapper)
+            }
+        }
+    }
+}
+
+
+/// A Utility Curve defined in ActionSet data rather than registered from code. 
+/// 
+/// Every definition produces a lookup table Curve (see `BakedUtilityCurve`) registered 
+/// in the `UtilityCurveRegistry` under the definition's name, so it can be used as a 
+/// Curve key by any Consideration, in any ActionSet, once the defining ActionSet is loaded.
+#[derive(Clone, Debug, bevy::reflect::Reflect)]
+#[cfg_attr(feature = "actionset_loader", derive(Serialize, Deserialize))]
+pub struct UtilityCurveDefinition {
+    /// The key the defined Curve will be registered under.
+    pub name: CurveIdentifier,
+
+    /// What the Curve is built from.
+    pub source: UtilityCurveDefinitionSource,
+}
+
+/// The supported ways of defining a Utility Curve in data.
+#[derive(Clone, Debug, bevy::reflect::Reflect)]
+#[cfg_attr(feature = "actionset_loader", derive(Serialize, Deserialize))]
+pub enum UtilityCurveDefinitionSource {
+    /// Bakes another Curve (built-in or registered from code) into a lookup table.
+    /// The resolution defaults to `DEFAULT_BAKED_CURVE_RESOLUTION` if not provided.
+    Baked {
+        curve: CurveIdentifier,
+        resolution: Option<usize>,
+    },
+
+    /// A hand-authored lookup table of evenly spaced samples from t=0.0 to t=1.0 (inclusive).
+    Table {
+        samples: crate::types::CraniumList<ActionScore>,
+    },
+}
+
+/// A System that registers any Curves defined in the ActionSets currently in the `ActionSetStore`.
+/// 
+/// ActionSets are processed in order of their names, and Curves in the order they appear in, 
+/// so a definition can reference Curves defined earlier in the same ActionSet. Definitions that 
+/// cannot be registered are logged and skipped.
+/// 
+/// Each name is only registered from data once; later definitions reusing it are skipped. 
+/// Among other things, this keeps a Baked definition using its own name as the source from 
+/// re-baking its already baked output over and over.
+/// 
+/// This is meant to run whenever the `ActionSetStore` changes (which is how the CraniumPlugin 
+/// schedules it), so the defined Curves become available shortly after their ActionSet gets stored.
+pub fn register_actionset_curve_definitions(
+    actionset_store: bevy::prelude::Res<crate::smart_object::ActionSetStore>,
+    mut registry: bevy::prelude::ResMut<UtilityCurveRegistry>,
+    mut registered_from_data: bevy::prelude::Local<bevy::platform::collections::HashSet<String>>,
+) {
+    let mut actionsets: crate::types::CraniumList<_> = actionset_store.map_by_name.iter().collect();
+    actionsets.sort_by_key(|(name, _)| *name);
+
+    for (_, actionset) in actionsets {
+        for definition in actionset.curves.iter() {
+            let name: &str = core::borrow::Borrow::borrow(&definition.name);
+            if registered_from_data.contains(name) {
+                continue;
+            }
+
+            let _res = registry.register_curve_definition(definition);
+            if _res.is_ok() {
+                registered_from_data.insert(name.to_owned());
+            }
+
+            #[cfg(feature = "logging")]
+            match _res {
+                Ok(_) => bevy::log::debug!(
+                    "register_actionset_curve_definitions: Registered Curve {:?} from ActionSet {:?}", 
+                    &definition.name, &actionset.name,
+                ),
+                Err(err) => bevy::log::error!(
+                    "register_actionset_curve_definitions: Failed to register Curve {:?} from ActionSet {:?} - {:?}", 
+                    &definition.name, &actionset.name, err,
+                ),
+            }
+        }
+    }
 }
 
 
@@ -1040,6 +1396,33 @@ pub trait AcceptsCurveRegistrations {
         curve: U, 
         key: IS,
     ) -> &mut Self;
+
+    /// Registers a Curve baked into a lookup table of the given resolution (see `BakedUtilityCurve`).
+    /// 
+    /// Handy for expensive Custom Curves; `DEFAULT_BAKED_CURVE_RESOLUTION` is a sensible starting point.
+    fn register_baked_utility_curve<
+        U: UtilityCurve,
+        IS: Into<String>
+    >(
+        &mut self, 
+        curve: U, 
+        key: IS,
+        resolution: usize,
+    ) -> &mut Self;
+
+    /// Registers a Curve only if it passes validation (see `curve_analysis::CurveValidation`).
+    /// 
+    /// Rejected Curves are logged as errors and not registered, so any Consideration using 
+    /// the key will be handled by the `NoCurveMatchStrategyConfig` like any other unknown key.
+    fn register_validated_utility_curve<
+        U: UtilityCurve + 'static,
+        IS: Into<String>
+    >(
+        &mut self, 
+        curve: U, 
+        key: IS,
+        validation: crate::curve_analysis::CurveValidation,
+    ) -> &mut Self;
 }
 
 impl AcceptsCurveRegistrations for bevy::prelude::World {
@@ -1074,6 +1457,68 @@ impl AcceptsCurveRegistrations for bevy::prelude::World {
 
         self
     }
+
+    fn register_baked_utility_curve<
+        U: UtilityCurve,
+        IS: Into<String>
+    >(
+        &mut self, 
+        curve: U, 
+        key: IS,
+        resolution: usize,
+    ) -> &mut Self {
+        let mut registry = self.get_resource_or_init::<UtilityCurveRegistry>();
+        let curve_key = crate::types::UtilityCurveKey::from(key.into());
+        let baked = BakedUtilityCurve::bake(&curve, resolution);
+
+        #[cfg(feature = "logging")]
+        bevy::log::debug!(
+            "Baked Curve for key {:?} at resolution {:?}, error bound: {:?}",
+            curve_key, baked.resolution(), baked.error_bound(),
+        );
+        
+        let old = registry.mapping.insert(
+            curve_key.to_owned(), 
+            SupportedUtilityCurve::Baked(baked)
+        );
+
+        match old {
+            None => {},
+            Some(_) => {
+                #[cfg(feature = "logging")]
+                bevy::log::warn!(
+                    "Detected a key collision for key {:?}. Ejecting previous registration...",
+                    curve_key
+                );
+            } 
+        };
+
+        self
+    }
+    fn register_validated_utility_curve<
+        U: UtilityCurve + 'static,
+        IS: Into<String>
+    >(
+        &mut self, 
+        curve: U, 
+        key: IS,
+        validation: crate::curve_analysis::CurveValidation,
+    ) -> &mut Self {
+        let mut registry = self.get_resource_or_init::<UtilityCurveRegistry>();
+        let curve_key = crate::types::UtilityCurveKey::from(key.into());
+
+        let _res = registry.register_validated_curve(curve, curve_key.to_owned(), &validation);
+
+        #[cfg(feature = "logging")]
+        if let Err(err) = _res {
+            bevy::log::error!(
+                "Curve for key {:?} failed validation and was not registered: {:?}",
+                curve_key, err
+            );
+        }
+
+        self
+    }
 }
 
 impl AcceptsCurveRegistrations for bevy::prelude::App {
@@ -1088,5 +1533,138 @@ impl AcceptsCurveRegistrations for bevy::prelude::App {
         self.world_mut().register_utility_curve(curve, key);
         self
     }
+
+    fn register_baked_utility_curve<
+        U: UtilityCurve,
+        IS: Into<String>
+    >(
+        &mut self, 
+        curve: U, 
+        key: IS,
+        resolution: usize,
+    ) -> &mut Self {
+        self.world_mut().register_baked_utility_curve(curve, key, resolution);
+        self
+    }
+
+    fn register_validated_utility_curve<
+        U: UtilityCurve + 'static,
+        IS: Into<String>
+    >(
+        &mut self, 
+        curve: U, 
+        key: IS,
+        validation: crate::curve_analysis::CurveValidation,
+    ) -> &mut Self {
+        self.world_mut().register_validated_utility_curve(curve, key, validation);
+        self
+    }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_baked_curve_matches_entries() {
+        let baked = CURVE_SQUARE.bake(5);
+        assert_eq!(baked.resolution(), 5);
+        assert_eq!(baked.samples(), &[0., 0.0625, 0.25, 0.5625, 1.]);
+        assert_eq!(baked.sample_safe(0.5), 0.25);
+        assert_eq!(baked.sample_safe(1.), 1.);
+        // Halfway between two entries, we LERP.
+        assert!((baked.sample_safe(0.625) - 0.40625).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_baked_curve_error_bound() {
+        let coarse = CURVE_SQUARE.bake(MIN_BAKED_CURVE_RESOLUTION);
+        let fine = CURVE_SQUARE.bake(DEFAULT_BAKED_CURVE_RESOLUTION);
+
+        // A straight line vs x^2 is worst at x=0.5: 0.5 - 0.25.
+        assert!((coarse.error_bound() - 0.25).abs() < 1e-6);
+        assert!(fine.error_bound() < 1e-4);
+
+        // Linear Curves bake losslessly.
+        assert!(CURVE_LINEAR.bake(DEFAULT_BAKED_CURVE_RESOLUTION).error_bound() < 1e-6);
+    }
+
+    #[test]
+    fn test_curve_definitions() {
+        let mut registry = UtilityCurveRegistry::default();
+
+        let baked = UtilityCurveDefinition {
+            name: "test::BakedGauss".into(),
+            source: UtilityCurveDefinitionSource::Baked { curve: "QuadGauss".into(), resolution: Some(64) },
+        };
+        let table = UtilityCurveDefinition {
+            name: "test::Table".into(),
+            source: UtilityCurveDefinitionSource::Table { samples: [0., 1., 0.].into() },
+        };
+        let bad_source = UtilityCurveDefinition {
+            name: "test::Bad".into(),
+            source: UtilityCurveDefinitionSource::Baked { curve: "test::Missing".into(), resolution: None },
+        };
+        let builtin_clash = UtilityCurveDefinition {
+            name: "Linear".into(),
+            source: UtilityCurveDefinitionSource::Table { samples: [0., 1.].into() },
+        };
+
+        assert!(registry.register_curve_definition(&baked).is_ok());
+        assert!(registry.register_curve_definition(&table).is_ok());
+        assert_eq!(
+            registry.register_curve_definition(&bad_source).err(), 
+            Some(CurveRegistrationError::NotInRegistry("test::Missing".into()))
+        );
+        assert_eq!(
+            registry.register_curve_definition(&builtin_clash).err(), 
+            Some(CurveRegistrationError::ConflictsWithBuiltin("Linear".into()))
+        );
+
+        let table_curve = registry.get_curve_by_name("test::Table").unwrap();
+        assert_eq!(table_curve.sample_safe(0.5), 1.);
+        assert_eq!(table_curve.sample_safe(0.25), 0.5);
+    }
+
+    #[test]
+    fn test_actionset_curve_definitions_register_once() {
+        use bevy::prelude::World;
+        use crate::actionset::ActionSet;
+        use crate::smart_object::ActionSetStore;
+
+        let table = |name: &str, samples: &[ActionScore]| UtilityCurveDefinition {
+            name: name.into(),
+            source: UtilityCurveDefinitionSource::Table { samples: samples.into() },
+        };
+        let rebake = UtilityCurveDefinition {
+            name: "test::Lumpy".into(),
+            source: UtilityCurveDefinitionSource::Baked { curve: "test::Lumpy".into(), resolution: Some(4) },
+        };
+
+        let mut world = World::new();
+        let mut registry = UtilityCurveRegistry::default();
+        registry.register_curve_definition(&table("test::Lumpy", &[0., 1., 0.])).unwrap();
+        world.insert_resource(registry);
+
+        let mut store = ActionSetStore::default();
+        for (name, curves) in [
+            ("b", [table("test::Shared", &[1., 1.]), rebake]),
+            ("a", [table("test::Shared", &[0., 1.]), table("test::Other", &[0., 0.])]),
+        ] {
+            store.map_by_name.insert(name.into(), ActionSet::new(name, [].into()).with_curves(curves.into()));
+        }
+        world.insert_resource(store);
+
+        for _ in 0..3 {
+            world.run_system_cached(register_actionset_curve_definitions).unwrap();
+        }
+
+        let registry = world.resource::<UtilityCurveRegistry>();
+        // The ActionSets are processed by name, so "a" wins the shared name.
+        assert_eq!(registry.get_curve_by_name("test::Shared").unwrap().sample_safe(0.), 0.);
+        // Baked from the original table exactly once.
+        let lumpy = registry.get_curve_by_name("test::Lumpy").unwrap();
+        assert!((lumpy.sample_safe(1. / 3.) - 2. / 3.).abs() < 1e-6);
+    }
+}
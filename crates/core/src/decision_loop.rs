@@ -13,13 +13,18 @@ use bevy::prelude::*;
 use crate::actions;
 use crate::ai::{AIController};
 use crate::context_fetchers::{ContextFetcherKeyToSystemMap, ShouldReinitCfQueries};
-use crate::considerations::{ConsiderationKeyToSystemMap, ShouldReinitConsiderationQueries};
+use crate::considerations::{ConsiderationData, ConsiderationKeyToSystemMap, ShouldReinitConsiderationQueries};
+use crate::crowds::{CrowdAssignment, CrowdDispatch, CrowdPawns};
+use crate::identifiers::ConsiderationIdentifier;
 use crate::curves::{SupportedUtilityCurve, UtilityCurve, UtilityCurveRegistry, resolve_curve_from_name};
+use crate::dynamic_bounds::DynamicBound;
 use crate::errors::NoCurveMatchStrategyConfig;
 use crate::events::{AiActionPicked, AiDecisionInitiated, AiDecisionRequested, SomeAiDecisionProcessed};
-use crate::lods::{AiLevelOfDetail};
+use crate::lods::{AiLevelOfDetail, LodUpdateFrequency};
+use crate::normalization::NormalizationMode;
 use crate::pawn::Pawn;
-use crate::smart_object::ActionSetStore;
+use crate::response_surfaces::{UtilityResponseSurface, UtilityResponseSurfaceRegistry};
+use crate::state_machines::AvailableActionSets;
 use crate::types::{self, ActionContextRef, ActionScore, ActionTemplateRef, ThreadSafeRef};
 
 /// Correction formula as per the GDC 2015 "Building a Better Centaur AI" 
@@ -85,13 +90,293 @@ fn consideration_adjustment(
 }
 
 
+/// Raw values of a Consideration for every Context of the ActionTemplate being scored, 
+/// for `NormalizationMode`s that normalize relative to other Contexts (e.g. Percentile). 
+/// 
+/// Keyed by Consideration, computed on first use and reused for all Contexts of the same Template.
+type ConsiderationPopulationCache = types::CraniumKvMap<ConsiderationIdentifier, types::CraniumList<ActionScore>>;
+
+/// Normalizes a raw Consideration value with a given mode, fetching (or computing and caching) 
+/// the population of raw values across all Contexts first if the mode needs it.
+fn normalize_consideration_score(
+    mode: NormalizationMode,
+    raw_score: types::ActionScore,
+    min: types::ActionScore,
+    max: types::ActionScore,
+    consideration_name: &ConsiderationIdentifier,
+    population_cache: &mut ConsiderationPopulationCache,
+    compute_population: impl FnOnce() -> types::CraniumList<ActionScore>,
+) -> types::ActionScore {
+    let population = match mode.needs_population() {
+        false => None,
+        true => Some(
+            population_cache
+                .entry(consideration_name.clone())
+                .or_insert_with(compute_population)
+                .as_slice()
+        ),
+    };
+
+    mode.normalize(raw_score, min, max, population)
+}
+
+
+/// Runs a single Consideration System by key, for the auxiliary inputs of a Consideration 
+/// (e.g. the y input of a Response Surface). 
+/// 
+/// Returns None if the Consideration errored or returned None itself; as with the main 
+/// Consideration loop, an unresolvable key or a poisoned lock are treated as fatal.
+pub(crate) fn run_consideration_system(
+    consideration_system_map: &ConsiderationKeyToSystemMap,
+    consideration_name: &ConsiderationIdentifier,
+    inputs: (types::AiEntity, types::PawnEntityRef, ActionContextRef),
+    world_ref: &World,
+) -> Option<types::ActionScore> {
+    let system_guard = match consideration_system_map.mapping.get(consideration_name) {
+        Some(guard) => guard,
+        None => {
+            #[cfg(feature = "logging")]
+            bevy::log::error!(
+                "decision_engine: Failed to resolve Consideration '{:}' to a System!", 
+                consideration_name
+            );
+            panic!("Consideration failed - could not resolve to a System!");
+        }
+    };
+
+    let res = match system_guard.write() {
+        Ok(mut consideration_system) => consideration_system.run_readonly(inputs, world_ref),
+        Err(_err) => {
+            #[cfg(feature = "logging")]
+            bevy::log::error!(
+                "decision_engine: Consideration '{:}' errored - lock poisoned ({:?})!", 
+                consideration_name, 
+                &_err
+            );
+            panic!("Consideration failed - lock poisoned!");
+        }
+    };
+
+    match res {
+        Ok(maybe_val) => maybe_val,
+        Err(_err) => {
+            #[cfg(feature = "logging")]
+            bevy::log::error!(
+                "decision_engine: Consideration '{:}' errored: {:?}", 
+                consideration_name, 
+                &_err
+            );
+            None
+        }
+    }
+}
+
+
+/// Resolves a Consideration min/max to a value, using the `DynamicBound` if there is one 
+/// and falling back to the constant if there isn't or if it could not be resolved.
+fn resolve_consideration_bound(
+    bound: Option<&DynamicBound>,
+    fallback: types::ActionScore,
+    consideration_system_map: &ConsiderationKeyToSystemMap,
+    inputs: (types::AiEntity, types::PawnEntityRef, ActionContextRef),
+    world_ref: &World,
+) -> types::ActionScore {
+    let bound = match bound {
+        None => return fallback,
+        Some(bound) => bound,
+    };
+
+    // Unlike the Considerations being scored, an unknown bound key is not fatal; we just use the fallback.
+    let resolved = bound.resolve(
+        world_ref, 
+        inputs, 
+        |key| match consideration_system_map.mapping.contains_key(key) {
+            true => run_consideration_system(consideration_system_map, key, inputs, world_ref),
+            false => None,
+        },
+    );
+
+    match resolved {
+        Ok(val) if !val.is_nan() => val,
+        _unresolved => {
+            #[cfg(feature = "logging")]
+            bevy::log::warn!(
+                "decision_engine: AI {:?} - could not resolve dynamic bound {:?} ({:?}), using the fallback value {:?}.",
+                inputs.0, bound, _unresolved, fallback,
+            );
+            fallback
+        }
+    }
+}
+
+
+/// Runs a Consideration for every Context in a list, collecting the raw values that came out fine.
+fn collect_consideration_population(
+    consideration_system_map: &ConsiderationKeyToSystemMap,
+    consideration_name: &ConsiderationIdentifier,
+    contexts: &[ActionContextRef],
+    (audience, pawn): (types::AiEntity, types::PawnEntityRef),
+    world_ref: &World,
+) -> types::CraniumList<ActionScore> {
+    contexts
+        .iter()
+        .filter_map(|ctx| run_consideration_system(
+            consideration_system_map, 
+            consideration_name, 
+            (audience, pawn, *ctx), 
+            world_ref,
+        ))
+        .collect()
+}
+
+
+/// Scores a list of Considerations for a single Context outside of the decision engine (e.g. for goals
+/// or HTN methods), returning the adjusted product of their curve-sampled values.
+/// 
+/// Unlike the decision engine, this does not support Response Surfaces, LOD fallbacks or normalizing 
+/// relative to other Contexts; anything that cannot be scored (e.g. unknown Considerations) scores zero.
+pub(crate) fn score_considerations(
+    considerations: &[ConsiderationData],
+    consideration_system_map: &ConsiderationKeyToSystemMap,
+    inputs: (types::AiEntity, types::PawnEntityRef, ActionContextRef),
+    world_ref: &World,
+) -> types::ActionScore {
+    let utility_curve_registry = world_ref.get_resource::<UtilityCurveRegistry>();
+    let mut score = types::MAX_CONSIDERATION_SCORE;
+
+    for cons in considerations {
+        let key: &str = cons.consideration_name.borrow();
+        if !consideration_system_map.mapping.contains_key(key) {
+            #[cfg(feature = "logging")]
+            bevy::log::warn!("score_considerations: Unknown Consideration {:?}, scoring it as zero.", key);
+            return types::MIN_CONSIDERATION_SCORE;
+        }
+
+        let Some(raw) = run_consideration_system(consideration_system_map, &cons.consideration_name, inputs, world_ref) else {
+            return types::MIN_CONSIDERATION_SCORE;
+        };
+
+        let curve = utility_curve_registry
+            .and_then(|registry| registry.get_curve_by_name(&cons.curve_name))
+            .or_else(|| resolve_curve_from_name(&cons.curve_name));
+        let Some(curve) = curve else {
+            #[cfg(feature = "logging")]
+            bevy::log::warn!("score_considerations: Unknown Curve {:?} for Consideration {:?}, scoring it as zero.", &cons.curve_name, key);
+            return types::MIN_CONSIDERATION_SCORE;
+        };
+
+        let min = resolve_consideration_bound(cons.min_from.as_ref(), cons.min, consideration_system_map, inputs, world_ref);
+        let max = resolve_consideration_bound(cons.max_from.as_ref(), cons.max, consideration_system_map, inputs, world_ref);
+
+        // Same fixup as in the decision engine; normalizing with flipped bounds would panic.
+        let (min, max) = match min <= max {
+            true => (min, max),
+            false => {
+                #[cfg(feature = "logging")]
+                bevy::log::error!(
+                    "score_considerations: Min/Max values for Consideration {:?} were flipped, min={:?} > max={:?}. \
+                    They have been flipped back so Min<=Max for you for now.",
+                    key, min, max,
+                );
+                (max, min)
+            }
+        };
+
+        score *= curve.sample_safe(cons.normalization.normalize(raw, min, max, None));
+        if score <= types::MIN_CONSIDERATION_SCORE {
+            return types::MIN_CONSIDERATION_SCORE;
+        }
+    }
+
+    consideration_adjustment(score, considerations.len())
+}
+
+
+/// Tracks when an AI last started a decision, for LOD-based decision throttling.
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct AiLastDecisionTime(pub Option<core::time::Duration>);
+
+/// A decision request that arrived before the AI's LOD decision interval was up.
+/// 
+/// It will be re-raised once the interval passes (see `release_deferred_decisions()`). 
+/// If more requests arrive in the meantime, only the latest one is kept.
+#[derive(Component, Clone)]
+pub struct DeferredDecisionRequest {
+    pub smart_objects: Option<types::SmartObjects>,
+    pub due: core::time::Duration,
+}
+
+/// A System that re-raises deferred decision requests once they are due.
+pub fn release_deferred_decisions(
+    time: Option<Res<Time<Virtual>>>,
+    query: Query<(Entity, &DeferredDecisionRequest)>,
+    mut commands: Commands,
+) {
+    let Some(now) = time.map(|time| time.elapsed()) else {
+        return;
+    };
+
+    for (ai, deferred) in query.iter() {
+        if deferred.due > now {
+            continue;
+        }
+
+        commands.entity(ai).remove::<DeferredDecisionRequest>();
+        commands.trigger(AiDecisionRequested {
+            entity: ai,
+            smart_objects: deferred.smart_objects.clone(),
+        });
+    }
+}
+
 /// A helper Observer that handles the setup for a Decision.
+/// 
+/// If the `LodUpdateFrequency` Resource is present, this also throttles decisions based on 
+/// the AI's LOD - requests that arrive too early are deferred rather than processed immediately.
 pub fn prepare_ai(
     event: On<AiDecisionRequested>,
     should_reinit_cf_queries: Option<ResMut<ShouldReinitCfQueries>>,
     should_reinit_cons_queries: Option<ResMut<ShouldReinitConsiderationQueries>>,
+    lod_frequency: Option<Res<LodUpdateFrequency>>,
+    time: Option<Res<Time<Virtual>>>,
+    mut schedule_query: Query<(Option<&AiLevelOfDetail>, Option<&mut AiLastDecisionTime>), With<AIController>>,
     mut commands: Commands,
 ) {
+    if let (Some(lod_frequency), Some(time)) = (lod_frequency, time) 
+        && let Ok((maybe_lod, maybe_last_decision)) = schedule_query.get_mut(event.entity)
+    {
+        let now = time.elapsed();
+        let lod = maybe_lod.map(|lod| lod.get_current_lod()).unwrap_or_default();
+        let last_decision = maybe_last_decision.as_ref().and_then(|last| last.0);
+
+        // Inactive AIs are passed through as-is; the decision engine will discard them itself.
+        if let Some(interval) = lod_frequency.decisions.interval_for(lod) 
+            && !lod_frequency.decisions.is_due(lod, last_decision, now) 
+        {
+            let due = last_decision.unwrap_or(now) + interval;
+
+            #[cfg(feature = "logging")]
+            bevy::log::debug!(
+                "prepare_ai: AI {:?} on LOD {:?} requested a decision too early, deferring until {:?}", 
+                event.entity, lod, due
+            );
+
+            commands.entity(event.entity).insert(DeferredDecisionRequest {
+                smart_objects: event.smart_objects.clone(),
+                due,
+            });
+            return;
+        }
+
+        match maybe_last_decision {
+            Some(mut last) => last.0 = Some(now),
+            None => { commands.entity(event.entity).insert(AiLastDecisionTime(Some(now))); },
+        }
+
+        // This request supersedes any pending one.
+        commands.entity(event.entity).remove::<DeferredDecisionRequest>();
+    }
+
     should_reinit_cf_queries.map(|mut res| {
         res.set(true);
     });
@@ -159,7 +444,7 @@ pub fn disable_consideration_reinit(
 pub fn decision_engine(
     event: On<AiDecisionInitiated>,
     world_ref: &World, 
-    actionset_store: Res<ActionSetStore>,
+    available_actionsets: AvailableActionSets,
     context_fetcher_system_map: Res<ContextFetcherKeyToSystemMap>,
     consideration_system_map: Res<ConsiderationKeyToSystemMap>,
     entity_checker: Query<Entity, With<AIController>>, 
@@ -167,6 +452,7 @@ pub fn decision_engine(
     pawn_query: Query<Option<&Pawn>>,
     utility_curve_registry: Option<Res<UtilityCurveRegistry>>,
     no_match_strategy_config: Option<Res<NoCurveMatchStrategyConfig>>,
+    response_surface_registry: Option<Res<UtilityResponseSurfaceRegistry>>,
     mut commands: Commands,
 ) {
     // Marks that SOMEONE has done some AI processing in this world-loop tick. 
@@ -226,12 +512,8 @@ pub fn decision_engine(
         Some(sos) => sos
     };
 
-    let available_actions = smartobjects.actionset_refs.iter().filter_map(
-        |actionset_key| {
-            let maybe_act = actionset_store.map_by_name.get(actionset_key);
-            maybe_act
-        }
-    )
+    // The AI's State Machine (if any) may only allow some of the ActionSets in its current state.
+    let available_actions = available_actionsets.for_ai(audience, smartobjects)
     .flat_map(|acts| {
         acts.actions
         .iter()
@@ -256,6 +538,15 @@ pub fn decision_engine(
             continue;
         }
 
+        if !available_actionsets.can_claim_resources(audience, &action_template) {
+            #[cfg(feature = "logging")]
+            bevy::log::debug!(
+                "decision_engine: AI {:?} - skipping Template {:?} - its resources are held by another AI layer.", 
+                &audience, &action_template.name,
+            );
+            continue;
+        }
+
         #[cfg(feature = "logging")]
         bevy::log::debug!(
             "decision_engine: AI {:?} - requesting Contexts for Template {:?} from CF {:?}", 
@@ -323,7 +614,10 @@ pub fn decision_engine(
             }
         };
 
-        for ctx in contexts {
+        // Only filled in if some Consideration uses a population-based NormalizationMode.
+        let mut population_cache = ConsiderationPopulationCache::default();
+
+        for ctx in contexts.iter().copied() {
             // A flag that indicates the whole processed Context is unusable; 
             // when true, this loop should continue out to the next value and
             // any nested loop should break ASAP to avoid wasting processing.
@@ -366,6 +660,20 @@ pub fn decision_engine(
             let mut consideration_count: usize = 0;
 
             for (cons_cnt, cons) in action_template.considerations.iter().enumerate() {
+                // Per-Consideration LODs - outside of its range, a Consideration is either 
+                // swapped for its (cheaper) fallback or skipped, i.e. treated as neutral.
+                let cons_key = match cons.key_for_lod(&lod_level) {
+                    Some(key) => key,
+                    None => {
+                        #[cfg(feature = "logging")]
+                        bevy::log::debug!(
+                            "decision_engine: AI {:?} - skipping Consideration '{:}' for Action {:?} at LOD {:?}",
+                            &audience, &cons.consideration_name, &action_template.name, &lod_level,
+                        );
+                        continue;
+                    }
+                };
+
                 // We'll use the Registry resource if we have one and fall back to the hardcoded pool if we do not.
                 let mut maybe_resolved_curve: Option<SupportedUtilityCurve> = utility_curve_registry
                     .as_ref()
@@ -412,7 +720,7 @@ pub fn decision_engine(
                                 "decision_engine: AI {:?} - failed to resolve Curve key {:?} to a SupportedUtilityCurve, skipping Consideration {:?}!", 
                                 &audience,
                                 &cons.curve_name,
-                                &cons.consideration_name,
+                                cons_key,
                             );
                             continue;
                         },
@@ -453,7 +761,7 @@ pub fn decision_engine(
                 let resolved_curve = maybe_resolved_curve.unwrap();
 
                 let consideration_system = consideration_system_map.mapping
-                    .get(&cons.consideration_name)
+                    .get(cons_key)
                 ;
 
                 match consideration_system {
@@ -462,7 +770,7 @@ pub fn decision_engine(
                         bevy::log::error!(
                             "decision_engine: AI {:?} - Failed to resolve Consideration '{:}' to a System!", 
                             &audience,
-                            &cons.consideration_name
+                            cons_key
                         );
                         // Uncomment if the panic! below is ever removed:
                         // break;
@@ -490,7 +798,7 @@ pub fn decision_engine(
                                 bevy::log::error!(
                                     "AI {:?} - Consideration '{:}' errored - lock poisoned ({:?})!", 
                                     &audience, 
-                                    &cons.consideration_name, 
+                                    cons_key, 
                                     &res
                                 );
                                 // Uncomment if the panic! below is ever removed:
@@ -512,7 +820,7 @@ pub fn decision_engine(
                                 bevy::log::error!(
                                     "decision_engine: AI {:?} - Consideration '{:}' errored: {:?}", 
                                     &audience, 
-                                    &cons.consideration_name, 
+                                    cons_key, 
                                     &_err
                                 );
                                 curr_score = types::MIN_CONSIDERATION_SCORE;
@@ -532,7 +840,7 @@ pub fn decision_engine(
                                     bevy::log::info!(
                                         "decision_engine: AI {:?} - Consideration '{:}' returned a None score, indicating a nonfatal error. Defaulting to zero score.", 
                                         &audience, 
-                                        &cons.consideration_name, 
+                                        cons_key, 
                                     );
                                     curr_score = types::MIN_CONSIDERATION_SCORE;
                                     skip_this_context = true; break;
@@ -540,8 +848,21 @@ pub fn decision_engine(
                             }
                         };
 
-                        let (true_min, true_max) = match cons.min <= cons.max {
-                            true => (cons.min, cons.max),
+                        // Dynamic bounds get resolved per evaluation; the constants are the fallback.
+                        let bound_inputs = (
+                            audience.entity(),
+                            maybe_pawn.clone().and_then(|p| p.to_entity()),
+                            ctx_ref,
+                        );
+                        let cons_min = resolve_consideration_bound(
+                            cons.min_from.as_ref(), cons.min, &consideration_system_map, bound_inputs, world_ref,
+                        );
+                        let cons_max = resolve_consideration_bound(
+                            cons.max_from.as_ref(), cons.max, &consideration_system_map, bound_inputs, world_ref,
+                        );
+
+                        let (true_min, true_max) = match cons_min <= cons_max {
+                            true => (cons_min, cons_max),
                             false => {
                                 #[cfg(feature = "logging")]
                                 bevy::log::error!(
@@ -549,22 +870,127 @@ pub fn decision_engine(
                                     were flipped, min={:?} > max={:?}. 
                                     They have been flipped back so Min<=Max for you for now. 
                                     This fixup is not guaranteed to be in place in future versions of the library!",
-                                    cons.consideration_name,
+                                    cons_key,
                                     &action_template.name,
-                                    cons.min,
-                                    cons.max,
+                                    cons_min,
+                                    cons_max,
                                 );
-                                (cons.max, cons.min)
+                                (cons_max, cons_min)
                             }
                         };
 
                         // Remap the raw Consideration score (arbitrary value) to a unit interval. 
-                        // Values outside of range get saturated to min/max (as appropriate), so 
+                        // How exactly depends on the NormalizationMode; for the default Linear mode, 
+                        // values outside of range get saturated to min/max (as appropriate), so 
                         // e.g. if min = -1 and raw_score = -5, we read the raw_score as just -1.
-                        // Similarly if max = -4 and raw_score = -1, we read the raw_score as just -4.
-                        let rescaled_score = (raw_score - true_min).clamp(true_min, true_max) / (true_max - true_min);
+                        let rescaled_score = normalize_consideration_score(
+                            cons.normalization,
+                            raw_score, 
+                            true_min, 
+                            true_max,
+                            cons_key,
+                            &mut population_cache,
+                            || collect_consideration_population(
+                                &consideration_system_map, 
+                                cons_key, 
+                                &contexts, 
+                                (audience.entity(), maybe_pawn.clone().and_then(|p| p.to_entity())), 
+                                world_ref,
+                            ),
+                        );
+
+                        let score = match &cons.surface {
+                            None => resolved_curve.sample_safe(rescaled_score),
+                            Some(surface_input) => {
+                                // Two-input Consideration - the score above is the x input, 
+                                // the surface input Consideration provides the y input, 
+                                // and the Curve is applied to the Surface output.
+                                let maybe_surface = response_surface_registry
+                                    .as_ref()
+                                    .and_then(|registry| registry.get_surface_by_name(&surface_input.surface_name))
+                                ;
+
+                                let surface = match maybe_surface {
+                                    Some(surface) => surface,
+                                    None => {
+                                        #[cfg(feature = "logging")]
+                                        bevy::log::error!(
+                                            "decision_engine: AI {:?} - Failed to resolve Response Surface key {:?} for Consideration '{:}', discarding the Context.", 
+                                            &audience,
+                                            &surface_input.surface_name,
+                                            cons_key,
+                                        );
+                                        curr_score = types::MIN_CONSIDERATION_SCORE;
+                                        skip_this_context = true; break;
+                                    }
+                                };
+
+                                let maybe_raw_y = run_consideration_system(
+                                    &consideration_system_map,
+                                    &surface_input.consideration_name,
+                                    (
+                                        audience.entity(),
+                                        maybe_pawn.clone().and_then(|p| p.to_entity()),
+                                        ctx_ref,
+                                    ),
+                                    world_ref,
+                                );
+
+                                let raw_y = match maybe_raw_y {
+                                    Some(val) => val,
+                                    None => {
+                                        // Same as a None from the primary input - nonfatal, but this Context is out.
+                                        #[cfg(feature = "logging")]
+                                        bevy::log::info!(
+                                            "decision_engine: AI {:?} - Surface input Consideration '{:}' returned no score, indicating a nonfatal error. Defaulting to zero score.", 
+                                            &audience, 
+                                            &surface_input.consideration_name, 
+                                        );
+                                        curr_score = types::MIN_CONSIDERATION_SCORE;
+                                        skip_this_context = true; break;
+                                    }
+                                };
+
+                                let rescaled_y = normalize_consideration_score(
+                                    surface_input.normalization,
+                                    raw_y, 
+                                    surface_input.min.min(surface_input.max), 
+                                    surface_input.max.max(surface_input.min),
+                                    &surface_input.consideration_name,
+                                    &mut population_cache,
+                                    || collect_consideration_population(
+                                        &consideration_system_map, 
+                                        &surface_input.consideration_name, 
+                                        &contexts, 
+                                        (audience.entity(), maybe_pawn.clone().and_then(|p| p.to_entity())), 
+                                        world_ref,
+                                    ),
+                                );
+                                let surface_score = surface.sample_safe(rescaled_score, rescaled_y);
 
-                        let score = resolved_curve.sample_safe(rescaled_score);
+                                #[cfg(feature = "logging")]
+                                bevy::log::debug!(
+                                    "decision_engine: AI {:?} - Consideration '{:}' sampled Response Surface {:?}: 
+                                    - Y input '{:}' raw => {:?}
+                                    - Y input normalized ({:?}, min={:?}, max={:?}) => {:?}
+                                    - Surface output at ({:?}, {:?}) => {:?}",
+                                    audience,
+                                    cons_key,
+                                    surface_input.surface_name,
+                                    surface_input.consideration_name,
+                                    raw_y,
+                                    surface_input.normalization,
+                                    surface_input.min,
+                                    surface_input.max,
+                                    rescaled_y,
+                                    rescaled_score,
+                                    rescaled_y,
+                                    surface_score,
+                                );
+
+                                resolved_curve.sample_safe(surface_score)
+                            }
+                        };
 
                         // The actual (raw) score is the product of all Consideration scores so far.
                         curr_score *= score;
@@ -573,13 +999,16 @@ pub fn decision_engine(
                         bevy::log::debug!(
                             "decision_engine: AI {:?} - Consideration '{:}' for Action {:?}:  
                             - Raw score => {:?}
-                            - Rescaled w/ min/max => {:?}
+                            - Normalized ({:?}, min={:?}, max={:?}) => {:?}
                             - Adjusted w/ Curve {:?} => {:?}
                             - Current running total score for Action => {:?}",
                             audience,
-                            cons.consideration_name,
+                            cons_key,
                             &action_template.name,
                             raw_score,
+                            cons.normalization,
+                            true_min,
+                            true_max,
                             rescaled_score,
                             cons.curve_name,
                             score,
@@ -599,7 +1028,7 @@ pub fn decision_engine(
                             bevy::log::debug!(
                                 "decision_engine: AI {:?} - Consideration '{:}' for Action {:?} - curr_score {:?} is below the template best of {:?}, discarding the Context.",
                                 audience,
-                                cons.consideration_name,
+                                cons_key,
                                 &action_template.name,
                                 curr_score,
                                 best_scoring_template,
@@ -697,6 +1126,7 @@ pub fn decision_engine(
                 action_name: best_template.name.to_owned(),
                 action_context: best_context.to_owned(),
                 action_score: best_score,
+                goal: available_actionsets.current_goal(audience).map(|goal| goal.to_owned()),
             };
 
             commands.trigger(pick_evt);
@@ -728,6 +1158,7 @@ pub fn trigger_dispatch_to_user_actions(
 
 pub fn handle_dispatch_to_user_actions(
     pawn_query: Query<Option<&Pawn>>,
+    crowd_query: Query<(&CrowdDispatch, Option<&CrowdAssignment>, Option<&CrowdPawns>)>,
     mut commands: Commands,
     mut reader: MessageReader<crate::events::AiActionDispatchToUserCode>,
     mut callback_registry: ResMut<actions::ActionHandlerKeyToSystemMap>,
@@ -755,6 +1186,31 @@ pub fn handle_dispatch_to_user_actions(
 
         let ai = msg.entity;
         let ctx = msg.action_context;
+        let tracker = msg.action_tracker;
+
+        if let Ok((dispatch, assignment, crowd)) = crowd_query.get(ai) && dispatch.is_per_pawn() {
+            // Crowd AIs dispatching per Pawn run the ActionHandler for every Pawn still in the crowd, 
+            // with the Contexts assigned when the Action was picked (or the AI's own, if it was not).
+            let members: types::CraniumList<types::PawnEntity> = crowd
+                .map(|pawns| pawns.iter().collect())
+                .unwrap_or_default();
+            let per_pawn: types::CraniumList<(types::PawnEntity, ActionContextRef)> = match assignment
+                .filter(|assigned| assigned.is_for(action_key, ctx))
+            {
+                Some(assigned) => assigned.assignments
+                    .iter()
+                    .filter(|(pawn, _)| members.contains(pawn))
+                    .copied()
+                    .collect(),
+                None => members.iter().map(|pawn| (*pawn, ctx)).collect(),
+            };
+
+            for (pawn, pawn_ctx) in per_pawn {
+                callback.call((ai, Some(pawn), pawn_ctx, tracker), commands.reborrow());
+            }
+            continue;
+        }
+
         let pawn = pawn_query
             .get(ai)
             .ok().flatten()
@@ -762,6 +1218,130 @@ pub fn handle_dispatch_to_user_actions(
             .flatten()
         ;
 
-        callback.call((ai, pawn, ctx), commands.reborrow());
+        callback.call((ai, pawn, ctx, tracker), commands.reborrow());
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bevy::platform::prelude::{String, ToOwned, vec};
+    use crate::actions::ActionTemplate;
+    use crate::actionset::ActionSet;
+    use crate::considerations::{AcceptsConsiderationRegistrations, ConsiderationInputs};
+    use crate::context_fetchers::{AcceptsContextFetcherRegistrations, ContextFetcherInputs, ContextFetcherOutputs};
+    use crate::lods::{AiLevelOfDetailValue, LOD_NORMAL};
+    use crate::smart_object::ActionSetStore;
+    use super::*;
+
+    fn ten(_: ConsiderationInputs) -> Option<ActionScore> {
+        Some(10.)
+    }
+
+    fn low(_: ConsiderationInputs) -> Option<ActionScore> {
+        Some(0.1)
+    }
+
+    fn half(_: ConsiderationInputs) -> Option<ActionScore> {
+        Some(0.5)
+    }
+
+    fn high(_: ConsiderationInputs) -> Option<ActionScore> {
+        Some(1.)
+    }
+
+    fn self_context(In((ai, _)): ContextFetcherInputs) -> ContextFetcherOutputs {
+        vec![ai]
+    }
+
+    #[derive(Resource, Default)]
+    struct LastPick(Option<String>);
+
+    fn record_pick(event: On<AiActionPicked>, mut last_pick: ResMut<LastPick>) {
+        last_pick.0 = Some(event.action_name.to_owned());
+    }
+
+    #[test]
+    fn test_lod_fallback_consideration() {
+        let mut world = World::new();
+        world.init_resource::<LastPick>();
+        world.add_observer(decision_engine);
+        world.add_observer(record_pick);
+        world.register_context_fetcher(self_context, "test::Self");
+        world.register_consideration(low, "test::Precise");
+        world.register_consideration(high, "test::Cheap");
+        world.register_consideration(half, "test::Half");
+        crate::context_fetchers::reinit_cf_queries(&mut world);
+        crate::considerations::reinit_consideration_queries(&mut world);
+
+        // Precise scores low at high detail, but uses its (higher-scoring) fallback below NORMAL detail.
+        let precise = ConsiderationData::new("test::Precise", "Linear", 0., 1.)
+            .with_lod_range(None, Some(LOD_NORMAL))
+            .with_lod_fallback("test::Cheap");
+        let steady = ConsiderationData::new("test::Half", "Linear", 0., 1.);
+        let actionset = ActionSet::new("Test", vec![
+            ActionTemplate::new("Precise", "test::Self", vec![precise], 1., "Precise", None, None),
+            ActionTemplate::new("Steady", "test::Self", vec![steady], 1., "Steady", None, None),
+        ]);
+        let mut store = ActionSetStore::default();
+        store.map_by_name.insert("Test".into(), actionset);
+        world.insert_resource(store);
+
+        let smart_objects = crate::types::SmartObjects { actionset_refs: ThreadSafeRef::new(vec!["Test".into()]) };
+        let ai = world.spawn((AIController::default(), AiLevelOfDetail::new_from_value(LOD_NORMAL))).id();
+        let pick = |world: &mut World| {
+            world.trigger(AiDecisionInitiated { entity: ai, smart_objects: Some(smart_objects.clone()) });
+            world.flush();
+            world.resource_mut::<LastPick>().0.take()
+        };
+
+        assert_eq!(pick(&mut world).as_deref(), Some("Steady"));
+
+        world.get_mut::<AiLevelOfDetail>(ai).unwrap().set_lod(AiLevelOfDetailValue::new(LOD_NORMAL + 1));
+        assert_eq!(pick(&mut world).as_deref(), Some("Precise"));
+    }
+
+    #[test]
+    fn test_consideration_bound_fallback() {
+        let mut world = World::new();
+        world.register_consideration(ten, "test::Ten");
+        let ai = world.spawn_empty().id();
+        let inputs = (ai, None, ai);
+
+        // Considerations need to be initialized before they can be run read-only.
+        crate::considerations::reinit_consideration_queries(&mut world);
+
+        let map = world.resource::<ConsiderationKeyToSystemMap>();
+        let known = DynamicBound::Consideration("test::Ten".into());
+        let unknown = DynamicBound::Consideration("test::Missing".into());
+
+        assert_eq!(resolve_consideration_bound(Some(&known), 1., map, inputs, &world), 10.);
+        assert_eq!(resolve_consideration_bound(Some(&unknown), 1., map, inputs, &world), 1.);
+        assert_eq!(resolve_consideration_bound(None, 1., map, inputs, &world), 1.);
+    }
+
+    #[test]
+    fn test_score_considerations_with_flipped_bounds() {
+        let mut world = World::new();
+        world.register_consideration(half, "test::Half");
+        world.register_consideration(low, "test::Low");
+        let ai = world.spawn_empty().id();
+        let inputs = (ai, None, ai);
+        crate::considerations::reinit_consideration_queries(&mut world);
+
+        let map = world.resource::<ConsiderationKeyToSystemMap>();
+        let score = |considerations: &[ConsiderationData]| score_considerations(considerations, map, inputs, &world);
+
+        let ordered = ConsiderationData::new("test::Half", "Linear", 0., 2.);
+        let flipped = ConsiderationData::new("test::Half", "Linear", 2., 0.);
+        assert_eq!(score(&[flipped]), score(&[ordered]));
+
+        // A dynamic max resolving below the min gets the same treatment.
+        let dynamic = ConsiderationData::new("test::Half", "Linear", 0.3, 1.)
+            .with_dynamic_max(DynamicBound::Consideration("test::Low".into()));
+        let resolved = ConsiderationData::new("test::Half", "Linear", 0.1, 0.3);
+        let expected = score(&[resolved]);
+        assert!(expected > 0.);
+        assert_eq!(score(&[dynamic]), expected);
     }
 }
@@ -0,0 +1,218 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+If a copy of the MPL was not distributed with this file,
+You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+//! Dynamic Bounds - Consideration min/max values looked up from the World at decision time.
+//!
+//! A Consideration's min and max are normally constants in the ActionSet data. That works fine
+//! for values with a universal scale, but not for things like 'the Pawn's max health' or 'the
+//! weapon's range', which differ between entities - you'd need a separate ActionSet for each.
+//!
+//! Instead, a Consideration can declare a `DynamicBound` for its min and/or max, which gets
+//! resolved for every evaluation, using the same AI, Pawn and Context as the Consideration itself.
+//! The constant min/max is still used as a fallback if the bound cannot be resolved.
+//!
+//! Bounds can come from:
+//! 1) Another registered Consideration - arbitrary logic, same as any Consideration.
+//! 2) A reflect path into a Component on the Pawn, Context or AI - no code required, but the
+//!    Component type must be registered for reflection with `#[reflect(Component)]`
+//!    (e.g. `app.register_type::<Health>()`) and the field must be a primitive number.
+
+use bevy::platform::prelude::{String, ToOwned, ToString};
+use bevy::prelude::*;
+use bevy::reflect::{GetPath, PartialReflect};
+use crate::errors::DynamicBoundError;
+use crate::identifiers::ConsiderationIdentifier;
+use crate::types::{ActionContextRef, ActionScore, AiEntity, PawnEntityRef};
+
+#[cfg(feature = "actionset_loader")]
+use serde::{Serialize, Deserialize};
+
+/// Which Entity a reflected `DynamicBound` reads from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect)]
+#[cfg_attr(feature = "actionset_loader", derive(Serialize, Deserialize))]
+pub enum BoundSource {
+    /// The Pawn controlled by the AI; fails to resolve for AIs without one.
+    Pawn,
+    /// The Context being scored.
+    Context,
+    /// The AI (Controller) itself.
+    Ai,
+}
+
+/// A Consideration min/max value that is looked up from the World for each evaluation.
+#[derive(Clone, Debug, Reflect)]
+#[cfg_attr(feature = "actionset_loader", derive(Serialize, Deserialize))]
+pub enum DynamicBound {
+    /// The raw output of another registered Consideration, run with the same inputs.
+    Consideration(ConsiderationIdentifier),
+
+    /// A numeric field of a Component, read using reflection.
+    Reflected {
+        /// Which Entity to read the Component from.
+        source: BoundSource,
+        /// The type path (e.g. `my_game::Health`) or short type path (e.g. `Health`) of the Component.
+        component: String,
+        /// A reflect path to a field in the Component (e.g. `max` or `stats.max_health`).
+        /// Leave empty to read the Component itself (e.g. for `struct MaxHealth(f32)`, use `0` or leave empty).
+        #[cfg_attr(feature = "actionset_loader", serde(default))]
+        path: String,
+    },
+}
+
+impl DynamicBound {
+    /// Reads a bound from a Component on the Pawn.
+    pub fn pawn_field<IS: Into<String>, IP: Into<String>>(component: IS, path: IP) -> Self {
+        Self::Reflected { source: BoundSource::Pawn, component: component.into(), path: path.into() }
+    }
+
+    /// Reads a bound from a Component on the Context.
+    pub fn context_field<IS: Into<String>, IP: Into<String>>(component: IS, path: IP) -> Self {
+        Self::Reflected { source: BoundSource::Context, component: component.into(), path: path.into() }
+    }
+
+    /// Resolves the bound to a value.
+    ///
+    /// Consideration lookups are delegated to the provided callback, as running Considerations
+    /// is the decision loop's job; it should return None if the Consideration failed to produce a value.
+    pub fn resolve(
+        &self,
+        world: &World,
+        (ai, pawn, context): (AiEntity, PawnEntityRef, ActionContextRef),
+        run_consideration: impl FnOnce(&ConsiderationIdentifier) -> Option<ActionScore>,
+    ) -> Result<ActionScore, DynamicBoundError> {
+        match self {
+            Self::Consideration(key) => run_consideration(key)
+                .ok_or_else(|| DynamicBoundError::ConsiderationFailed(key.to_string())),
+
+            Self::Reflected { source, component, path } => {
+                let entity = match source {
+                    BoundSource::Pawn => pawn.ok_or(DynamicBoundError::NoPawn)?,
+                    BoundSource::Context => context,
+                    BoundSource::Ai => ai,
+                };
+                read_reflected_number(world, entity, component, path)
+            },
+        }
+    }
+}
+
+/// Reads a primitive number from a Component field using reflection, converting it to an ActionScore.
+pub fn read_reflected_number(
+    world: &World,
+    entity: Entity,
+    component: &str,
+    path: &str,
+) -> Result<ActionScore, DynamicBoundError> {
+    let type_registry = world
+        .get_resource::<AppTypeRegistry>()
+        .ok_or(DynamicBoundError::NoTypeRegistry)?
+        .read()
+    ;
+
+    let registration = type_registry
+        .get_with_type_path(component)
+        .or_else(|| type_registry.get_with_short_type_path(component))
+        .ok_or_else(|| DynamicBoundError::UnknownComponent(component.to_owned()))?
+    ;
+
+    let reflect_component = registration
+        .data::<ReflectComponent>()
+        .ok_or_else(|| DynamicBoundError::UnknownComponent(component.to_owned()))?
+    ;
+
+    let entity_ref = world
+        .get_entity(entity)
+        .map_err(|_| DynamicBoundError::MissingComponent(component.to_owned()))?
+    ;
+
+    let reflected = reflect_component
+        .reflect(entity_ref)
+        .ok_or_else(|| DynamicBoundError::MissingComponent(component.to_owned()))?
+    ;
+
+    let field: &dyn PartialReflect = match path.is_empty() {
+        true => reflected.as_partial_reflect(),
+        false => reflected
+            .reflect_path(path)
+            .map_err(|_| DynamicBoundError::BadPath(path.to_owned()))?,
+    };
+
+    reflected_as_score(field).ok_or_else(|| DynamicBoundError::NotNumeric(path.to_owned()))
+}
+
+/// Converts any reflected primitive number (or a single-field tuple struct wrapping one) to an ActionScore.
+pub(crate) fn reflected_as_score(value: &dyn PartialReflect) -> Option<ActionScore> {
+    macro_rules! try_numeric {
+        ($($num:ty),*) => {
+            $(
+                if let Some(val) = value.try_downcast_ref::<$num>() {
+                    return Some(*val as ActionScore)
+                }
+            )*
+        };
+    }
+    try_numeric!(f32, f64, u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+    // Newtypes like `struct MaxHealth(f32)` are common enough to unwrap automatically.
+    if let bevy::reflect::ReflectRef::TupleStruct(tuple_struct) = value.reflect_ref()
+        && tuple_struct.field_len() == 1 
+    {
+        return tuple_struct.field(0).and_then(reflected_as_score)
+    }
+
+    None
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Component, Reflect)]
+    #[reflect(Component)]
+    struct TestHealth {
+        current: f32,
+        max: u32,
+    }
+
+    #[derive(Component, Reflect)]
+    #[reflect(Component)]
+    struct TestRange(f64);
+
+    #[test]
+    fn test_reflected_bounds() {
+        let mut world = World::new();
+        world.init_resource::<AppTypeRegistry>();
+        {
+            let registry = world.resource::<AppTypeRegistry>();
+            let mut registry = registry.write();
+            registry.register::<TestHealth>();
+            registry.register::<TestRange>();
+        }
+
+        let pawn = world.spawn((TestHealth { current: 5., max: 250 }, TestRange(12.5))).id();
+        let ai = world.spawn_empty().id();
+        let inputs = (ai, Some(pawn), pawn);
+        let no_consideration = |_: &ConsiderationIdentifier| None;
+
+        let max_health = DynamicBound::pawn_field("TestHealth", "max");
+        assert_eq!(max_health.resolve(&world, inputs, no_consideration), Ok(250.));
+
+        let range = DynamicBound::context_field("TestRange", "");
+        assert_eq!(range.resolve(&world, inputs, no_consideration), Ok(12.5));
+
+        let bad_path = DynamicBound::pawn_field("TestHealth", "nope");
+        assert_eq!(bad_path.resolve(&world, inputs, no_consideration), Err(DynamicBoundError::BadPath("nope".into())));
+
+        let missing = DynamicBound::Reflected { source: BoundSource::Ai, component: "TestHealth".into(), path: "max".into() };
+        assert_eq!(missing.resolve(&world, inputs, no_consideration), Err(DynamicBoundError::MissingComponent("TestHealth".into())));
+
+        assert_eq!(max_health.resolve(&world, (ai, None, pawn), no_consideration), Err(DynamicBoundError::NoPawn));
+
+        let from_consideration = DynamicBound::Consideration("test::MaxHealth".into());
+        assert_eq!(from_consideration.resolve(&world, inputs, |_| Some(99.)), Ok(99.));
+    }
+}
@@ -16,6 +16,51 @@ pub enum DynResolutionError {
     NotInRegistry(String)
 }
 
+/// Reasons why a Utility Curve could not be built or registered.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CurveRegistrationError {
+    /// The key is taken by a built-in Curve; those cannot be overridden.
+    ConflictsWithBuiltin(String),
+    /// A Curve key we needed to build the Curve from could not be resolved.
+    NotInRegistry(String),
+    /// A lookup table had too few entries or contained NaNs.
+    InvalidTable,
+    /// The Curve failed validation by leaving the unit interval (see `curve_analysis::CurveValidation`).
+    OutOfRange(crate::curve_analysis::CurveRangeReport),
+    /// The Curve failed validation by not having the expected shape (see `curve_analysis::CurveValidation`).
+    UnexpectedShape {
+        expected: crate::curve_analysis::CurveShape,
+        found: crate::curve_analysis::CurveShape,
+    },
+}
+
+/// Reasons why a `DynamicBound` could not be resolved to a value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DynamicBoundError {
+    /// The bound reads from the Pawn, but the AI does not have one.
+    NoPawn,
+    /// The World has no `AppTypeRegistry` to look up Component types in.
+    NoTypeRegistry,
+    /// The Component type is not registered for reflection (or lacks `#[reflect(Component)]`).
+    UnknownComponent(String),
+    /// The Entity does not have the Component (or does not exist).
+    MissingComponent(String),
+    /// The reflect path does not point to a field in the Component.
+    BadPath(String),
+    /// The reflect path points to something that is not a primitive number.
+    NotNumeric(String),
+    /// The Consideration providing the bound failed to return a value.
+    ConsiderationFailed(String),
+}
+
+/// Reasons why a Behavior Tree could not be registered.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BehaviorTreeError {
+    /// Several children of a Parallel node run Action leaves with this ActionKey, so state change
+    /// requests sent for the AI and the ActionKey could not tell their ActionTrackers apart.
+    ParallelActionKeyConflict(String),
+}
+
 pub trait CurveResolverFn: Send + Sync + Fn(&String) -> crate::curves::SupportedUtilityCurve {}
 impl<F: Send + Sync + Fn(&String) -> crate::curves::SupportedUtilityCurve> CurveResolverFn for F {}
 
@@ -147,6 +192,25 @@ impl NoCurveMatchStrategyConfig {
         self.set(NoCurveMatchStrategy::log_and_default_to(curve_resolver))
     }
 
+    /// Like `set_log_and_use_default()`, but instead of a hand-written mapping function, 
+    /// each missing key is mapped to the built-in Curve closest to the provided reference 
+    /// Curve for that key (see `curve_analysis::closest_builtin_resolver()` for details).
+    /// 
+    /// Keys without a reference Curve resolve to Linear.
+    pub fn set_log_and_use_closest_builtin<
+        IS: Into<String>,
+        C: crate::curves::UtilityCurve,
+        I: IntoIterator<Item = (IS, C)>
+    >(
+        &mut self, 
+        reference_curves: I,
+    ) -> &mut Self {
+        self.set_log_and_use_default(crate::curve_analysis::closest_builtin_resolver(
+            reference_curves, 
+            crate::curve_analysis::DEFAULT_CURVE_ANALYSIS_RESOLUTION,
+        ))
+    }
+
     /// Configures the app to select a fallback Curve using the 
     /// provided (`'static`!) mapping function if a Curve key cannot  
     /// be resolves to a Curve without logging a warning.
@@ -37,6 +37,9 @@ pub struct AiActionPicked {
     /// The Utility score; this is so that we can decide whether to possibly 
     /// override this with a higher-priority Action later on.
     pub action_score: crate::types::ActionScore,
+
+    /// The goal the AI was pursuing when it picked this Action, if it uses the goal tier.
+    pub goal: Option<String>,
 }
 
 impl AiActionPicked {
@@ -64,6 +67,7 @@ impl AiActionPicked {
             action_name: action_name,
             action_context: wrapped_ctx,
             action_score: action_score,
+            goal: None,
         }
     }
 }
@@ -126,6 +130,10 @@ pub struct AiActionDispatchToUserCode {
     /// The Utility score; this is so that we can decide whether to possibly 
     /// override this with a higher-priority Action later on.
     pub action_score: crate::types::ActionScore,
+
+    /// The ActionTracker this dispatch is for, if any. 
+    /// Passed on to the ActionHandler, so that it can address the Action it is running.
+    pub action_tracker: crate::types::ActionTrackerEntityRef,
 }
 
 impl AiActionDispatchToUserCode {
@@ -153,8 +161,14 @@ impl AiActionDispatchToUserCode {
             action_name: action_name,
             action_context: wrapped_ctx,
             action_score: action_score,
+            action_tracker: None,
         }
     }
+
+    pub fn with_action_tracker(mut self, action_tracker: crate::types::ActionTrackerEntity) -> Self {
+        self.action_tracker = Some(action_tracker);
+        self
+    }
 }
 
 
@@ -197,6 +211,7 @@ mod tests {
             action_context: ctx2,
             action_score: 1.,
             entity: entity.into(),
+            goal: None,
         });
     }
 
@@ -0,0 +1,513 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+If a copy of the MPL was not distributed with this file,
+You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+//! Goals - a second, higher Utility tier picking *what to pursue* before the AI picks *how*.
+//!
+//! Flat Utility over every ActionTemplate gets unwieldy past a few dozen of them. With the goal tier,
+//! AIs first pick a `GoalTemplate` (scored with the same Considerations and Curves as Actions), and
+//! then only consider the Actions from the ActionSets that goal exposes.
+//!
+//! Goals are declared in ActionSets (see `ActionSet::goals`); an AI with the `AiGoal` Component considers
+//! all goals from the ActionSets of its SmartObjects that its State Machine (if any) allows. Goals are
+//! scored against the AI's Pawn (or the AI, if it has no Pawn) as the Context.
+//!
+//! Once picked, a goal sticks until:
+//! - it is completed, i.e. all of its `completed_when` facts hold on the AI's Blackboard,
+//! - it is invalidated, i.e. it scores zero or is no longer available to the AI, or
+//! - another goal outscores it by more than `GoalSettings::switch_margin`.
+//!
+//! Every change of goals triggers an `AiGoalChanged` event and a new decision for the AI; the goal
+//! the AI was pursuing is also reported in `AiActionPicked`. AIs without a goal are not restricted.
+
+use core::time::Duration;
+use bevy::ecs::system::SystemState;
+use bevy::platform::prelude::{String, ToOwned};
+use bevy::prelude::*;
+
+use crate::blackboard::Blackboard;
+use crate::considerations::{ConsiderationData, ConsiderationKeyToSystemMap, ensure_consideration_queries};
+use crate::decision_loop::score_considerations;
+use crate::events::AiDecisionRequested;
+use crate::goap::{GoapFact, GoapState};
+use crate::lods::AiLevelOfDetail;
+use crate::pawn::Pawn;
+use crate::smart_object::SmartObjects;
+use crate::state_machines::AvailableActionSets;
+use crate::types::{ActionScore, ActionSetRef, AiEntity, CraniumList};
+
+#[cfg(feature = "actionset_loader")]
+use serde::{Serialize, Deserialize};
+
+#[cfg(feature = "actionset_loader")]
+fn default_goal_priority() -> ActionScore {
+    1.
+}
+
+/// Something an AI may pursue, along with the ActionSets it can use to do so.
+#[derive(Clone, Debug, Reflect)]
+#[cfg_attr(feature = "actionset_loader", derive(Serialize, Deserialize))]
+pub struct GoalTemplate {
+    pub name: String,
+
+    #[cfg_attr(feature = "actionset_loader", serde(default))]
+    pub considerations: CraniumList<ConsiderationData>,
+
+    #[cfg_attr(feature = "actionset_loader", serde(default = "default_goal_priority"))]
+    pub priority: ActionScore,
+
+    /// The ActionSets available to the action tier while the AI pursues this goal.
+    pub actionsets: CraniumList<ActionSetRef>,
+
+    /// Facts (read from the AI's Blackboard) that mark the goal as completed once they all hold.
+    /// If empty, the goal is never completed - only invalidated or outscored.
+    #[cfg_attr(feature = "actionset_loader", serde(default))]
+    pub completed_when: CraniumList<GoapFact>,
+}
+
+impl GoalTemplate {
+    pub fn new<IS: Into<String>>(name: IS) -> Self {
+        Self {
+            name: name.into(),
+            considerations: CraniumList::new(),
+            priority: 1.,
+            actionsets: CraniumList::new(),
+            completed_when: CraniumList::new(),
+        }
+    }
+
+    pub fn with_consideration(mut self, consideration: ConsiderationData) -> Self {
+        self.considerations.push(consideration);
+        self
+    }
+
+    pub fn with_priority(mut self, priority: ActionScore) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_actionset<IS: Into<String>>(mut self, actionset: IS) -> Self {
+        self.actionsets.push(actionset.into());
+        self
+    }
+
+    pub fn with_completion(mut self, fact: GoapFact) -> Self {
+        self.completed_when.push(fact);
+        self
+    }
+
+    /// True if the goal has completion facts and they all hold.
+    pub fn is_completed(&self, blackboard: Option<&Blackboard>) -> bool {
+        if self.completed_when.is_empty() {
+            return false;
+        }
+        let facts = self.completed_when.iter().map(|fact| fact.fact.as_str());
+        GoapState::from_blackboard(blackboard, facts).satisfies(&self.completed_when)
+    }
+}
+
+/// Tuning knobs for the goal tier.
+#[derive(Resource, Debug, Clone)]
+pub struct GoalSettings {
+    /// How much better another goal must score to replace the current one.
+    pub switch_margin: ActionScore,
+    /// How often each AI re-evaluates its goals.
+    pub evaluation_interval: Duration,
+}
+
+impl Default for GoalSettings {
+    fn default() -> Self {
+        Self {
+            switch_margin: 0.1,
+            evaluation_interval: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Enables the goal tier for an AI and tracks the goal it pursues.
+#[derive(Component, Debug, Clone, Default, Reflect)]
+pub struct AiGoal {
+    current: Option<String>,
+    actionsets: CraniumList<ActionSetRef>,
+    score: ActionScore,
+    last_evaluated: Option<Duration>,
+}
+
+impl AiGoal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn current(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    /// The score of the current goal as of the last evaluation.
+    pub fn score(&self) -> ActionScore {
+        self.score
+    }
+
+    /// True if the current goal (if any) exposes the ActionSet.
+    pub fn allows(&self, actionset: &str) -> bool {
+        self.current.is_none() || self.actionsets.iter().any(|allowed| allowed == actionset)
+    }
+
+    /// Makes the AI re-evaluate its goals the next time around, regardless of the evaluation interval.
+    pub fn request_evaluation(&mut self) {
+        self.last_evaluated = None;
+    }
+}
+
+/// Why an AI changed goals.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect)]
+pub enum GoalChangeReason {
+    /// The AI had no goal before.
+    Picked,
+    Completed,
+    Invalidated,
+    Outscored,
+}
+
+/// Signals that an AI has changed goals.
+#[derive(EntityEvent, Debug, Clone)]
+pub struct AiGoalChanged {
+    pub entity: AiEntity,
+    pub from_goal: Option<String>,
+    pub to_goal: Option<String>,
+    pub score: ActionScore,
+    pub reason: GoalChangeReason,
+}
+
+struct GoalChoice {
+    name: String,
+    actionsets: CraniumList<ActionSetRef>,
+}
+
+struct GoalEvaluation {
+    /// The new goal (None if there is nothing worth pursuing), or None to keep the current one.
+    change: Option<(Option<GoalChoice>, GoalChangeReason)>,
+    score: ActionScore,
+}
+
+fn evaluate_ai_goal(
+    world: &World,
+    ai: AiEntity,
+    goal: &AiGoal,
+    available: &AvailableActionSets,
+    consideration_map: Option<&ConsiderationKeyToSystemMap>,
+    settings: &GoalSettings,
+) -> Option<GoalEvaluation> {
+    let smart_objects = world.get::<SmartObjects>(ai)?;
+    let pawn = world.get::<Pawn>(ai).and_then(|pawn| pawn.clone().to_entity());
+    let blackboard = world.get::<Blackboard>(ai);
+    let current = goal.current();
+
+    let mut best: Option<(&GoalTemplate, ActionScore)> = None;
+    let mut current_score: Option<ActionScore> = None;
+    let mut current_completed = false;
+
+    for template in available.goals_for(ai, smart_objects) {
+        let is_current = current == Some(template.name.as_str());
+        if template.is_completed(blackboard) {
+            current_completed |= is_current;
+            continue;
+        }
+
+        let score = template.priority * match consideration_map {
+            Some(consideration_map) => score_considerations(&template.considerations, consideration_map, (ai, pawn, pawn.unwrap_or(ai)), world),
+            None if template.considerations.is_empty() => 1.,
+            None => 0.,
+        };
+
+        if is_current {
+            current_score = Some(score);
+        }
+        if score > 0. && best.is_none_or(|(_, best_score)| score > best_score) {
+            best = Some((template, score));
+        }
+    }
+
+    let best_score = best.map(|(_, score)| score).unwrap_or(0.);
+    let to_best = best.map(|(template, _)| GoalChoice { name: template.name.to_owned(), actionsets: template.actionsets.clone() });
+
+    let evaluation = match (current, current_score) {
+        (None, _) if best.is_none() => GoalEvaluation { change: None, score: 0. },
+        (None, _) => GoalEvaluation { change: Some((to_best, GoalChangeReason::Picked)), score: best_score },
+        (Some(_), _) if current_completed => GoalEvaluation { change: Some((to_best, GoalChangeReason::Completed)), score: best_score },
+        (Some(_), None) => GoalEvaluation { change: Some((to_best, GoalChangeReason::Invalidated)), score: best_score },
+        (Some(_), Some(score)) if score <= 0. => GoalEvaluation { change: Some((to_best, GoalChangeReason::Invalidated)), score: best_score },
+        (Some(current), Some(score)) => {
+            let outscored = best.is_some_and(|(template, best_score)| {
+                template.name != current && best_score > score + settings.switch_margin
+            });
+            match outscored {
+                true => GoalEvaluation { change: Some((to_best, GoalChangeReason::Outscored)), score: best_score },
+                false => GoalEvaluation { change: None, score },
+            }
+        },
+    };
+    Some(evaluation)
+}
+
+/// An exclusive System re-evaluating the goals of all active AIs with the `AiGoal` Component, as
+/// often as `GoalSettings::evaluation_interval` allows.
+pub fn evaluate_goals(world: &mut World) {
+    let settings = world.get_resource::<GoalSettings>().cloned().unwrap_or_default();
+    let now = world.get_resource::<Time>().map(|time| time.elapsed()).unwrap_or_default();
+
+    let mut query = world.query::<(Entity, &AiGoal, Option<&AiLevelOfDetail>)>();
+    let due: CraniumList<Entity> = query
+        .iter(world)
+        .filter(|(_, _, maybe_lod)| !maybe_lod.is_some_and(|lod| lod.get_current_lod().is_inactive()))
+        .filter(|(_, goal, _)| goal.last_evaluated.is_none_or(|last| now.saturating_sub(last) >= settings.evaluation_interval))
+        .map(|(ai, _, _)| ai)
+        .collect();
+
+    if due.is_empty() {
+        return;
+    }
+
+    // The Consideration Systems need to be initialized before we can run them.
+    ensure_consideration_queries(world);
+
+    let mut available_state: SystemState<AvailableActionSets> = SystemState::new(world);
+    let evaluations: CraniumList<(Entity, Option<GoalEvaluation>)> = {
+        let world_ref: &World = world;
+        let available = available_state.get(world_ref);
+        let consideration_map = world_ref.get_resource::<ConsiderationKeyToSystemMap>();
+
+        due.into_iter()
+            .map(|ai| {
+                let evaluation = world_ref.get::<AiGoal>(ai).and_then(|goal| {
+                    evaluate_ai_goal(world_ref, ai, goal, &available, consideration_map, &settings)
+                });
+                (ai, evaluation)
+            })
+            .collect()
+    };
+
+    for (ai, maybe_evaluation) in evaluations {
+        let Some(mut goal) = world.get_mut::<AiGoal>(ai) else {
+            continue;
+        };
+        goal.last_evaluated = Some(now);
+
+        let Some(evaluation) = maybe_evaluation else {
+            continue;
+        };
+        goal.score = evaluation.score;
+
+        let Some((to_goal, reason)) = evaluation.change else {
+            continue;
+        };
+
+        let from_goal = goal.current.take();
+        let (to_goal, actionsets) = match to_goal {
+            Some(choice) => (Some(choice.name), choice.actionsets),
+            None => (None, CraniumList::new()),
+        };
+        goal.current = to_goal.clone();
+        goal.actionsets = actionsets;
+
+        #[cfg(feature = "logging")]
+        bevy::log::debug!("evaluate_goals: AI {:?} - goal {:?} -> {:?} ({:?})", ai, &from_goal, &to_goal, reason);
+
+        let smart_objects = world.get::<SmartObjects>(ai).cloned();
+        world.trigger(AiGoalChanged { entity: ai, from_goal, to_goal, score: evaluation.score, reason });
+        world.trigger(AiDecisionRequested { entity: ai, smart_objects });
+    }
+}
+
+/// Sets up the goal tier (see the module docs).
+///
+/// The decision engine respects the AIs' current goals regardless of this Plugin.
+pub struct GoalPlugin;
+
+impl Plugin for GoalPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<GoalSettings>()
+            .add_systems(PreUpdate, evaluate_goals.after(crate::state_machines::evaluate_state_transitions))
+        ;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bevy::platform::prelude::{Vec, vec};
+    use super::*;
+    use crate::actions::ActionTemplate;
+    use crate::actionset::ActionSet;
+    use crate::considerations::{AcceptsConsiderationRegistrations, ConsiderationInputs, ConsiderationOutputs};
+    use crate::context_fetchers::{AcceptsContextFetcherRegistrations, ContextFetcherInputs, ContextFetcherOutputs};
+    use crate::events::{AiActionPicked, AiDecisionInitiated};
+    use crate::smart_object::ActionSetStore;
+    use crate::types::ThreadSafeRef;
+
+    #[derive(Component)]
+    struct Needs {
+        hunger: ActionScore,
+        tiredness: ActionScore,
+    }
+
+    fn hunger(In((_, _, context)): ConsiderationInputs, query: Query<&Needs>) -> ConsiderationOutputs {
+        query.get(context).ok().map(|needs| needs.hunger)
+    }
+
+    fn tiredness(In((_, _, context)): ConsiderationInputs, query: Query<&Needs>) -> ConsiderationOutputs {
+        query.get(context).ok().map(|needs| needs.tiredness)
+    }
+
+    fn self_context(In((ai, _)): ContextFetcherInputs) -> ContextFetcherOutputs {
+        vec![ai]
+    }
+
+    #[derive(Resource, Default)]
+    struct GoalChanges(Vec<(Option<String>, GoalChangeReason)>);
+
+    fn record_goal_change(event: On<AiGoalChanged>, mut changes: ResMut<GoalChanges>) {
+        changes.0.push((event.to_goal.to_owned(), event.reason));
+    }
+
+    fn smart_objects(actionsets: &[&str]) -> SmartObjects {
+        SmartObjects { actionset_refs: ThreadSafeRef::new(actionsets.iter().map(|&name| name.into()).collect()) }
+    }
+
+    /// An App with an "Eat" goal exposing the "Hunger" ActionSet it is defined in and a "Sleep"
+    /// goal doing the same for the "Rest" one, scored by the AI's `Needs`.
+    fn needs_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(GoalPlugin).init_resource::<ActionSetStore>().init_resource::<GoalChanges>();
+        app.add_observer(record_goal_change);
+        app.insert_resource(GoalSettings { switch_margin: 0.2, evaluation_interval: Duration::ZERO });
+        app.world_mut().register_consideration(hunger, "test::Hunger");
+        app.world_mut().register_consideration(tiredness, "test::Tiredness");
+
+        let mut store = app.world_mut().resource_mut::<ActionSetStore>();
+        for (actionset, goal, key) in [("Hunger", "Eat", "test::Hunger"), ("Rest", "Sleep", "test::Tiredness")] {
+            let goal = GoalTemplate::new(goal)
+                .with_consideration(ConsiderationData::new(key, "Linear", 0., 1.))
+                .with_actionset(actionset);
+            store.map_by_name.insert(actionset.into(), ActionSet::new(actionset, vec![]).with_goals(vec![goal]));
+        }
+        app
+    }
+
+    fn evaluate(app: &mut App, ai: Entity, hunger: ActionScore, tiredness: ActionScore) -> Option<(Option<String>, GoalChangeReason)> {
+        app.world_mut().entity_mut(ai).insert(Needs { hunger, tiredness });
+        app.world_mut().run_system_cached(evaluate_goals).unwrap();
+        app.world_mut().flush();
+
+        let mut changes = app.world_mut().resource_mut::<GoalChanges>();
+        assert!(changes.0.len() <= 1);
+        changes.0.pop()
+    }
+
+    #[test]
+    fn test_goals_gate_actionsets() {
+        let mut app = App::new();
+        app.add_plugins(GoalPlugin).init_resource::<ActionSetStore>();
+        app.world_mut().resource_mut::<GoalSettings>().evaluation_interval = Duration::ZERO;
+
+        let needs = ActionSet::new("Needs", CraniumList::new()).with_goals(vec![
+            GoalTemplate::new("Eat").with_priority(0.5).with_actionset("Food"),
+            GoalTemplate::new("Sleep").with_priority(0.8).with_actionset("Bed").with_completion(GoapFact::is("rested")),
+        ]);
+        app.world_mut().resource_mut::<ActionSetStore>().map_by_name.insert("Needs".into(), needs);
+
+        let smart_objects = SmartObjects { actionset_refs: ThreadSafeRef::new(vec!["Needs".into()]) };
+        let ai = app.world_mut().spawn((AiGoal::new(), smart_objects)).id();
+        let mut state: SystemState<AvailableActionSets> = SystemState::new(app.world_mut());
+
+        app.world_mut().run_system_cached(evaluate_goals).unwrap();
+        assert_eq!(app.world().get::<AiGoal>(ai).unwrap().current(), Some("Sleep"));
+        {
+            let available = state.get(app.world());
+            assert!(available.is_allowed(ai, "Bed"));
+            assert!(!available.is_allowed(ai, "Food"));
+        }
+
+        let mut blackboard = Blackboard::new();
+        blackboard.set_value("rested", true);
+        app.world_mut().entity_mut(ai).insert(blackboard);
+
+        app.world_mut().run_system_cached(evaluate_goals).unwrap();
+        assert_eq!(app.world().get::<AiGoal>(ai).unwrap().current(), Some("Eat"));
+        assert!(state.get(app.world()).is_allowed(ai, "Food"));
+    }
+
+    #[test]
+    fn test_goal_switch_margin() {
+        let mut app = needs_app();
+        let ai = app.world_mut().spawn((AiGoal::new(), smart_objects(&["Hunger", "Rest"]))).id();
+
+        assert_eq!(evaluate(&mut app, ai, 0.5, 0.4), Some((Some("Eat".into()), GoalChangeReason::Picked)));
+
+        // Sleep scores better now, but not by more than the switch margin...
+        assert_eq!(evaluate(&mut app, ai, 0.5, 0.6), None);
+        assert_eq!(evaluate(&mut app, ai, 0.5, 0.7), None);
+        assert_eq!(app.world().get::<AiGoal>(ai).unwrap().current(), Some("Eat"));
+        assert_eq!(app.world().get::<AiGoal>(ai).unwrap().score(), 0.5);
+
+        // ...until it does.
+        assert_eq!(evaluate(&mut app, ai, 0.5, 0.8), Some((Some("Sleep".into()), GoalChangeReason::Outscored)));
+        assert_eq!(app.world().get::<AiGoal>(ai).unwrap().score(), 0.8);
+    }
+
+    #[test]
+    fn test_goal_invalidation() {
+        let mut app = needs_app();
+        let ai = app.world_mut().spawn((AiGoal::new(), smart_objects(&["Hunger", "Rest"]))).id();
+
+        assert_eq!(evaluate(&mut app, ai, 0.9, 0.4), Some((Some("Eat".into()), GoalChangeReason::Picked)));
+
+        // A current goal scoring zero is dropped, even if the alternative is within the switch margin.
+        assert_eq!(evaluate(&mut app, ai, 0., 0.1), Some((Some("Sleep".into()), GoalChangeReason::Invalidated)));
+
+        // So is one the AI no longer has access to...
+        app.world_mut().entity_mut(ai).insert(smart_objects(&["Hunger"]));
+        assert_eq!(evaluate(&mut app, ai, 0.1, 0.9), Some((Some("Eat".into()), GoalChangeReason::Invalidated)));
+
+        // ...leaving the AI without a goal if nothing else is worth pursuing.
+        assert_eq!(evaluate(&mut app, ai, 0., 0.9), Some((None, GoalChangeReason::Invalidated)));
+        assert_eq!(app.world().get::<AiGoal>(ai).unwrap().current(), None);
+    }
+
+    #[derive(Resource, Default)]
+    struct PickedGoals(Vec<Option<String>>);
+
+    fn record_picked_goal(event: On<AiActionPicked>, mut picked: ResMut<PickedGoals>) {
+        picked.0.push(event.goal.to_owned());
+    }
+
+    #[test]
+    fn test_goal_reported_on_picked_actions() {
+        let mut app = needs_app();
+        app.init_resource::<PickedGoals>();
+        app.add_observer(crate::decision_loop::decision_engine).add_observer(record_picked_goal);
+        app.world_mut().register_context_fetcher(self_context, "test::Self");
+        crate::context_fetchers::reinit_cf_queries(app.world_mut());
+        crate::considerations::reinit_consideration_queries(app.world_mut());
+
+        let eat = ActionTemplate::new("Eat", "test::Self", vec![ConsiderationData::new("test::Hunger", "Linear", 0., 1.)], 1., "Eat", None, None);
+        let mut store = app.world_mut().resource_mut::<ActionSetStore>();
+        store.map_by_name.get_mut("Hunger").unwrap().actions.push(eat);
+
+        let smart_objects = smart_objects(&["Hunger", "Rest"]);
+        let ai = app.world_mut().spawn((crate::ai::AIController::default(), AiGoal::new(), smart_objects.clone())).id();
+        let decide = |app: &mut App| {
+            app.world_mut().trigger(AiDecisionInitiated { entity: ai, smart_objects: Some(smart_objects.clone()) });
+            app.world_mut().flush();
+            app.world_mut().resource_mut::<PickedGoals>().0.pop()
+        };
+
+        app.world_mut().entity_mut(ai).insert(Needs { hunger: 0.5, tiredness: 0. });
+        assert_eq!(decide(&mut app), Some(None));
+
+        assert_eq!(evaluate(&mut app, ai, 0.5, 0.), Some((Some("Eat".into()), GoalChangeReason::Picked)));
+        assert_eq!(decide(&mut app), Some(Some("Eat".into())));
+    }
+}
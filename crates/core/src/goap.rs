@@ -0,0 +1,459 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+If a copy of the MPL was not distributed with this file,
+You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+//! GOAP - Goal-Oriented Action Planning on top of Utility AI.
+//!
+//! Utility AI picks the single best thing to do right now, which falls short for multi-step goals
+//! like 'get the key, open the door, loot the chest' - none of the steps may look very useful on
+//! their own. GOAP fills that gap by planning a sequence of Actions that achieves a goal.
+//!
+//! The two play together like this:
+//! - ActionTemplates declare symbolic `preconditions` and `effects` (plus an optional `cost`) in data.
+//! - Goals are just ActionTemplates with an ActionKey starting with `GoapGoal::`, scored by the usual
+//!   Utility AI decision process; the goal's `effects` are the facts it wants to make true.
+//! - Once a goal gets picked, an A* planner finds the cheapest sequence of the AI's other ActionTemplates
+//!   that leads from the current facts to the goal's facts.
+//! - The plan runs as a chain of ActionTrackers (separate Entities owned by the AI), all spawned in the
+//!   `ActionState::Queued` state; each one becomes Ready when the previous step succeeds. If a step
+//!   fails or gets cancelled, the rest of the plan is dropped and the AI replans (up to a limit).
+//!   See the `plans` module for details.
+//!
+//! Facts are named booleans, read from the AI's `Blackboard` (missing values are false; numbers count
+//! as true if non-zero). By default, the effects of every successful step get written back to the
+//! Blackboard, but you can also keep them up to date from your own game logic.
+//!
+//! The Context for each step is the first one returned by the step's ContextFetcher at planning time;
+//! ActionTemplates whose ContextFetcher returns nothing are not used for planning at all.
+
+use bevy::ecs::system::SystemState;
+use bevy::platform::prelude::{String, ToOwned};
+use bevy::prelude::*;
+
+use crate::actions::{
+    AcceptsActionHandlerRegistrations, ActionHandlerInputs, ActionHandlerKeyToSystemMap,
+    ActionPickCallback, ActionTemplate,
+};
+use crate::blackboard::Blackboard;
+use crate::context_fetchers::ContextFetcherKeyToSystemMap;
+use crate::pawn::Pawn;
+use crate::plans::{
+    AiReplanRequested, PlanKind, PlannedStep, PlansPlugin, first_context, give_up_on_goal,
+    plannable_actionsets, start_plan, take_plan_requests,
+};
+use crate::smart_object::ActionSetStore;
+use crate::state_machines::AvailableActionSets;
+use crate::types::{ActionKey, ActionScore, AiEntity, CraniumKvMap, CraniumList};
+
+#[cfg(feature = "actionset_loader")]
+use serde::{Serialize, Deserialize};
+
+/// ActionKey prefix marking ActionTemplates as GOAP goals.
+pub const GOAP_GOAL_ACTION_PREFIX: &str = "GoapGoal::";
+
+/// The default cost of an Action in a plan.
+pub const DEFAULT_GOAP_ACTION_COST: ActionScore = 1.;
+
+#[cfg(feature = "actionset_loader")]
+fn default_fact_value() -> bool {
+    true
+}
+
+/// A named boolean fact about the World, e.g. `has_key = true`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Reflect)]
+#[cfg_attr(feature = "actionset_loader", derive(Serialize, Deserialize))]
+pub struct GoapFact {
+    pub fact: String,
+    #[cfg_attr(feature = "actionset_loader", serde(default = "default_fact_value"))]
+    pub value: bool,
+}
+
+impl GoapFact {
+    pub fn new<IS: Into<String>>(fact: IS, value: bool) -> Self {
+        Self { fact: fact.into(), value }
+    }
+
+    /// Shorthand for a fact that should be true.
+    pub fn is<IS: Into<String>>(fact: IS) -> Self {
+        Self::new(fact, true)
+    }
+
+    /// Shorthand for a fact that should be false.
+    pub fn not<IS: Into<String>>(fact: IS) -> Self {
+        Self::new(fact, false)
+    }
+}
+
+/// A snapshot of all the facts relevant to a plan; facts that are not listed are false.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct GoapState {
+    // Kept sorted by fact name, so that equal states compare and hash equal.
+    facts: CraniumList<GoapFact>,
+}
+
+impl GoapState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the specified facts from a Blackboard.
+    pub fn from_blackboard<'a>(blackboard: Option<&Blackboard>, fact_names: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut state = Self::new();
+        for name in fact_names {
+            let value = blackboard
+                .and_then(|blackboard| blackboard.get_number(name))
+                .map(|number| number != 0.)
+                .unwrap_or(false);
+            state.set(name, value);
+        }
+        state
+    }
+
+    pub fn get(&self, fact: &str) -> bool {
+        self.facts
+            .binary_search_by(|known| known.fact.as_str().cmp(fact))
+            .map(|idx| self.facts[idx].value)
+            .unwrap_or(false)
+    }
+
+    pub fn set(&mut self, fact: &str, value: bool) -> &mut Self {
+        match self.facts.binary_search_by(|known| known.fact.as_str().cmp(fact)) {
+            Ok(idx) => self.facts[idx].value = value,
+            Err(idx) => self.facts.insert(idx, GoapFact::new(fact, value)),
+        }
+        self
+    }
+
+    /// True if all the facts hold in this state.
+    pub fn satisfies(&self, facts: &[GoapFact]) -> bool {
+        facts.iter().all(|fact| self.get(&fact.fact) == fact.value)
+    }
+
+    /// How many of the facts do not hold in this state.
+    pub fn distance_to(&self, facts: &[GoapFact]) -> usize {
+        facts.iter().filter(|fact| self.get(&fact.fact) != fact.value).count()
+    }
+
+    /// A copy of this state with the facts applied.
+    pub fn with_applied(&self, facts: &[GoapFact]) -> Self {
+        let mut new = self.clone();
+        for fact in facts {
+            new.set(&fact.fact, fact.value);
+        }
+        new
+    }
+}
+
+/// A single Action the planner may use.
+#[derive(Clone, Debug)]
+pub struct GoapPlannerAction {
+    pub preconditions: CraniumList<GoapFact>,
+    pub effects: CraniumList<GoapFact>,
+    pub cost: ActionScore,
+}
+
+impl GoapPlannerAction {
+    pub fn from_template(template: &ActionTemplate) -> Self {
+        Self {
+            preconditions: template.preconditions.clone(),
+            effects: template.effects.clone(),
+            cost: template.cost.unwrap_or(DEFAULT_GOAP_ACTION_COST).max(0.),
+        }
+    }
+}
+
+struct PlannerNode {
+    state: GoapState,
+    cost: ActionScore,
+    estimate: ActionScore,
+    parent: Option<(usize, usize)>,
+}
+
+/// Finds the cheapest sequence of Actions (as indices into the slice) leading from the start state to the goal.
+///
+/// Returns an empty plan if the goal already holds, and None if there is no plan within the node budget.
+pub fn plan(
+    start: &GoapState,
+    goal: &[GoapFact],
+    actions: &[GoapPlannerAction],
+    max_nodes: usize,
+) -> Option<CraniumList<usize>> {
+    // An unsatisfied goal takes at least one more Action, but a single Action may fix several facts at once,
+    // so this is as far as we can go while keeping the heuristic admissible (and the plans optimal).
+    let min_cost = actions.iter().map(|action| action.cost).fold(ActionScore::INFINITY, ActionScore::min);
+    let min_cost = if min_cost.is_finite() { min_cost } else { 0. };
+    let heuristic = |state: &GoapState| if state.satisfies(goal) { 0. } else { min_cost };
+
+    let mut nodes: CraniumList<PlannerNode> = CraniumList::new();
+    let mut open: CraniumList<usize> = CraniumList::new();
+    let mut best_costs: CraniumKvMap<GoapState, ActionScore> = CraniumKvMap::default();
+
+    nodes.push(PlannerNode { state: start.clone(), cost: 0., estimate: heuristic(start), parent: None });
+    open.push(0);
+    best_costs.insert(start.clone(), 0.);
+
+    while !open.is_empty() {
+        let (open_idx, _) = open
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| {
+                let (a, b) = (&nodes[**a], &nodes[**b]);
+                (a.cost + a.estimate).total_cmp(&(b.cost + b.estimate))
+            })?;
+        let current = open.swap_remove(open_idx);
+
+        if nodes[current].state.satisfies(goal) {
+            let mut steps = CraniumList::new();
+            let mut cursor = current;
+            while let Some((parent, action)) = nodes[cursor].parent {
+                steps.push(action);
+                cursor = parent;
+            }
+            steps.reverse();
+            return Some(steps);
+        }
+
+        // Skip nodes superseded by a cheaper path to the same state.
+        if best_costs.get(&nodes[current].state).is_some_and(|best| *best < nodes[current].cost) {
+            continue;
+        }
+
+        for (action_idx, action) in actions.iter().enumerate() {
+            if !nodes[current].state.satisfies(&action.preconditions) {
+                continue;
+            }
+
+            let state = nodes[current].state.with_applied(&action.effects);
+            let cost = nodes[current].cost + action.cost;
+            if best_costs.get(&state).is_some_and(|best| *best <= cost) {
+                continue;
+            }
+
+            if nodes.len() >= max_nodes {
+                #[cfg(feature = "logging")]
+                bevy::log::debug!("goap::plan: Ran out of the node budget ({:?}), giving up.", max_nodes);
+                return None;
+            }
+
+            best_costs.insert(state.clone(), cost);
+            let estimate = heuristic(&state);
+            nodes.push(PlannerNode { state, cost, estimate, parent: Some((current, action_idx)) });
+            open.push(nodes.len() - 1);
+        }
+    }
+
+    None
+}
+
+/// Tuning knobs for the GOAP planner; see also `PlanSettings` for running the plans.
+#[derive(Resource, Debug, Clone)]
+pub struct GoapSettings {
+    /// The maximum number of states the planner may explore per plan.
+    pub max_nodes: usize,
+}
+
+impl Default for GoapSettings {
+    fn default() -> Self {
+        Self { max_nodes: 1024 }
+    }
+}
+
+/// Asks the GOAP planner to plan for a goal; inserted by goal ActionHandlers.
+#[derive(Component, Debug, Clone)]
+pub struct GoapGoalRequest {
+    /// The ActionKey of the goal ActionTemplate.
+    pub goal: ActionKey,
+    pub replans: u32,
+}
+
+/// Builds an ActionHandler requesting a plan for the goal.
+///
+/// Registered automatically for all `GoapGoal::` ActionKeys in stored ActionSets.
+pub fn goal_handler<IS: Into<String>>(goal: IS) -> ActionPickCallback {
+    let goal: String = goal.into();
+    ActionPickCallback::new(move |inputs: ActionHandlerInputs, mut commands: Commands| {
+        let (ai, _pawn, _context, _tracker) = inputs;
+        if let Ok(mut cmds) = commands.get_entity(ai) {
+            cmds.insert(GoapGoalRequest { goal: goal.clone(), replans: 0 });
+        }
+    })
+}
+
+/// Builds a plan for the AI, if possible. Returns None if there is no plan at all.
+fn build_plan(
+    world: &World,
+    ai: AiEntity,
+    goal_key: &ActionKey,
+    available: &AvailableActionSets,
+    settings: &GoapSettings,
+) -> Option<CraniumList<PlannedStep>> {
+    let store = world.get_resource::<ActionSetStore>()?;
+    let cf_map = world.get_resource::<ContextFetcherKeyToSystemMap>()?;
+
+    let goal_actionset = store.map_by_name.values().find(|actionset| {
+        actionset.actions.iter().any(|template| template.action_key == *goal_key)
+    })?;
+    let goal = goal_actionset.actions.iter().find(|template| template.action_key == *goal_key)?;
+
+    let pawn = world.get::<Pawn>(ai).and_then(|pawn| pawn.clone().to_entity());
+
+    let mut candidates: CraniumList<PlannedStep> = CraniumList::new();
+    for actionset in plannable_actionsets(world, ai, &goal_actionset.name, available) {
+        for template in actionset.actions.iter() {
+            if template.effects.is_empty() || template.action_key.starts_with(GOAP_GOAL_ACTION_PREFIX) {
+                continue;
+            }
+            let Some(context) = first_context(template, cf_map, (ai, pawn), world) else {
+                continue;
+            };
+            candidates.push(PlannedStep { template: template.clone(), context });
+        }
+    }
+
+    let fact_names = goal.effects.iter()
+        .chain(candidates.iter().flat_map(|step| step.template.preconditions.iter().chain(step.template.effects.iter())))
+        .map(|fact| fact.fact.as_str());
+    let start = GoapState::from_blackboard(world.get::<Blackboard>(ai), fact_names);
+
+    let planner_actions: CraniumList<GoapPlannerAction> = candidates.iter()
+        .map(|step| GoapPlannerAction::from_template(&step.template))
+        .collect();
+
+    let steps = plan(&start, &goal.effects, &planner_actions, settings.max_nodes)?;
+
+    // The same Action may well be used more than once in a plan.
+    Some(steps.into_iter().map(|idx| candidates[idx].clone()).collect())
+}
+
+/// An exclusive System turning `GoapGoalRequest`s into plans.
+pub fn plan_goap_goals(world: &mut World) {
+    let Some(requests) = take_plan_requests::<GoapGoalRequest>(world, |request| &request.goal) else {
+        return;
+    };
+
+    let settings = world.get_resource::<GoapSettings>().cloned().unwrap_or_default();
+    let mut available_state: SystemState<AvailableActionSets> = SystemState::new(world);
+
+    let plans: CraniumList<(Entity, GoapGoalRequest, Option<CraniumList<PlannedStep>>)> = {
+        let available = available_state.get(world);
+        requests
+            .into_iter()
+            .map(|(ai, request)| {
+                let steps = build_plan(world, ai, &request.goal, &available, &settings);
+                (ai, request, steps)
+            })
+            .collect()
+    };
+
+    for (ai, request, maybe_steps) in plans {
+        match maybe_steps {
+            Some(steps) => start_plan(world, ai, PlanKind::Goap, request.goal, steps, request.replans),
+            None => {
+                #[cfg(feature = "logging")]
+                bevy::log::debug!("plan_goap_goals: AI {:?} found no plan for goal {:?}", ai, &request.goal);
+
+                give_up_on_goal(&mut world.commands(), ai, PlanKind::Goap, request.goal);
+                world.flush();
+            }
+        }
+    }
+}
+
+/// An Observer turning GOAP replan requests back into `GoapGoalRequest`s.
+pub fn replan_goap_goals(
+    event: On<AiReplanRequested>,
+    mut commands: Commands,
+) {
+    if event.kind != PlanKind::Goap {
+        return;
+    }
+    if let Ok(mut cmds) = commands.get_entity(event.entity) {
+        cmds.insert(GoapGoalRequest { goal: event.goal.to_owned(), replans: event.replans });
+    }
+}
+
+/// An exclusive System that registers goal ActionHandlers for all `GoapGoal::` ActionKeys in the stored ActionSets.
+pub fn register_actionset_goals(world: &mut World) {
+    let goal_keys: CraniumList<ActionKey> = {
+        let Some(store) = world.get_resource::<ActionSetStore>() else {
+            return;
+        };
+        let registered = world.get_resource::<ActionHandlerKeyToSystemMap>();
+
+        store.map_by_name.values()
+            .flat_map(|actionset| actionset.actions.iter())
+            .map(|template| &template.action_key)
+            .filter(|key| key.starts_with(GOAP_GOAL_ACTION_PREFIX))
+            .filter(|key| !registered.is_some_and(|reg| reg.mapping.contains_key(key.as_str())))
+            .cloned()
+            .collect()
+    };
+
+    for key in goal_keys {
+        #[cfg(feature = "logging")]
+        bevy::log::debug!("register_actionset_goals: Registered goal ActionHandler {:?}", &key);
+
+        world.register_action_handler(goal_handler(key.to_owned()), key);
+    }
+}
+
+/// Sets up GOAP planning (see the module docs).
+pub struct GoapPlugin;
+
+impl Plugin for GoapPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<PlansPlugin>() {
+            app.add_plugins(PlansPlugin);
+        }
+
+        app
+            .init_resource::<GoapSettings>()
+            .add_observer(replan_goap_goals)
+            .add_systems(
+                First,
+                register_actionset_goals.run_if(resource_exists_and_changed::<ActionSetStore>)
+            )
+            .add_systems(
+                FixedPostUpdate,
+                plan_goap_goals.after(crate::decision_loop::handle_dispatch_to_user_actions)
+            )
+        ;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action(preconditions: &[GoapFact], effects: &[GoapFact], cost: ActionScore) -> GoapPlannerAction {
+        GoapPlannerAction { preconditions: preconditions.to_vec(), effects: effects.to_vec(), cost }
+    }
+
+    #[test]
+    fn test_plan_key_door_chest() {
+        let actions = [
+            action(&[GoapFact::is("door_open")], &[GoapFact::is("looted")], 1.),
+            action(&[GoapFact::is("has_key")], &[GoapFact::is("door_open")], 1.),
+            action(&[], &[GoapFact::is("has_key")], 2.),
+            // A shortcut that is more expensive than doing it properly.
+            action(&[], &[GoapFact::is("door_open")], 5.),
+        ];
+
+        let goal = [GoapFact::is("looted")];
+        let steps = plan(&GoapState::new(), &goal, &actions, 128).unwrap();
+        assert_eq!(steps, [2, 1, 0]);
+
+        let mut has_key = GoapState::new();
+        has_key.set("has_key", true);
+        assert_eq!(plan(&has_key, &goal, &actions, 128).unwrap(), [1, 0]);
+
+        let mut done = GoapState::new();
+        done.set("looted", true);
+        assert!(plan(&done, &goal, &actions, 128).unwrap().is_empty());
+
+        assert!(plan(&GoapState::new(), &[GoapFact::is("flying")], &actions, 128).is_none());
+    }
+}
@@ -0,0 +1,581 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+If a copy of the MPL was not distributed with this file,
+You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+//! HTN - Hierarchical Task Network planning on top of Utility AI.
+//!
+//! Where GOAP searches for *any* sequence of Actions reaching a goal, HTN follows designer-authored
+//! recipes: a `HtnDomain` is a tree of compound tasks, each with one or more `HtnMethod`s that
+//! decompose it into subtasks. A subtask is either another compound task of the same domain or a
+//! primitive task, which is simply the `action_key` of an ActionTemplate the AI has access to.
+//!
+//! The two planners share the same building blocks:
+//! - Domains are declared in ActionSets (see `ActionSet::htn_domains`) and picked via ActionTemplates
+//!   with an ActionKey of `HtnDomain::<domain name>`, scored by the usual Utility AI decision process.
+//! - Methods and primitive tasks use the same symbolic facts as GOAP (`preconditions` and `effects`,
+//!   read from the AI's Blackboard); a method or primitive is only used if its preconditions hold.
+//! - Out of the applicable methods, the planner tries the one with the highest utility first: the
+//!   method's `priority` times the (adjusted) product of its `considerations`. Methods scoring zero
+//!   are not used at all. Ties go to the method declared first. If a method's subtasks cannot be
+//!   planned, the planner backtracks and tries the next method.
+//! - Plans run through the same ActionTracker chains as GOAP plans; see the `plans` module.
+//!
+//! Method Considerations are scored against the AI's Pawn (or the AI, if it has no Pawn) as the Context.
+//! Response Surfaces and normalization relative to other Contexts are not supported.
+
+use bevy::ecs::system::SystemState;
+use bevy::platform::prelude::{String, ToOwned};
+use bevy::prelude::*;
+
+use crate::actions::{
+    AcceptsActionHandlerRegistrations, ActionHandlerInputs, ActionHandlerKeyToSystemMap,
+    ActionPickCallback,
+};
+use crate::blackboard::Blackboard;
+use crate::considerations::{ConsiderationData, ConsiderationKeyToSystemMap, ensure_consideration_queries};
+use crate::context_fetchers::ContextFetcherKeyToSystemMap;
+use crate::decision_loop::score_considerations;
+use crate::goap::{GOAP_GOAL_ACTION_PREFIX, GoapFact, GoapPlannerAction, GoapState};
+use crate::pawn::Pawn;
+use crate::plans::{
+    AiReplanRequested, PlanKind, PlannedStep, PlansPlugin, first_context, give_up_on_goal,
+    plannable_actionsets, start_plan, take_plan_requests,
+};
+use crate::smart_object::ActionSetStore;
+use crate::state_machines::AvailableActionSets;
+use crate::types::{ActionKey, ActionScore, AiEntity, CraniumKvMap, CraniumList};
+
+#[cfg(feature = "actionset_loader")]
+use serde::{Serialize, Deserialize};
+
+/// ActionKey prefix marking ActionTemplates as requests to plan with the HTN domain of the same name.
+pub const HTN_DOMAIN_ACTION_PREFIX: &str = "HtnDomain::";
+
+#[cfg(feature = "actionset_loader")]
+fn default_method_priority() -> ActionScore {
+    1.
+}
+
+/// One way of decomposing a compound task.
+#[derive(Clone, Debug, Reflect)]
+#[cfg_attr(feature = "actionset_loader", derive(Serialize, Deserialize))]
+pub struct HtnMethod {
+    pub name: String,
+
+    /// Facts that must hold (at this point of the plan) for the method to be used.
+    #[cfg_attr(feature = "actionset_loader", serde(default))]
+    pub preconditions: CraniumList<GoapFact>,
+
+    /// Considerations scoring how appealing the method is, on top of its `priority`.
+    #[cfg_attr(feature = "actionset_loader", serde(default))]
+    pub considerations: CraniumList<ConsiderationData>,
+
+    #[cfg_attr(feature = "actionset_loader", serde(default = "default_method_priority"))]
+    pub priority: ActionScore,
+
+    /// Names of compound tasks of the domain or ActionKeys of primitive tasks, in order.
+    pub subtasks: CraniumList<String>,
+}
+
+impl HtnMethod {
+    pub fn new<IS: Into<String>>(name: IS) -> Self {
+        Self {
+            name: name.into(),
+            preconditions: CraniumList::new(),
+            considerations: CraniumList::new(),
+            priority: 1.,
+            subtasks: CraniumList::new(),
+        }
+    }
+
+    pub fn with_precondition(mut self, fact: GoapFact) -> Self {
+        self.preconditions.push(fact);
+        self
+    }
+
+    pub fn with_consideration(mut self, consideration: ConsiderationData) -> Self {
+        self.considerations.push(consideration);
+        self
+    }
+
+    pub fn with_priority(mut self, priority: ActionScore) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_subtask<IS: Into<String>>(mut self, subtask: IS) -> Self {
+        self.subtasks.push(subtask.into());
+        self
+    }
+}
+
+/// A task decomposed into subtasks by one of its methods.
+#[derive(Clone, Debug, Reflect)]
+#[cfg_attr(feature = "actionset_loader", derive(Serialize, Deserialize))]
+pub struct HtnCompoundTask {
+    pub name: String,
+    pub methods: CraniumList<HtnMethod>,
+}
+
+impl HtnCompoundTask {
+    pub fn new<IS: Into<String>>(name: IS) -> Self {
+        Self { name: name.into(), methods: CraniumList::new() }
+    }
+
+    pub fn with_method(mut self, method: HtnMethod) -> Self {
+        self.methods.push(method);
+        self
+    }
+}
+
+/// A named network of compound tasks, planned starting from the `root` task.
+#[derive(Clone, Debug, Reflect)]
+#[cfg_attr(feature = "actionset_loader", derive(Serialize, Deserialize))]
+pub struct HtnDomain {
+    pub name: String,
+    pub root: String,
+    #[cfg_attr(feature = "actionset_loader", serde(default))]
+    pub compound_tasks: CraniumList<HtnCompoundTask>,
+}
+
+impl HtnDomain {
+    pub fn new<IN: Into<String>, IR: Into<String>>(name: IN, root: IR) -> Self {
+        Self { name: name.into(), root: root.into(), compound_tasks: CraniumList::new() }
+    }
+
+    pub fn with_task(mut self, task: HtnCompoundTask) -> Self {
+        self.compound_tasks.push(task);
+        self
+    }
+
+    pub fn get_task(&self, name: &str) -> Option<&HtnCompoundTask> {
+        self.compound_tasks.iter().find(|task| task.name == name)
+    }
+
+    /// All the subtasks of this domain that are not compound tasks themselves.
+    pub fn primitive_tasks(&self) -> impl Iterator<Item = &str> {
+        self.compound_tasks.iter()
+            .flat_map(|task| task.methods.iter())
+            .flat_map(|method| method.subtasks.iter())
+            .map(|subtask| subtask.as_str())
+            .filter(|subtask| self.get_task(subtask).is_none())
+    }
+}
+
+/// All known HTN domains, by name.
+#[derive(Resource, Debug, Default)]
+pub struct HtnDomainRegistry {
+    pub domains: CraniumKvMap<String, HtnDomain>,
+}
+
+impl HtnDomainRegistry {
+    /// Adds a domain, replacing any previous one under the same name.
+    pub fn register_domain(&mut self, domain: HtnDomain) -> &mut Self {
+        #[cfg(feature = "logging")]
+        if domain.get_task(&domain.root).is_none() {
+            bevy::log::warn!(
+                "HtnDomainRegistry: root task {:?} of HTN domain {:?} is not defined!",
+                &domain.root, &domain.name
+            );
+        }
+
+        self.domains.insert(domain.name.to_owned(), domain);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&HtnDomain> {
+        self.domains.get(name)
+    }
+}
+
+/// Tuning knobs for the HTN planner; see also `PlanSettings` for running the plans.
+#[derive(Resource, Debug, Clone)]
+pub struct HtnSettings {
+    /// How deeply compound tasks may nest; guards against domains that recurse forever.
+    pub max_depth: usize,
+}
+
+impl Default for HtnSettings {
+    fn default() -> Self {
+        Self { max_depth: 32 }
+    }
+}
+
+/// A primitive task the planner may use.
+#[derive(Clone, Debug)]
+pub struct HtnPrimitive {
+    pub action_key: ActionKey,
+    pub action: GoapPlannerAction,
+}
+
+struct HtnDecomposer<'a, F: Fn(&HtnMethod) -> ActionScore> {
+    domain: &'a HtnDomain,
+    primitives: &'a [HtnPrimitive],
+    method_score: F,
+    max_depth: usize,
+}
+
+impl<'a, F: Fn(&HtnMethod) -> ActionScore> HtnDecomposer<'a, F> {
+    /// Plans the first of the pending tasks, then (recursively) the rest of them.
+    ///
+    /// Pending tasks are tagged with how deeply nested they are.
+    fn decompose(&self, pending: &[(&'a str, usize)], state: &GoapState, plan: &mut CraniumList<usize>) -> bool {
+        let Some(((task, depth), rest)) = pending.split_first() else {
+            return true;
+        };
+
+        if let Some(compound) = self.domain.get_task(task) {
+            if *depth >= self.max_depth {
+                #[cfg(feature = "logging")]
+                bevy::log::warn!("HTN: domain {:?} nests deeper than {:?} tasks, giving up.", &self.domain.name, self.max_depth);
+                return false;
+            }
+
+            let mut methods: CraniumList<(ActionScore, &HtnMethod)> = compound.methods.iter()
+                .filter(|method| state.satisfies(&method.preconditions))
+                .map(|method| ((self.method_score)(method), method))
+                .filter(|(score, _)| *score > 0.)
+                .collect();
+            // Stable, so ties keep the declaration order.
+            methods.sort_by(|(left, _), (right, _)| right.total_cmp(left));
+
+            for (_, method) in methods {
+                let mut expanded: CraniumList<(&'a str, usize)> = method.subtasks.iter()
+                    .map(|subtask| (subtask.as_str(), depth + 1))
+                    .collect();
+                expanded.extend_from_slice(rest);
+
+                let checkpoint = plan.len();
+                if self.decompose(&expanded, state, plan) {
+                    return true;
+                }
+                plan.truncate(checkpoint);
+            }
+            return false;
+        }
+
+        let Some(idx) = self.primitives.iter().position(|primitive| primitive.action_key == *task) else {
+            #[cfg(feature = "logging")]
+            bevy::log::debug!("HTN: task {:?} of domain {:?} is not available.", task, &self.domain.name);
+            return false;
+        };
+
+        let action = &self.primitives[idx].action;
+        if !state.satisfies(&action.preconditions) {
+            return false;
+        }
+
+        plan.push(idx);
+        if self.decompose(rest, &state.with_applied(&action.effects), plan) {
+            return true;
+        }
+        plan.pop();
+        false
+    }
+}
+
+/// Decomposes the root task of the domain into primitive tasks (as indices into the slice).
+///
+/// `method_score` rates the methods; see the module docs for how methods get picked.
+/// Returns None if the root task cannot be decomposed in the start state.
+pub fn decompose(
+    domain: &HtnDomain,
+    start: &GoapState,
+    primitives: &[HtnPrimitive],
+    method_score: impl Fn(&HtnMethod) -> ActionScore,
+    max_depth: usize,
+) -> Option<CraniumList<usize>> {
+    let decomposer = HtnDecomposer { domain, primitives, method_score, max_depth };
+    let mut plan = CraniumList::new();
+    decomposer
+        .decompose(&[(domain.root.as_str(), 0)], start, &mut plan)
+        .then_some(plan)
+}
+
+/// Asks the HTN planner to plan with a domain; inserted by domain ActionHandlers.
+#[derive(Component, Debug, Clone)]
+pub struct HtnPlanRequest {
+    /// The ActionKey of the ActionTemplate that picked the domain (`HtnDomain::<domain name>`).
+    pub goal: ActionKey,
+    pub replans: u32,
+}
+
+/// Builds an ActionHandler requesting a plan with the domain named by the `HtnDomain::` ActionKey.
+///
+/// Registered automatically for all `HtnDomain::` ActionKeys in stored ActionSets.
+pub fn domain_handler<IS: Into<String>>(goal: IS) -> ActionPickCallback {
+    let goal: String = goal.into();
+    ActionPickCallback::new(move |inputs: ActionHandlerInputs, mut commands: Commands| {
+        let (ai, _pawn, _context, _tracker) = inputs;
+        if let Ok(mut cmds) = commands.get_entity(ai) {
+            cmds.insert(HtnPlanRequest { goal: goal.clone(), replans: 0 });
+        }
+    })
+}
+
+/// Builds a plan for the AI, if possible. Returns None if there is no plan at all.
+fn build_plan(
+    world: &World,
+    ai: AiEntity,
+    goal_key: &ActionKey,
+    available: &AvailableActionSets,
+    settings: &HtnSettings,
+) -> Option<CraniumList<PlannedStep>> {
+    let domain_name = goal_key.strip_prefix(HTN_DOMAIN_ACTION_PREFIX)?;
+    let domain = world.get_resource::<HtnDomainRegistry>()?.get(domain_name)?;
+    let store = world.get_resource::<ActionSetStore>()?;
+    let cf_map = world.get_resource::<ContextFetcherKeyToSystemMap>()?;
+    let consideration_map = world.get_resource::<ConsiderationKeyToSystemMap>();
+
+    let goal_actionset = store.map_by_name.values().find(|actionset| {
+        actionset.actions.iter().any(|template| template.action_key == *goal_key)
+    })?;
+
+    let pawn = world.get::<Pawn>(ai).and_then(|pawn| pawn.clone().to_entity());
+
+    let mut candidates: CraniumList<PlannedStep> = CraniumList::new();
+    for actionset in plannable_actionsets(world, ai, &goal_actionset.name, available) {
+        for template in actionset.actions.iter() {
+            let key = &template.action_key;
+            if key.starts_with(HTN_DOMAIN_ACTION_PREFIX) || key.starts_with(GOAP_GOAL_ACTION_PREFIX) {
+                continue;
+            }
+            // The first matching template with a Context wins.
+            if candidates.iter().any(|step| step.template.action_key == *key)
+                || !domain.primitive_tasks().any(|task| task == key)
+            {
+                continue;
+            }
+            let Some(context) = first_context(template, cf_map, (ai, pawn), world) else {
+                continue;
+            };
+            candidates.push(PlannedStep { template: template.clone(), context });
+        }
+    }
+
+    let fact_names = domain.compound_tasks.iter()
+        .flat_map(|task| task.methods.iter())
+        .flat_map(|method| method.preconditions.iter())
+        .chain(candidates.iter().flat_map(|step| step.template.preconditions.iter().chain(step.template.effects.iter())))
+        .map(|fact| fact.fact.as_str());
+    let start = GoapState::from_blackboard(world.get::<Blackboard>(ai), fact_names);
+
+    let primitives: CraniumList<HtnPrimitive> = candidates.iter()
+        .map(|step| HtnPrimitive {
+            action_key: step.template.action_key.to_owned(),
+            action: GoapPlannerAction::from_template(&step.template),
+        })
+        .collect();
+
+    let method_score = |method: &HtnMethod| match consideration_map {
+        Some(consideration_map) => {
+            method.priority * score_considerations(&method.considerations, consideration_map, (ai, pawn, pawn.unwrap_or(ai)), world)
+        },
+        None if method.considerations.is_empty() => method.priority,
+        None => 0.,
+    };
+
+    let steps = decompose(domain, &start, &primitives, method_score, settings.max_depth)?;
+
+    Some(steps.into_iter().map(|idx| candidates[idx].clone()).collect())
+}
+
+/// An exclusive System turning `HtnPlanRequest`s into plans.
+pub fn plan_htn_tasks(world: &mut World) {
+    let Some(requests) = take_plan_requests::<HtnPlanRequest>(world, |request| &request.goal) else {
+        return;
+    };
+
+    // Method Considerations need to be initialized before we can run them.
+    ensure_consideration_queries(world);
+
+    let settings = world.get_resource::<HtnSettings>().cloned().unwrap_or_default();
+    let mut available_state: SystemState<AvailableActionSets> = SystemState::new(world);
+
+    let plans: CraniumList<(Entity, HtnPlanRequest, Option<CraniumList<PlannedStep>>)> = {
+        let available = available_state.get(world);
+        requests
+            .into_iter()
+            .map(|(ai, request)| {
+                let steps = build_plan(world, ai, &request.goal, &available, &settings);
+                (ai, request, steps)
+            })
+            .collect()
+    };
+
+    for (ai, request, maybe_steps) in plans {
+        match maybe_steps {
+            Some(steps) => start_plan(world, ai, PlanKind::Htn, request.goal, steps, request.replans),
+            None => {
+                #[cfg(feature = "logging")]
+                bevy::log::debug!("plan_htn_tasks: AI {:?} found no decomposition for {:?}", ai, &request.goal);
+
+                give_up_on_goal(&mut world.commands(), ai, PlanKind::Htn, request.goal);
+                world.flush();
+            }
+        }
+    }
+}
+
+/// An Observer turning HTN replan requests back into `HtnPlanRequest`s.
+pub fn replan_htn_tasks(
+    event: On<AiReplanRequested>,
+    mut commands: Commands,
+) {
+    if event.kind != PlanKind::Htn {
+        return;
+    }
+    if let Ok(mut cmds) = commands.get_entity(event.entity) {
+        cmds.insert(HtnPlanRequest { goal: event.goal.to_owned(), replans: event.replans });
+    }
+}
+
+/// An exclusive System that registers the HTN domains defined in the stored ActionSets, plus
+/// the ActionHandlers for any `HtnDomain::` ActionKeys in them.
+///
+/// ActionSets are processed in order of their names, so if several of them define a domain
+/// with the same name, the first definition wins.
+pub fn register_actionset_htn_domains(world: &mut World) {
+    let mut domains: CraniumList<HtnDomain> = CraniumList::new();
+    let mut domain_keys: CraniumList<ActionKey> = CraniumList::new();
+
+    {
+        let Some(store) = world.get_resource::<ActionSetStore>() else {
+            return;
+        };
+        let registered = world.get_resource::<ActionHandlerKeyToSystemMap>();
+
+        let mut actionsets: CraniumList<_> = store.map_by_name.iter().collect();
+        actionsets.sort_by_key(|(name, _)| *name);
+
+        for (_, actionset) in actionsets {
+            for domain in actionset.htn_domains.iter() {
+                if !domains.iter().any(|known| known.name == domain.name) {
+                    domains.push(domain.clone());
+                }
+            }
+
+            for template in actionset.actions.iter() {
+                if template.action_key.starts_with(HTN_DOMAIN_ACTION_PREFIX)
+                    && !registered.is_some_and(|reg| reg.mapping.contains_key(&template.action_key))
+                {
+                    domain_keys.push(template.action_key.to_owned());
+                }
+            }
+        }
+    }
+
+    {
+        let mut registry = world.get_resource_or_init::<HtnDomainRegistry>();
+        for domain in domains {
+            #[cfg(feature = "logging")]
+            bevy::log::debug!("register_actionset_htn_domains: Registered HTN domain {:?}", &domain.name);
+
+            registry.register_domain(domain);
+        }
+    }
+
+    for key in domain_keys {
+        world.register_action_handler(domain_handler(key.to_owned()), key);
+    }
+}
+
+/// Sets up HTN planning (see the module docs).
+pub struct HtnPlugin;
+
+impl Plugin for HtnPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<PlansPlugin>() {
+            app.add_plugins(PlansPlugin);
+        }
+
+        app
+            .init_resource::<HtnSettings>()
+            .init_resource::<HtnDomainRegistry>()
+            .add_observer(replan_htn_tasks)
+            .add_systems(
+                First,
+                register_actionset_htn_domains.run_if(resource_exists_and_changed::<ActionSetStore>)
+            )
+            .add_systems(
+                FixedPostUpdate,
+                plan_htn_tasks.after(crate::decision_loop::handle_dispatch_to_user_actions)
+            )
+        ;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn primitive(key: &str, preconditions: &[GoapFact], effects: &[GoapFact]) -> HtnPrimitive {
+        HtnPrimitive {
+            action_key: key.into(),
+            action: GoapPlannerAction { preconditions: preconditions.to_vec(), effects: effects.to_vec(), cost: 1. },
+        }
+    }
+
+    #[test]
+    fn test_decompose_with_backtracking_and_scores() {
+        let domain = HtnDomain::new("Guard", "BeGuard")
+            .with_task(HtnCompoundTask::new("BeGuard")
+                .with_method(HtnMethod::new("Fight").with_priority(2.).with_subtask("Arm").with_subtask("Attack"))
+                .with_method(HtnMethod::new("Patrol").with_subtask("Walk"))
+            )
+            .with_task(HtnCompoundTask::new("Arm")
+                .with_method(HtnMethod::new("AlreadyArmed").with_precondition(GoapFact::is("armed")))
+                .with_method(HtnMethod::new("Rearm").with_subtask("DrawSword"))
+            );
+
+        let primitives = [
+            primitive("Walk", &[], &[]),
+            primitive("DrawSword", &[GoapFact::is("has_sword")], &[GoapFact::is("armed")]),
+            primitive("Attack", &[GoapFact::is("armed")], &[]),
+        ];
+        let by_priority = |method: &HtnMethod| method.priority;
+
+        // No sword - fighting fails, so the planner backtracks to patrolling.
+        assert_eq!(decompose(&domain, &GoapState::new(), &primitives, by_priority, 8).unwrap(), [0]);
+
+        let mut has_sword = GoapState::new();
+        has_sword.set("has_sword", true);
+        assert_eq!(decompose(&domain, &has_sword, &primitives, by_priority, 8).unwrap(), [1, 2]);
+
+        let mut armed = GoapState::new();
+        armed.set("armed", true);
+        assert_eq!(decompose(&domain, &armed, &primitives, by_priority, 8).unwrap(), [2]);
+
+        // Methods scoring zero are never used.
+        let no_fighting = |method: &HtnMethod| if method.name == "Fight" { 0. } else { method.priority };
+        assert_eq!(decompose(&domain, &armed, &primitives, no_fighting, 8).unwrap(), [0]);
+
+        // Too shallow to reach the primitives of the fighting branch.
+        let shallow = decompose(&domain, &has_sword, &primitives, by_priority, 1).unwrap();
+        assert_eq!(shallow, [0]);
+    }
+
+    #[test]
+    fn test_actionset_htn_domains_register_in_name_order() {
+        use crate::actionset::ActionSet;
+
+        let mut world = World::new();
+        let mut store = ActionSetStore::default();
+        for (name, root) in [("c", "Sleep"), ("a", "BeGuard"), ("b", "Fight")] {
+            let domain = HtnDomain::new("Guard", root);
+            store.map_by_name.insert(name.into(), ActionSet::new(name, [].into()).with_htn_domains([domain].into()));
+        }
+        world.insert_resource(store);
+
+        for _ in 0..3 {
+            world.run_system_cached(register_actionset_htn_domains).unwrap();
+        }
+
+        // The ActionSets are processed by name, so "a" wins the shared name.
+        let registry = world.resource::<HtnDomainRegistry>();
+        assert_eq!(registry.get("Guard").unwrap().root, "BeGuard");
+    }
+}
@@ -134,3 +134,34 @@ impl core::fmt::Display for ConsiderationIdentifier {
         self.0.fmt(f)
     }
 }
+
+
+#[derive(Reflect, Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(any(feature = "actionset_loader"), derive(Serialize, Deserialize))]
+#[cfg_attr(any(feature = "actionset_loader"), serde(transparent))]
+pub struct SenseIdentifier(String);
+
+
+impl SenseIdentifier {
+    pub fn from_string(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl<IS: Into<String>> From<IS> for SenseIdentifier {
+    fn from(value: IS) -> Self {
+        Self::from_string(value.into())
+    }
+}
+
+impl Borrow<str> for SenseIdentifier {
+    fn borrow(&self) -> &str {
+        self.0.borrow()
+    }
+}
+
+impl core::fmt::Display for SenseIdentifier {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.0.fmt(f)
+    }
+}
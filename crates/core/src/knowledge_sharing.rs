@@ -0,0 +1,416 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+If a copy of the MPL was not distributed with this file,
+You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+//! Knowledge Sharing - group blackboards for factions, squads and the like.
+//!
+//! On their own, AIs only know what they perceived themselves (see `memories` and `senses`).
+//! That is great for stealth, less so for a squad that should converge on the intruder one of
+//! them spotted.
+//!
+//! A Knowledge Group is just an Entity with the `KnowledgeGroup` Component, which keeps the shared
+//! knowledge of the group in its own `Memories`. AIs join a group with the `MemberOf` relationship.
+//! Groups can be members of other groups too, so a squad can belong to a faction.
+//!
+//! Members with the `KnowledgeSharing` Component broadcast whatever they perceive to their group,
+//! after a delay and with a configurable reliability (the chance that any one fact gets through).
+//! Groups with `KnowledgeSharing` forward what they learn to their own group in the same way,
+//! up to `MAX_GROUP_DEPTH` hops away from the original sharer (so cyclic groups cannot loop forever).
+//! The reliability rolls are deterministic (a hash of the fact, not a true RNG), so replays
+//! and networked simulations stay in sync.
+//!
+//! Considerations and ContextFetchers can read personal and group knowledge through the
+//! `Knowledge` SystemParam; a couple of ready-made Considerations are provided as well.
+
+use core::hash::BuildHasher;
+use core::time::Duration;
+
+use bevy::ecs::system::SystemParam;
+use bevy::platform::hash::FixedHasher;
+use bevy::prelude::*;
+
+use crate::considerations::{ConsiderationInputs, ConsiderationOutputs};
+use crate::memories::{Memories, Memory};
+use crate::senses::PerceptionsRecorded;
+use crate::types::{ActionScore, CraniumList};
+
+/// How many levels of nested groups we follow before assuming there is a cycle.
+pub const MAX_GROUP_DEPTH: usize = 8;
+
+/// Makes an Entity (an AI or a group) a member of a Knowledge Group.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+#[relationship(relationship_target = GroupMembers)]
+pub struct MemberOf(pub Entity);
+
+/// All members of a Knowledge Group; maintained automatically based on `MemberOf`.
+#[derive(Component, Debug, Default)]
+#[relationship_target(relationship = MemberOf)]
+pub struct GroupMembers(bevy::platform::prelude::Vec<Entity>);
+
+/// Marks an Entity as a Knowledge Group; the group's shared knowledge is kept in its `Memories`.
+#[derive(Component, Debug, Default, Clone, Copy)]
+#[require(Memories)]
+pub struct KnowledgeGroup;
+
+/// Makes an AI (or a group) share what it knows with its own group.
+#[derive(Component, Debug, Clone)]
+pub struct KnowledgeSharing {
+    /// How long it takes for a fact to reach the group.
+    pub delay: Duration,
+    /// The chance (0.0 to 1.0) that any given fact reaches the group at all.
+    pub reliability: f32,
+    /// Facts the sharer is less confident about than this are not shared.
+    pub min_confidence: ActionScore,
+}
+
+impl Default for KnowledgeSharing {
+    fn default() -> Self {
+        Self {
+            delay: Duration::ZERO,
+            reliability: 1.,
+            min_confidence: 0.,
+        }
+    }
+}
+
+impl KnowledgeSharing {
+    pub fn new(delay: Duration, reliability: f32) -> Self {
+        Self { delay, reliability, ..Default::default() }
+    }
+
+    /// Decides whether a specific fact gets through, deterministically.
+    fn delivers(&self, sharer: Entity, group: Entity, fact: Entity, perceived_at: Duration) -> bool {
+        if self.reliability >= 1. {
+            return true;
+        }
+        let roll = FixedHasher.hash_one((sharer, group, fact, perceived_at)) as f64 / u64::MAX as f64;
+        (roll as f32) < self.reliability
+    }
+}
+
+/// A fact on its way to a Knowledge Group.
+#[derive(Debug, Clone)]
+pub struct SharedFact {
+    pub sharer: Entity,
+    pub group: Entity,
+    pub entity: Entity,
+    pub position: Option<Vec3>,
+    pub confidence: ActionScore,
+    pub perceived_at: Duration,
+    pub deliver_at: Duration,
+    /// How many groups the fact went through before this one; zero if it comes straight from the AI.
+    pub hops: usize,
+}
+
+/// All facts that have been shared, but have not reached their group yet.
+#[derive(Resource, Debug, Default)]
+pub struct PendingSharedFacts {
+    pub facts: CraniumList<SharedFact>,
+}
+
+impl PendingSharedFacts {
+    /// Queues a fact up for delivery from the sharer to its group, subject to its sharing config.
+    ///
+    /// The fact arrives `config.delay` after `sent_at`, i.e. delays add up as facts get forwarded from
+    /// group to group; `perceived_at` is kept as-is for the Memories of the groups.
+    ///
+    /// `hops` is the number of groups the fact already went through; facts that would exceed
+    /// `MAX_GROUP_DEPTH` are dropped.
+    pub fn share(
+        &mut self,
+        sharer: Entity,
+        group: Entity,
+        config: &KnowledgeSharing,
+        (entity, position, confidence, perceived_at): (Entity, Option<Vec3>, ActionScore, Duration),
+        sent_at: Duration,
+        hops: usize,
+    ) {
+        // Nobody needs to be told about themselves.
+        if entity == group || confidence < config.min_confidence {
+            return;
+        }
+
+        if hops >= MAX_GROUP_DEPTH {
+            #[cfg(feature = "logging")]
+            bevy::log::debug!("Knowledge about {:?} from {:?} went through too many groups, not forwarding it", entity, sharer);
+            return;
+        }
+
+        if !config.delivers(sharer, group, entity, perceived_at) {
+            #[cfg(feature = "logging")]
+            bevy::log::debug!("Knowledge about {:?} from {:?} got lost on the way to group {:?}", entity, sharer, group);
+            return;
+        }
+
+        self.facts.push(SharedFact {
+            sharer,
+            group,
+            entity,
+            position,
+            confidence,
+            perceived_at,
+            deliver_at: sent_at + config.delay,
+            hops,
+        });
+    }
+}
+
+/// An Observer that queues up whatever a sharing AI perceived for delivery to its group.
+pub fn share_perceptions(
+    event: On<PerceptionsRecorded>,
+    sharers: Query<(&KnowledgeSharing, &MemberOf)>,
+    mut pending: ResMut<PendingSharedFacts>,
+) {
+    let Ok((config, group)) = sharers.get(event.entity) else {
+        return;
+    };
+
+    for perception in event.perceptions.iter() {
+        pending.share(
+            event.entity,
+            group.0,
+            config,
+            (perception.entity, perception.position, perception.confidence, event.perceived_at),
+            event.perceived_at,
+            0,
+        );
+    }
+}
+
+/// A System that delivers due shared facts to their groups (and forwards them further up, if configured).
+pub fn deliver_shared_facts(
+    time: Option<Res<Time<Virtual>>>,
+    mut pending: ResMut<PendingSharedFacts>,
+    mut groups: Query<&mut Memories, With<KnowledgeGroup>>,
+    forwarders: Query<(&KnowledgeSharing, &MemberOf), With<KnowledgeGroup>>,
+) {
+    let Some(now) = time.map(|time| time.elapsed()) else {
+        return;
+    };
+    if pending.facts.is_empty() {
+        return;
+    }
+
+    let (due, waiting): (CraniumList<SharedFact>, CraniumList<SharedFact>) = core::mem::take(&mut pending.facts)
+        .into_iter()
+        .partition(|fact| fact.deliver_at <= now)
+    ;
+    pending.facts = waiting;
+
+    for fact in due {
+        let Ok(mut memories) = groups.get_mut(fact.group) else {
+            continue;
+        };
+        memories.perceive(fact.entity, fact.position, fact.confidence, fact.perceived_at);
+
+        if let Ok((config, parent)) = forwarders.get(fact.group) {
+            pending.share(
+                fact.group,
+                parent.0,
+                config,
+                (fact.entity, fact.position, fact.confidence, fact.perceived_at),
+                now,
+                fact.hops + 1,
+            );
+        }
+    }
+}
+
+/// Where to look for knowledge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect)]
+pub enum KnowledgeScope {
+    /// Only what the AI itself remembers.
+    Personal,
+    /// Only what the AI's groups (direct and indirect) remember.
+    Group,
+    /// Both of the above.
+    Any,
+}
+
+/// A SystemParam for reading personal and group knowledge, e.g. in Considerations.
+#[derive(SystemParam)]
+pub struct Knowledge<'w, 's> {
+    memories: Query<'w, 's, &'static Memories>,
+    memberships: Query<'w, 's, &'static MemberOf>,
+}
+
+impl Knowledge<'_, '_> {
+    /// What the AI itself remembers, if it has Memories.
+    pub fn personal(&self, ai: Entity) -> Option<&Memories> {
+        self.memories.get(ai).ok()
+    }
+
+    /// The groups the AI belongs to, innermost first (e.g. squad, then faction).
+    pub fn groups(&self, ai: Entity) -> CraniumList<Entity> {
+        let mut groups = CraniumList::new();
+        let mut current = ai;
+
+        while let Ok(group) = self.memberships.get(current) {
+            if groups.len() >= MAX_GROUP_DEPTH || groups.contains(&group.0) {
+                break;
+            }
+            groups.push(group.0);
+            current = group.0;
+        }
+        groups
+    }
+
+    /// The most confident Memory about an Entity within the specified scope.
+    pub fn recall(&self, ai: Entity, entity: Entity, scope: KnowledgeScope) -> Option<&Memory> {
+        let personal = match scope {
+            KnowledgeScope::Group => None,
+            _ => self.personal(ai).and_then(|memories| memories.recall(entity)),
+        };
+
+        let group = match scope {
+            KnowledgeScope::Personal => None,
+            _ => self.groups(ai)
+                .into_iter()
+                .filter_map(|group| self.memories.get(group).ok())
+                .filter_map(|memories| memories.recall(entity))
+                .max_by(|a, b| a.confidence.total_cmp(&b.confidence)),
+        };
+
+        [personal, group]
+            .into_iter()
+            .flatten()
+            .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+    }
+
+    /// How confident the AI can be about an Entity using knowledge in the specified scope.
+    pub fn confidence(&self, ai: Entity, entity: Entity, scope: KnowledgeScope) -> ActionScore {
+        self.recall(ai, entity, scope).map(|memory| memory.confidence).unwrap_or(0.)
+    }
+}
+
+/// A ready-made Consideration that returns how confident the AI's groups are about the Context (0.0 to 1.0).
+pub fn group_memory_confidence(
+    inputs: ConsiderationInputs,
+    knowledge: Knowledge,
+) -> ConsiderationOutputs {
+    let (ai, _pawn, context) = inputs.0;
+    Some(knowledge.confidence(ai, context, KnowledgeScope::Group))
+}
+
+/// A ready-made Consideration that returns how confident the AI is about the Context (0.0 to 1.0),
+/// based on both its own and its groups' knowledge.
+pub fn known_confidence(
+    inputs: ConsiderationInputs,
+    knowledge: Knowledge,
+) -> ConsiderationOutputs {
+    let (ai, _pawn, context) = inputs.0;
+    Some(knowledge.confidence(ai, context, KnowledgeScope::Any))
+}
+
+/// Sets up knowledge sharing between AIs and their Knowledge Groups.
+pub struct KnowledgeSharingPlugin;
+
+impl Plugin for KnowledgeSharingPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .init_resource::<PendingSharedFacts>()
+        .add_observer(share_perceptions)
+        .add_systems(PreUpdate, deliver_shared_facts.after(crate::senses::update_senses))
+        ;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::senses::Perception;
+
+    #[test]
+    fn test_sharing_up_the_chain() {
+        let mut app = App::new();
+        app.add_plugins((bevy::time::TimePlugin, KnowledgeSharingPlugin));
+        app.insert_resource(bevy::time::TimeUpdateStrategy::ManualDuration(Duration::from_millis(250)));
+
+        let faction = app.world_mut().spawn(KnowledgeGroup).id();
+        let squad = app.world_mut().spawn((KnowledgeGroup, MemberOf(faction), KnowledgeSharing::new(Duration::from_secs(2), 1.))).id();
+        let scout = app.world_mut().spawn((MemberOf(squad), KnowledgeSharing::new(Duration::from_secs(1), 1.))).id();
+        let loner = app.world_mut().spawn(MemberOf(squad)).id();
+        let intruder = app.world_mut().spawn_empty().id();
+
+        let perceive = |app: &mut App, ai: Entity| {
+            app.world_mut().trigger(PerceptionsRecorded {
+                entity: ai,
+                perceptions: [Perception { entity: intruder, position: Some(Vec3::ONE), confidence: 0.8, stimulus_priority: None }].into(),
+                perceived_at: Duration::ZERO,
+            });
+        };
+
+        // The loner does not share at all.
+        perceive(&mut app, loner);
+        assert!(app.world().resource::<PendingSharedFacts>().facts.is_empty());
+
+        perceive(&mut app, scout);
+
+        // Delivered to the squad after the scout's delay, then to the faction after the squad's delay on top.
+        let (mut squad_knows_at, mut faction_knows_at) = (None, None);
+        for _ in 0..20 {
+            app.update();
+            let now = app.world().resource::<Time<Virtual>>().elapsed();
+            if squad_knows_at.is_none() && app.world().get::<Memories>(squad).unwrap().knows(intruder) {
+                squad_knows_at = Some(now);
+            }
+            if faction_knows_at.is_none() && app.world().get::<Memories>(faction).unwrap().knows(intruder) {
+                faction_knows_at = Some(now);
+            }
+        }
+        assert_eq!(squad_knows_at, Some(Duration::from_secs(1)));
+        assert_eq!(faction_knows_at, Some(Duration::from_secs(3)));
+        // The groups remember when the fact was perceived, not when it arrived.
+        assert_eq!(app.world().get::<Memories>(faction).unwrap().recall(intruder).unwrap().last_seen, Duration::ZERO);
+
+        let mut state = bevy::ecs::system::SystemState::<Knowledge>::new(app.world_mut());
+        let knowledge = state.get(app.world());
+        assert_eq!(knowledge.groups(loner), [squad, faction].into_iter().collect::<CraniumList<_>>());
+        assert_eq!(knowledge.confidence(loner, intruder, KnowledgeScope::Personal), 0.);
+        assert!((knowledge.confidence(loner, intruder, KnowledgeScope::Any) - 0.8).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_cyclic_groups_stop_forwarding() {
+        let mut app = App::new();
+        app.add_plugins((bevy::time::TimePlugin, KnowledgeSharingPlugin));
+        app.insert_resource(bevy::time::TimeUpdateStrategy::ManualDuration(Duration::from_millis(250)));
+
+        let world = app.world_mut();
+        let red = world.spawn((KnowledgeGroup, KnowledgeSharing::default())).id();
+        let blue = world.spawn((KnowledgeGroup, KnowledgeSharing::default(), MemberOf(red))).id();
+        world.entity_mut(red).insert(MemberOf(blue));
+        let scout = world.spawn((MemberOf(red), KnowledgeSharing::default())).id();
+        let intruder = world.spawn_empty().id();
+
+        world.trigger(PerceptionsRecorded {
+            entity: scout,
+            perceptions: [Perception { entity: intruder, position: None, confidence: 0.8, stimulus_priority: None }].into(),
+            perceived_at: Duration::ZERO,
+        });
+
+        for _ in 0..(MAX_GROUP_DEPTH * 2) {
+            app.update();
+        }
+
+        let world = app.world();
+        assert!(world.resource::<PendingSharedFacts>().facts.is_empty());
+        assert!(world.get::<Memories>(red).unwrap().knows(intruder));
+        assert!(world.get::<Memories>(blue).unwrap().knows(intruder));
+    }
+
+    #[test]
+    fn test_reliability_rolls() {
+        let config = KnowledgeSharing::new(Duration::ZERO, 0.5);
+        let (sharer, group) = (Entity::from_raw_u32(1).unwrap(), Entity::from_raw_u32(2).unwrap());
+
+        let delivered = (0..1000u64)
+            .filter(|idx| config.delivers(sharer, group, sharer, Duration::from_millis(*idx)))
+            .count()
+        ;
+        assert!((400..600).contains(&delivered), "{}", delivered);
+    }
+}
@@ -0,0 +1,350 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+If a copy of the MPL was not distributed with this file,
+You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+//! AI Layers - several AIs driving the same Pawn at the same time.
+//!
+//! A character often needs separate brains for locomotion, combat and speech, all running at once.
+//! Each of those is just a regular AI with the same `Pawn`, tagged with an `AiLayer`; each decides
+//! on its own and keeps its own ActionTracker.
+//!
+//! Layers share the Pawn's body, though. ActionTemplates declare the Pawn resources they need
+//! exclusive use of (see `ActionTemplate::resources`, e.g. `"legs"`, `"hands"` or `"voice"`); once
+//! a layer picks an Action, the Action's resources are locked for it on the Pawn (see
+//! `ResourceLocks`) until the Action ends or the layer picks something else.
+//!
+//! Conflicts are resolved by the Pawn's `ArbitrationPolicy`:
+//! - `Priority` (the default) - a layer can take resources held by a layer with a lower priority,
+//!   which Cancels the other layer's Action and triggers a `LayerResourcePreempted` event for it.
+//!   Layers never take resources from layers of the same or higher priority.
+//! - `FirstCome` - resources stay with the layer that locked them first until its Action ends.
+//!
+//! The decision engine skips Actions whose resources the AI could not get, so blocked layers fall
+//! back to their best Action that does not need them. AIs without an `AiLayer` are not arbitrated.
+
+use bevy::ecs::system::SystemParam;
+use bevy::platform::prelude::{String, ToOwned};
+use bevy::prelude::*;
+
+use crate::action_runtime::Tracks;
+use crate::action_state::{AiActionStateChange, AiActionStateChangeRequest, ActionState};
+use crate::actions::ActionTemplate;
+use crate::events::AiActionPicked;
+use crate::pawn::Pawn;
+use crate::smart_object::{ActionSetStore, SmartObjects};
+use crate::types::{ActionKey, AiEntity, CraniumKvMap, CraniumList, PawnEntity};
+
+/// The conventional resource name for Actions that move the Pawn around.
+pub const RESOURCE_LEGS: &str = "legs";
+/// The conventional resource name for Actions that use the Pawn's hands (attacking, using items...).
+pub const RESOURCE_HANDS: &str = "hands";
+/// The conventional resource name for Actions that make the Pawn speak.
+pub const RESOURCE_VOICE: &str = "voice";
+
+/// Tags an AI as one of several layers driving the same Pawn.
+#[derive(Component, Clone, Debug, Reflect)]
+pub struct AiLayer {
+    /// A name for the layer (e.g. "Locomotion"); for debugging and your own bookkeeping only.
+    pub name: String,
+    /// Layers with a higher priority win resource conflicts (under `ArbitrationPolicy::Priority`).
+    pub priority: i32,
+}
+
+impl AiLayer {
+    pub fn new<IS: Into<String>>(name: IS, priority: i32) -> Self {
+        Self { name: name.into(), priority }
+    }
+}
+
+/// How conflicts between the layers of a Pawn are resolved (see the module docs).
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq, Reflect)]
+pub enum ArbitrationPolicy {
+    #[default]
+    Priority,
+    FirstCome,
+}
+
+impl ArbitrationPolicy {
+    /// True if a layer with the specified priority may take a resource from its current holder.
+    pub fn allows_takeover(&self, priority: i32, holder: &ResourceLock) -> bool {
+        match self {
+            Self::Priority => priority > holder.priority,
+            Self::FirstCome => false,
+        }
+    }
+}
+
+/// A Pawn resource held by one of its layers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResourceLock {
+    pub ai: AiEntity,
+    pub action_key: ActionKey,
+    pub priority: i32,
+}
+
+/// The resources of a Pawn currently held by its layers.
+#[derive(Component, Clone, Debug, Default)]
+pub struct ResourceLocks {
+    locks: CraniumKvMap<String, ResourceLock>,
+}
+
+impl ResourceLocks {
+    pub fn holder(&self, resource: &str) -> Option<&ResourceLock> {
+        self.locks.get(resource)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &ResourceLock)> {
+        self.locks.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.locks.is_empty()
+    }
+
+    /// Releases all resources held by the AI.
+    pub fn release_all(&mut self, ai: AiEntity) {
+        self.locks.retain(|_, lock| lock.ai != ai);
+    }
+
+    /// Releases the resources held by the AI for the Action.
+    pub fn release(&mut self, ai: AiEntity, action_key: &str) {
+        self.locks.retain(|_, lock| lock.ai != ai || lock.action_key != action_key);
+    }
+}
+
+/// Signals that a layer's Action was Cancelled because a higher-priority layer took one of its resources.
+#[derive(EntityEvent, Debug, Clone)]
+pub struct LayerResourcePreempted {
+    /// The layer AI that lost the resource.
+    pub entity: AiEntity,
+    /// The layer AI that took the resource.
+    pub by: AiEntity,
+    pub resource: String,
+    /// The Action that got Cancelled.
+    pub action_key: ActionKey,
+}
+
+/// A SystemParam checking whether layer AIs can get the resources their Actions need.
+#[derive(SystemParam)]
+pub struct LayerArbitration<'w, 's> {
+    layers: Query<'w, 's, (&'static AiLayer, &'static Pawn)>,
+    pawns: Query<'w, 's, (&'static ResourceLocks, Option<&'static ArbitrationPolicy>)>,
+}
+
+impl LayerArbitration<'_, '_> {
+    /// True if the AI could lock all of the resources right now (always true for AIs without an `AiLayer`).
+    pub fn can_claim(&self, ai: AiEntity, resources: &[String]) -> bool {
+        if resources.is_empty() {
+            return true;
+        }
+        let Ok((layer, pawn)) = self.layers.get(ai) else {
+            return true;
+        };
+        let Some(Ok((locks, policy))) = pawn.as_entity().map(|pawn| self.pawns.get(*pawn)) else {
+            return true;
+        };
+        let policy = policy.copied().unwrap_or_default();
+
+        resources.iter().all(|resource| match locks.holder(resource) {
+            None => true,
+            Some(holder) => holder.ai == ai || policy.allows_takeover(layer.priority, holder),
+        })
+    }
+
+    /// True if the AI could lock all of the resources the ActionTemplate needs right now.
+    pub fn can_claim_template(&self, ai: AiEntity, template: &ActionTemplate) -> bool {
+        self.can_claim(ai, &template.resources)
+    }
+}
+
+/// Locks the resources on the layer AI's Pawn for its newly picked Action, releasing whatever the AI
+/// held for its previous one and taking resources from lower-priority layers as needed.
+///
+/// If some resource cannot be taken, nothing is locked and the Action is Cancelled instead.
+pub fn claim_layer_resources(
+    world: &mut World,
+    ai: AiEntity,
+    pawn: PawnEntity,
+    action_key: ActionKey,
+    resources: CraniumList<String>,
+) {
+    let Some(priority) = world.get::<AiLayer>(ai).map(|layer| layer.priority) else {
+        return;
+    };
+    let policy = world.get::<ArbitrationPolicy>(pawn).copied().unwrap_or_default();
+
+    let preempted = {
+        let Ok(mut pawn_entity) = world.get_entity_mut(pawn) else {
+            return;
+        };
+        if !pawn_entity.contains::<ResourceLocks>() {
+            pawn_entity.insert(ResourceLocks::default());
+        }
+        let mut locks = pawn_entity.get_mut::<ResourceLocks>().unwrap();
+        locks.release_all(ai);
+
+        let mut preempted: CraniumList<(String, ResourceLock)> = CraniumList::new();
+        let mut blocked = false;
+        for resource in resources.iter() {
+            let Some(holder) = locks.holder(resource) else {
+                continue;
+            };
+            if !policy.allows_takeover(priority, holder) {
+                blocked = true;
+                break;
+            }
+            if !preempted.iter().any(|(_, lock)| lock.ai == holder.ai) {
+                preempted.push((resource.to_owned(), holder.clone()));
+            }
+        }
+
+        if blocked {
+            None
+        } else {
+            for (_, lock) in preempted.iter() {
+                locks.release_all(lock.ai);
+            }
+            for resource in resources {
+                locks.locks.insert(resource, ResourceLock { ai, action_key: action_key.to_owned(), priority });
+            }
+            Some(preempted)
+        }
+    };
+
+    let Some(preempted) = preempted else {
+        #[cfg(feature = "logging")]
+        bevy::log::debug!(
+            "claim_layer_resources: AI {:?} - some resources of {:?} are held by other layers, Cancelling it.",
+            ai, &action_key
+        );
+
+        world.write_message(AiActionStateChangeRequest { entity: ai, action: action_key, to_state: ActionState::Cancelled });
+        return;
+    };
+
+    for (resource, lock) in preempted {
+        #[cfg(feature = "logging")]
+        bevy::log::debug!(
+            "claim_layer_resources: AI {:?} took resource {:?} from AI {:?}, Cancelling {:?}.",
+            ai, &resource, lock.ai, &lock.action_key
+        );
+
+        world.write_message(AiActionStateChangeRequest {
+            entity: lock.ai,
+            action: lock.action_key.to_owned(),
+            to_state: ActionState::Cancelled,
+        });
+        world.trigger(LayerResourcePreempted { entity: lock.ai, by: ai, resource, action_key: lock.action_key });
+    }
+}
+
+/// An Observer locking the resources of Actions picked by layer AIs.
+pub fn lock_picked_resources(
+    event: On<AiActionPicked>,
+    store: Option<Res<ActionSetStore>>,
+    query: Query<(&Pawn, Option<&SmartObjects>), With<AiLayer>>,
+    mut commands: Commands,
+) {
+    let ai = event.entity;
+    let Ok((pawn, smart_objects)) = query.get(ai) else {
+        return;
+    };
+    let Some(pawn) = pawn.as_entity().copied() else {
+        return;
+    };
+
+    let resources = store
+        .as_ref()
+        .zip(smart_objects)
+        .and_then(|(store, smart_objects)| store.find_template(smart_objects, &event.action_key, &event.action_name))
+        .map(|template| template.resources.to_owned())
+        .unwrap_or_default();
+
+    let action_key = event.action_key.to_owned();
+    commands.queue(move |world: &mut World| {
+        claim_layer_resources(world, ai, pawn, action_key, resources);
+    });
+}
+
+/// An Observer releasing the resources held for Actions that ended.
+pub fn release_finished_resources(
+    event: On<AiActionStateChange>,
+    owner_query: Query<&Tracks>,
+    pawn_query: Query<&Pawn, With<AiLayer>>,
+    mut locks_query: Query<&mut ResourceLocks>,
+) {
+    if !event.to_state.is_terminal() {
+        return;
+    }
+    let ai = owner_query.get(event.entity).map(|owner| owner.0).unwrap_or(event.entity);
+    let Some(pawn) = pawn_query.get(ai).ok().and_then(|pawn| pawn.as_entity().copied()) else {
+        return;
+    };
+    if let Ok(mut locks) = locks_query.get_mut(pawn) {
+        locks.release(ai, &event.action);
+    }
+}
+
+/// Sets up resource arbitration between the AI layers of a Pawn (see the module docs).
+pub struct LayersPlugin;
+
+impl Plugin for LayersPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_observer(lock_picked_resources)
+            .add_observer(release_finished_resources)
+        ;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bevy::platform::prelude::vec;
+    use super::*;
+
+    fn can_claim(world: &mut World, ai: AiEntity, resource: &str) -> bool {
+        let resources: CraniumList<String> = vec![resource.into()];
+        world.run_system_cached_with(
+            |In((ai, resources)): In<(AiEntity, CraniumList<String>)>, arbitration: LayerArbitration| {
+                arbitration.can_claim(ai, &resources)
+            },
+            (ai, resources),
+        ).unwrap()
+    }
+
+    #[test]
+    fn test_priority_arbitration() {
+        let mut world = World::new();
+        world.init_resource::<Messages<AiActionStateChangeRequest>>();
+        let pawn = world.spawn_empty().id();
+        let locomotion = world.spawn((AiLayer::new("Locomotion", 0), Pawn::new_populated(pawn))).id();
+        let combat = world.spawn((AiLayer::new("Combat", 1), Pawn::new_populated(pawn))).id();
+
+        claim_layer_resources(&mut world, locomotion, pawn, "Wander".into(), vec![RESOURCE_LEGS.into()]);
+        assert!(can_claim(&mut world, locomotion, RESOURCE_LEGS));
+        assert!(can_claim(&mut world, combat, RESOURCE_LEGS));
+
+        claim_layer_resources(
+            &mut world, combat, pawn, "Charge".into(), vec![RESOURCE_LEGS.into(), RESOURCE_HANDS.into()],
+        );
+        assert!(!can_claim(&mut world, locomotion, RESOURCE_LEGS));
+        assert!(can_claim(&mut world, locomotion, RESOURCE_VOICE));
+
+        let locks = world.get::<ResourceLocks>(pawn).unwrap();
+        assert_eq!(locks.holder(RESOURCE_LEGS).map(|lock| lock.ai), Some(combat));
+        assert_eq!(locks.holder(RESOURCE_HANDS).map(|lock| lock.ai), Some(combat));
+
+        // The preempted layer gets its Action Cancelled.
+        let requests = world.resource::<Messages<AiActionStateChangeRequest>>();
+        let cancelled: CraniumList<&AiActionStateChangeRequest> = requests.iter_current_update_messages().collect();
+        assert_eq!(cancelled.len(), 1);
+        assert_eq!((cancelled[0].entity, cancelled[0].action.as_str()), (locomotion, "Wander"));
+
+        world.entity_mut(pawn).insert(ArbitrationPolicy::FirstCome);
+        world.get_mut::<AiLayer>(locomotion).unwrap().priority = 5;
+        assert!(!can_claim(&mut world, locomotion, RESOURCE_HANDS));
+    }
+}
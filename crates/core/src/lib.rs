@@ -7,22 +7,41 @@ You can obtain one at https://mozilla.org/MPL/2.0/.
 
 pub mod ai;
 pub mod actions;
+pub mod behavior_trees;
+pub mod blackboard;
 pub mod actionset;
+pub mod action_queue;
 pub mod action_runtime;
 pub mod action_state;
+pub mod action_timing;
 pub mod considerations;
 pub mod context_fetchers;
+pub mod crowds;
 pub mod curves;
+pub mod curve_analysis;
 // pub mod brain;
 pub mod decision_loop;
+pub mod dynamic_bounds;
 pub mod errors;
 pub mod entity_identifier;
 pub mod events;
+pub mod goals;
+pub mod goap;
+pub mod htn;
 pub mod identifiers;
+pub mod knowledge_sharing;
+pub mod layers;
 pub mod lods;
-// pub mod memories;
+pub mod lod_driver;
+pub mod normalization;
+pub mod orders;
+pub mod memories;
 pub mod pawn;
-// pub mod senses;
+pub mod plans;
+pub mod response_surfaces;
+pub mod senses;
 pub mod smart_object;
+pub mod state_machines;
+pub mod stimuli;
 mod thread_safe_wrapper;
 pub mod types;
@@ -0,0 +1,275 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+If a copy of the MPL was not distributed with this file,
+You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+//! A built-in LOD driver - assigns AI LODs based on the distance to the nearest 'observer'.
+//!
+//! The `lods` module provides the LOD levels and their integration into the decision loop,
+//! but something still has to decide what LOD each AI should be on. For a lot of apps, the
+//! answer is 'whatever is close to the player/camera gets full detail, whatever is far away
+//! gets less or none', which is exactly what this module does.
+//!
+//! To use it:
+//! 1) Add the `LodDriverPlugin` (generic over how positions are read; `GlobalTransform` by default).
+//! 2) Mark your player(s)/camera(s) with the `LodObserver` Component.
+//! 3) Optionally, tweak the distances with the `LodDistanceBands` Resource.
+//!
+//! Every AI then gets an LOD based on the distance from its Pawn (or the AI entity itself, if it has
+//! no Pawn) to the nearest observer. AIs marked with `ManualLod` are left alone, so you can still
+//! drive special cases (bosses, scripted scenes...) yourself.
+//!
+//! The bands use hysteresis, so an AI hovering around a band edge will not flip-flop between
+//! two LODs every frame - it has to move `hysteresis` units past an edge to drop to a lower LOD.
+//!
+//! Whenever an AI's LOD changes, an `AiLodChanged` EntityEvent is triggered for the AI.
+
+use core::marker::PhantomData;
+
+use bevy::prelude::*;
+
+use crate::ai::AIController;
+use crate::lods::{AiLevelOfDetail, AiLevelOfDetailValue, LOD_ELEVATED, LOD_INACTIVE, LOD_MINIMAL, LOD_NORMAL};
+use crate::pawn::Pawn;
+use crate::types::AiLodLevelPrimitive;
+
+/// Something that can tell the LOD driver where an Entity is.
+///
+/// Implemented for `GlobalTransform` and `Transform` out of the box; implement it for your own
+/// Component if your positions live elsewhere (e.g. a 2D grid position in a roguelike).
+pub trait LodPosition: Component {
+    fn lod_position(&self) -> Vec3;
+}
+
+impl LodPosition for GlobalTransform {
+    fn lod_position(&self) -> Vec3 {
+        self.translation()
+    }
+}
+
+impl LodPosition for Transform {
+    fn lod_position(&self) -> Vec3 {
+        self.translation
+    }
+}
+
+/// Marks an Entity as an LOD 'observer' (e.g. a player or a camera); AIs near observers get higher detail.
+#[derive(Component, Default, Debug, Clone, Copy)]
+pub struct LodObserver;
+
+/// Marks an AI as having its LOD managed manually; the LOD driver will not touch it.
+#[derive(Component, Default, Debug, Clone, Copy)]
+pub struct ManualLod;
+
+/// Signals that an AI's LOD has changed from one level to another.
+///
+/// The `from_lod` is None if the AI did not have an `AiLevelOfDetail` Component before.
+#[derive(EntityEvent, Debug, Clone)]
+pub struct AiLodChanged {
+    pub entity: Entity,
+    pub from_lod: Option<AiLevelOfDetailValue>,
+    pub to_lod: AiLevelOfDetailValue,
+}
+
+/// The distances (from the nearest observer) at which the LOD driver switches LODs.
+///
+/// An AI within `elevated` gets `LOD_ELEVATED`, within `normal` gets `LOD_NORMAL`, within `minimal`
+/// gets `LOD_MINIMAL`, and anything further out is `LOD_INACTIVE`. Set a band to zero to skip it
+/// (e.g. the default skips `LOD_ELEVATED` entirely, as that is usually for special situations only).
+#[derive(Resource, Debug, Clone, Reflect)]
+pub struct LodDistanceBands {
+    pub elevated: f32,
+    pub normal: f32,
+    pub minimal: f32,
+
+    /// How far past a band edge an AI has to move before it drops to a lower LOD.
+    /// Going to a *higher* LOD happens as soon as the AI crosses the edge.
+    pub hysteresis: f32,
+}
+
+impl Default for LodDistanceBands {
+    fn default() -> Self {
+        Self {
+            elevated: 0.,
+            normal: 50.,
+            minimal: 150.,
+            hysteresis: 5.,
+        }
+    }
+}
+
+impl LodDistanceBands {
+    /// The LOD for a distance, ignoring hysteresis.
+    fn band_for_distance(&self, distance: f32) -> AiLodLevelPrimitive {
+        if distance < self.elevated {
+            LOD_ELEVATED
+        } else if distance < self.normal {
+            LOD_NORMAL
+        } else if distance < self.minimal {
+            LOD_MINIMAL
+        } else {
+            LOD_INACTIVE
+        }
+    }
+
+    /// The LOD for an AI at a given distance from the nearest observer, given its current LOD.
+    pub fn lod_for_distance(&self, distance: f32, current: Option<AiLevelOfDetailValue>) -> AiLevelOfDetailValue {
+        let target = self.band_for_distance(distance);
+
+        let level = match current.map(|lod| lod.to_primitive()) {
+            // Dropping detail - only do that if we'd still drop it when 'hysteresis' units closer.
+            Some(current) if target > current => self.band_for_distance(distance - self.hysteresis).max(current),
+            _ => target,
+        };
+
+        AiLevelOfDetailValue::new(level)
+    }
+}
+
+/// The AIs the LOD driver is responsible for, i.e. any AI not opted out via `ManualLod`.
+type DrivenAiQuery<'w, 's> = Query<
+    'w, 's,
+    (Entity, Option<&'static Pawn>, Option<&'static mut AiLevelOfDetail>),
+    (With<AIController>, Without<ManualLod>),
+>;
+
+/// A System that assigns LODs to AIs based on their distance to the nearest `LodObserver`.
+///
+/// If there are no observers at all, nothing is changed - otherwise a missing player
+/// (e.g. in a loading screen or between respawns) would deactivate every AI in the world.
+pub fn drive_lods_by_distance<P: LodPosition>(
+    bands: Res<LodDistanceBands>,
+    observers: Query<&P, With<LodObserver>>,
+    positions: Query<&P>,
+    mut ais: DrivenAiQuery,
+    mut commands: Commands,
+) {
+    let observer_positions: bevy::platform::prelude::Vec<Vec3> = observers.iter().map(|pos| pos.lod_position()).collect();
+    if observer_positions.is_empty() {
+        return;
+    }
+
+    for (ai, maybe_pawn, maybe_lod) in ais.iter_mut() {
+        let positioned_entity = maybe_pawn.and_then(|pawn| pawn.as_entity().copied()).unwrap_or(ai);
+        let Ok(position) = positions.get(positioned_entity) else {
+            continue;
+        };
+        let position = position.lod_position();
+
+        let distance = observer_positions
+            .iter()
+            .map(|observer| observer.distance(position))
+            .fold(f32::INFINITY, f32::min)
+        ;
+
+        let current = maybe_lod.as_ref().map(|lod| lod.get_current_lod());
+        let new_lod = bands.lod_for_distance(distance, current);
+
+        if current.map(|lod| lod == new_lod).unwrap_or(false) {
+            continue;
+        }
+
+        match maybe_lod {
+            Some(mut lod) => lod.set_lod(new_lod),
+            None => { commands.entity(ai).insert(AiLevelOfDetail::new(new_lod)); },
+        }
+
+        #[cfg(feature = "logging")]
+        bevy::log::debug!("drive_lods_by_distance: AI {:?} LOD changed {:?} -> {:?}", ai, current, new_lod);
+
+        commands.trigger(AiLodChanged {
+            entity: ai,
+            from_lod: current,
+            to_lod: new_lod,
+        });
+    }
+}
+
+/// Sets up automatic, distance-based LOD assignment for AIs (see the module docs).
+///
+/// The type parameter is the Component positions are read from; `GlobalTransform` by default.
+pub struct LodDriverPlugin<P: LodPosition = GlobalTransform> {
+    pub bands: LodDistanceBands,
+    _position: PhantomData<P>,
+}
+
+impl<P: LodPosition> LodDriverPlugin<P> {
+    pub fn new(bands: LodDistanceBands) -> Self {
+        Self { bands, _position: PhantomData }
+    }
+}
+
+impl<P: LodPosition> Default for LodDriverPlugin<P> {
+    fn default() -> Self {
+        Self::new(LodDistanceBands::default())
+    }
+}
+
+impl<P: LodPosition> Plugin for LodDriverPlugin<P> {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(self.bands.clone())
+        .add_systems(PreUpdate, drive_lods_by_distance::<P>)
+        ;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lod_bands_hysteresis() {
+        let bands = LodDistanceBands::default();
+        let normal = Some(AiLevelOfDetailValue::new(LOD_NORMAL));
+        let minimal = Some(AiLevelOfDetailValue::new(LOD_MINIMAL));
+
+        assert_eq!(bands.lod_for_distance(10., None), AiLevelOfDetailValue::new(LOD_NORMAL));
+        assert_eq!(bands.lod_for_distance(1000., None), AiLevelOfDetailValue::new(LOD_INACTIVE));
+
+        // Just past the edge - sticks to the current LOD...
+        assert_eq!(bands.lod_for_distance(52., normal), AiLevelOfDetailValue::new(LOD_NORMAL));
+        // ...until it's past the hysteresis margin.
+        assert_eq!(bands.lod_for_distance(56., normal), AiLevelOfDetailValue::new(LOD_MINIMAL));
+        // Coming back in switches right at the edge.
+        assert_eq!(bands.lod_for_distance(49., minimal), AiLevelOfDetailValue::new(LOD_NORMAL));
+        // A big jump skips bands entirely.
+        assert_eq!(bands.lod_for_distance(1000., normal), AiLevelOfDetailValue::new(LOD_INACTIVE));
+    }
+
+    #[test]
+    fn test_lod_driver_assigns_and_notifies() {
+        #[derive(Resource, Default)]
+        struct Changes(usize);
+
+        let mut app = App::new();
+        app
+        .add_plugins(LodDriverPlugin::<Transform>::default())
+        .init_resource::<Changes>()
+        .add_observer(|_: On<AiLodChanged>, mut changes: ResMut<Changes>| changes.0 += 1)
+        ;
+
+        app.world_mut().spawn((LodObserver, Transform::default()));
+        let pawn = app.world_mut().spawn(Transform::from_xyz(100., 0., 0.)).id();
+        let ai = app.world_mut().spawn((AIController::default(), Pawn::new_populated(pawn))).id();
+        let manual = app.world_mut().spawn((AIController::default(), ManualLod, Transform::default())).id();
+
+        app.update();
+
+        let lod_of = |app: &App, ent| app.world().get::<AiLevelOfDetail>(ent).map(|lod| lod.get_current_lod());
+        assert_eq!(lod_of(&app, ai), Some(AiLevelOfDetailValue::new(LOD_MINIMAL)));
+        assert_eq!(lod_of(&app, manual), None);
+        assert_eq!(app.world().resource::<Changes>().0, 1);
+
+        // No change, no event.
+        app.update();
+        assert_eq!(app.world().resource::<Changes>().0, 1);
+
+        app.world_mut().get_mut::<Transform>(pawn).unwrap().translation.x = 10.;
+        app.update();
+        assert_eq!(lod_of(&app, ai), Some(AiLevelOfDetailValue::new(LOD_NORMAL)));
+        assert_eq!(app.world().resource::<Changes>().0, 2);
+    }
+}
@@ -27,13 +27,23 @@ You can obtain one at https://mozilla.org/MPL/2.0/.
 //! In practice, this is implemented as a simple value and a pair of attributes on the ActionTemplate, 
 //! `min_lod` and `max_lod`. A Template is skipped if its AI's current LOD is not between those two values.
 //! 
+//! Individual Considerations can declare their own `lod_min` and `lod_max` as well; outside of that range, 
+//! the Consideration is skipped (i.e. treated as neutral) or replaced by a cheaper `lod_fallback` Consideration, 
+//! so that the Template remains available with a cheaper approximation of the same behavior.
+//! 
 //! The exact logic of the LOD-setting systems are left up to the user; the library provides the levels 
 //! and an integration of the LODs into the core Utility AI engine, since user code cannot hook into it. 
+//! For the common case of 'detail based on distance to the player/camera', there is a ready-made 
+//! driver in the `lod_driver` module that you can use instead of rolling your own.
 //! 
 //! By default all AIs are running on LOD_NORMAL at all times. This is to ensure this feature 
 //! works entirely as an opt-in solution for those applications that actually need it and don't 
 //! slow down your development in applications that do not call for it.
 //! 
+//! On top of filtering ActionTemplates, the LOD also controls how *often* an AI gets processed - 
+//! decision requests and Action ticks are throttled to a configurable interval per LOD band 
+//! (see `LodUpdateFrequency`), so low-detail AIs can keep running, just at a fraction of the cost.
+//! 
 //! If you do not specify otherwise, the min LOD for any Action is `LOD_NORMAL` and the max LOD is `LOD_MINIMAL`.
 //! 
 //! This means you can just use `LOD_NORMAL` and `LOD_INACTIVE` as your only two levels 
@@ -43,7 +53,10 @@ You can obtain one at https://mozilla.org/MPL/2.0/.
 //! (1) - the other piece, also available via this library, is grouping - AIs do not have to correspond 
 //! to NPCs 1:1, a whole crowd can share one collective 'brain' that controls the overall 'flow'.
 
+use core::time::Duration;
+
 use bevy::ecs::component::Component;
+use bevy::ecs::resource::Resource;
 
 use crate::types::AiLodLevelPrimitive;
 
@@ -65,7 +78,7 @@ pub const LOD_MINIMAL: AiLodLevelPrimitive = AiLodLevelPrimitive::MAX - 1;   //
 pub const LOD_INACTIVE: AiLodLevelPrimitive = AiLodLevelPrimitive::MAX;   // i.e. 255u8
 
 
-#[derive(Clone, Copy, bevy::reflect::Reflect, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, bevy::reflect::Reflect, Debug)]
 pub struct AiLevelOfDetailValue(AiLodLevelPrimitive);
 
 impl AiLevelOfDetailValue {
@@ -113,4 +126,102 @@ impl AiLevelOfDetail {
     pub fn get_current_lod(&self) -> AiLevelOfDetailValue {
         self.lod
     }
+
+    pub fn set_lod(&mut self, level: AiLevelOfDetailValue) {
+        self.lod = level
+    }
+}
+
+
+/// How long an AI has to wait between two runs of something, for each LOD band.
+/// 
+/// LODs between the named levels use the interval of the next lower-detail band 
+/// (e.g. anything between `LOD_NORMAL` and `LOD_MINIMAL` uses the `minimal` interval).
+/// A zero interval means 'every time'. Inactive AIs never run at all.
+#[derive(Clone, Debug, bevy::reflect::Reflect)]
+pub struct LodIntervals {
+    pub elevated: Duration,
+    pub normal: Duration,
+    pub minimal: Duration,
+}
+
+impl LodIntervals {
+    pub const fn new(elevated: Duration, normal: Duration, minimal: Duration) -> Self {
+        Self { elevated, normal, minimal }
+    }
+
+    /// The interval for an AI on the specified LOD; None if the AI should not run at all.
+    pub fn interval_for(&self, lod: AiLevelOfDetailValue) -> Option<Duration> {
+        let level = lod.to_primitive();
+
+        if level == LOD_INACTIVE {
+            None
+        } else if level == LOD_ELEVATED {
+            Some(self.elevated)
+        } else if level <= LOD_NORMAL {
+            Some(self.normal)
+        } else {
+            Some(self.minimal)
+        }
+    }
+
+    /// True if enough time has passed since the last run for an AI on the specified LOD to run again.
+    /// Anything that has never run before is always due (unless inactive).
+    pub fn is_due(&self, lod: AiLevelOfDetailValue, last_run: Option<Duration>, now: Duration) -> bool {
+        match (self.interval_for(lod), last_run) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(interval), Some(last_run)) => now.saturating_sub(last_run) >= interval,
+        }
+    }
+}
+
+/// Controls how often AIs on each LOD band get to make decisions, tick their Actions and update their Senses.
+/// 
+/// Decision requests arriving before an AI's interval is up are not dropped, but deferred 
+/// until it is, so user code can keep requesting decisions whenever it sees fit.
+/// 
+/// By default, AIs on `LOD_ELEVATED` and `LOD_NORMAL` run unthrottled, so this only kicks in 
+/// for AIs you (or the LOD driver) have explicitly demoted to lower LODs.
+#[derive(Resource, Clone, Debug, bevy::reflect::Reflect)]
+pub struct LodUpdateFrequency {
+    /// Minimum time between two decisions of an AI.
+    pub decisions: LodIntervals,
+    /// Minimum time between two ticks of a (ticked) Action.
+    pub ticks: LodIntervals,
+    /// Minimum time between two updates of any one Sense of an AI (on top of the Sense's own interval).
+    pub senses: LodIntervals,
+}
+
+impl Default for LodUpdateFrequency {
+    fn default() -> Self {
+        Self {
+            decisions: LodIntervals::new(Duration::ZERO, Duration::ZERO, Duration::from_secs(1)),
+            ticks: LodIntervals::new(Duration::ZERO, Duration::ZERO, Duration::from_millis(500)),
+            senses: LodIntervals::new(Duration::ZERO, Duration::ZERO, Duration::from_secs(1)),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lod_intervals() {
+        let intervals = LodIntervals::new(Duration::ZERO, Duration::from_secs(1), Duration::from_secs(10));
+        let lod = |lvl| AiLevelOfDetailValue::new(lvl);
+        let secs = Duration::from_secs;
+
+        assert_eq!(intervals.interval_for(lod(LOD_ELEVATED)), Some(Duration::ZERO));
+        assert_eq!(intervals.interval_for(lod(LOD_NORMAL - 1)), Some(secs(1)));
+        assert_eq!(intervals.interval_for(lod(LOD_NORMAL + 1)), Some(secs(10)));
+        assert_eq!(intervals.interval_for(lod(LOD_INACTIVE)), None);
+
+        assert!(intervals.is_due(lod(LOD_MINIMAL), None, secs(0)));
+        assert!(!intervals.is_due(lod(LOD_MINIMAL), Some(secs(5)), secs(10)));
+        assert!(intervals.is_due(lod(LOD_MINIMAL), Some(secs(5)), secs(15)));
+        assert!(!intervals.is_due(lod(LOD_INACTIVE), None, secs(15)));
+    }
 }
@@ -0,0 +1,329 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+If a copy of the MPL was not distributed with this file,
+You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+//! Memories - a per-AI knowledge base of facts about other Entities.
+//!
+//! Real-time AIs that act on the *true* state of the World tend to feel like cheaters - they know
+//! where you are after you've broken line of sight, they know about the enemy behind the wall...
+//!
+//! The `Memories` Component gives an AI its own, possibly outdated, view of the World instead.
+//! Each remembered Entity has a `Memory` recording where it was last known to be, when it was last
+//! observed, how confident the AI is in that knowledge, and any custom (reflectable) values you want
+//! to attach to it (e.g. 'was hostile', 'had N ammo left').
+//!
+//! Confidence decays over time (exponentially, with a per-AI half-life), and memories whose
+//! confidence falls below a threshold are forgotten altogether. Refreshing a memory (e.g. from
+//! the Senses, or from your own game logic) restores its confidence.
+//!
+//! As this is just a Component, Considerations and ContextFetchers can read it using plain old
+//! `Query<&Memories>`; a couple of ready-made ones are provided in this module as well - register
+//! them under any key you like, e.g. `app.register_context_fetcher(remembered_entities, "RememberedEntities")`.
+
+use core::time::Duration;
+
+use bevy::math::ops;
+use bevy::platform::prelude::{Box, String};
+use bevy::prelude::*;
+
+use crate::considerations::{ConsiderationInputs, ConsiderationOutputs};
+use crate::context_fetchers::{ContextFetcherInputs, ContextFetcherOutputs};
+use crate::types::{ActionScore, CraniumKvMap};
+
+/// The default time it takes for a Memory to lose half of its confidence.
+pub const DEFAULT_MEMORY_HALF_LIFE: Duration = Duration::from_secs(30);
+
+/// The default confidence below which Memories are forgotten.
+pub const DEFAULT_FORGET_THRESHOLD: ActionScore = 0.05;
+
+/// Everything an AI remembers about a single Entity.
+#[derive(Debug)]
+pub struct Memory {
+    /// Where the Entity was when last observed, if known.
+    pub last_known_position: Option<Vec3>,
+
+    /// When the Entity was last observed (in virtual/game time elapsed).
+    pub last_seen: Duration,
+
+    /// How sure the AI is that this knowledge is still accurate, from 0.0 to 1.0.
+    pub confidence: ActionScore,
+
+    /// Arbitrary extra facts, keyed by name.
+    values: CraniumKvMap<String, Box<dyn Reflect>>,
+}
+
+impl Memory {
+    pub fn new(last_seen: Duration) -> Self {
+        Self {
+            last_known_position: None,
+            last_seen,
+            confidence: 1.,
+            values: CraniumKvMap::default(),
+        }
+    }
+
+    /// How long ago the Entity was last observed.
+    pub fn age(&self, now: Duration) -> Duration {
+        now.saturating_sub(self.last_seen)
+    }
+
+    /// Marks the Entity as just observed at full confidence.
+    pub fn refresh(&mut self, now: Duration) -> &mut Self {
+        self.last_seen = now;
+        self.confidence = 1.;
+        self
+    }
+
+    /// Records a custom fact about the Entity, replacing any previous value under the same key.
+    pub fn set_value<T: Reflect, IS: Into<String>>(&mut self, key: IS, value: T) -> &mut Self {
+        self.values.insert(key.into(), Box::new(value));
+        self
+    }
+
+    /// Retrieves a custom fact about the Entity; None if unknown or not of the requested type.
+    pub fn get_value<T: Reflect>(&self, key: &str) -> Option<&T> {
+        self.values.get(key).and_then(|val| val.downcast_ref::<T>())
+    }
+
+    /// Retrieves a custom fact about the Entity without knowing its type.
+    pub fn get_value_reflect(&self, key: &str) -> Option<&dyn Reflect> {
+        self.values.get(key).map(|val| val.as_ref())
+    }
+
+    pub fn remove_value(&mut self, key: &str) -> Option<Box<dyn Reflect>> {
+        self.values.remove(key)
+    }
+}
+
+/// A per-AI knowledge base - what this AI knows (or thinks it knows) about other Entities.
+#[derive(Component, Debug)]
+pub struct Memories {
+    facts: CraniumKvMap<Entity, Memory>,
+
+    /// How long it takes for a Memory to lose half of its confidence; None to never decay.
+    pub half_life: Option<Duration>,
+
+    /// Memories with a confidence below this value are forgotten.
+    pub forget_threshold: ActionScore,
+}
+
+impl Default for Memories {
+    fn default() -> Self {
+        Self {
+            facts: CraniumKvMap::default(),
+            half_life: Some(DEFAULT_MEMORY_HALF_LIFE),
+            forget_threshold: DEFAULT_FORGET_THRESHOLD,
+        }
+    }
+}
+
+impl Memories {
+    pub fn new(half_life: Option<Duration>, forget_threshold: ActionScore) -> Self {
+        Self {
+            half_life,
+            forget_threshold,
+            ..Default::default()
+        }
+    }
+
+    /// Records that the Entity was observed right now, creating a new Memory if needed.
+    ///
+    /// Returns the Memory, so that you can record further details about it.
+    pub fn observe(&mut self, entity: Entity, now: Duration) -> &mut Memory {
+        self.facts
+            .entry(entity)
+            .or_insert_with(|| Memory::new(now))
+            .refresh(now)
+    }
+
+    /// Records that the Entity was observed right now at a specific position.
+    pub fn observe_at(&mut self, entity: Entity, position: Vec3, now: Duration) -> &mut Memory {
+        let memory = self.observe(entity, now);
+        memory.last_known_position = Some(position);
+        memory
+    }
+
+    /// Records a (possibly uncertain) observation of the Entity, e.g. from a Sense.
+    ///
+    /// Unlike `observe()`, this does not necessarily restore full confidence - the Memory ends up 
+    /// with whichever is higher, the confidence it already had or the confidence of the observation.
+    pub fn perceive(&mut self, entity: Entity, position: Option<Vec3>, confidence: ActionScore, now: Duration) -> &mut Memory {
+        let memory = self.facts.entry(entity).or_insert_with(|| {
+            let mut memory = Memory::new(now);
+            memory.confidence = 0.;
+            memory
+        });
+
+        // Observations may arrive out of order (e.g. shared by other AIs with a delay).
+        memory.last_seen = memory.last_seen.max(now);
+        memory.confidence = memory.confidence.max(confidence.clamp(0., 1.));
+        if position.is_some() {
+            memory.last_known_position = position;
+        }
+        memory
+    }
+
+    pub fn recall(&self, entity: Entity) -> Option<&Memory> {
+        self.facts.get(&entity)
+    }
+
+    pub fn recall_mut(&mut self, entity: Entity) -> Option<&mut Memory> {
+        self.facts.get_mut(&entity)
+    }
+
+    pub fn knows(&self, entity: Entity) -> bool {
+        self.facts.contains_key(&entity)
+    }
+
+    /// How confident the AI is about the Entity; zero if it does not remember it at all.
+    pub fn confidence(&self, entity: Entity) -> ActionScore {
+        self.recall(entity).map(|memory| memory.confidence).unwrap_or(0.)
+    }
+
+    pub fn forget(&mut self, entity: Entity) -> Option<Memory> {
+        self.facts.remove(&entity)
+    }
+
+    pub fn forget_all(&mut self) {
+        self.facts.clear()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &Memory)> {
+        self.facts.iter().map(|(entity, memory)| (*entity, memory))
+    }
+
+    /// All remembered Entities the AI is at least this confident about.
+    pub fn iter_confident(&self, min_confidence: ActionScore) -> impl Iterator<Item = (Entity, &Memory)> {
+        self.iter().filter(move |(_, memory)| memory.confidence >= min_confidence)
+    }
+
+    /// The most recently observed Entity, if any.
+    pub fn most_recent(&self) -> Option<(Entity, &Memory)> {
+        self.iter().max_by_key(|(_, memory)| memory.last_seen)
+    }
+
+    pub fn len(&self) -> usize {
+        self.facts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.facts.is_empty()
+    }
+
+    /// Decays the confidence of all Memories by the time elapsed and forgets the ones that fell below the threshold.
+    pub fn decay(&mut self, elapsed: Duration) {
+        let Some(half_life) = self.half_life.filter(|hl| !hl.is_zero()) else {
+            return;
+        };
+
+        let factor = ops::exp2(-elapsed.as_secs_f32() / half_life.as_secs_f32());
+        let threshold = self.forget_threshold;
+
+        self.facts.retain(|_, memory| {
+            memory.confidence *= factor;
+            memory.confidence >= threshold
+        });
+    }
+}
+
+/// A System that decays all AIs' Memories over (virtual) time.
+pub fn decay_memories(
+    time: Option<Res<Time<Virtual>>>,
+    mut query: Query<&mut Memories>,
+) {
+    let Some(elapsed) = time.map(|time| time.delta()) else {
+        return;
+    };
+
+    if elapsed.is_zero() {
+        return;
+    }
+
+    for mut memories in query.iter_mut() {
+        memories.decay(elapsed);
+    }
+}
+
+/// A ready-made ContextFetcher that returns every Entity the AI remembers.
+pub fn remembered_entities(
+    inputs: ContextFetcherInputs,
+    query: Query<&Memories>,
+) -> ContextFetcherOutputs {
+    let (ai, _pawn) = inputs.0;
+
+    query
+        .get(ai)
+        .map(|memories| memories.iter().map(|(entity, _)| entity).collect())
+        .unwrap_or_default()
+}
+
+/// A ready-made Consideration that returns how confident the AI is about the Context (0.0 to 1.0).
+pub fn memory_confidence(
+    inputs: ConsiderationInputs,
+    query: Query<&Memories>,
+) -> ConsiderationOutputs {
+    let (ai, _pawn, context) = inputs.0;
+    query.get(ai).ok().map(|memories| memories.confidence(context))
+}
+
+/// A ready-made Consideration that returns how long ago (in seconds) the AI last observed the Context.
+///
+/// Returns None (i.e. discards the Context) if the AI does not remember the Context at all.
+pub fn memory_age_seconds(
+    inputs: ConsiderationInputs,
+    query: Query<&Memories>,
+    time: Res<Time<Virtual>>,
+) -> ConsiderationOutputs {
+    let (ai, _pawn, context) = inputs.0;
+    let memory = query.get(ai).ok()?.recall(context)?;
+    Some(memory.age(time.elapsed()).as_secs_f32())
+}
+
+/// Sets up Memory decay for all AIs with the `Memories` Component.
+pub struct MemoriesPlugin;
+
+impl Plugin for MemoriesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PreUpdate, decay_memories);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memories_decay_and_values() {
+        let mut world = World::new();
+        let target = world.spawn_empty().id();
+        let other = world.spawn_empty().id();
+
+        let mut memories = Memories::new(Some(Duration::from_secs(10)), 0.2);
+        memories
+            .observe_at(target, Vec3::X, Duration::from_secs(1))
+            .set_value("hostile", true)
+        ;
+        memories.observe(other, Duration::from_secs(2));
+
+        assert_eq!(memories.recall(target).unwrap().get_value::<bool>("hostile"), Some(&true));
+        assert_eq!(memories.recall(target).unwrap().get_value::<f32>("hostile"), None);
+        assert_eq!(memories.most_recent().map(|(ent, _)| ent), Some(other));
+
+        memories.decay(Duration::from_secs(10));
+        assert!((memories.confidence(target) - 0.5).abs() < 1e-4);
+
+        // Re-observing restores confidence, but keeps known facts.
+        memories.observe(target, Duration::from_secs(12));
+        assert_eq!(memories.confidence(target), 1.);
+        assert_eq!(memories.recall(target).unwrap().last_known_position, Some(Vec3::X));
+
+        // 'other' drops to 0.125 and gets forgotten; 'target' is at 0.25 and stays.
+        memories.decay(Duration::from_secs(20));
+        assert!(memories.knows(target));
+        assert!(!memories.knows(other));
+        assert_eq!(memories.confidence(other), 0.);
+    }
+}
@@ -0,0 +1,205 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+If a copy of the MPL was not distributed with this file,
+You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+//! Normalization - how raw Consideration outputs get mapped onto the unit interval for Curves.
+//!
+//! Considerations return values in whatever units make sense for them (meters, hitpoints, enemy counts...),
+//! while Curves only accept inputs from 0.0 to 1.0, so every raw value has to be normalized first.
+//!
+//! The default, `Linear`, maps the Consideration's min to 0.0 and its max to 1.0 and saturates outside of that.
+//! That is fine for most values, but poor for others - e.g. the difference between 1m and 10m usually
+//! matters a lot more than the difference between 100m and 110m, which is what `Logarithmic` is for.
+//!
+//! The `Percentile` and `ZScore` modes are special in that they normalize a value *relative to the other
+//! Contexts in the same decision* rather than a fixed min/max. This makes them self-calibrating,
+//! but it also means the Consideration has to be evaluated for every Context up-front, which defeats
+//! some of the early-out optimizations of the decision loop - use them where it matters.
+
+use bevy::math::ops;
+use bevy::reflect::Reflect;
+use crate::types::{ActionScore, MIN_CONSIDERATION_SCORE, MAX_CONSIDERATION_SCORE};
+
+#[cfg(feature = "actionset_loader")]
+use serde::{Serialize, Deserialize};
+
+/// How far from the mean (in standard deviations) a value has to be to saturate in the `ZScore` mode.
+pub const ZSCORE_SATURATION: ActionScore = 3.;
+
+/// How many 'sigmoid units' the min-max span covers in the `Sigmoid` mode.
+///
+/// At 12, min and max get squashed to roughly 0.0025 and 0.9975 respectively.
+pub const SIGMOID_SPAN: ActionScore = 12.;
+
+/// The supported ways of mapping a raw Consideration value onto the unit interval.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Reflect)]
+#[cfg_attr(feature = "actionset_loader", derive(Serialize, Deserialize))]
+pub enum NormalizationMode {
+    /// `(raw - min) / (max - min)`, saturating outside of the min/max range.
+    #[default]
+    Linear,
+
+    /// Like Linear, but on a log scale (offset so that min maps to zero), saturating outside of the min/max range.
+    ///
+    /// Differences close to the min matter more than differences close to the max; good for distances and the like.
+    Logarithmic,
+
+    /// A reciprocal falloff from 1.0 at min to 0.0 at max, saturating outside of the min/max range.
+    ///
+    /// Note that this is *decreasing*, unlike all other modes - it's 'closer is better' baked into the input.
+    /// Compared to Linear + AntiLinear Curve, it drops off much faster right past the min.
+    Inverse,
+
+    /// A logistic squash centered on the midpoint of min/max.
+    ///
+    /// Unlike the other fixed-range modes, this never fully saturates, so values outside
+    /// of the range still differ (slightly) from each other.
+    Sigmoid,
+
+    /// The fraction of all Contexts in the current decision with a lower raw value (ties count as half).
+    ///
+    /// Ignores min/max entirely. Great for long-tailed values like counts; the result is always evenly spread.
+    Percentile,
+
+    /// The standard score of the raw value among all Contexts in the current decision,
+    /// mapped so that the mean lands at 0.5 and `ZSCORE_SATURATION` deviations saturate.
+    ///
+    /// Ignores min/max entirely.
+    ZScore,
+}
+
+impl NormalizationMode {
+    /// True if this mode normalizes relative to the raw values of other Contexts,
+    /// i.e. it needs a population passed into `normalize()` to work properly.
+    pub fn needs_population(&self) -> bool {
+        matches!(self, Self::Percentile | Self::ZScore)
+    }
+
+    /// Maps a raw value to the unit interval.
+    ///
+    /// The min/max must not be flipped (i.e. min <= max).
+    ///
+    /// The population is all raw values of the same Consideration across the Contexts
+    /// being compared (including the value being normalized) and is only used by modes
+    /// where `needs_population()` is true; those treat a missing or empty population
+    /// as if the value was the only one.
+    pub fn normalize(
+        &self,
+        raw: ActionScore,
+        min: ActionScore,
+        max: ActionScore,
+        population: Option<&[ActionScore]>,
+    ) -> ActionScore {
+        let span = max - min;
+        let clamped = raw.clamp(min, max);
+
+        let normalized = match self {
+            Self::Linear => match span > 0. {
+                true => (clamped - min) / span,
+                false => step(raw, max),
+            },
+            Self::Logarithmic => match span > 0. {
+                true => ops::ln_1p(clamped - min) / ops::ln_1p(span),
+                false => step(raw, max),
+            },
+            Self::Inverse => match span > 0. {
+                true => {
+                    let floor = 1. / (1. + span);
+                    ((1. / (1. + clamped - min)) - floor) / (1. - floor)
+                },
+                false => 1. - step(raw, max),
+            },
+            Self::Sigmoid => {
+                let midpoint = min + span / 2.;
+                let scale = match span > 0. {
+                    true => SIGMOID_SPAN / span,
+                    false => SIGMOID_SPAN,
+                };
+                1. / (1. + ops::exp(-(raw - midpoint) * scale))
+            },
+            Self::Percentile => {
+                let population = population.unwrap_or(&[]);
+                match population.is_empty() {
+                    true => 0.5,
+                    false => {
+                        let below = population.iter().filter(|val| **val < raw).count() as ActionScore;
+                        let equal = population.iter().filter(|val| **val == raw).count() as ActionScore;
+                        (below + equal / 2.) / population.len() as ActionScore
+                    }
+                }
+            },
+            Self::ZScore => {
+                let population = population.unwrap_or(&[]);
+                let count = population.len() as ActionScore;
+                let mean = population.iter().sum::<ActionScore>() / count;
+                let variance = population.iter().map(|val| (val - mean) * (val - mean)).sum::<ActionScore>() / count;
+                let std_dev = ops::sqrt(variance);
+
+                match std_dev > 0. {
+                    true => 0.5 + ((raw - mean) / std_dev) / (2. * ZSCORE_SATURATION),
+                    // Either no population or every value is the same - nothing stands out.
+                    false => 0.5,
+                }
+            },
+        };
+
+        normalized.clamp(MIN_CONSIDERATION_SCORE, MAX_CONSIDERATION_SCORE)
+    }
+}
+
+/// The degenerate case of a zero-width range - anything at or above the threshold is 'max'.
+fn step(raw: ActionScore, threshold: ActionScore) -> ActionScore {
+    match raw >= threshold {
+        true => MAX_CONSIDERATION_SCORE,
+        false => MIN_CONSIDERATION_SCORE,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: ActionScore, b: ActionScore) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn test_fixed_range_modes() {
+        use NormalizationMode::*;
+
+        for mode in [Linear, Logarithmic, Sigmoid] {
+            assert!(mode.normalize(0., 0., 100., None) < 0.01, "{:?}", mode);
+            assert!(mode.normalize(100., 0., 100., None) > 0.99, "{:?}", mode);
+        }
+
+        assert!(close(Linear.normalize(25., 0., 100., None), 0.25));
+        assert!(close(Linear.normalize(-5., 10., 20., None), 0.));
+        assert!(close(Linear.normalize(15., 10., 20., None), 0.5));
+        assert!(close(Sigmoid.normalize(50., 0., 100., None), 0.5));
+
+        // Log scale front-loads the range...
+        assert!(Logarithmic.normalize(10., 0., 100., None) > 0.5);
+        // ...while Inverse flips it and drops off even faster.
+        assert!(close(Inverse.normalize(0., 0., 100., None), 1.));
+        assert!(close(Inverse.normalize(100., 0., 100., None), 0.));
+        assert!(Inverse.normalize(10., 0., 100., None) < 0.1);
+    }
+
+    #[test]
+    fn test_population_modes() {
+        use NormalizationMode::*;
+        let population = [1., 2., 3., 4., 1000.];
+
+        assert!(close(Percentile.normalize(1., 0., 0., Some(&population)), 0.1));
+        assert!(close(Percentile.normalize(3., 0., 0., Some(&population)), 0.5));
+        assert!(close(Percentile.normalize(1000., 0., 0., Some(&population)), 0.9));
+
+        assert!(ZScore.normalize(1000., 0., 0., Some(&population)) > 0.8);
+        assert!(ZScore.normalize(1., 0., 0., Some(&population)) < 0.5);
+        assert!(close(ZScore.normalize(7., 0., 0., Some(&[7., 7.])), 0.5));
+        assert!(close(ZScore.normalize(7., 0., 0., None), 0.5));
+    }
+}
@@ -0,0 +1,552 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+If a copy of the MPL was not distributed with this file,
+You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+//! Orders - Commander AIs directing their subordinate AIs.
+//!
+//! AIs join a commander's chain of command with the `CommandedBy` relationship. A commander passes
+//! an `Order` down to a subordinate, which stores it as a Component. The Order does not force the
+//! subordinate to do anything - it is just another input for its Utility scoring, so a subordinate
+//! may obey, ignore or only partially follow an Order, depending on how its ActionSets score it.
+//!
+//! An Order's `kind` is the ActionKey the commander wants the subordinate to run. Its lifecycle is
+//! tracked automatically:
+//! - Issued - the subordinate got the Order (replacing any previous one, which gets Cancelled),
+//! - Accepted - the subordinate picked an Action with the Order's kind as its ActionKey,
+//! - Completed/Failed/Cancelled - the accepted Action ended in the matching state,
+//! - Ignored - the subordinate explicitly refused the Order (e.g. with the `Order::Ignore` Action).
+//!
+//! Each change is reported back to the commander as an `OrderReport` event. Finished Orders are
+//! removed from the subordinate.
+//!
+//! The `OrdersPlugin` registers the following keys, which you can use from ActionSet data directly:
+//!
+//! - Consideration `Order::Has` - 1.0 if the AI has an Order, 0.0 otherwise.
+//! - Consideration `Order::Compliance` - the AI's `Obedience` times the Order's priority (0.0 if no Order);
+//!   put it through a Curve to make the AI obey (high scores), ignore (zero) or half-heartedly follow it.
+//! - Consideration `Order::IsTarget` - 1.0 if the Context is one of the Order's targets, 0.0 otherwise.
+//! - ContextFetcher `Order::Targets` - the targets of the AI's Order.
+//! - Action `Order::Ignore` - reports the AI's Order as Ignored.
+//!
+//! The following prefixed keys get registered whenever they show up in a stored ActionSet:
+//!
+//! - Consideration `OrderIs::<kind>` - 1.0 if the AI has an Order of the kind, 0.0 otherwise.
+//! - Action `IssueOrder::<kind>` - issues an Order of the kind, targeting the Action's Context,
+//!   to all subordinates of the AI that do not have that exact Order already, then Succeeds.
+
+use core::borrow::Borrow;
+
+use bevy::platform::prelude::{String, ToOwned, Vec, vec};
+use bevy::prelude::*;
+
+use crate::actions::{
+    AcceptsActionHandlerRegistrations, ActionHandlerInputs, ActionHandlerKeyToSystemMap, ActionPickCallback,
+};
+use crate::action_runtime::{ActionTracker, Tracks};
+use crate::action_state::{ActionState, AiActionStateChange, AiActionStateChangeRequest};
+use crate::considerations::{
+    AcceptsConsiderationRegistrations, ConsiderationInputs, ConsiderationKeyToSystemMap, ConsiderationOutputs,
+    ensure_consideration_queries,
+};
+use crate::context_fetchers::{AcceptsContextFetcherRegistrations, ContextFetcherInputs, ContextFetcherOutputs};
+use crate::events::{AiActionPicked, AiDecisionRequested};
+use crate::smart_object::{ActionSetStore, SmartObjects};
+use crate::types::{ActionKey, ActionScore, AiEntity, CraniumList};
+
+/// Key of the built-in Consideration checking whether the AI has an Order.
+pub const ORDER_HAS_CONSIDERATION: &str = "Order::Has";
+/// Key of the built-in Consideration scoring how willing the AI is to follow its Order.
+pub const ORDER_COMPLIANCE_CONSIDERATION: &str = "Order::Compliance";
+/// Key of the built-in Consideration checking whether the Context is a target of the AI's Order.
+pub const ORDER_IS_TARGET_CONSIDERATION: &str = "Order::IsTarget";
+/// Key of the built-in ContextFetcher returning the targets of the AI's Order.
+pub const ORDER_TARGETS_CONTEXT_FETCHER: &str = "Order::Targets";
+/// Key of the built-in ActionHandler reporting the AI's Order as Ignored.
+pub const ORDER_IGNORE_ACTION: &str = "Order::Ignore";
+/// Prefix for Consideration keys checking the kind of the AI's Order.
+pub const ORDER_IS_PREFIX: &str = "OrderIs::";
+/// Prefix for ActionHandler keys issuing Orders to the AI's subordinates.
+pub const ORDER_ISSUE_PREFIX: &str = "IssueOrder::";
+
+/// Puts an AI under the command of another AI.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+#[relationship(relationship_target = Subordinates)]
+pub struct CommandedBy(pub Entity);
+
+/// All AIs under the command of a commander AI; maintained automatically based on `CommandedBy`.
+#[derive(Component, Debug, Default)]
+#[relationship_target(relationship = CommandedBy)]
+pub struct Subordinates(Vec<Entity>);
+
+/// How willing a subordinate AI is to follow Orders, from 0.0 to 1.0; AIs without it are fully obedient.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Obedience(pub ActionScore);
+
+impl Default for Obedience {
+    fn default() -> Self {
+        Self(1.)
+    }
+}
+
+/// Where an Order is in its lifecycle (see the module docs).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect)]
+pub enum OrderState {
+    Issued,
+    Accepted,
+    Completed,
+    Failed,
+    Ignored,
+    Cancelled,
+}
+
+impl OrderState {
+    pub fn is_terminal(&self) -> bool {
+        !matches!(self, Self::Issued | Self::Accepted)
+    }
+}
+
+/// An Order from a commander AI, stored on the subordinate AI it was issued to.
+#[derive(Component, Clone, Debug)]
+pub struct Order {
+    /// What the commander wants done; the ActionKey of the Action that carries the Order out.
+    pub kind: ActionKey,
+    /// The commander AI that issued the Order.
+    pub issued_by: AiEntity,
+    /// What the Order is about, e.g. the Entity to attack or the place to go to.
+    pub targets: CraniumList<Entity>,
+    /// How much the commander cares about the Order, from 0.0 to 1.0.
+    pub priority: ActionScore,
+    state: OrderState,
+}
+
+impl Order {
+    pub fn new<IS: Into<ActionKey>>(kind: IS, issued_by: AiEntity) -> Self {
+        Self {
+            kind: kind.into(),
+            issued_by,
+            targets: CraniumList::new(),
+            priority: 1.,
+            state: OrderState::Issued,
+        }
+    }
+
+    pub fn with_targets(mut self, targets: CraniumList<Entity>) -> Self {
+        self.targets = targets;
+        self
+    }
+
+    pub fn with_priority(mut self, priority: ActionScore) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn state(&self) -> OrderState {
+        self.state
+    }
+
+    /// True if the other Order is the same kind, from the same commander, with the same targets.
+    pub fn is_same_order(&self, other: &Order) -> bool {
+        self.kind == other.kind && self.issued_by == other.issued_by && self.targets == other.targets
+    }
+}
+
+/// Reports a change in the lifecycle of an Order back to the commander that issued it.
+#[derive(EntityEvent, Debug, Clone)]
+pub struct OrderReport {
+    /// The commander AI.
+    pub entity: AiEntity,
+    pub subordinate: AiEntity,
+    pub kind: ActionKey,
+    pub state: OrderState,
+}
+
+/// Gives the subordinate AI a new Order, Cancelling its previous one (if any), and requests a new
+/// decision for it so it can react right away.
+pub fn issue_order(world: &mut World, subordinate: AiEntity, order: Order) {
+    if world.get_entity(subordinate).is_err() {
+        #[cfg(feature = "logging")]
+        bevy::log::warn!("issue_order: AI {:?} does not exist, dropping Order {:?}", subordinate, &order.kind);
+        return;
+    }
+
+    update_order(world, subordinate, OrderState::Cancelled);
+
+    let commander = order.issued_by;
+    let kind = order.kind.to_owned();
+    world.entity_mut(subordinate).insert(Order { state: OrderState::Issued, ..order });
+    world.trigger(OrderReport { entity: commander, subordinate, kind, state: OrderState::Issued });
+
+    let smart_objects = world.get::<SmartObjects>(subordinate).cloned();
+    world.trigger(AiDecisionRequested { entity: subordinate, smart_objects });
+}
+
+/// Moves the subordinate AI's Order (if any) to a new state and reports it to the commander.
+///
+/// Orders that reach a terminal state are removed from the subordinate.
+pub fn update_order(world: &mut World, subordinate: AiEntity, state: OrderState) {
+    let Some(mut order) = world.get_mut::<Order>(subordinate) else {
+        return;
+    };
+    if order.state == state {
+        return;
+    }
+    order.state = state;
+    let commander = order.issued_by;
+    let kind = order.kind.to_owned();
+
+    if state.is_terminal() {
+        world.entity_mut(subordinate).remove::<Order>();
+    }
+
+    #[cfg(feature = "logging")]
+    bevy::log::debug!("update_order: AI {:?} - Order {:?} is now {:?}", subordinate, &kind, state);
+
+    world.trigger(OrderReport { entity: commander, subordinate, kind, state });
+}
+
+/// Convenience methods for managing an AI's Orders from Commands.
+pub trait OrderCommandsExt {
+    /// Gives the AI a new Order (see `issue_order()`).
+    fn issue_order(&mut self, order: Order) -> &mut Self;
+
+    /// Moves the AI's Order to a new state (see `update_order()`).
+    fn update_order(&mut self, state: OrderState) -> &mut Self;
+}
+
+impl OrderCommandsExt for EntityCommands<'_> {
+    fn issue_order(&mut self, order: Order) -> &mut Self {
+        self.queue(move |entity: EntityWorldMut| {
+            let subordinate = entity.id();
+            issue_order(entity.into_world_mut(), subordinate, order);
+        })
+    }
+
+    fn update_order(&mut self, state: OrderState) -> &mut Self {
+        self.queue(move |entity: EntityWorldMut| {
+            let subordinate = entity.id();
+            update_order(entity.into_world_mut(), subordinate, state);
+        })
+    }
+}
+
+/// Builds an ActionHandler that issues an Order of the kind, targeting the Action's Context,
+/// to all subordinates of the AI.
+///
+/// Subordinates that already have the same Order keep it, so dispatching the Action again (e.g. on
+/// every tick) does not restart their Orders. Issuing is instant, so the Action Succeeds right away.
+pub fn issue_order_handler<IS: Into<ActionKey>>(kind: IS) -> ActionPickCallback {
+    let kind: ActionKey = kind.into();
+    ActionPickCallback::new(move |inputs: ActionHandlerInputs, mut commands: Commands| {
+        let (ai, _pawn, context, tracker) = inputs;
+        let kind = kind.to_owned();
+
+        commands.queue(move |world: &mut World| {
+            let subordinates: CraniumList<Entity> = world
+                .get::<Subordinates>(ai)
+                .map(|subordinates| subordinates.iter().collect())
+                .unwrap_or_default();
+
+            for subordinate in subordinates {
+                let order = Order::new(kind.to_owned(), ai).with_targets(vec![context]);
+                if world.get::<Order>(subordinate).is_some_and(|current| current.is_same_order(&order)) {
+                    continue;
+                }
+                issue_order(world, subordinate, order);
+            }
+
+            let Some(tracker) = tracker else {
+                return;
+            };
+            let Some(action_key) = world.get::<ActionTracker>(tracker).map(|tracker| tracker.0.action.action_key.to_owned()) else {
+                return;
+            };
+            world.write_message(AiActionStateChangeRequest { entity: tracker, action: action_key, to_state: ActionState::Succeeded });
+        });
+    })
+}
+
+/// Builds an ActionHandler that reports the AI's Order as Ignored.
+pub fn ignore_order_handler() -> ActionPickCallback {
+    ActionPickCallback::new(|inputs: ActionHandlerInputs, mut commands: Commands| {
+        let (ai, _pawn, _context, _tracker) = inputs;
+        if let Ok(mut cmds) = commands.get_entity(ai) {
+            cmds.update_order(OrderState::Ignored);
+        }
+    })
+}
+
+/// A ready-made Consideration that returns 1.0 if the AI has an Order and 0.0 otherwise.
+pub fn has_order(
+    inputs: ConsiderationInputs,
+    query: Query<&Order>,
+) -> ConsiderationOutputs {
+    let (ai, _pawn, _context) = inputs.0;
+    Some(if query.contains(ai) { 1. } else { 0. })
+}
+
+/// A ready-made Consideration that returns how willing the AI is to follow its Order (0.0 to 1.0).
+pub fn order_compliance(
+    inputs: ConsiderationInputs,
+    query: Query<(&Order, Option<&Obedience>)>,
+) -> ConsiderationOutputs {
+    let (ai, _pawn, _context) = inputs.0;
+    let score = query
+        .get(ai)
+        .map(|(order, obedience)| {
+            let obedience = obedience.copied().unwrap_or_default().0;
+            (obedience * order.priority).clamp(0., 1.)
+        })
+        .unwrap_or(0.);
+    Some(score)
+}
+
+/// A ready-made Consideration that returns 1.0 if the Context is a target of the AI's Order and 0.0 otherwise.
+pub fn order_target_is_context(
+    inputs: ConsiderationInputs,
+    query: Query<&Order>,
+) -> ConsiderationOutputs {
+    let (ai, _pawn, context) = inputs.0;
+    let is_target = query.get(ai).is_ok_and(|order| order.targets.contains(&context));
+    Some(if is_target { 1. } else { 0. })
+}
+
+/// A ready-made ContextFetcher that returns the targets of the AI's Order.
+pub fn order_targets(
+    inputs: ContextFetcherInputs,
+    query: Query<&Order>,
+) -> ContextFetcherOutputs {
+    let (ai, _pawn) = inputs.0;
+    query.get(ai).map(|order| order.targets.to_owned()).unwrap_or_default()
+}
+
+/// Registers a Consideration that returns 1.0 if the AI has an Order of the kind and 0.0 otherwise.
+pub fn register_order_kind_consideration<IS: Into<String>>(world: &mut World, kind: IS, key: IS) {
+    let kind: String = kind.into();
+    world.register_consideration(
+        move |inputs: ConsiderationInputs, query: Query<&Order>| -> ConsiderationOutputs {
+            let (ai, _pawn, _context) = inputs.0;
+            let matches = query.get(ai).is_ok_and(|order| order.kind == kind);
+            Some(if matches { 1. } else { 0. })
+        },
+        key,
+    );
+}
+
+/// An Observer marking Orders as Accepted once the subordinate picks an Action of the Order's kind.
+pub fn accept_orders(
+    event: On<AiActionPicked>,
+    query: Query<&Order>,
+    mut commands: Commands,
+) {
+    let ai = event.entity;
+    let Ok(order) = query.get(ai) else {
+        return;
+    };
+
+    if order.state == OrderState::Issued && order.kind == event.action_key {
+        commands.entity(ai).update_order(OrderState::Accepted);
+    }
+}
+
+/// An Observer finishing Accepted Orders once the Action carrying them out ends.
+pub fn finish_orders(
+    event: On<AiActionStateChange>,
+    owner_query: Query<&Tracks>,
+    query: Query<&Order>,
+    mut commands: Commands,
+) {
+    if !event.to_state.is_terminal() {
+        return;
+    }
+    let ai = owner_query.get(event.entity).map(|owner| owner.0).unwrap_or(event.entity);
+    let Ok(order) = query.get(ai) else {
+        return;
+    };
+    if order.state != OrderState::Accepted || order.kind != event.action {
+        return;
+    }
+
+    let state = match event.to_state {
+        ActionState::Succeeded => OrderState::Completed,
+        ActionState::Failed => OrderState::Failed,
+        _ => OrderState::Cancelled,
+    };
+    commands.entity(ai).update_order(state);
+}
+
+/// An exclusive System that registers the Considerations and ActionHandlers for all Order-prefixed
+/// keys used in the stored ActionSets (see the module docs).
+///
+/// Keys that already have a registration are left alone, so you can always override them.
+pub fn register_actionset_order_keys(world: &mut World) {
+    let mut consideration_keys: CraniumList<String> = CraniumList::new();
+    let mut action_keys: CraniumList<ActionKey> = CraniumList::new();
+
+    {
+        let Some(store) = world.get_resource::<ActionSetStore>() else {
+            return;
+        };
+        let registered_considerations = world.get_resource::<ConsiderationKeyToSystemMap>();
+        let registered_actions = world.get_resource::<ActionHandlerKeyToSystemMap>();
+
+        for actionset in store.map_by_name.values() {
+            for template in actionset.actions.iter() {
+                if template.action_key.starts_with(ORDER_ISSUE_PREFIX)
+                    && !registered_actions.is_some_and(|reg| reg.mapping.contains_key(&template.action_key))
+                {
+                    action_keys.push(template.action_key.to_owned());
+                }
+
+                for consideration in template.considerations.iter() {
+                    let key: &str = consideration.consideration_name.borrow();
+                    if key.starts_with(ORDER_IS_PREFIX)
+                        && !registered_considerations.is_some_and(|reg| reg.mapping.contains_key(key))
+                    {
+                        consideration_keys.push(key.to_owned());
+                    }
+                }
+            }
+        }
+    }
+
+    let registered_any_consideration = !consideration_keys.is_empty();
+    for key in consideration_keys {
+        let kind = key.strip_prefix(ORDER_IS_PREFIX).unwrap_or_default().to_owned();
+
+        #[cfg(feature = "logging")]
+        bevy::log::debug!("register_actionset_order_keys: Registered Consideration {:?}", &key);
+
+        register_order_kind_consideration(world, kind, key);
+    }
+
+    for key in action_keys {
+        let kind = key.strip_prefix(ORDER_ISSUE_PREFIX).unwrap_or_default().to_owned();
+
+        #[cfg(feature = "logging")]
+        bevy::log::debug!("register_actionset_order_keys: Registered ActionHandler {:?}", &key);
+
+        world.register_action_handler(issue_order_handler(kind), key);
+    }
+
+    // New Consideration Systems need to be initialized before their first run.
+    if registered_any_consideration {
+        ensure_consideration_queries(world);
+    }
+}
+
+/// Sets up Orders between commander and subordinate AIs (see the module docs).
+pub struct OrdersPlugin;
+
+impl Plugin for OrdersPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .register_consideration(has_order, ORDER_HAS_CONSIDERATION)
+            .register_consideration(order_compliance, ORDER_COMPLIANCE_CONSIDERATION)
+            .register_consideration(order_target_is_context, ORDER_IS_TARGET_CONSIDERATION)
+            .register_context_fetcher(order_targets, ORDER_TARGETS_CONTEXT_FETCHER)
+            .register_action_handler(ignore_order_handler(), ORDER_IGNORE_ACTION)
+            .add_observer(accept_orders)
+            .add_observer(finish_orders)
+            .add_systems(
+                First,
+                register_actionset_order_keys.run_if(resource_exists_and_changed::<ActionSetStore>)
+            )
+        ;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::action_runtime::{ActionTrackerSpawnConfig, ActionTrackerState, TickBasedActionTrackerPlugin, spawn_action_tracker};
+    use crate::action_state::{ActionStateUpdatesPlugin, action_state_update_handler};
+    use crate::actions::{Action, ScoredAction};
+    use crate::decision_loop::handle_dispatch_to_user_actions;
+    use crate::events::AiActionDispatchToUserCode;
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct Reports(CraniumList<(AiEntity, ActionKey, OrderState)>);
+
+    fn compliance(world: &mut World, ai: AiEntity) -> ConsiderationOutputs {
+        world.run_system_cached_with(order_compliance, (ai, None, Entity::PLACEHOLDER)).unwrap()
+    }
+
+    #[test]
+    fn test_order_lifecycle_reports() {
+        let mut app = App::new();
+        app.add_plugins(OrdersPlugin).init_resource::<Reports>();
+        app.add_observer(|report: On<OrderReport>, mut reports: ResMut<Reports>| {
+            reports.0.push((report.subordinate, report.kind.to_owned(), report.state));
+        });
+
+        let world = app.world_mut();
+        let commander = world.spawn_empty().id();
+        let grunt = world.spawn((CommandedBy(commander), Obedience(0.5))).id();
+        let target = world.spawn_empty().id();
+
+        issue_order(world, grunt, Order::new("Attack", commander).with_targets(vec![target]));
+        assert_eq!(compliance(world, grunt), Some(0.5));
+
+        issue_order(world, grunt, Order::new("Retreat", commander).with_priority(0.5));
+        assert_eq!(compliance(world, grunt), Some(0.25));
+
+        world.trigger(AiActionPicked::new(grunt, "Retreat".into(), "Retreat".into(), Entity::PLACEHOLDER, 1.));
+        world.flush();
+        update_order(world, grunt, OrderState::Completed);
+
+        assert!(world.get::<Order>(grunt).is_none());
+        assert_eq!(compliance(world, grunt), Some(0.));
+        assert_eq!(world.get::<Subordinates>(commander).unwrap().len(), 1);
+        assert_eq!(world.resource::<Reports>().0, vec![
+            (grunt, "Attack".into(), OrderState::Issued),
+            (grunt, "Attack".into(), OrderState::Cancelled),
+            (grunt, "Retreat".into(), OrderState::Issued),
+            (grunt, "Retreat".into(), OrderState::Accepted),
+            (grunt, "Retreat".into(), OrderState::Completed),
+        ]);
+    }
+
+    #[test]
+    fn test_issue_order_handler_issues_once_per_tracker() {
+        let mut app = App::new();
+        app.add_plugins((OrdersPlugin, ActionStateUpdatesPlugin, TickBasedActionTrackerPlugin))
+            .add_message::<AiActionDispatchToUserCode>()
+            .init_resource::<Time>()
+            .init_resource::<Time<Real>>()
+            .init_resource::<Reports>()
+            .register_action_handler(issue_order_handler("Attack"), "IssueOrder::Attack");
+        app.add_observer(|report: On<OrderReport>, mut reports: ResMut<Reports>| {
+            reports.0.push((report.subordinate, report.kind.to_owned(), report.state));
+        });
+
+        let world = app.world_mut();
+        let commander = world.spawn_empty().id();
+        let grunt = world.spawn(CommandedBy(commander)).id();
+        let target = world.spawn_empty().id();
+
+        let action = ScoredAction {
+            action: Action { name: "Charge".into(), context: target, action_key: "IssueOrder::Attack".into() },
+            score: 1.,
+        };
+        let config = ActionTrackerSpawnConfig::builder().set_use_ticker(true).build();
+        let tracker = spawn_action_tracker(&mut world.commands(), commander, action, Some(&config), Default::default(), ActionTrackerState::ready(), ()).unwrap();
+        world.flush();
+
+        // Ticks dispatch the Action again until its Succeeded state is processed.
+        for _ in 0..3 {
+            world.run_schedule(FixedPostUpdate);
+            world.run_system_cached(handle_dispatch_to_user_actions).unwrap();
+        }
+
+        let order = world.get::<Order>(grunt).unwrap();
+        assert_eq!(order.state(), OrderState::Issued);
+        assert_eq!(order.targets, vec![target]);
+        assert_eq!(world.resource::<Reports>().0, vec![(grunt, "Attack".into(), OrderState::Issued)]);
+
+        world.run_system_cached(action_state_update_handler).unwrap();
+        assert_eq!(world.get::<ActionTrackerState>(tracker).unwrap().0, ActionState::Succeeded);
+    }
+}
@@ -0,0 +1,442 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+If a copy of the MPL was not distributed with this file,
+You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+//! Plans - running sequences of Actions produced by the planners (see the `goap` and `htn` modules).
+//!
+//! A plan runs as a sequence of entries in the AI's `ActionQueue` (see the `action_queue` module), so
+//! each step becomes Ready once the step before it succeeds. ActionHandlers for the steps get the owning
+//! AI as usual, and can update the step's state by sending `AiActionStateChangeRequest`s for the AI and
+//! the step's Action.
+//!
+//! If a step fails or gets cancelled, the queue drops the rest of the plan and the planner that made it
+//! is asked to replan (via `AiReplanRequested`), up to `PlanSettings::max_replans` times in a row.
+//! Past that, the AI gives up on the goal until it picks a different Action.
+//!
+//! Picking any Action other than the plan's goal drops the plan, as the AI has changed its mind.
+
+use bevy::platform::prelude::{String, ToOwned};
+use bevy::prelude::*;
+
+use crate::action_queue::{ActionQueueCommandsExt, ActionQueuePlugin, clear_action_queue, enqueue_action};
+use crate::action_state::{ActionState, AiActionStateChange};
+use crate::actions::{Action, ActionTemplate, ScoredAction};
+use crate::actionset::ActionSet;
+use crate::blackboard::BlackboardCommandsExt;
+use crate::context_fetchers::{ContextFetcherKeyToSystemMap, ensure_cf_queries};
+use crate::events::AiActionPicked;
+use crate::goap::GoapFact;
+use crate::smart_object::{ActionSetStore, SmartObjects};
+use crate::state_machines::AvailableActionSets;
+use crate::types::{ActionContextRef, ActionKey, AiEntity, CraniumList};
+
+/// Which planner a plan came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect)]
+pub enum PlanKind {
+    Goap,
+    Htn,
+}
+
+/// Tuning knobs for running plans.
+#[derive(Resource, Debug, Clone)]
+pub struct PlanSettings {
+    /// How many times in a row an AI may replan after a step fails before giving up on the goal.
+    pub max_replans: u32,
+    /// If set, the GOAP effects of successful steps are written to the AI's Blackboard.
+    pub apply_effects_on_success: bool,
+}
+
+impl Default for PlanSettings {
+    fn default() -> Self {
+        Self {
+            max_replans: 3,
+            apply_effects_on_success: true,
+        }
+    }
+}
+
+/// A single step of a plan about to be started.
+#[derive(Clone, Debug)]
+pub struct PlannedStep {
+    pub template: ActionTemplate,
+    pub context: ActionContextRef,
+}
+
+/// The plan an AI is currently executing.
+#[derive(Component, Debug, Clone)]
+pub struct AiPlan {
+    pub kind: PlanKind,
+    /// The ActionKey of the Action that made the AI plan (e.g. a GOAP goal).
+    pub goal: ActionKey,
+    /// The ActionTracker Entities of all steps, in order.
+    pub steps: CraniumList<Entity>,
+    pub current: usize,
+    pub replans: u32,
+}
+
+impl AiPlan {
+    pub fn current_step(&self) -> Option<Entity> {
+        self.steps.get(self.current).copied()
+    }
+}
+
+/// Links a step's ActionTracker to its plan.
+#[derive(Component, Debug, Clone)]
+pub struct AiPlanStep {
+    pub ai: AiEntity,
+    pub index: usize,
+    pub action_key: ActionKey,
+    pub effects: CraniumList<GoapFact>,
+}
+
+/// Marks that the AI could not achieve the goal; its planner will not plan for it until the AI picks another Action.
+#[derive(Component, Debug, Clone)]
+pub struct AiPlanFailed {
+    pub kind: PlanKind,
+    pub goal: ActionKey,
+}
+
+/// Signals that the AI has a new plan.
+#[derive(EntityEvent, Debug, Clone)]
+pub struct AiPlanCreated {
+    pub entity: AiEntity,
+    pub kind: PlanKind,
+    pub goal: ActionKey,
+    /// The names of the planned Actions, in order.
+    pub steps: CraniumList<String>,
+}
+
+/// Signals that the AI has finished (or given up on) its plan.
+#[derive(EntityEvent, Debug, Clone)]
+pub struct AiPlanFinished {
+    pub entity: AiEntity,
+    pub kind: PlanKind,
+    pub goal: ActionKey,
+    pub succeeded: bool,
+}
+
+/// Asks the planner of the specified kind to plan for the goal again, after a step of the previous plan failed.
+#[derive(EntityEvent, Debug, Clone)]
+pub struct AiReplanRequested {
+    pub entity: AiEntity,
+    pub kind: PlanKind,
+    pub goal: ActionKey,
+    /// How many times in a row the AI has replanned for this goal so far, including this time.
+    pub replans: u32,
+}
+
+/// Runs the template's ContextFetcher, returning the first Context (if any).
+pub(crate) fn first_context(
+    template: &ActionTemplate,
+    cf_map: &ContextFetcherKeyToSystemMap,
+    inputs: (AiEntity, Option<Entity>),
+    world: &World,
+) -> Option<ActionContextRef> {
+    let system = cf_map.mapping.get(&template.context_fetcher_name.0)?;
+    let mut system = system.write().ok()?;
+    system.run_readonly(inputs, world).ok()?.first().copied()
+}
+
+/// The stored ActionSets a planner may use for the AI: the AI's `SmartObjects` ActionSets (if it has
+/// the Component) plus the 'home' ActionSet the planning request came from, minus anything the AI's
+/// State Machine does not allow in its current state.
+pub fn plannable_actionsets<'a>(
+    world: &'a World,
+    ai: AiEntity,
+    home_actionset: &'a str,
+    available: &AvailableActionSets,
+) -> CraniumList<&'a ActionSet> {
+    let Some(store) = world.get_resource::<ActionSetStore>() else {
+        return CraniumList::new();
+    };
+
+    let mut keys: CraniumList<&str> = CraniumList::new();
+    keys.push(home_actionset);
+    if let Some(smart_objects) = world.get::<SmartObjects>(ai) {
+        keys.extend(smart_objects.actionset_refs.iter().map(|key| key.as_str()));
+    }
+    keys.sort_unstable();
+    keys.dedup();
+
+    keys.into_iter()
+        .filter(|key| available.is_allowed(ai, key))
+        .filter_map(|key| store.map_by_name.get(key))
+        .collect()
+}
+
+/// Takes all pending planning requests of the specified type off the AIs.
+///
+/// Requests for the goal the AI is already pursuing (planners' ActionHandlers keep asking while it
+/// does) or has given up on are dropped. If any requests remain, this also brings the ContextFetcher
+/// Systems up to date with the World, so that planners can run them.
+pub fn take_plan_requests<R: Component + Clone>(
+    world: &mut World,
+    goal_of: impl Fn(&R) -> &ActionKey,
+) -> Option<CraniumList<(Entity, R)>> {
+    let mut request_query = world.query::<(Entity, &R, Option<&AiPlan>, Option<&AiPlanFailed>)>();
+
+    let mut requests: CraniumList<(Entity, R)> = CraniumList::new();
+    let mut handled: CraniumList<Entity> = CraniumList::new();
+    for (ai, request, plan, failed) in request_query.iter(world) {
+        handled.push(ai);
+        let goal = goal_of(request);
+        if plan.is_some_and(|plan| plan.goal == *goal) || failed.is_some_and(|failed| failed.goal == *goal) {
+            continue;
+        }
+        requests.push((ai, request.clone()));
+    }
+
+    for ai in handled {
+        world.entity_mut(ai).remove::<R>();
+    }
+
+    if requests.is_empty() {
+        return None;
+    }
+
+    // Planning resolves ContextFetchers, which need to be initialized first.
+    ensure_cf_queries(world);
+
+    Some(requests)
+}
+
+/// Queues the ActionTrackers for a plan and makes it the AI's current plan.
+///
+/// Empty plans are ignored, as there is nothing to run.
+pub fn start_plan(
+    world: &mut World,
+    ai: AiEntity,
+    kind: PlanKind,
+    goal: ActionKey,
+    steps: CraniumList<PlannedStep>,
+    replans: u32,
+) {
+    if steps.is_empty() {
+        return;
+    }
+
+    let names: CraniumList<String> = steps.iter().map(|step| step.template.name.to_owned()).collect();
+
+    #[cfg(feature = "logging")]
+    bevy::log::debug!("start_plan: AI {:?} starting {:?} plan {:?} for {:?}", ai, kind, &names, &goal);
+
+    // Any previous plan is superseded.
+    if world.entity_mut(ai).take::<AiPlan>().is_some() {
+        clear_action_queue(world, ai);
+    }
+
+    let trackers: CraniumList<Entity> = steps
+        .into_iter()
+        .enumerate()
+        .map(|(index, step)| {
+            let action = ScoredAction {
+                action: Action {
+                    name: step.template.name.to_owned(),
+                    context: step.context,
+                    action_key: step.template.action_key.to_owned(),
+                },
+                score: step.template.priority,
+            };
+            let tracker = enqueue_action(world, ai, action, None);
+            world.entity_mut(tracker).insert(AiPlanStep {
+                ai,
+                index,
+                action_key: step.template.action_key,
+                effects: step.template.effects,
+            });
+            tracker
+        })
+        .collect();
+
+    world.entity_mut(ai).insert(AiPlan {
+        kind,
+        goal: goal.to_owned(),
+        steps: trackers,
+        current: 0,
+        replans,
+    });
+    world.trigger(AiPlanCreated { entity: ai, kind, goal, steps: names });
+}
+
+/// Marks the goal as failed for the AI.
+pub fn give_up_on_goal(commands: &mut Commands, ai: AiEntity, kind: PlanKind, goal: ActionKey) {
+    #[cfg(feature = "logging")]
+    bevy::log::debug!("give_up_on_goal: AI {:?} gave up on {:?}", ai, &goal);
+
+    if let Ok(mut cmds) = commands.get_entity(ai) {
+        cmds.remove::<AiPlan>().insert(AiPlanFailed { kind, goal: goal.to_owned() });
+    }
+    commands.trigger(AiPlanFinished { entity: ai, kind, goal, succeeded: false });
+}
+
+/// An Observer moving plans forward as their steps finish.
+pub fn advance_plans(
+    event: On<AiActionStateChange>,
+    step_query: Query<&AiPlanStep>,
+    mut plan_query: Query<&mut AiPlan>,
+    settings: Option<Res<PlanSettings>>,
+    mut commands: Commands,
+) {
+    let Ok(step) = step_query.get(event.entity) else {
+        return;
+    };
+    let Ok(mut plan) = plan_query.get_mut(step.ai) else {
+        return;
+    };
+    if plan.current_step() != Some(event.entity) {
+        return;
+    }
+
+    let settings = settings.map(|res| res.clone()).unwrap_or_default();
+
+    match event.to_state {
+        ActionState::Succeeded => {
+            if settings.apply_effects_on_success && let Ok(mut cmds) = commands.get_entity(step.ai) {
+                for effect in step.effects.iter() {
+                    cmds.set_blackboard_value(effect.fact.to_owned(), effect.value);
+                }
+            }
+
+            plan.current += 1;
+            if plan.current_step().is_none() {
+                #[cfg(feature = "logging")]
+                bevy::log::debug!("advance_plans: AI {:?} completed the plan for {:?}", step.ai, &plan.goal);
+
+                commands.trigger(AiPlanFinished { entity: step.ai, kind: plan.kind, goal: plan.goal.to_owned(), succeeded: true });
+                commands.entity(step.ai).remove::<AiPlan>();
+            }
+        },
+        ActionState::Failed | ActionState::Cancelled => {
+            // The ActionQueue cancels the remaining steps for us.
+            let goal = plan.goal.to_owned();
+            if plan.replans < settings.max_replans {
+                #[cfg(feature = "logging")]
+                bevy::log::debug!("advance_plans: AI {:?} - step {:?} did not succeed, replanning.", step.ai, &step.action_key);
+
+                commands.entity(step.ai).remove::<AiPlan>();
+                commands.trigger(AiReplanRequested { entity: step.ai, kind: plan.kind, goal, replans: plan.replans + 1 });
+            } else {
+                give_up_on_goal(&mut commands, step.ai, plan.kind, goal);
+            }
+        },
+        _ => {},
+    }
+}
+
+/// An Observer dropping the AI's plan once it picks a different Action.
+pub fn abandon_plans(
+    event: On<AiActionPicked>,
+    plan_query: Query<&AiPlan>,
+    mut commands: Commands,
+) {
+    let ai = event.entity;
+    let Ok(mut cmds) = commands.get_entity(ai) else {
+        return;
+    };
+
+    // A new decision is a new chance to achieve previously failed goals.
+    cmds.remove::<AiPlanFailed>();
+
+    if let Ok(plan) = plan_query.get(ai) && plan.goal != event.action_key {
+        cmds.remove::<AiPlan>().clear_action_queue();
+    }
+}
+
+/// Sets up running plans; added by the planner Plugins, so you should not need to add it yourself.
+pub struct PlansPlugin;
+
+impl Plugin for PlansPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<ActionQueuePlugin>() {
+            app.add_plugins(ActionQueuePlugin);
+        }
+
+        app
+            .init_resource::<PlanSettings>()
+            .add_observer(advance_plans)
+            .add_observer(abandon_plans)
+        ;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bevy::platform::prelude::vec;
+    use crate::action_runtime::ActionTrackerState;
+    use crate::action_state::{ActionStateUpdatesPlugin, AiActionStateChangeRequest, action_state_update_handler};
+    use crate::context_fetchers::{AcceptsContextFetcherRegistrations, ContextFetcherInputs, ContextFetcherOutputs};
+    use crate::goap::{GoapGoalRequest, GoapPlugin, plan_goap_goals};
+    use super::*;
+
+    fn self_context(In((ai, _)): ContextFetcherInputs) -> ContextFetcherOutputs {
+        vec![ai]
+    }
+
+    fn state_of(app: &App, tracker: Entity) -> ActionState {
+        *app.world().get::<ActionTrackerState>(tracker).unwrap().get_state()
+    }
+
+    fn set_state(app: &mut App, tracker: Entity, to_state: ActionState) {
+        let action = app.world().get::<AiPlanStep>(tracker).unwrap().action_key.to_owned();
+        app.world_mut().write_message(AiActionStateChangeRequest { entity: tracker, action, to_state });
+        app.world_mut().run_system_cached(action_state_update_handler).unwrap();
+        app.world_mut().flush();
+        app.world_mut().run_system_cached(action_state_update_handler).unwrap();
+    }
+
+    fn plan_steps(app: &App, ai: Entity) -> CraniumList<Entity> {
+        app.world().get::<AiPlan>(ai).unwrap().steps.to_owned()
+    }
+
+    #[test]
+    fn test_plans_run_as_queued_chains() {
+        let mut app = App::new();
+        app.add_plugins((ActionStateUpdatesPlugin, GoapPlugin))
+            .init_resource::<ActionSetStore>()
+            .insert_resource(PlanSettings { max_replans: 1, apply_effects_on_success: true });
+        app.register_context_fetcher(self_context, "test::Self");
+
+        let template = |key: &str| ActionTemplate::new(key, "test::Self", vec![], 1., key, None, None);
+        let actionset = ActionSet::new("Looter", vec![
+            template("GoapGoal::Loot").with_goap(vec![], vec![GoapFact::is("looted")]),
+            template("GetKey").with_goap(vec![], vec![GoapFact::is("has_key")]),
+            template("OpenDoor").with_goap(vec![GoapFact::is("has_key")], vec![GoapFact::is("door_open")]),
+            template("LootChest").with_goap(vec![GoapFact::is("door_open")], vec![GoapFact::is("looted")]),
+        ]);
+        app.world_mut().resource_mut::<ActionSetStore>().map_by_name.insert("Looter".into(), actionset);
+
+        let ai = app.world_mut().spawn(GoapGoalRequest { goal: "GoapGoal::Loot".into(), replans: 0 }).id();
+        plan_goap_goals(app.world_mut());
+
+        // The plan is queued as a whole; only the first step can start right away.
+        let steps = plan_steps(&app, ai);
+        assert_eq!(steps.len(), 3);
+        assert_eq!(state_of(&app, steps[0]), ActionState::Ready);
+        assert_eq!(state_of(&app, steps[1]), ActionState::Queued);
+        assert_eq!(state_of(&app, steps[2]), ActionState::Queued);
+
+        set_state(&mut app, steps[0], ActionState::Succeeded);
+        assert_eq!(state_of(&app, steps[1]), ActionState::Ready);
+        assert_eq!(app.world().get::<AiPlan>(ai).unwrap().current_step(), Some(steps[1]));
+
+        // A failed step drops the rest of the plan and replans from the facts achieved so far.
+        set_state(&mut app, steps[1], ActionState::Failed);
+        assert_eq!(state_of(&app, steps[2]), ActionState::Cancelled);
+        assert!(app.world().get::<AiPlan>(ai).is_none());
+        assert_eq!(app.world().get::<GoapGoalRequest>(ai).unwrap().replans, 1);
+
+        plan_goap_goals(app.world_mut());
+        let replanned = plan_steps(&app, ai);
+        assert_eq!(replanned.len(), 2);
+        assert_eq!(app.world().get::<AiPlanStep>(replanned[0]).unwrap().action_key, "OpenDoor");
+
+        // Out of replans - the AI gives up on the goal.
+        set_state(&mut app, replanned[0], ActionState::Cancelled);
+        assert!(app.world().get::<AiPlan>(ai).is_none());
+        assert!(app.world().get::<GoapGoalRequest>(ai).is_none());
+        assert_eq!(app.world().get::<AiPlanFailed>(ai).unwrap().goal, "GoapGoal::Loot");
+    }
+}
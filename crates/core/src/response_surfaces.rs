@@ -0,0 +1,496 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+If a copy of the MPL was not distributed with this file,
+You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+//! Utility Response Surfaces - the two-input big siblings of Utility Curves.
+//!
+//! A Utility Curve maps one normalized input to a score. Most of the time, that is all you need -
+//! if two inputs matter, you use two Considerations and their scores get multiplied together.
+//!
+//! However, multiplying two Curves can only ever express decisions where the two inputs
+//! are *independent* of each other. Some decisions just don't work like that.
+//!
+//! Consider a Flee Action scored on Health and the number of nearby enemies.
+//! At full Health, we only want to flee from a big crowd; at low Health, even one enemy is too many;
+//! and at *very* low Health against a big crowd, fleeing is pointless so we might as well go out swinging.
+//! No pair of 1D Curves multiplied together can draw that shape - but a 2D surface can.
+//!
+//! Response Surfaces have a unit square domain (both inputs are normalized to 0.0-1.0
+//! the same way Consideration inputs are for Curves) and a unit interval range,
+//! so the same invariants as for Utility Curves apply, just with one more input.
+//!
+//! To use a Response Surface, add a `surface` entry to a Consideration in ActionSet data
+//! (see `ResponseSurfaceInputData`). The Consideration's own input becomes the first (x) input,
+//! the Consideration named in the `surface` entry provides the second (y) input, and the
+//! Consideration's Curve gets applied to the surface's output (use `Linear` to pass it through as-is).
+//!
+//! The most important items in this module are:
+//! 1) The `UtilityResponseSurface` trait, which defines what a Response Surface is.
+//! 2) The `BilinearGridSurface`, a grid of values that gets interpolated - the data-friendly option.
+//! 3) The `AnalyticSurface`, a wrapper over any plain function - the code-friendly option.
+//! 4) The `UtilityResponseSurfaceRegistry` Resource, which resolves Surface keys used in ActionSets.
+
+use bevy::prelude::*;
+use bevy::platform::prelude::{String, ToOwned};
+use bevy::platform::sync::Arc;
+use crate::errors::CurveRegistrationError;
+use crate::identifiers::CurveIdentifier;
+use crate::types::{ActionScore, CraniumKvMap, CraniumList, MIN_CONSIDERATION_SCORE, MAX_CONSIDERATION_SCORE};
+
+#[cfg(feature = "actionset_loader")]
+use serde::{Serialize, Deserialize};
+
+/// Surface functions suitable for Utility scoring purposes; a Utility Curve with two inputs.
+///
+/// All eligible functions must have a unit square domain (i.e. <0.0; 1.0> for both inputs)
+/// **AND** an output range of a unit interval - or at least you must be willing to allow
+/// them to be clamped to it by using the `UtilityResponseSurface::sample_safe(&self, x, y)` method.
+pub trait UtilityResponseSurface: Send + Sync {
+    /// Samples the Surface without any clamping.
+    ///
+    /// Generally, you should use `sample_safe()` instead, which handles the clamping for you.
+    fn sample_unchecked(&self, x: ActionScore, y: ActionScore) -> ActionScore;
+
+    /// **IMPORTANT!** Use this method for sampling for Utility purposes.
+    ///
+    /// Samples a given point on the Surface, clamping **both** inputs and the output to the unit interval.
+    fn sample_safe(&self, x: ActionScore, y: ActionScore) -> ActionScore {
+        let clamp_x = x.clamp(MIN_CONSIDERATION_SCORE, MAX_CONSIDERATION_SCORE);
+        let clamp_y = y.clamp(MIN_CONSIDERATION_SCORE, MAX_CONSIDERATION_SCORE);
+        let raw = self.sample_unchecked(clamp_x, clamp_y);
+        raw.clamp(MIN_CONSIDERATION_SCORE, MAX_CONSIDERATION_SCORE)
+    }
+}
+
+/// A Response Surface defined by a grid of values, bilinearly interpolated in between.
+///
+/// The grid is evenly spaced and includes the edges of the unit square, i.e. a 2x2 grid
+/// holds just the four corners, while a 3x3 grid also has the edge midpoints and the center.
+///
+/// Values are stored row-major with rows along the y axis, i.e. value `(x_idx, y_idx)`
+/// lives at index `y_idx * width + x_idx`. Visually, if you write the grid out as a
+/// table, the x input picks the column and the y input picks the row.
+///
+/// This is the easiest kind of Surface to author in data and has a predictable,
+/// constant cost (four lookups and three LERPs) no matter what shape it describes.
+///
+/// The values are stored behind an Arc, so cloning a BilinearGridSurface is cheap.
+#[derive(Clone, Debug)]
+pub struct BilinearGridSurface {
+    width: usize,
+    height: usize,
+    values: Arc<[ActionScore]>,
+}
+
+impl BilinearGridSurface {
+    /// The smallest allowed grid dimension (i.e. just the two edges).
+    pub const MIN_GRID_SIZE: usize = 2;
+
+    /// Creates a new grid Surface from row-major values (see the struct docs for the layout).
+    ///
+    /// Values are clamped to the unit interval.
+    ///
+    /// Fails if either dimension is below `MIN_GRID_SIZE`, the dimensions are too large to
+    /// multiply out, the number of values does not match them, or any value is NaN.
+    pub fn new<I: IntoIterator<Item = ActionScore>>(
+        width: usize,
+        height: usize,
+        values: I
+    ) -> Result<Self, CurveRegistrationError> {
+        let values: Arc<[ActionScore]> = values
+            .into_iter()
+            .map(|val| val.clamp(MIN_CONSIDERATION_SCORE, MAX_CONSIDERATION_SCORE))
+            .collect()
+        ;
+
+        let bad_dims = width < Self::MIN_GRID_SIZE || height < Self::MIN_GRID_SIZE;
+        let bad_len = width.checked_mul(height) != Some(values.len());
+
+        if bad_dims || bad_len || values.iter().any(|val| val.is_nan()) {
+            return Err(CurveRegistrationError::InvalidTable)
+        }
+
+        Ok(Self { width, height, values })
+    }
+
+    /// Samples any Surface into a grid of the given dimensions.
+    ///
+    /// This is the 2D equivalent of baking a Utility Curve and is handy for
+    /// turning an expensive AnalyticSurface into a cheap and constant-cost one.
+    pub fn bake<S: UtilityResponseSurface + ?Sized>(surface: &S, width: usize, height: usize) -> Self {
+        let width = width.max(Self::MIN_GRID_SIZE);
+        let height = height.max(Self::MIN_GRID_SIZE);
+        let last_x = (width - 1) as ActionScore;
+        let last_y = (height - 1) as ActionScore;
+
+        let values = (0..height)
+            .flat_map(|y_idx| (0..width).map(move |x_idx| (x_idx, y_idx)))
+            .map(|(x_idx, y_idx)| surface.sample_safe(x_idx as ActionScore / last_x, y_idx as ActionScore / last_y))
+            .collect()
+        ;
+
+        Self { width, height, values }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    fn value_at(&self, x_idx: usize, y_idx: usize) -> ActionScore {
+        self.values[y_idx * self.width + x_idx]
+    }
+}
+
+/// Finds the lower grid index and the fractional offset from it for a normalized input.
+fn grid_position(t: ActionScore, size: usize) -> (usize, ActionScore) {
+    let last_idx = size - 1;
+    let position = t.clamp(MIN_CONSIDERATION_SCORE, MAX_CONSIDERATION_SCORE) * last_idx as ActionScore;
+
+    // Position is never negative, so truncating is the same as flooring here.
+    let lower_idx = (position as usize).min(last_idx - 1);
+    (lower_idx, position - lower_idx as ActionScore)
+}
+
+impl UtilityResponseSurface for BilinearGridSurface {
+    fn sample_unchecked(&self, x: ActionScore, y: ActionScore) -> ActionScore {
+        let (x_idx, x_frac) = grid_position(x, self.width);
+        let (y_idx, y_frac) = grid_position(y, self.height);
+
+        let bottom_left = self.value_at(x_idx, y_idx);
+        let bottom_right = self.value_at(x_idx + 1, y_idx);
+        let top_left = self.value_at(x_idx, y_idx + 1);
+        let top_right = self.value_at(x_idx + 1, y_idx + 1);
+
+        let bottom = bottom_left + (bottom_right - bottom_left) * x_frac;
+        let top = top_left + (top_right - top_left) * x_frac;
+        bottom + (top - bottom) * y_frac
+    }
+}
+
+/// A Response Surface defined by a plain function of the two normalized inputs.
+///
+/// This is the most flexible kind of Surface, but the cost is entirely up to the function.
+/// If it gets expensive, consider baking it with `BilinearGridSurface::bake()`.
+///
+/// For example, a Surface that scores highest when both inputs are close to each other:
+/// `AnalyticSurface::new(|x, y| 1. - (x - y).abs())`.
+#[derive(Clone)]
+pub struct AnalyticSurface<F: Fn(ActionScore, ActionScore) -> ActionScore + Send + Sync> {
+    func: F,
+}
+
+impl<F: Fn(ActionScore, ActionScore) -> ActionScore + Send + Sync> AnalyticSurface<F> {
+    pub const fn new(func: F) -> Self {
+        Self { func }
+    }
+}
+
+impl<F: Fn(ActionScore, ActionScore) -> ActionScore + Send + Sync> UtilityResponseSurface for AnalyticSurface<F> {
+    fn sample_unchecked(&self, x: ActionScore, y: ActionScore) -> ActionScore {
+        (self.func)(x, y)
+    }
+}
+
+/// All Response Surfaces the library knows how to store and resolve by key.
+#[derive(Clone)]
+pub enum SupportedResponseSurface {
+    /// A grid of values, interpolated bilinearly (registered from code or defined in data).
+    ///
+    /// **COST:** Cheap and constant - four lookups and three LERPs.
+    Grid(BilinearGridSurface),
+
+    /// A user-defined Surface type registered in the UtilityResponseSurfaceRegistry.
+    ///
+    /// Due to the Arc<dyn T> overhead, these will be a bit less performant than
+    /// an equivalent Grid Surface would be, on top of whatever the Surface itself costs.
+    Custom(Arc<dyn UtilityResponseSurface>),
+}
+
+impl core::fmt::Debug for SupportedResponseSurface {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Grid(grid) => f.debug_tuple("Grid").field(grid).finish(),
+            Self::Custom(_) => f.debug_tuple("Custom").finish(),
+        }
+    }
+}
+
+impl UtilityResponseSurface for SupportedResponseSurface {
+    fn sample_unchecked(&self, x: ActionScore, y: ActionScore) -> ActionScore {
+        match self {
+            Self::Grid(grid) => grid.sample_unchecked(x, y),
+            Self::Custom(arc) => arc.sample_safe(x, y),
+        }
+    }
+}
+
+/// A map that lets us request Response Surfaces by a string key and register new entries.
+///
+/// Unlike Curves, there are no built-in Surfaces; all keys are user-provided.
+#[derive(Resource, Clone, Default)]
+pub struct UtilityResponseSurfaceRegistry {
+    mapping: CraniumKvMap<String, SupportedResponseSurface>
+}
+
+impl UtilityResponseSurfaceRegistry {
+    pub fn get_surface_by_name<S: core::borrow::Borrow<str>>(&self, name: S) -> Option<SupportedResponseSurface> {
+        self.mapping.get(name.borrow()).cloned()
+    }
+
+    /// Registers a Surface under a key, returning the previous registration (if any).
+    pub fn register_surface(
+        &mut self,
+        surface: SupportedResponseSurface,
+        name: String
+    ) -> Option<SupportedResponseSurface> {
+        self.mapping.insert(name, surface)
+    }
+
+    /// Registers a Surface defined in ActionSet data.
+    ///
+    /// Fails if the definition contains an invalid grid.
+    pub fn register_surface_definition(
+        &mut self,
+        definition: &UtilityResponseSurfaceDefinition,
+    ) -> Result<SupportedResponseSurface, CurveRegistrationError> {
+        use core::borrow::Borrow;
+        let name: &str = definition.name.borrow();
+
+        let surface = match &definition.source {
+            UtilityResponseSurfaceDefinitionSource::Grid { width, height, values } => {
+                SupportedResponseSurface::Grid(
+                    BilinearGridSurface::new(*width, *height, values.iter().copied())?
+                )
+            }
+        };
+
+        self.register_surface(surface.clone(), name.to_owned());
+        Ok(surface)
+    }
+}
+
+
+/// A Response Surface defined in ActionSet data rather than registered from code.
+///
+/// Every definition gets registered in the `UtilityResponseSurfaceRegistry` under the
+/// definition's name, so it can be used as a Surface key by any Consideration, in any
+/// ActionSet, once the defining ActionSet is loaded.
+#[derive(Clone, Debug, Reflect)]
+#[cfg_attr(feature = "actionset_loader", derive(Serialize, Deserialize))]
+pub struct UtilityResponseSurfaceDefinition {
+    /// The key the defined Surface will be registered under.
+    pub name: CurveIdentifier,
+
+    /// What the Surface is built from.
+    pub source: UtilityResponseSurfaceDefinitionSource,
+}
+
+/// The supported ways of defining a Response Surface in data.
+#[derive(Clone, Debug, Reflect)]
+#[cfg_attr(feature = "actionset_loader", derive(Serialize, Deserialize))]
+pub enum UtilityResponseSurfaceDefinitionSource {
+    /// A `BilinearGridSurface`; values are row-major (the x input picks the column, the y input picks the row).
+    Grid {
+        width: usize,
+        height: usize,
+        values: CraniumList<ActionScore>,
+    },
+}
+
+/// A System that registers any Response Surfaces defined in the ActionSets currently in the `ActionSetStore`.
+///
+/// ActionSets are processed in order of their names, and each name is only registered from data
+/// once, so the first definition of a name wins. Definitions that cannot be registered are logged
+/// and skipped.
+///
+/// This is meant to run whenever the `ActionSetStore` changes (which is how the CraniumPlugin
+/// schedules it), so the defined Surfaces become available shortly after their ActionSet gets stored.
+pub fn register_actionset_surface_definitions(
+    actionset_store: Res<crate::smart_object::ActionSetStore>,
+    mut registry: ResMut<UtilityResponseSurfaceRegistry>,
+    mut registered_from_data: Local<bevy::platform::collections::HashSet<String>>,
+) {
+    let mut actionsets: CraniumList<_> = actionset_store.map_by_name.iter().collect();
+    actionsets.sort_by_key(|(name, _)| *name);
+
+    for (_, actionset) in actionsets {
+        for definition in actionset.surfaces.iter() {
+            let name: &str = core::borrow::Borrow::borrow(&definition.name);
+            if registered_from_data.contains(name) {
+                continue;
+            }
+
+            let _res = registry.register_surface_definition(definition);
+            if _res.is_ok() {
+                registered_from_data.insert(name.to_owned());
+            }
+
+            #[cfg(feature = "logging")]
+            match _res {
+                Ok(_) => bevy::log::debug!(
+                    "register_actionset_surface_definitions: Registered Surface {:?} from ActionSet {:?}",
+                    &definition.name, &actionset.name,
+                ),
+                Err(err) => bevy::log::error!(
+                    "register_actionset_surface_definitions: Failed to register Surface {:?} from ActionSet {:?} - {:?}",
+                    &definition.name, &actionset.name, err,
+                ),
+            }
+        }
+    }
+}
+
+
+/// Something that allows us to register a UtilityResponseSurface to the World.
+///
+/// Note that for convenience, the first registration attempt
+/// will initialize *an empty registry* if one does not exist yet, so
+/// you don't need to use `app.init_resource::<UtilityResponseSurfaceRegistry>()`
+/// unless you want to be explicit about it.
+pub trait AcceptsResponseSurfaceRegistrations {
+    fn register_response_surface<
+        S: UtilityResponseSurface + 'static,
+        IS: Into<String>
+    >(
+        &mut self,
+        surface: S,
+        key: IS,
+    ) -> &mut Self;
+}
+
+impl AcceptsResponseSurfaceRegistrations for World {
+    fn register_response_surface<
+        S: UtilityResponseSurface + 'static,
+        IS: Into<String>
+    >(
+        &mut self,
+        surface: S,
+        key: IS,
+    ) -> &mut Self {
+        let mut registry = self.get_resource_or_init::<UtilityResponseSurfaceRegistry>();
+        let surface_key: String = key.into();
+
+        let old = registry.register_surface(
+            SupportedResponseSurface::Custom(Arc::new(surface)),
+            surface_key.to_owned(),
+        );
+
+        match old {
+            None => {},
+            Some(_) => {
+                #[cfg(feature = "logging")]
+                bevy::log::warn!(
+                    "Detected a key collision for key {:?}. Ejecting previous registration...",
+                    surface_key
+                );
+            }
+        };
+
+        self
+    }
+}
+
+impl AcceptsResponseSurfaceRegistrations for App {
+    fn register_response_surface<
+        S: UtilityResponseSurface + 'static,
+        IS: Into<String>
+    >(
+        &mut self,
+        surface: S,
+        key: IS,
+    ) -> &mut Self {
+        self.world_mut().register_response_surface(surface, key);
+        self
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bevy::platform::prelude::vec;
+    use super::*;
+
+    #[test]
+    fn test_grid_surface_bilinear_interpolation() {
+        // Rows are y: bottom row (y=0) is [0, 1], top row (y=1) is [1, 0].
+        let grid = BilinearGridSurface::new(2, 2, [0., 1., 1., 0.]).unwrap();
+
+        assert_eq!(grid.sample_safe(0., 0.), 0.);
+        assert_eq!(grid.sample_safe(1., 0.), 1.);
+        assert_eq!(grid.sample_safe(0., 1.), 1.);
+        assert_eq!(grid.sample_safe(1., 1.), 0.);
+        assert_eq!(grid.sample_safe(0.5, 0.5), 0.5);
+        assert!((grid.sample_safe(0.25, 0.) - 0.25).abs() < 1e-6);
+
+        // Out of range inputs get clamped.
+        assert_eq!(grid.sample_safe(-3., 5.), 1.);
+
+        assert!(BilinearGridSurface::new(2, 2, [0., 1., 1.]).is_err());
+        assert!(BilinearGridSurface::new(1, 3, [0., 1., 1.]).is_err());
+        assert_eq!(BilinearGridSurface::new(usize::MAX, 2, [0., 1., 1., 0.]).err(), Some(CurveRegistrationError::InvalidTable));
+    }
+
+    #[test]
+    fn test_baked_analytic_surface() {
+        let analytic = AnalyticSurface::new(|x, y| x * y);
+        let baked = BilinearGridSurface::bake(&analytic, 3, 3);
+
+        assert_eq!(baked.width(), 3);
+        assert_eq!(baked.sample_safe(1., 1.), 1.);
+        assert_eq!(baked.sample_safe(0.5, 1.), 0.5);
+        assert_eq!(baked.sample_safe(0.5, 0.5), analytic.sample_safe(0.5, 0.5));
+    }
+
+    #[test]
+    fn test_surface_definitions() {
+        let mut registry = UtilityResponseSurfaceRegistry::default();
+
+        let good = UtilityResponseSurfaceDefinition {
+            name: "test::Grid".into(),
+            source: UtilityResponseSurfaceDefinitionSource::Grid { width: 2, height: 2, values: [0., 0., 1., 1.].into() },
+        };
+        let bad = UtilityResponseSurfaceDefinition {
+            name: "test::Bad".into(),
+            source: UtilityResponseSurfaceDefinitionSource::Grid { width: 3, height: 2, values: [0., 1.].into() },
+        };
+
+        assert!(registry.register_surface_definition(&good).is_ok());
+        assert_eq!(registry.register_surface_definition(&bad).err(), Some(CurveRegistrationError::InvalidTable));
+
+        let surface = registry.get_surface_by_name("test::Grid").unwrap();
+        assert_eq!(surface.sample_safe(0.3, 0.75), 0.75);
+        assert!(registry.get_surface_by_name("test::Bad").is_none());
+    }
+
+    #[test]
+    fn test_actionset_surface_definitions_register_once() {
+        use crate::actionset::ActionSet;
+        use crate::smart_object::ActionSetStore;
+
+        let grid = |values: [ActionScore; 4]| UtilityResponseSurfaceDefinition {
+            name: "test::Shared".into(),
+            source: UtilityResponseSurfaceDefinitionSource::Grid { width: 2, height: 2, values: values.into() },
+        };
+
+        let mut world = World::new();
+        world.init_resource::<UtilityResponseSurfaceRegistry>();
+        let mut store = ActionSetStore::default();
+        for (name, values) in [("c", [0.5; 4]), ("a", [0.; 4]), ("b", [1.; 4])] {
+            store.map_by_name.insert(name.into(), ActionSet::new(name, [].into()).with_surfaces(vec![grid(values)]));
+        }
+        world.insert_resource(store);
+
+        for _ in 0..3 {
+            world.run_system_cached(register_actionset_surface_definitions).unwrap();
+        }
+
+        // The ActionSets are processed by name, so "a" wins the shared name.
+        let registry = world.resource::<UtilityResponseSurfaceRegistry>();
+        assert_eq!(registry.get_surface_by_name("test::Shared").unwrap().sample_safe(0.5, 0.5), 0.);
+    }
+}
@@ -1,41 +1,613 @@
-/* 
-This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0. 
-If a copy of the MPL was not distributed with this file, 
-You can obtain one at https://mozilla.org/MPL/2.0/. 
+/*
+This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+If a copy of the MPL was not distributed with this file,
+You can obtain one at https://mozilla.org/MPL/2.0/.
 */
+
+//! Senses - the perception layer that feeds an AI's Memories.
+//!
+//! Rather than every Consideration and ContextFetcher looking at the true state of the World,
+//! AIs can *perceive* it through their Senses, which write what they notice into the AI's `Memories`.
+//! Decision logic can then work off of the Memories, giving you AIs that can be snuck past,
+//! lose track of their targets, investigate noises and so on.
+//!
+//! Senses work a lot like Considerations: you implement the `Sense` trait, register it under a key
+//! (`app.register_sense(MySense, "MySense")`), and list the keys an AI should use in its `Senses` Component.
+//! Each AI runs each of its Senses at its own cadence, further throttled by the AI's LOD
+//! (see `LodUpdateFrequency::senses`); inactive AIs do not sense anything at all.
+//!
+//! A few Senses are provided out of the box (but not registered - pick your own keys and parameters):
+//! - `VisionSense` - sees `Perceivable` Entities within a range and a view cone, with an optional
+//!   line-of-sight hook for occlusion checks (raycasts, grid visibility, whatever your game uses).
+//! - `HearingSense` - hears `Stimulus` Messages of the `Noise` kind emitted by game code.
+//! - `StimulusSense` - perceives `Stimulus` Messages of any kinds you specify (damage, dropped items...).
+//!
+//! Positions are read from `GlobalTransform`s; the sensing Entity is the AI's Pawn if it has one
+//! and the AI itself otherwise.
+
+use core::time::Duration;
+
+use bevy::platform::prelude::String;
+use bevy::platform::sync::Arc;
 use bevy::prelude::*;
-use crate::ai::*;
-use crate::memories::{Memories};
 
-// An AI 
-#[derive(Component)]
-struct Senses(crate::types::CraniumList<Box<dyn Sense>>);
+use crate::identifiers::SenseIdentifier;
+use crate::lods::{AiLevelOfDetail, LodUpdateFrequency};
+use crate::events::AiDecisionRequested;
+use crate::memories::Memories;
+use crate::pawn::Pawn;
+use crate::smart_object::SmartObjects;
+use crate::stimuli::{ActiveStimuli, ActiveStimulus, StimulusKind, StimulusPriority, StimulusRouting};
+use crate::types::{ActionScore, AiEntity, CraniumKvMap, CraniumList, PawnEntityRef};
 
+/// Marks an Entity as something AIs can see with a `VisionSense`.
+#[derive(Component, Default, Debug, Clone, Copy)]
+pub struct Perceivable;
+
+/// Something a Sense noticed.
+#[derive(Debug, Clone)]
+pub struct Perception {
+    pub entity: Entity,
+    /// Where the Entity was perceived to be, if the Sense can tell.
+    pub position: Option<Vec3>,
+    /// How sure the Sense is about the Perception, from 0.0 to 1.0.
+    pub confidence: ActionScore,
+    /// The priority of the (new) Stimulus that caused this Perception, if any.
+    pub stimulus_priority: Option<StimulusPriority>,
+}
 
-trait Sense: Send + Sync {
-    fn update(&self, memories: &mut Memories, world: &World);
+/// Signals that an AI has perceived something and recorded it in its Memories.
+/// 
+/// Used to share knowledge with other AIs (see the `knowledge_sharing` module), 
+/// but feel free to hook your own logic into it too.
+#[derive(EntityEvent, Debug, Clone)]
+pub struct PerceptionsRecorded {
+    pub entity: AiEntity,
+    pub perceptions: CraniumList<Perception>,
+    /// When the Perceptions happened (in virtual/game time elapsed).
+    pub perceived_at: Duration,
 }
 
-struct VisionSense {
-    range: f32,
+/// Everything a Sense gets to work with when it runs for an AI.
+pub struct SenseInputs<'a> {
+    pub ai: AiEntity,
+    pub pawn: PawnEntityRef,
+    /// The Entity doing the sensing - the Pawn if there is one, the AI otherwise.
+    pub sensor: Entity,
+    pub sensor_transform: Option<GlobalTransform>,
+    pub now: Duration,
+    /// When this Sense last ran for this AI; None if this is the first time.
+    pub last_run: Option<Duration>,
+    /// The `ActiveStimuli::next_sequence()` as of the last time this Sense ran for this AI;
+    /// Stimuli that are newer than that have not been perceived by it yet.
+    pub stimuli_seen: u64,
+    /// All `Perceivable` Entities and their positions.
+    pub perceivables: &'a [(Entity, Vec3)],
+    /// All currently active Stimuli.
+    pub stimuli: &'a [ActiveStimulus],
+}
+
+/// A way for an AI to perceive the World.
+///
+/// Senses get read-only access to the World and report whatever they noticed
+/// as `Perception`s, which are then recorded in the AI's Memories.
+pub trait Sense: Send + Sync + 'static {
+    fn sense(&self, inputs: &SenseInputs, world: &World, perceived: &mut CraniumList<Perception>);
+
+    /// Whether this Sense can perceive Stimuli of the specified kind at all.
+    /// 
+    /// Used to route interrupting Stimuli; Senses that do not use Stimuli can leave this as is.
+    fn perceives(&self, _kind: &StimulusKind) -> bool {
+        false
+    }
+}
+
+/// A user-provided line-of-sight check, called as `(world, sensor, target) -> visible?`.
+pub type LineOfSightFn = Arc<dyn Fn(&World, Entity, Entity) -> bool + Send + Sync>;
+
+/// A Sense that sees `Perceivable` Entities within a range and a view cone.
+#[derive(Clone)]
+pub struct VisionSense {
+    pub range: f32,
+    /// Half of the view cone angle, in radians; PI (or more) for all-around vision.
+    pub half_fov: f32,
+    /// An optional occlusion check; everything in the cone is visible if None.
+    pub line_of_sight: Option<LineOfSightFn>,
+}
+
+impl VisionSense {
+    pub fn new(range: f32, half_fov: f32) -> Self {
+        Self { range, half_fov, line_of_sight: None }
+    }
+
+    pub fn with_line_of_sight<F: Fn(&World, Entity, Entity) -> bool + Send + Sync + 'static>(mut self, check: F) -> Self {
+        self.line_of_sight = Some(Arc::new(check));
+        self
+    }
 }
 
 impl Sense for VisionSense {
-    fn update(&self, memories: &mut Memories, world: &World) {
-        // Query visible entities and update memories
-        
+    fn sense(&self, inputs: &SenseInputs, world: &World, perceived: &mut CraniumList<Perception>) {
+        let Some(transform) = inputs.sensor_transform else {
+            return;
+        };
+        let eye = transform.translation();
+        let forward = transform.forward();
+
+        for (target, position) in inputs.perceivables.iter().copied() {
+            if target == inputs.sensor || eye.distance(position) > self.range {
+                continue;
+            }
+
+            let to_target = position - eye;
+            if to_target != Vec3::ZERO && forward.angle_between(to_target) > self.half_fov {
+                continue;
+            }
+
+            let visible = self.line_of_sight
+                .as_ref()
+                .map(|check| check(world, inputs.sensor, target))
+                .unwrap_or(true)
+            ;
+            if !visible {
+                continue;
+            }
+
+            perceived.push(Perception { 
+                entity: target, 
+                position: Some(position), 
+                confidence: 1., 
+                stimulus_priority: None,
+            });
+        }
     }
 }
 
-// Sense system
-fn update_senses(
-    mut query: Query<(&AIController, &Senses, &mut Memories)>,
-    world: &World,
+/// Perceives all active Stimuli of the matching kinds within their radius times the sensitivity.
+/// 
+/// The AI remembers each Stimulus' source as being where the Stimulus was, with a confidence 
+/// that falls off linearly with distance (but never below the minimum confidence).
+fn perceive_stimuli(
+    inputs: &SenseInputs,
+    matches_kind: impl Fn(&StimulusKind) -> bool,
+    sensitivity: f32,
+    min_confidence: ActionScore,
+    perceived: &mut CraniumList<Perception>,
 ) {
-    for (_, senses, mut memories) in query.iter_mut() {
-        for sense in senses.0.iter() {
-            sense.update(&mut memories, world);
+    let Some(transform) = inputs.sensor_transform else {
+        return;
+    };
+    let sensor_position = transform.translation();
+
+    for active in inputs.stimuli.iter() {
+        let stimulus = &active.stimulus;
+        if !matches_kind(&stimulus.kind) {
+            continue;
+        }
+        let Some(source) = stimulus.source.filter(|src| *src != inputs.sensor) else {
+            continue;
+        };
+
+        let radius = stimulus.radius * sensitivity;
+        let distance = sensor_position.distance(stimulus.position);
+        if radius <= 0. || distance > radius {
+            continue;
         }
+
+        perceived.push(Perception {
+            entity: source,
+            position: Some(stimulus.position),
+            confidence: (1. - distance / radius).max(min_confidence),
+            // Only new Stimuli count for interrupts; otherwise we'd re-trigger on every run.
+            stimulus_priority: active.is_newer_than(inputs.stimuli_seen).then_some(stimulus.priority),
+        });
+    }
+}
+
+/// A Sense that hears `Noise` Stimuli.
+#[derive(Clone, Debug)]
+pub struct HearingSense {
+    pub sensitivity: f32,
+    pub min_confidence: ActionScore,
+}
+
+impl Default for HearingSense {
+    fn default() -> Self {
+        Self { sensitivity: 1., min_confidence: 0.25 }
+    }
+}
+
+impl Sense for HearingSense {
+    fn sense(&self, inputs: &SenseInputs, _world: &World, perceived: &mut CraniumList<Perception>) {
+        perceive_stimuli(inputs, |kind| self.perceives(kind), self.sensitivity, self.min_confidence, perceived);
     }
+
+    fn perceives(&self, kind: &StimulusKind) -> bool {
+        *kind == StimulusKind::Noise
+    }
+}
+
+/// A Sense that perceives Stimuli of any of the specified kinds.
+#[derive(Clone, Debug)]
+pub struct StimulusSense {
+    pub kinds: CraniumList<StimulusKind>,
+    pub sensitivity: f32,
+    pub min_confidence: ActionScore,
+}
+
+impl StimulusSense {
+    pub fn new<I: IntoIterator<Item = StimulusKind>>(kinds: I) -> Self {
+        Self { kinds: kinds.into_iter().collect(), sensitivity: 1., min_confidence: 0.25 }
+    }
+}
+
+impl Sense for StimulusSense {
+    fn sense(&self, inputs: &SenseInputs, _world: &World, perceived: &mut CraniumList<Perception>) {
+        perceive_stimuli(inputs, |kind| self.perceives(kind), self.sensitivity, self.min_confidence, perceived);
+    }
+
+    fn perceives(&self, kind: &StimulusKind) -> bool {
+        self.kinds.contains(kind)
+    }
+}
+
+/// Maps Sense keys to Sense implementations.
+#[derive(Resource, Default, Clone)]
+pub struct SenseRegistry {
+    pub mapping: CraniumKvMap<SenseIdentifier, Arc<dyn Sense>>,
+}
+
+impl SenseRegistry {
+    pub fn register_sense<S: Sense, IS: Into<String>>(&mut self, sense: S, key: IS) -> &mut Self {
+        self.mapping.insert(SenseIdentifier::from(key.into()), Arc::new(sense));
+        self
+    }
+
+    pub fn get_sense_by_name(&self, key: &SenseIdentifier) -> Option<Arc<dyn Sense>> {
+        self.mapping.get(key).cloned()
+    }
+}
+
+/// Something that allows us to register a Sense to the World.
+///
+/// Note that for convenience, the first registration attempt
+/// will initialize *an empty registry* if one does not exist yet.
+pub trait AcceptsSenseRegistrations {
+    fn register_sense<S: Sense, IS: Into<String>>(&mut self, sense: S, key: IS) -> &mut Self;
+}
+
+impl AcceptsSenseRegistrations for World {
+    fn register_sense<S: Sense, IS: Into<String>>(&mut self, sense: S, key: IS) -> &mut Self {
+        self.get_resource_or_init::<SenseRegistry>().register_sense(sense, key);
+        self
+    }
+}
+
+impl AcceptsSenseRegistrations for App {
+    fn register_sense<S: Sense, IS: Into<String>>(&mut self, sense: S, key: IS) -> &mut Self {
+        self.world_mut().register_sense(sense, key);
+        self
+    }
+}
+
+/// One Sense used by an AI, with its own cadence.
+#[derive(Debug, Clone)]
+pub struct SenseSlot {
+    pub key: SenseIdentifier,
+    /// The minimum time between two runs of this Sense for this AI; zero means every frame.
+    pub interval: Duration,
+    pub last_run: Option<Duration>,
+    /// The `ActiveStimuli::next_sequence()` as of the last run; zero if the Sense has not run yet.
+    pub stimuli_seen: u64,
+}
+
+impl SenseSlot {
+    fn is_due(&self, now: Duration, lod_interval: Duration) -> bool {
+        let interval = self.interval.max(lod_interval);
+        self.last_run.map(|last| now.saturating_sub(last) >= interval).unwrap_or(true)
+    }
+}
+
+/// The Senses an AI perceives the World through.
+///
+/// AIs with Senses get a `Memories` Component added automatically if they do not have one already.
+#[derive(Component, Debug, Clone, Default)]
+pub struct Senses {
+    pub slots: CraniumList<SenseSlot>,
 }
 
+impl Senses {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a Sense (by registry key), running at most once per interval.
+    pub fn with_sense<IS: Into<String>>(mut self, key: IS, interval: Duration) -> Self {
+        self.slots.push(SenseSlot { key: SenseIdentifier::from(key.into()), interval, last_run: None, stimuli_seen: 0 });
+        self
+    }
+}
+
+/// An exclusive System that runs all due Senses for all AIs and records the results in their Memories.
+/// 
+/// Senses that perceive a new interrupting Stimulus (see `StimulusRouting`) run regardless of their 
+/// cadence, and AIs that actually perceive one request a new decision.
+pub fn update_senses(world: &mut World) {
+    let Some(now) = world.get_resource::<Time<Virtual>>().map(|time| time.elapsed()) else {
+        return;
+    };
+    let Some(registry) = world.get_resource::<SenseRegistry>().cloned() else {
+        return;
+    };
+    let lod_frequency = world.get_resource::<LodUpdateFrequency>().cloned();
+    let routing = world.get_resource::<StimulusRouting>().cloned().unwrap_or_default();
+    let stimuli_seen = world.get_resource::<ActiveStimuli>().map(|active| active.next_sequence()).unwrap_or_default();
+    let stimuli = world
+        .get_resource_mut::<ActiveStimuli>()
+        .map(|mut active| core::mem::take(&mut active.stimuli))
+        .unwrap_or_default()
+    ;
+
+    // 1. Figure out who needs to sense what.
+    let mut ai_query = world.query::<(Entity, &Senses, Option<&Pawn>, Option<&AiLevelOfDetail>)>();
+    let mut due_work: CraniumList<(AiEntity, PawnEntityRef, CraniumList<usize>)> = CraniumList::new();
+
+    for (ai, senses, maybe_pawn, maybe_lod) in ai_query.iter(world) {
+        let lod = maybe_lod.map(|lod| lod.get_current_lod()).unwrap_or_default();
+        let lod_interval = match &lod_frequency {
+            None => Some(Duration::ZERO),
+            Some(frequency) => frequency.senses.interval_for(lod),
+        };
+
+        // Inactive AIs are blind and deaf.
+        let Some(lod_interval) = lod_interval else {
+            continue;
+        };
+
+        let due: CraniumList<usize> = senses.slots
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| {
+                slot.is_due(now, lod_interval) || stimuli.iter().any(|active| 
+                    routing.should_interrupt(active.stimulus.priority)
+                    && active.is_newer_than(slot.stimuli_seen)
+                    && registry
+                        .get_sense_by_name(&slot.key)
+                        .map(|sense| sense.perceives(&active.stimulus.kind))
+                        .unwrap_or(false)
+                )
+            })
+            .map(|(idx, _)| idx)
+            .collect()
+        ;
+
+        if !due.is_empty() {
+            due_work.push((ai, maybe_pawn.and_then(|pawn| pawn.as_entity().copied()), due));
+        }
+    }
+
+    if due_work.is_empty() {
+        restore_stimuli(world, stimuli);
+        return;
+    }
+
+    // 2. Sense away, with read-only World access.
+    let mut perceivable_query = world.query_filtered::<(Entity, &GlobalTransform), With<Perceivable>>();
+    let perceivables: CraniumList<(Entity, Vec3)> = perceivable_query
+        .iter(world)
+        .map(|(entity, transform)| (entity, transform.translation()))
+        .collect()
+    ;
+
+    let mut results: CraniumList<(AiEntity, CraniumList<usize>, CraniumList<Perception>)> = CraniumList::new();
+    {
+        let world_ref: &World = world;
+
+        for (ai, pawn, due) in due_work {
+            let sensor = pawn.unwrap_or(ai);
+            let Some(senses) = world_ref.get::<Senses>(ai) else {
+                continue;
+            };
+
+            let mut perceived = CraniumList::new();
+            for idx in due.iter() {
+                let slot = &senses.slots[*idx];
+                let Some(sense) = registry.get_sense_by_name(&slot.key) else {
+                    #[cfg(feature = "logging")]
+                    bevy::log::warn!("update_senses: AI {:?} uses an unregistered Sense {:?}, skipping it.", ai, slot.key);
+                    continue;
+                };
+
+                let inputs = SenseInputs {
+                    ai,
+                    pawn,
+                    sensor,
+                    sensor_transform: world_ref.get::<GlobalTransform>(sensor).copied(),
+                    now,
+                    last_run: slot.last_run,
+                    stimuli_seen: slot.stimuli_seen,
+                    perceivables: &perceivables,
+                    stimuli: &stimuli,
+                };
+                sense.sense(&inputs, world_ref, &mut perceived);
+            }
+
+            results.push((ai, due, perceived));
+        }
+    }
+
+    restore_stimuli(world, stimuli);
+
+    // 3. Record the results.
+    for (ai, ran, perceived) in results {
+        let Ok(mut ai_entity) = world.get_entity_mut(ai) else {
+            continue;
+        };
+
+        if let Some(mut senses) = ai_entity.get_mut::<Senses>() {
+            for idx in ran {
+                senses.slots[idx].last_run = Some(now);
+                senses.slots[idx].stimuli_seen = stimuli_seen;
+            }
+        }
+
+        if !ai_entity.contains::<Memories>() {
+            ai_entity.insert(Memories::default());
+        }
+
+        if perceived.is_empty() {
+            continue;
+        }
+
+        #[cfg(feature = "logging")]
+        bevy::log::debug!("update_senses: AI {:?} perceived {:?}", ai, perceived);
+
+        let interrupted = perceived
+            .iter()
+            .any(|perception| perception.stimulus_priority.map(|prio| routing.should_interrupt(prio)).unwrap_or(false))
+        ;
+
+        if let Some(mut memories) = ai_entity.get_mut::<Memories>() {
+            for perception in perceived.iter() {
+                memories.perceive(perception.entity, perception.position, perception.confidence, now);
+            }
+        }
+
+        let smart_objects = ai_entity.get::<SmartObjects>().cloned();
+        world.trigger(PerceptionsRecorded { entity: ai, perceptions: perceived, perceived_at: now });
+
+        if interrupted {
+            #[cfg(feature = "logging")]
+            bevy::log::debug!("update_senses: AI {:?} perceived an interrupting Stimulus, requesting a decision.", ai);
+
+            world.trigger(AiDecisionRequested { entity: ai, smart_objects });
+        }
+    }
+}
+
+/// Puts the Stimuli taken out for the duration of `update_senses()` back.
+fn restore_stimuli(world: &mut World, stimuli: CraniumList<ActiveStimulus>) {
+    if let Some(mut active) = world.get_resource_mut::<ActiveStimuli>() {
+        active.stimuli = stimuli;
+    }
+}
+
+/// Sets up the Senses and Stimuli, running them after Memories decay so fresh Perceptions do not get decayed right away.
+pub struct SensesPlugin;
+
+impl Plugin for SensesPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .init_resource::<SenseRegistry>()
+        .init_resource::<ActiveStimuli>()
+        .init_resource::<StimulusRouting>()
+        .add_message::<crate::stimuli::Stimulus>()
+        .add_systems(
+            PreUpdate,
+            (crate::stimuli::collect_stimuli, update_senses)
+                .chain()
+                .after(crate::memories::decay_memories)
+        )
+        ;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stimuli::Stimulus;
+
+    #[test]
+    fn test_vision_and_hearing() {
+        let mut app = App::new();
+        app
+        .add_plugins((bevy::time::TimePlugin, crate::memories::MemoriesPlugin, SensesPlugin))
+        .register_sense(VisionSense::new(10., core::f32::consts::FRAC_PI_4), "Vision")
+        .register_sense(HearingSense::default(), "Hearing")
+        ;
+
+        // Looking down -Z, the default forward.
+        let ai = app.world_mut().spawn((
+            Senses::new()
+                .with_sense("Vision", Duration::ZERO)
+                .with_sense("Hearing", Duration::ZERO),
+            GlobalTransform::default(),
+        )).id();
+        let in_view = app.world_mut().spawn((Perceivable, GlobalTransform::from_xyz(0., 0., -5.))).id();
+        let behind = app.world_mut().spawn((Perceivable, GlobalTransform::from_xyz(0., 0., 5.))).id();
+        let too_far = app.world_mut().spawn((Perceivable, GlobalTransform::from_xyz(0., 0., -50.))).id();
+
+        app.world_mut().write_message(Stimulus::noise(behind, Vec3::new(0., 0., 5.), 10.));
+        app.update();
+
+        let memories = app.world().get::<Memories>(ai).unwrap();
+        assert_eq!(memories.confidence(in_view), 1.);
+        assert!((memories.confidence(behind) - 0.5).abs() < 1e-4);
+        assert!(!memories.knows(too_far));
+    }
+
+    #[test]
+    fn test_urgent_stimulus_requests_decision() {
+        #[derive(Resource, Default)]
+        struct Requests(usize);
+
+        let mut app = App::new();
+        app
+        .add_plugins((bevy::time::TimePlugin, crate::memories::MemoriesPlugin, SensesPlugin))
+        .register_sense(StimulusSense::new([StimulusKind::Damage]), "Pain")
+        .insert_resource(StimulusRouting::interrupts_at(StimulusPriority::Urgent))
+        .init_resource::<Requests>()
+        .add_observer(|_: On<AiDecisionRequested>, mut requests: ResMut<Requests>| requests.0 += 1)
+        ;
+
+        // A long interval, so only interrupts make the Sense run after the first update.
+        let ai = app.world_mut().spawn((
+            Senses::new().with_sense("Pain", Duration::from_secs(3600)),
+            GlobalTransform::default(),
+        )).id();
+        let attacker = app.world_mut().spawn_empty().id();
+        app.update();
+
+        let hit = Stimulus::new(StimulusKind::Damage, Vec3::X, 5.).with_source(attacker);
+        app.world_mut().write_message(hit.clone());
+        app.update();
+        assert_eq!(app.world().resource::<Requests>().0, 0);
+
+        app.world_mut().write_message(hit.with_priority(StimulusPriority::Urgent));
+        app.update();
+        assert_eq!(app.world().resource::<Requests>().0, 1);
+        assert!(app.world().get::<Memories>(ai).unwrap().knows(attacker));
+    }
+
+    #[test]
+    fn test_urgent_stimuli_interrupt_while_paused() {
+        #[derive(Resource, Default)]
+        struct Requests(usize);
+
+        let mut app = App::new();
+        app
+        .add_plugins((bevy::time::TimePlugin, crate::memories::MemoriesPlugin, SensesPlugin))
+        .register_sense(StimulusSense::new([StimulusKind::Damage]), "Pain")
+        .insert_resource(StimulusRouting::interrupts_at(StimulusPriority::Urgent))
+        .init_resource::<Requests>()
+        .add_observer(|_: On<AiDecisionRequested>, mut requests: ResMut<Requests>| requests.0 += 1)
+        ;
+
+        app.world_mut().spawn((
+            Senses::new().with_sense("Pain", Duration::from_secs(3600)),
+            GlobalTransform::default(),
+        ));
+        let attacker = app.world_mut().spawn_empty().id();
+        app.update();
+        app.world_mut().resource_mut::<Time<Virtual>>().pause();
+
+        // The game time stands still, but each new hit is still a new Stimulus.
+        let hit = Stimulus::new(StimulusKind::Damage, Vec3::X, 5.).with_source(attacker).with_priority(StimulusPriority::Urgent);
+        for expected_requests in 1..=3 {
+            app.world_mut().write_message(hit.clone());
+            app.update();
+            assert_eq!(app.world().resource::<Requests>().0, expected_requests);
+        }
+    }
+}
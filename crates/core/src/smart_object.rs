@@ -42,6 +42,21 @@ pub struct ActionSetStore {
     pub map_by_name: types::CraniumKvMap<String, ActionSet>
 }
 
+impl ActionSetStore {
+    /// Finds the ActionTemplate behind a picked Action among the SmartObjects' ActionSets.
+    pub fn find_template(
+        &self,
+        smart_objects: &SmartObjects,
+        action_key: &str,
+        action_name: &str,
+    ) -> Option<&crate::actions::ActionTemplate> {
+        smart_objects.actionset_refs.iter()
+            .filter_map(|key| self.map_by_name.get(key))
+            .flat_map(|actionset| actionset.actions.iter())
+            .find(|template| template.action_key == action_key && template.name == action_name)
+    }
+}
+
 
 /// A collection of all SmartObjects an Entity has access to at the moment.
 #[derive(Component, Default, Reflect, Clone)]
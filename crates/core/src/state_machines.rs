@@ -0,0 +1,700 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+If a copy of the MPL was not distributed with this file,
+You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+//! State Machines - a coarse layer on top of Utility AI deciding which ActionSets are in play.
+//!
+//! Pure Utility AI is great at picking the best thing to do *right now*, but designers often think
+//! in terms of modes - an NPC is Patrolling, or in Combat, or Fleeing - each with its own repertoire.
+//! Encoding that with Considerations alone works, but tends to be fiddly and hard to read.
+//!
+//! An `AiStateMachine` Component links an AI to a `StateMachineDefinition` (usually loaded from data,
+//! as part of an ActionSet). Each state lists the ActionSets it allows; while the AI is in that state,
+//! the decision engine only considers the AI's SmartObjects ActionSets on that list (plus the ones
+//! allowed globally by the machine). AIs without an `AiStateMachine` are not affected at all.
+//!
+//! Transitions between states can be triggered by:
+//! - Events - either trigger an `AiStateEvent` from your own code, or pick an Action with the key
+//!   `AiStateEvent::<event name>`; the `StateMachinePlugin` registers ActionHandlers for those automatically.
+//! - Considerations - the raw value of a Consideration crossing a threshold (checked every frame).
+//!   As there is no Action to speak of here, the Context passed to the Consideration is the Pawn
+//!   (or the AI itself, if there is no Pawn).
+//! - Direct requests - trigger `AiStateTransitionRequested` to force a specific state.
+//!
+//! Every transition triggers an `AiStateChanged` event for the AI.
+
+use core::borrow::Borrow;
+
+use bevy::ecs::system::SystemParam;
+use bevy::platform::prelude::{String, ToOwned, Vec};
+use bevy::prelude::*;
+
+use crate::actions::{
+    ActionHandlerInputs, ActionHandlerKeyToSystemMap, ActionPickCallback, ActionTemplate, AcceptsActionHandlerRegistrations,
+};
+use crate::actionset::ActionSet;
+use crate::considerations::{ConsiderationKeyToSystemMap, ensure_consideration_queries};
+use crate::decision_loop::run_consideration_system;
+use crate::events::AiDecisionRequested;
+use crate::goals::{AiGoal, GoalTemplate};
+use crate::identifiers::ConsiderationIdentifier;
+use crate::layers::LayerArbitration;
+use crate::lods::AiLevelOfDetail;
+use crate::pawn::Pawn;
+use crate::smart_object::{ActionSetStore, SmartObjects};
+use crate::types::{ActionScore, ActionSetRef, AiEntity, CraniumKvMap, CraniumList};
+
+#[cfg(feature = "actionset_loader")]
+use serde::{Serialize, Deserialize};
+
+/// ActionHandler key prefix for triggering an `AiStateEvent` for the AI picking the Action.
+pub const STATE_EVENT_ACTION_PREFIX: &str = "AiStateEvent::";
+
+/// What makes a transition fire.
+#[derive(Clone, Debug, Reflect)]
+#[cfg_attr(feature = "actionset_loader", derive(Serialize, Deserialize))]
+pub enum StateTransitionTrigger {
+    /// An `AiStateEvent` with this name.
+    Event(String),
+
+    /// The raw value of the Consideration reaching the threshold (or falling to it, if `below` is set).
+    Consideration {
+        consideration: ConsiderationIdentifier,
+        threshold: ActionScore,
+        #[cfg_attr(feature = "actionset_loader", serde(default))]
+        below: bool,
+    },
+}
+
+impl StateTransitionTrigger {
+    /// True if the value of the Consideration should fire the transition.
+    pub fn is_met_by(&self, value: ActionScore) -> bool {
+        match self {
+            Self::Event(_) => false,
+            Self::Consideration { threshold, below: false, .. } => value >= *threshold,
+            Self::Consideration { threshold, below: true, .. } => value <= *threshold,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Reflect)]
+#[cfg_attr(feature = "actionset_loader", derive(Serialize, Deserialize))]
+pub struct StateTransitionDefinition {
+    /// The name of the state to transition to.
+    pub to: String,
+    pub trigger: StateTransitionTrigger,
+}
+
+/// A single state of a State Machine.
+#[derive(Clone, Debug, Reflect)]
+#[cfg_attr(feature = "actionset_loader", derive(Serialize, Deserialize))]
+pub struct AiStateDefinition {
+    pub name: String,
+
+    /// The ActionSets available to the AI while in this state.
+    #[cfg_attr(feature = "actionset_loader", serde(default))]
+    pub actionsets: CraniumList<ActionSetRef>,
+
+    /// Transitions out of this state; if several fire at once, the first one listed wins.
+    #[cfg_attr(feature = "actionset_loader", serde(default))]
+    pub transitions: CraniumList<StateTransitionDefinition>,
+}
+
+impl AiStateDefinition {
+    pub fn new<IS: Into<String>>(name: IS) -> Self {
+        Self {
+            name: name.into(),
+            actionsets: CraniumList::new(),
+            transitions: CraniumList::new(),
+        }
+    }
+
+    pub fn with_actionset<IS: Into<String>>(mut self, actionset: IS) -> Self {
+        self.actionsets.push(actionset.into());
+        self
+    }
+
+    /// Transitions to the target state when an `AiStateEvent` with the specified name gets triggered.
+    pub fn on_event<IE: Into<String>, IS: Into<String>>(mut self, event: IE, to: IS) -> Self {
+        self.transitions.push(StateTransitionDefinition {
+            to: to.into(),
+            trigger: StateTransitionTrigger::Event(event.into()),
+        });
+        self
+    }
+
+    /// Transitions to the target state when the Consideration's raw value reaches the threshold.
+    pub fn on_consideration_above<IC: Into<ConsiderationIdentifier>, IS: Into<String>>(
+        mut self, consideration: IC, threshold: ActionScore, to: IS,
+    ) -> Self {
+        self.transitions.push(StateTransitionDefinition {
+            to: to.into(),
+            trigger: StateTransitionTrigger::Consideration { consideration: consideration.into(), threshold, below: false },
+        });
+        self
+    }
+
+    /// Transitions to the target state when the Consideration's raw value falls to the threshold.
+    pub fn on_consideration_below<IC: Into<ConsiderationIdentifier>, IS: Into<String>>(
+        mut self, consideration: IC, threshold: ActionScore, to: IS,
+    ) -> Self {
+        self.transitions.push(StateTransitionDefinition {
+            to: to.into(),
+            trigger: StateTransitionTrigger::Consideration { consideration: consideration.into(), threshold, below: true },
+        });
+        self
+    }
+}
+
+/// A State Machine, as defined in data.
+#[derive(Clone, Debug, Reflect)]
+#[cfg_attr(feature = "actionset_loader", derive(Serialize, Deserialize))]
+pub struct StateMachineDefinition {
+    pub name: String,
+    pub initial_state: String,
+    pub states: CraniumList<AiStateDefinition>,
+
+    /// ActionSets available in every state.
+    #[cfg_attr(feature = "actionset_loader", serde(default))]
+    pub global_actionsets: CraniumList<ActionSetRef>,
+
+    /// If set, AIs request a new decision right after changing states (if they have a `SmartObjects` Component).
+    #[cfg_attr(feature = "actionset_loader", serde(default))]
+    pub redecide_on_transition: bool,
+}
+
+impl StateMachineDefinition {
+    pub fn new<IN: Into<String>, IS: Into<String>>(name: IN, initial_state: IS) -> Self {
+        Self {
+            name: name.into(),
+            initial_state: initial_state.into(),
+            states: CraniumList::new(),
+            global_actionsets: CraniumList::new(),
+            redecide_on_transition: false,
+        }
+    }
+
+    pub fn with_state(mut self, state: AiStateDefinition) -> Self {
+        self.states.push(state);
+        self
+    }
+
+    pub fn with_global_actionset<IS: Into<String>>(mut self, actionset: IS) -> Self {
+        self.global_actionsets.push(actionset.into());
+        self
+    }
+
+    pub fn with_redecide_on_transition(mut self, redecide: bool) -> Self {
+        self.redecide_on_transition = redecide;
+        self
+    }
+
+    pub fn get_state(&self, name: &str) -> Option<&AiStateDefinition> {
+        self.states.iter().find(|state| state.name == name)
+    }
+
+    /// True if the ActionSet is available in the specified state.
+    pub fn allows(&self, state: &str, actionset: &str) -> bool {
+        self.global_actionsets.iter().any(|allowed| allowed == actionset)
+        || self.get_state(state).is_some_and(|state| state.actionsets.iter().any(|allowed| allowed == actionset))
+    }
+}
+
+/// All known State Machine definitions, by name.
+#[derive(Resource, Debug, Default)]
+pub struct StateMachineRegistry {
+    pub machines: CraniumKvMap<String, StateMachineDefinition>,
+}
+
+impl StateMachineRegistry {
+    /// Adds a definition, replacing any previous one under the same name.
+    pub fn register_state_machine(&mut self, definition: StateMachineDefinition) -> &mut Self {
+        #[cfg(feature = "logging")]
+        if definition.get_state(&definition.initial_state).is_none() {
+            bevy::log::warn!(
+                "StateMachineRegistry: initial state {:?} of State Machine {:?} is not defined!",
+                &definition.initial_state, &definition.name
+            );
+        }
+
+        self.machines.insert(definition.name.to_owned(), definition);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&StateMachineDefinition> {
+        self.machines.get(name)
+    }
+}
+
+/// Links an AI to a State Machine and tracks the state it is in.
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct AiStateMachine {
+    /// The name of the `StateMachineDefinition` to use.
+    pub machine: String,
+
+    /// The current state; None means the machine's initial state.
+    state: Option<String>,
+}
+
+impl AiStateMachine {
+    pub fn new<IS: Into<String>>(machine: IS) -> Self {
+        Self { machine: machine.into(), state: None }
+    }
+
+    /// Starts in the specified state instead of the initial state of the machine.
+    pub fn with_state<IS: Into<String>>(mut self, state: IS) -> Self {
+        self.state = Some(state.into());
+        self
+    }
+
+    /// The current state, with the initial state of the definition filled in if needed.
+    pub fn current_state<'a>(&'a self, definition: &'a StateMachineDefinition) -> &'a str {
+        self.state.as_deref().unwrap_or(&definition.initial_state)
+    }
+}
+
+/// Triggers the transitions listening to this event in the AI's current state, if any.
+#[derive(EntityEvent, Debug, Clone)]
+pub struct AiStateEvent {
+    pub entity: AiEntity,
+    pub event: String,
+}
+
+/// Moves the AI to the specified state unconditionally (as long as the state exists).
+#[derive(EntityEvent, Debug, Clone)]
+pub struct AiStateTransitionRequested {
+    pub entity: AiEntity,
+    pub to_state: String,
+}
+
+/// Signals that the AI has changed states.
+#[derive(EntityEvent, Debug, Clone)]
+pub struct AiStateChanged {
+    pub entity: AiEntity,
+    pub machine: String,
+    pub from_state: String,
+    pub to_state: String,
+}
+
+/// A SystemParam resolving which of the AI's ActionSets are available to it right now, 
+/// based on its State Machine's current state and its current goal (see the `goals` module).
+/// 
+/// It also checks whether AI layers can get the Pawn resources of an Action (see the `layers` module).
+#[derive(SystemParam)]
+pub struct AvailableActionSets<'w, 's> {
+    store: Res<'w, ActionSetStore>,
+    registry: Option<Res<'w, StateMachineRegistry>>,
+    state_machines: Query<'w, 's, &'static AiStateMachine>,
+    goals: Query<'w, 's, &'static AiGoal>,
+    arbitration: LayerArbitration<'w, 's>,
+}
+
+impl AvailableActionSets<'_, '_> {
+    /// True if the AI's current state (if any) allows the ActionSet.
+    pub fn is_allowed_by_state(&self, ai: AiEntity, actionset: &str) -> bool {
+        let Ok(state_machine) = self.state_machines.get(ai) else {
+            return true;
+        };
+
+        let definition = self.registry.as_ref().and_then(|registry| registry.get(&state_machine.machine));
+        match definition {
+            Some(definition) => definition.allows(state_machine.current_state(definition), actionset),
+            None => {
+                #[cfg(feature = "logging")]
+                bevy::log::warn!(
+                    "AvailableActionSets: AI {:?} uses an unknown State Machine {:?}, not filtering its ActionSets.",
+                    ai, &state_machine.machine
+                );
+                true
+            }
+        }
+    }
+
+    /// True if both the AI's current state and its current goal (if any) allow the ActionSet.
+    pub fn is_allowed(&self, ai: AiEntity, actionset: &str) -> bool {
+        self.is_allowed_by_state(ai, actionset)
+        && self.goals.get(ai).map(|goal| goal.allows(actionset)).unwrap_or(true)
+    }
+
+    /// True if the AI could get all the Pawn resources the ActionTemplate needs (see the `layers` module).
+    pub fn can_claim_resources(&self, ai: AiEntity, template: &ActionTemplate) -> bool {
+        self.arbitration.can_claim_template(ai, template)
+    }
+
+    /// The goal the AI currently pursues, if any.
+    pub fn current_goal(&self, ai: AiEntity) -> Option<&str> {
+        self.goals.get(ai).ok().and_then(|goal| goal.current())
+    }
+
+    /// The stored ActionSets from the SmartObjects that the AI's current state and goal allow.
+    pub fn for_ai<'a>(&'a self, ai: AiEntity, smart_objects: &'a SmartObjects) -> impl Iterator<Item = &'a ActionSet> {
+        smart_objects.actionset_refs
+            .iter()
+            .filter(move |key| self.is_allowed(ai, key))
+            .filter_map(|key| self.store.map_by_name.get(key))
+    }
+
+    /// The goals from the SmartObjects' ActionSets that the AI's current state allows.
+    pub fn goals_for<'a>(&'a self, ai: AiEntity, smart_objects: &'a SmartObjects) -> impl Iterator<Item = &'a GoalTemplate> {
+        smart_objects.actionset_refs
+            .iter()
+            .filter(move |key| self.is_allowed_by_state(ai, key))
+            .filter_map(|key| self.store.map_by_name.get(key))
+            .flat_map(|actionset| actionset.goals.iter())
+    }
+}
+
+/// Builds an ActionHandler that triggers an `AiStateEvent` for the AI picking the Action.
+pub fn state_event_handler<IS: Into<String>>(event: IS) -> ActionPickCallback {
+    let event: String = event.into();
+    ActionPickCallback::new(move |inputs: ActionHandlerInputs, mut commands: Commands| {
+        let (ai, _pawn, _context, _tracker) = inputs;
+        commands.trigger(AiStateEvent { entity: ai, event: event.clone() });
+    })
+}
+
+/// An Observer resolving `AiStateEvent`s to transitions.
+pub fn handle_state_events(
+    event: On<AiStateEvent>,
+    registry: Option<Res<StateMachineRegistry>>,
+    query: Query<&AiStateMachine>,
+    mut commands: Commands,
+) {
+    let Ok(state_machine) = query.get(event.entity) else {
+        return;
+    };
+    let Some(definition) = registry.as_ref().and_then(|registry| registry.get(&state_machine.machine)) else {
+        return;
+    };
+    let Some(state) = definition.get_state(state_machine.current_state(definition)) else {
+        return;
+    };
+
+    let target = state.transitions.iter().find(|transition| {
+        matches!(&transition.trigger, StateTransitionTrigger::Event(name) if *name == event.event)
+    });
+
+    if let Some(transition) = target {
+        commands.trigger(AiStateTransitionRequested { entity: event.entity, to_state: transition.to.to_owned() });
+    }
+}
+
+/// An Observer that applies `AiStateTransitionRequested`s.
+pub fn handle_state_transition_requests(
+    event: On<AiStateTransitionRequested>,
+    registry: Option<Res<StateMachineRegistry>>,
+    mut query: Query<(&mut AiStateMachine, Option<&SmartObjects>)>,
+    mut commands: Commands,
+) {
+    let ai = event.entity;
+    let Ok((mut state_machine, smart_objects)) = query.get_mut(ai) else {
+        return;
+    };
+    let Some(definition) = registry.as_ref().and_then(|registry| registry.get(&state_machine.machine)) else {
+        return;
+    };
+
+    if definition.get_state(&event.to_state).is_none() {
+        #[cfg(feature = "logging")]
+        bevy::log::warn!(
+            "handle_state_transition_requests: State {:?} does not exist in State Machine {:?}, ignoring.",
+            &event.to_state, &state_machine.machine
+        );
+        return;
+    }
+
+    let from_state = state_machine.current_state(definition).to_owned();
+    if from_state == event.to_state {
+        return;
+    }
+
+    #[cfg(feature = "logging")]
+    bevy::log::debug!("handle_state_transition_requests: AI {:?} - {:?} -> {:?}", ai, &from_state, &event.to_state);
+
+    state_machine.state = Some(event.to_state.to_owned());
+    commands.trigger(AiStateChanged {
+        entity: ai,
+        machine: state_machine.machine.to_owned(),
+        from_state,
+        to_state: event.to_state.to_owned(),
+    });
+
+    if definition.redecide_on_transition && let Some(smart_objects) = smart_objects {
+        commands.trigger(AiDecisionRequested { entity: ai, smart_objects: Some(smart_objects.clone()) });
+    }
+}
+
+/// An exclusive System firing the Consideration-based transitions of all active AIs' current states.
+pub fn evaluate_state_transitions(world: &mut World) {
+    let mut query = world.query::<(Entity, &AiStateMachine, Option<&Pawn>, Option<&AiLevelOfDetail>)>();
+
+    let has_consideration_transitions = world
+        .get_resource::<StateMachineRegistry>()
+        .map(|registry| registry.machines.values().any(|machine| {
+            machine.states.iter().flat_map(|state| state.transitions.iter()).any(|transition| {
+                matches!(transition.trigger, StateTransitionTrigger::Consideration { .. })
+            })
+        }))
+        .unwrap_or(false);
+
+    if !has_consideration_transitions {
+        return;
+    }
+
+    // The Consideration Systems need to be initialized before we can run them.
+    ensure_consideration_queries(world);
+
+    let mut requests: Vec<AiStateTransitionRequested> = Vec::new();
+
+    {
+        let world_ref: &World = world;
+        let (Some(registry), Some(consideration_map)) = (
+            world_ref.get_resource::<StateMachineRegistry>(),
+            world_ref.get_resource::<ConsiderationKeyToSystemMap>(),
+        ) else {
+            return;
+        };
+
+        for (ai, state_machine, maybe_pawn, maybe_lod) in query.iter(world_ref) {
+            if maybe_lod.is_some_and(|lod| lod.get_current_lod().is_inactive()) {
+                continue;
+            }
+
+            let Some(definition) = registry.get(&state_machine.machine) else {
+                continue;
+            };
+            let Some(state) = definition.get_state(state_machine.current_state(definition)) else {
+                continue;
+            };
+
+            let pawn = maybe_pawn.and_then(|pawn| pawn.clone().to_entity());
+            let context = pawn.unwrap_or(ai);
+
+            let fired = state.transitions.iter().find(|transition| {
+                let StateTransitionTrigger::Consideration { consideration, .. } = &transition.trigger else {
+                    return false;
+                };
+
+                let key: &str = consideration.borrow();
+                if !consideration_map.mapping.contains_key(key) {
+                    #[cfg(feature = "logging")]
+                    bevy::log::warn!("evaluate_state_transitions: Unknown Consideration {:?}, skipping.", key);
+                    return false;
+                }
+
+                run_consideration_system(consideration_map, consideration, (ai, pawn, context), world_ref)
+                    .is_some_and(|value| transition.trigger.is_met_by(value))
+            });
+
+            if let Some(transition) = fired {
+                requests.push(AiStateTransitionRequested { entity: ai, to_state: transition.to.to_owned() });
+            }
+        }
+    }
+
+    for request in requests {
+        world.trigger(request);
+    }
+}
+
+/// An exclusive System that registers the State Machines defined in the stored ActionSets, plus
+/// the ActionHandlers for any `AiStateEvent::` Action keys used in them (see the module docs).
+///
+/// ActionSets are processed in order of their names, so if several of them define a State Machine
+/// with the same name, the first definition wins.
+pub fn register_actionset_state_machines(world: &mut World) {
+    let mut definitions: Vec<StateMachineDefinition> = Vec::new();
+    let mut event_keys: Vec<String> = Vec::new();
+
+    {
+        let Some(store) = world.get_resource::<ActionSetStore>() else {
+            return;
+        };
+        let registered_actions = world.get_resource::<ActionHandlerKeyToSystemMap>();
+
+        let mut actionsets: CraniumList<_> = store.map_by_name.iter().collect();
+        actionsets.sort_by_key(|(name, _)| *name);
+
+        for (_, actionset) in actionsets {
+            for definition in actionset.state_machines.iter() {
+                if !definitions.iter().any(|known| known.name == definition.name) {
+                    definitions.push(definition.clone());
+                }
+            }
+
+            for template in actionset.actions.iter() {
+                if template.action_key.starts_with(STATE_EVENT_ACTION_PREFIX)
+                    && !registered_actions.is_some_and(|reg| reg.mapping.contains_key(&template.action_key))
+                {
+                    event_keys.push(template.action_key.to_owned());
+                }
+            }
+        }
+    }
+
+    {
+        let mut registry = world.get_resource_or_init::<StateMachineRegistry>();
+        for definition in definitions {
+            #[cfg(feature = "logging")]
+            bevy::log::debug!("register_actionset_state_machines: Registered State Machine {:?}", &definition.name);
+
+            registry.register_state_machine(definition);
+        }
+    }
+
+    for key in event_keys {
+        let event = key[STATE_EVENT_ACTION_PREFIX.len()..].to_owned();
+        world.register_action_handler(state_event_handler(event), key);
+    }
+}
+
+/// Sets up State Machine transitions and the data-driven registration of State Machines.
+///
+/// The decision engine respects the AIs' current states regardless of this Plugin.
+pub struct StateMachinePlugin;
+
+impl Plugin for StateMachinePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<StateMachineRegistry>()
+            .add_observer(handle_state_events)
+            .add_observer(handle_state_transition_requests)
+            .add_systems(
+                First,
+                register_actionset_state_machines.run_if(resource_exists_and_changed::<ActionSetStore>)
+            )
+            .add_systems(PreUpdate, evaluate_state_transitions)
+        ;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bevy::platform::prelude::vec;
+    use crate::actionset::ActionSet;
+    use crate::considerations::{AcceptsConsiderationRegistrations, ConsiderationInputs, ConsiderationOutputs};
+    use crate::decision_loop::handle_dispatch_to_user_actions;
+    use crate::events::AiActionDispatchToUserCode;
+    use super::*;
+
+    #[derive(Component)]
+    struct Alarm(f32);
+
+    fn alarm_level(In((_, _, context)): ConsiderationInputs, query: Query<&Alarm>) -> ConsiderationOutputs {
+        query.get(context).ok().map(|alarm| alarm.0)
+    }
+
+    fn current_state(app: &App, ai: Entity) -> String {
+        let registry = app.world().resource::<StateMachineRegistry>();
+        let state_machine = app.world().get::<AiStateMachine>(ai).unwrap();
+        state_machine.current_state(registry.get(&state_machine.machine).unwrap()).to_owned()
+    }
+
+    fn example_machine() -> StateMachineDefinition {
+        StateMachineDefinition::new("Guard", "Patrol")
+            .with_global_actionset("Idle")
+            .with_state(AiStateDefinition::new("Patrol").with_actionset("Patrol").on_event("Spotted", "Combat"))
+            .with_state(AiStateDefinition::new("Combat").with_actionset("Combat").on_event("Lost", "Patrol"))
+    }
+
+    #[test]
+    fn test_state_events_gate_actionsets() {
+        let mut app = App::new();
+        app.add_plugins(StateMachinePlugin).init_resource::<ActionSetStore>();
+        app.world_mut().resource_mut::<StateMachineRegistry>().register_state_machine(example_machine());
+
+        let ai = app.world_mut().spawn(AiStateMachine::new("Guard")).id();
+        let mut state: bevy::ecs::system::SystemState<AvailableActionSets> = bevy::ecs::system::SystemState::new(app.world_mut());
+
+        {
+            let available = state.get(app.world());
+            assert!(available.is_allowed(ai, "Patrol"));
+            assert!(available.is_allowed(ai, "Idle"));
+            assert!(!available.is_allowed(ai, "Combat"));
+        }
+
+        // Events not handled by the current state do nothing.
+        app.world_mut().trigger(AiStateEvent { entity: ai, event: "Lost".into() });
+        app.world_mut().trigger(AiStateEvent { entity: ai, event: "Spotted".into() });
+        app.world_mut().flush();
+
+        let available = state.get(app.world());
+        assert!(available.is_allowed(ai, "Combat"));
+        assert!(!available.is_allowed(ai, "Patrol"));
+    }
+
+    #[test]
+    fn test_consideration_transitions() {
+        let mut app = App::new();
+        app.add_plugins(StateMachinePlugin).init_resource::<ActionSetStore>();
+        app.register_consideration(alarm_level, "test::Alarm");
+        app.world_mut().resource_mut::<StateMachineRegistry>().register_state_machine(
+            StateMachineDefinition::new("Guard", "Patrol")
+                .with_state(AiStateDefinition::new("Patrol").on_consideration_above("test::Alarm", 0.5, "Combat"))
+                .with_state(AiStateDefinition::new("Combat").on_consideration_below("test::Alarm", 0.1, "Patrol"))
+        );
+
+        let ai = app.world_mut().spawn((AiStateMachine::new("Guard"), Alarm(0.3))).id();
+        app.update();
+        assert_eq!(current_state(&app, ai), "Patrol");
+
+        app.world_mut().get_mut::<Alarm>(ai).unwrap().0 = 0.5;
+        app.update();
+        assert_eq!(current_state(&app, ai), "Combat");
+
+        // Falling below the threshold to enter Combat is not enough to leave it.
+        app.world_mut().get_mut::<Alarm>(ai).unwrap().0 = 0.3;
+        app.update();
+        assert_eq!(current_state(&app, ai), "Combat");
+
+        app.world_mut().get_mut::<Alarm>(ai).unwrap().0 = 0.05;
+        app.update();
+        assert_eq!(current_state(&app, ai), "Patrol");
+    }
+
+    #[test]
+    fn test_state_event_action_handlers() {
+        let mut app = App::new();
+        app.add_plugins(StateMachinePlugin)
+            .init_resource::<ActionSetStore>()
+            .add_message::<AiActionDispatchToUserCode>();
+
+        let mut actionset = ActionSet::new("Guard", vec![
+            ActionTemplate::new("Raise the alarm", "test::CF", vec![], 1., "AiStateEvent::Spotted", None, None),
+        ]);
+        actionset.state_machines.push(example_machine());
+        app.world_mut().resource_mut::<ActionSetStore>().map_by_name.insert("Guard".to_owned(), actionset);
+        app.update();
+
+        let ai = app.world_mut().spawn(AiStateMachine::new("Guard")).id();
+        app.world_mut().write_message(AiActionDispatchToUserCode::new(
+            ai, "AiStateEvent::Spotted".into(), "Raise the alarm".into(), ai, 1.,
+        ));
+        app.world_mut().run_system_cached(handle_dispatch_to_user_actions).unwrap();
+        app.world_mut().flush();
+
+        assert_eq!(current_state(&app, ai), "Combat");
+    }
+
+    #[test]
+    fn test_actionset_state_machines_register_in_name_order() {
+        let mut world = World::new();
+        let mut store = ActionSetStore::default();
+        for (name, initial_state) in [("c", "Sleep"), ("a", "Patrol"), ("b", "Combat")] {
+            let machine = StateMachineDefinition::new("Guard", initial_state);
+            store.map_by_name.insert(name.into(), ActionSet::new(name, vec![]).with_state_machines(vec![machine]));
+        }
+        world.insert_resource(store);
+
+        for _ in 0..3 {
+            world.run_system_cached(register_actionset_state_machines).unwrap();
+        }
+
+        // The ActionSets are processed by name, so "a" wins the shared name.
+        let registry = world.resource::<StateMachineRegistry>();
+        assert_eq!(registry.get("Guard").unwrap().initial_state, "Patrol");
+    }
+}
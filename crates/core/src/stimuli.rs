@@ -0,0 +1,235 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+If a copy of the MPL was not distributed with this file,
+You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+//! Stimuli - perceivable things happening in the World, pushed to the AIs by game code.
+//!
+//! ContextFetchers and Considerations *pull* information from the World, which is fine for state,
+//! but a poor fit for things that *happen* - a gunshot, a scream, a crate getting smashed. Polling
+//! for those means either missing them or keeping extra bookkeeping around in your game code.
+//!
+//! Instead, game code can write a `Stimulus` Message whenever something perceivable happens.
+//! Each Stimulus stays active for its lifetime and gets routed to the Senses that perceive its kind
+//! (e.g. `HearingSense` for `StimulusKind::Noise`) of any AI within its radius, which records it
+//! in the AI's Memories like any other Perception.
+//!
+//! Optionally, Stimuli of a high enough priority can also make the AIs perceiving them request a new
+//! decision right away, so they can react without waiting for the current Action to finish - see
+//! `StimulusRouting`. This goes through the usual decision throttling, so far-away, low-LOD AIs
+//! still will not re-decide more often than their LOD allows.
+
+use core::time::Duration;
+
+use bevy::platform::prelude::String;
+use bevy::prelude::*;
+
+use crate::types::CraniumList;
+
+#[cfg(feature = "actionset_loader")]
+use serde::{Serialize, Deserialize};
+
+/// How long a Stimulus stays active unless specified otherwise.
+pub const DEFAULT_STIMULUS_LIFETIME: Duration = Duration::from_secs(5);
+
+/// What sort of thing happened.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Reflect)]
+#[cfg_attr(feature = "actionset_loader", derive(Serialize, Deserialize))]
+pub enum StimulusKind {
+    /// Something made a sound; the radius is how far it carries.
+    Noise,
+    /// Something dealt (or took) damage.
+    Damage,
+    /// Something dropped an item.
+    ItemDropped,
+    /// Anything else, identified by a key of your choice.
+    Custom(String),
+}
+
+/// How important a Stimulus is; used to decide whether it should interrupt the AIs perceiving it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Reflect)]
+#[cfg_attr(feature = "actionset_loader", derive(Serialize, Deserialize))]
+pub enum StimulusPriority {
+    /// Ambient stuff; never interrupts anything.
+    Background,
+    #[default]
+    Normal,
+    /// Things that generally warrant an immediate reaction (gunfire, explosions, screams...).
+    Urgent,
+}
+
+/// A perceivable event in the World, written by game code as a Message.
+#[derive(Message, Clone, Debug)]
+pub struct Stimulus {
+    pub kind: StimulusKind,
+    /// The Entity responsible for the Stimulus; Stimuli without one cannot be remembered and are ignored by the Senses.
+    pub source: Option<Entity>,
+    pub position: Vec3,
+    /// How far away (in world units) the Stimulus can be perceived by a Sense with a sensitivity of 1.0.
+    pub radius: f32,
+    /// How long the Stimulus stays active for (and so perceivable by AIs who walk into range later).
+    pub lifetime: Duration,
+    pub priority: StimulusPriority,
+}
+
+impl Stimulus {
+    pub fn new(kind: StimulusKind, position: Vec3, radius: f32) -> Self {
+        Self {
+            kind,
+            source: None,
+            position,
+            radius,
+            lifetime: DEFAULT_STIMULUS_LIFETIME,
+            priority: StimulusPriority::default(),
+        }
+    }
+
+    /// A sound made by the source, carrying as far as its loudness.
+    pub fn noise(source: Entity, position: Vec3, loudness: f32) -> Self {
+        Self::new(StimulusKind::Noise, position, loudness).with_source(source)
+    }
+
+    pub fn with_source(mut self, source: Entity) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    pub fn with_lifetime(mut self, lifetime: Duration) -> Self {
+        self.lifetime = lifetime;
+        self
+    }
+
+    pub fn with_priority(mut self, priority: StimulusPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+/// A Stimulus that is still active, with the time it was emitted at.
+#[derive(Clone, Debug)]
+pub struct ActiveStimulus {
+    pub emitted_at: Duration,
+    /// Assigned in the order the Stimuli get collected; unlike the emission time, it is unique
+    /// even for Stimuli emitted in the same frame (or while the game is paused).
+    pub sequence: u64,
+    pub stimulus: Stimulus,
+}
+
+impl ActiveStimulus {
+    /// True if the Stimulus was collected after the specified `ActiveStimuli::next_sequence()`
+    /// was read, i.e. it was not active yet back then.
+    pub fn is_newer_than(&self, next_sequence: u64) -> bool {
+        self.sequence >= next_sequence
+    }
+}
+
+/// All currently active Stimuli.
+#[derive(Resource, Debug, Default)]
+pub struct ActiveStimuli {
+    pub stimuli: CraniumList<ActiveStimulus>,
+    next_sequence: u64,
+}
+
+impl ActiveStimuli {
+    /// The sequence number the next collected Stimulus will get.
+    pub fn next_sequence(&self) -> u64 {
+        self.next_sequence
+    }
+}
+
+/// Controls what the library does with Stimuli beyond feeding them to the Senses.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct StimulusRouting {
+    /// If set, AIs that perceive a new Stimulus of at least this priority request a decision right away.
+    pub request_decision_at: Option<StimulusPriority>,
+}
+
+impl StimulusRouting {
+    pub fn interrupts_at(priority: StimulusPriority) -> Self {
+        Self { request_decision_at: Some(priority) }
+    }
+
+    /// True if a Stimulus of the specified priority should trigger a decision request.
+    pub fn should_interrupt(&self, priority: StimulusPriority) -> bool {
+        self.request_decision_at.map(|threshold| priority >= threshold).unwrap_or(false)
+    }
+}
+
+/// A System that records newly emitted Stimuli and expires the ones past their lifetime.
+pub fn collect_stimuli(
+    time: Option<Res<Time<Virtual>>>,
+    mut stimulus_reader: MessageReader<Stimulus>,
+    mut active: ResMut<ActiveStimuli>,
+) {
+    let Some(now) = time.map(|time| time.elapsed()) else {
+        return;
+    };
+
+    active.stimuli.retain(|active| now.saturating_sub(active.emitted_at) <= active.stimulus.lifetime);
+
+    for stimulus in stimulus_reader.read() {
+        #[cfg(feature = "logging")]
+        bevy::log::debug!("collect_stimuli: new Stimulus {:?}", stimulus);
+
+        let sequence = active.next_sequence;
+        active.next_sequence += 1;
+        active.stimuli.push(ActiveStimulus { emitted_at: now, sequence, stimulus: stimulus.clone() });
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect(world: &mut World, stimuli: &[Stimulus]) -> CraniumList<u64> {
+        for stimulus in stimuli {
+            world.write_message(stimulus.clone());
+        }
+        world.run_system_cached(collect_stimuli).unwrap();
+        world.resource::<ActiveStimuli>().stimuli.iter().map(|active| active.sequence).collect()
+    }
+
+    fn setup() -> World {
+        let mut world = World::new();
+        world.init_resource::<Time<Virtual>>();
+        world.init_resource::<ActiveStimuli>();
+        world.init_resource::<Messages<Stimulus>>();
+        world
+    }
+
+    #[test]
+    fn test_stimuli_expire_after_their_lifetime() {
+        let mut world = setup();
+        let short = Stimulus::new(StimulusKind::Damage, Vec3::ZERO, 1.).with_lifetime(Duration::from_secs(1));
+        let long = Stimulus::new(StimulusKind::Damage, Vec3::ZERO, 1.).with_lifetime(Duration::from_secs(3));
+        assert_eq!(collect(&mut world, &[short, long]), [0, 1]);
+
+        // Stimuli stay active for their whole lifetime...
+        world.resource_mut::<Time<Virtual>>().advance_by(Duration::from_secs(1));
+        assert_eq!(collect(&mut world, &[]), [0, 1]);
+
+        // ...and not any longer.
+        world.resource_mut::<Time<Virtual>>().advance_by(Duration::from_millis(500));
+        assert_eq!(collect(&mut world, &[]), [1]);
+        world.resource_mut::<Time<Virtual>>().advance_by(Duration::from_secs(2));
+        assert_eq!(collect(&mut world, &[]), [] as [u64; 0]);
+    }
+
+    #[test]
+    fn test_stimuli_emitted_at_the_same_time_are_new() {
+        let mut world = setup();
+        let noise = Stimulus::new(StimulusKind::Noise, Vec3::ZERO, 1.);
+
+        collect(&mut world, &[noise.clone()]);
+        let seen = world.resource::<ActiveStimuli>().next_sequence();
+
+        // The time does not move (e.g. the game is paused), but the new Stimulus still counts as new.
+        collect(&mut world, &[noise]);
+        let active = &world.resource::<ActiveStimuli>().stimuli;
+        assert_eq!(active[0].emitted_at, active[1].emitted_at);
+        assert!(!active[0].is_newer_than(seen));
+        assert!(active[1].is_newer_than(seen));
+    }
+}
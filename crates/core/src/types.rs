@@ -71,6 +71,8 @@ pub type ActionContextList = CraniumList<ActionContextRef>;
 pub type AiEntity = bevy::prelude::Entity;
 pub type PawnEntity = bevy::prelude::Entity;
 pub type PawnEntityRef = Option<PawnEntity>;
+pub type ActionTrackerEntity = bevy::prelude::Entity;
+pub type ActionTrackerEntityRef = Option<ActionTrackerEntity>;
 
 pub use crate::context_fetchers::ContextFetcherInputs;
 pub use crate::context_fetchers::ContextFetcherOutputs;
@@ -0,0 +1,371 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+If a copy of the MPL was not distributed with this file,
+You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+//! This crate extends the Cranium game AI library with a way to *see* Utility Curves.
+//!
+//! Picking a Curve key for a Consideration by name alone is guesswork; this renders
+//! any Curve the library can resolve - built-in, registered from code, or defined in
+//! ActionSet data - to a plain SVG image and/or a CSV table, with no GPU or windowing required.
+//!
+//! Most users will want the `cranium-curve-plot` binary in this crate; the library side
+//! is there for plotting Curves registered from code, which the binary cannot know about.
+//! To do that, build a `UtilityCurveRegistry` the same way your app does and feed the
+//! Curves into a `CurvePlot` yourself.
+
+use std::borrow::Borrow;
+use std::fmt::Write;
+
+use cranium_core::actionset::ActionSet;
+use cranium_core::considerations::ConsiderationData;
+use cranium_core::curves::{SupportedUtilityCurve, UtilityCurve, UtilityCurveRegistry, resolve_curve_from_name, BUILTIN_CURVE_KEYS};
+use cranium_core::errors::CurveRegistrationError;
+use cranium_core::normalization::NormalizationMode;
+use cranium_core::types::ActionScore;
+
+/// A sensible default number of points to sample each Curve at.
+pub const DEFAULT_PLOT_SAMPLES: usize = 101;
+
+/// How far past the min/max of a Consideration the plot extends, as a fraction of the min-max span.
+///
+/// This is what makes the saturation outside of the range visible on the plot.
+pub const INPUT_RANGE_PADDING: ActionScore = 0.25;
+
+// SVG layout, in pixels.
+const PLOT_SIZE: f32 = 400.;
+const MARGIN_LEFT: f32 = 60.;
+const MARGIN_TOP: f32 = 40.;
+const MARGIN_BOTTOM: f32 = 50.;
+const LEGEND_WIDTH: f32 = 220.;
+
+/// Series colors; overlays with more Curves than this will reuse them.
+const PALETTE: &[&str] = &[
+    "#1f77b4", "#d62728", "#2ca02c", "#ff7f0e",
+    "#9467bd", "#8c564b", "#e377c2", "#17becf",
+];
+
+/// A single Curve on a plot.
+#[derive(Clone, Debug)]
+pub struct PlotSeries {
+    pub label: String,
+    pub curve: SupportedUtilityCurve,
+}
+
+/// The raw (pre-normalization) input range of a Consideration, and how it gets normalized.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InputRange {
+    pub min: ActionScore,
+    pub max: ActionScore,
+    pub normalization: NormalizationMode,
+}
+
+impl InputRange {
+    /// A range with the default (Linear) normalization, fixing up flipped min/max values.
+    pub fn new(min: ActionScore, max: ActionScore) -> Self {
+        Self {
+            min: min.min(max),
+            max: max.max(min),
+            normalization: NormalizationMode::default(),
+        }
+    }
+
+    /// Reads the range and normalization from a Consideration, fixing up flipped min/max values.
+    pub fn from_consideration(consideration: &ConsiderationData) -> Self {
+        Self {
+            normalization: consideration.normalization,
+            ..Self::new(consideration.min, consideration.max)
+        }
+    }
+
+    /// Normalizes a raw input into the Curve domain, the same way the decision loop does.
+    ///
+    /// Modes that normalize relative to other Contexts (e.g. Percentile) have no fixed
+    /// mapping to plot, so they are plotted as if they were Linear.
+    pub fn normalize(&self, raw: ActionScore) -> ActionScore {
+        let mode = match self.normalization.needs_population() {
+            true => NormalizationMode::Linear,
+            false => self.normalization,
+        };
+        mode.normalize(raw, self.min, self.max, None)
+    }
+}
+
+/// One or more Curves rendered together on shared axes.
+///
+/// Without an `InputRange`, the x axis is the Curve domain (0.0 to 1.0). With one,
+/// the x axis is in raw input units, padded past both ends (see `INPUT_RANGE_PADDING`),
+/// with the min and max marked - i.e. what the Consideration will actually do with its inputs.
+#[derive(Clone, Debug)]
+pub struct CurvePlot {
+    pub title: String,
+    pub series: Vec<PlotSeries>,
+    pub samples: usize,
+    pub input_range: Option<InputRange>,
+}
+
+impl CurvePlot {
+    pub fn new<IS: Into<String>>(title: IS) -> Self {
+        Self {
+            title: title.into(),
+            series: Vec::new(),
+            samples: DEFAULT_PLOT_SAMPLES,
+            input_range: None,
+        }
+    }
+
+    /// Adds a Curve to the plot.
+    pub fn with_curve<IS: Into<String>>(mut self, label: IS, curve: SupportedUtilityCurve) -> Self {
+        self.series.push(PlotSeries { label: label.into(), curve });
+        self
+    }
+
+    pub fn with_samples(mut self, samples: usize) -> Self {
+        self.samples = samples.max(2);
+        self
+    }
+
+    pub fn with_input_range(mut self, input_range: InputRange) -> Self {
+        self.input_range = Some(input_range);
+        self
+    }
+
+    /// Shorthand for `with_input_range()` using the min/max of a Consideration.
+    pub fn with_consideration_range(self, consideration: &ConsiderationData) -> Self {
+        self.with_input_range(InputRange::from_consideration(consideration))
+    }
+
+    /// The (inclusive) span of raw input values shown on the x axis.
+    fn x_domain(&self) -> (ActionScore, ActionScore) {
+        match self.input_range {
+            None => (0., 1.),
+            Some(range) => {
+                let padding = match range.max - range.min {
+                    span if span > 0. => span * INPUT_RANGE_PADDING,
+                    _ => 1.,
+                };
+                (range.min - padding, range.max + padding)
+            }
+        }
+    }
+
+    /// Pairs of (raw input, normalized input) for every sample point.
+    fn sample_points(&self) -> impl Iterator<Item = (ActionScore, ActionScore)> + '_ {
+        let (lo, hi) = self.x_domain();
+        let last_idx = (self.samples.max(2) - 1) as ActionScore;
+
+        (0..self.samples.max(2)).map(move |idx| {
+            let raw = lo + (hi - lo) * (idx as ActionScore / last_idx);
+            let normalized = match self.input_range {
+                None => raw,
+                Some(range) => range.normalize(raw),
+            };
+            (raw, normalized)
+        })
+    }
+
+    /// Renders the sampled Curves as CSV; one row per sample point, one column per Curve.
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("input,normalized_input");
+        for series in self.series.iter() {
+            out.push(',');
+            out.push_str(&csv_escape(&series.label));
+        }
+        out.push('\n');
+
+        for (raw, normalized) in self.sample_points() {
+            let _ = write!(out, "{},{}", raw, normalized);
+            for series in self.series.iter() {
+                let _ = write!(out, ",{}", series.curve.sample_safe(normalized));
+            }
+            out.push('\n');
+        }
+
+        out
+    }
+
+    /// Renders the Curves as a standalone SVG image.
+    pub fn to_svg(&self) -> String {
+        let width = MARGIN_LEFT + PLOT_SIZE + LEGEND_WIDTH;
+        let height = MARGIN_TOP + PLOT_SIZE + MARGIN_BOTTOM;
+        let (lo, hi) = self.x_domain();
+
+        let to_px_x = |raw: ActionScore| MARGIN_LEFT + (raw - lo) / (hi - lo) * PLOT_SIZE;
+        let to_px_y = |score: ActionScore| MARGIN_TOP + (1. - score) * PLOT_SIZE;
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="sans-serif" font-size="12">"#,
+            w = width, h = height,
+        );
+        let _ = writeln!(out, r#"<rect width="100%" height="100%" fill="white"/>"#);
+        let _ = writeln!(
+            out,
+            r#"<text x="{}" y="{}" font-size="16" text-anchor="middle">{}</text>"#,
+            MARGIN_LEFT + PLOT_SIZE / 2., MARGIN_TOP / 2. + 5., xml_escape(&self.title),
+        );
+
+        // Grid and axis labels, in quarters.
+        for step in 0..=4 {
+            let frac = step as ActionScore / 4.;
+            let px_x = MARGIN_LEFT + frac * PLOT_SIZE;
+            let px_y = to_px_y(frac);
+            let raw_x = lo + (hi - lo) * frac;
+
+            let _ = writeln!(
+                out,
+                r##"<line x1="{x}" y1="{t}" x2="{x}" y2="{b}" stroke="#ddd"/><line x1="{l}" y1="{y}" x2="{r}" y2="{y}" stroke="#ddd"/>"##,
+                x = px_x, y = px_y, t = MARGIN_TOP, b = MARGIN_TOP + PLOT_SIZE, l = MARGIN_LEFT, r = MARGIN_LEFT + PLOT_SIZE,
+            );
+            let _ = writeln!(
+                out,
+                r#"<text x="{}" y="{}" text-anchor="middle">{}</text><text x="{}" y="{}" text-anchor="end">{}</text>"#,
+                px_x, MARGIN_TOP + PLOT_SIZE + 16., format_tick(raw_x),
+                MARGIN_LEFT - 6., px_y + 4., format_tick(frac),
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            r#"<rect x="{}" y="{}" width="{s}" height="{s}" fill="none" stroke="black"/>"#,
+            MARGIN_LEFT, MARGIN_TOP, s = PLOT_SIZE,
+        );
+        let x_label = match self.input_range {
+            None => "normalized input",
+            Some(_) => "raw input",
+        };
+        let _ = writeln!(
+            out,
+            r#"<text x="{}" y="{}" text-anchor="middle">{}</text>"#,
+            MARGIN_LEFT + PLOT_SIZE / 2., MARGIN_TOP + PLOT_SIZE + 36., x_label,
+        );
+
+        if let Some(range) = self.input_range {
+            for (label, value) in [("min", range.min), ("max", range.max)] {
+                let px_x = to_px_x(value);
+                let _ = writeln!(
+                    out,
+                    r##"<line x1="{x}" y1="{t}" x2="{x}" y2="{b}" stroke="#555" stroke-dasharray="4 3"/><text x="{x}" y="{ty}" text-anchor="middle" fill="#555">{l}={v}</text>"##,
+                    x = px_x, t = MARGIN_TOP, b = MARGIN_TOP + PLOT_SIZE, ty = MARGIN_TOP - 4., l = label, v = format_tick(value),
+                );
+            }
+        }
+
+        for (idx, series) in self.series.iter().enumerate() {
+            let color = PALETTE[idx % PALETTE.len()];
+            let points = self.sample_points()
+                .map(|(raw, normalized)| format!(
+                    "{:.2},{:.2}", to_px_x(raw), to_px_y(series.curve.sample_safe(normalized))
+                ))
+                .collect::<Vec<_>>()
+                .join(" ")
+            ;
+
+            let _ = writeln!(
+                out,
+                r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="2"/>"#,
+                points, color,
+            );
+
+            let legend_y = MARGIN_TOP + 10. + idx as f32 * 18.;
+            let legend_x = MARGIN_LEFT + PLOT_SIZE + 16.;
+            let _ = writeln!(
+                out,
+                r#"<line x1="{}" y1="{y}" x2="{}" y2="{y}" stroke="{}" stroke-width="3"/><text x="{}" y="{}">{}</text>"#,
+                legend_x, legend_x + 20., color, legend_x + 26., legend_y + 4., xml_escape(&series.label), y = legend_y,
+            );
+        }
+
+        out.push_str("</svg>\n");
+        out
+    }
+}
+
+/// One single-Curve plot per built-in Curve, in `BUILTIN_CURVE_KEYS` order.
+pub fn builtin_catalogue(samples: usize) -> impl Iterator<Item = (&'static str, CurvePlot)> {
+    BUILTIN_CURVE_KEYS.iter().filter_map(move |key| {
+        resolve_curve_from_name(*key).map(|curve| (
+            *key,
+            CurvePlot::new(*key).with_curve(*key, curve).with_samples(samples),
+        ))
+    })
+}
+
+/// Builds a Curve registry with the Curves defined in the provided ActionSets registered,
+/// mirroring what the CraniumPlugin does when the ActionSets get loaded.
+///
+/// Returns the registry alongside any definitions that failed to register.
+pub fn registry_from_actionsets<'a, I: IntoIterator<Item = &'a ActionSet>>(
+    actionsets: I
+) -> (UtilityCurveRegistry, Vec<(String, CurveRegistrationError)>) {
+    let mut registry = UtilityCurveRegistry::default();
+    let mut failures = Vec::new();
+
+    for actionset in actionsets {
+        for definition in actionset.curves.iter() {
+            if let Err(err) = registry.register_curve_definition(definition) {
+                let name: &str = definition.name.borrow();
+                failures.push((name.to_owned(), err));
+            }
+        }
+    }
+
+    (registry, failures)
+}
+
+/// Finds a Consideration in the provided ActionSets by its Action name and Consideration key.
+pub fn find_consideration<'a, I: IntoIterator<Item = &'a ActionSet>>(
+    actionsets: I,
+    action_name: &str,
+    consideration_name: &str,
+) -> Option<&'a ConsiderationData> {
+    actionsets
+        .into_iter()
+        .flat_map(|actionset| actionset.actions.iter())
+        .filter(|action| action.name == action_name)
+        .flat_map(|action| action.considerations.iter())
+        .find(|cons| cons.consideration_name.to_string() == consideration_name)
+}
+
+fn format_tick(value: ActionScore) -> String {
+    let formatted = format!("{:.2}", value);
+    formatted.trim_end_matches('0').trim_end_matches('.').to_owned()
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn csv_escape(text: &str) -> String {
+    match text.contains([',', '"', '\n']) {
+        false => text.to_owned(),
+        true => format!("\"{}\"", text.replace('"', "\"\"")),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_saturates_outside_input_range() {
+        let plot = CurvePlot::new("test")
+            .with_curve("Linear", resolve_curve_from_name("Linear").unwrap())
+            .with_curve("Square, but quoted", resolve_curve_from_name("Square").unwrap())
+            .with_input_range(InputRange::new(0., 100.))
+            .with_samples(7)
+        ;
+
+        let csv = plot.to_csv();
+        let rows: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(rows[0], "input,normalized_input,Linear,\"Square, but quoted\"");
+        // Padding is 25 on each side: -25, 0, 25, 50, 75, 100, 125.
+        assert_eq!(rows[1], "-25,0,0,0");
+        assert_eq!(rows[4], "50,0.5,0.5,0.25");
+        assert_eq!(rows[7], "125,1,1,1");
+    }
+}
@@ -0,0 +1,216 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+If a copy of the MPL was not distributed with this file,
+You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+//! `cranium-curve-plot` - renders Utility Curves to SVG and CSV files.
+//!
+//! Run with `--help` for usage.
+
+use std::borrow::Borrow;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use cranium_core::actionset::ActionSet;
+use cranium_curve_plotter::{builtin_catalogue, find_consideration, registry_from_actionsets, CurvePlot, InputRange, DEFAULT_PLOT_SAMPLES};
+
+const USAGE: &str = "\
+Renders Cranium Utility Curves to SVG and CSV.
+
+USAGE:
+    cranium-curve-plot plot [OPTIONS] [CURVE]...
+    cranium-curve-plot catalogue [--out-dir DIR] [--samples N] [--format FORMAT]
+
+Plots all CURVE keys on one overlaid plot. Keys can be built-in Curves
+or Curves defined in any ActionSet passed with --actionset.
+
+OPTIONS:
+    --actionset FILE              Load an ActionSet JSON file (repeatable); makes its
+                                  data-defined Curves and Considerations available.
+    --consideration ACTION CONS   Plot the Curve of Consideration CONS in Action ACTION
+                                  (from a loaded ActionSet), with its min/max marked.
+    --min N --max N               Mark a raw input range manually (both required).
+    --samples N                   Samples per Curve [default: 101].
+    --title TEXT                  Plot title [default: the Curve keys].
+    --out PATH                    Output path, without extension [default: curves].
+    --out-dir DIR                 Output directory for `catalogue` [default: curve_catalogue].
+    --format svg|csv|both         What to write [default: both].
+    -h, --help                    Print this message.
+";
+
+#[derive(Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Svg,
+    Csv,
+    Both,
+}
+
+struct Args {
+    command: String,
+    curves: Vec<String>,
+    actionsets: Vec<PathBuf>,
+    consideration: Option<(String, String)>,
+    min: Option<f32>,
+    max: Option<f32>,
+    samples: usize,
+    title: Option<String>,
+    out: PathBuf,
+    out_dir: PathBuf,
+    format: OutputFormat,
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut raw: I) -> Result<Args, String> {
+    let command = raw.next().ok_or("Missing command; expected `plot` or `catalogue`.")?;
+
+    let mut args = Args {
+        command,
+        curves: Vec::new(),
+        actionsets: Vec::new(),
+        consideration: None,
+        min: None,
+        max: None,
+        samples: DEFAULT_PLOT_SAMPLES,
+        title: None,
+        out: PathBuf::from("curves"),
+        out_dir: PathBuf::from("curve_catalogue"),
+        format: OutputFormat::Both,
+    };
+
+    let value_for = |flag: &str, raw: &mut I| raw.next().ok_or(format!("Missing value for {}", flag));
+    let parse_num = |flag: &str, val: String| val.parse::<f32>().map_err(|err| format!("Bad value for {}: {}", flag, err));
+
+    while let Some(arg) = raw.next() {
+        match arg.as_str() {
+            "--actionset" => args.actionsets.push(value_for(&arg, &mut raw)?.into()),
+            "--consideration" => {
+                let action = value_for(&arg, &mut raw)?;
+                let consideration = value_for(&arg, &mut raw)?;
+                args.consideration = Some((action, consideration));
+            },
+            "--min" => args.min = Some(parse_num(&arg, value_for(&arg, &mut raw)?)?),
+            "--max" => args.max = Some(parse_num(&arg, value_for(&arg, &mut raw)?)?),
+            "--samples" => args.samples = value_for(&arg, &mut raw)?
+                .parse()
+                .map_err(|err| format!("Bad value for --samples: {}", err))?,
+            "--title" => args.title = Some(value_for(&arg, &mut raw)?),
+            "--out" => args.out = value_for(&arg, &mut raw)?.into(),
+            "--out-dir" => args.out_dir = value_for(&arg, &mut raw)?.into(),
+            "--format" => args.format = match value_for(&arg, &mut raw)?.as_str() {
+                "svg" => OutputFormat::Svg,
+                "csv" => OutputFormat::Csv,
+                "both" => OutputFormat::Both,
+                other => return Err(format!("Unknown format {:?}; expected svg, csv or both.", other)),
+            },
+            flag if flag.starts_with("--") => return Err(format!("Unknown option {:?}", flag)),
+            _ => args.curves.push(arg),
+        }
+    }
+
+    Ok(args)
+}
+
+fn load_actionset(path: &Path) -> Result<ActionSet, String> {
+    let bytes = std::fs::read(path).map_err(|err| format!("Could not read {:?}: {}", path, err))?;
+    serde_json::from_slice(&bytes).map_err(|err| format!("Could not parse ActionSet {:?}: {}", path, err))
+}
+
+fn write_plot(plot: &CurvePlot, out: &Path, format: OutputFormat) -> Result<(), String> {
+    let write = |ext: &str, contents: String| {
+        let path = out.with_extension(ext);
+        std::fs::write(&path, contents).map_err(|err| format!("Could not write {:?}: {}", path, err))?;
+        println!("Wrote {}", path.display());
+        Ok::<(), String>(())
+    };
+
+    if format != OutputFormat::Csv {
+        write("svg", plot.to_svg())?;
+    }
+    if format != OutputFormat::Svg {
+        write("csv", plot.to_csv())?;
+    }
+    Ok(())
+}
+
+fn run_plot(args: Args) -> Result<(), String> {
+    let actionsets = args.actionsets
+        .iter()
+        .map(|path| load_actionset(path))
+        .collect::<Result<Vec<_>, _>>()?
+    ;
+
+    let (registry, failures) = registry_from_actionsets(actionsets.iter());
+    for (name, err) in failures {
+        eprintln!("Warning: could not register data-defined Curve {}: {:?}", name, err);
+    }
+
+    let mut plot = CurvePlot::new("").with_samples(args.samples);
+    let mut title_parts = Vec::new();
+
+    if let Some((action, consideration)) = &args.consideration {
+        let cons = find_consideration(actionsets.iter(), action, consideration)
+            .ok_or(format!("No Consideration {:?} in Action {:?} in the loaded ActionSets.", consideration, action))?
+        ;
+        let curve_key: &str = cons.curve_name.borrow();
+        let curve = registry.get_curve_by_name(&cons.curve_name)
+            .ok_or(format!("Consideration {:?} uses unknown Curve {}.", consideration, curve_key))?
+        ;
+        plot = plot
+            .with_curve(format!("{}/{}", action, consideration), curve)
+            .with_consideration_range(cons)
+        ;
+        title_parts.push(format!("{}/{}", action, consideration));
+    }
+
+    for key in args.curves.iter() {
+        let curve = registry.get_curve_by_name(key.as_str()).ok_or(format!("Unknown Curve key {:?}.", key))?;
+        plot = plot.with_curve(key.as_str(), curve);
+        title_parts.push(key.to_owned());
+    }
+
+    match (args.min, args.max) {
+        (Some(min), Some(max)) => plot = plot.with_input_range(InputRange::new(min, max)),
+        (None, None) => {},
+        _ => return Err("--min and --max must be used together.".to_owned()),
+    }
+
+    if plot.series.is_empty() {
+        return Err("Nothing to plot; pass some Curve keys or --consideration.".to_owned());
+    }
+
+    plot.title = args.title.unwrap_or_else(|| title_parts.join(" vs "));
+    write_plot(&plot, &args.out, args.format)
+}
+
+fn run_catalogue(args: Args) -> Result<(), String> {
+    std::fs::create_dir_all(&args.out_dir)
+        .map_err(|err| format!("Could not create {:?}: {}", &args.out_dir, err))?;
+
+    for (key, plot) in builtin_catalogue(args.samples) {
+        write_plot(&plot, &args.out_dir.join(key), args.format)?;
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let raw: Vec<String> = std::env::args().skip(1).collect();
+
+    if raw.is_empty() || raw.iter().any(|arg| arg == "-h" || arg == "--help") {
+        print!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    let result = parse_args(raw.into_iter()).and_then(|args| match args.command.as_str() {
+        "plot" => run_plot(args),
+        "catalogue" => run_catalogue(args),
+        other => Err(format!("Unknown command {:?}; expected `plot` or `catalogue`.", other)),
+    });
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
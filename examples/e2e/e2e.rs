@@ -13,7 +13,7 @@ use cranium_bevy_plugin::CraniumPlugin;
 
 // These imports are needed for setting up stuff behind the scenes. 
 // In a normal setup, you usually shouldn't need to import these.
-use cranium::action_runtime::{ActionTrackerState};
+use cranium::action_runtime::ActionTrackerState;
 use cranium::actionset::ActionSet;
 use cranium::considerations::{ConsiderationData};
 use cranium::curves::{LinearCurve, UtilityCurveExt};
@@ -27,6 +27,7 @@ struct ExampleActionEvent{
     entity: AiEntity, 
     _pawn: PawnEntityRef, 
     ctx: ActionContextRef,
+    tracker: ActionTrackerEntityRef,
 }
 
 impl ExampleActionEvent {
@@ -34,11 +35,13 @@ impl ExampleActionEvent {
         context: ActionContextRef, 
         ai: AiEntity, 
         pawn: PawnEntityRef,
+        tracker: ActionTrackerEntityRef,
     ) -> Self {
         Self {
             entity: ai,
             _pawn: pawn,
             ctx: context,
+            tracker: tracker,
         }
     }
 }
@@ -47,7 +50,7 @@ fn example_action(
     trigger: On<ExampleActionEvent>, 
     associated_ai_qry: Query<NameOrEntity, With<AIController>>,
     context_data_qry: Query<&ExampleStateMapContextComponent>,
-    mut tracker_state_qry: Query<&mut ActionTrackerState>,
+    tracker_state_qry: Query<&ActionTrackerState>,
     mut state_writer: MessageWriter<crate::action_state::AiActionStateChangeRequest>,
 ) {
     let event = trigger.event();
@@ -55,7 +58,8 @@ fn example_action(
 
     let ai_entity = event.entity;
 
-    let maybe_tracker_state = tracker_state_qry.get_mut(ai_entity);
+    // The ActionHandler passed on the ActionTracker of the dispatched Action, if any.
+    let maybe_tracker = event.tracker;
     
     let maybe_ai_owner = associated_ai_qry
         .get(ai_entity)
@@ -82,10 +86,10 @@ fn example_action(
 
     let state_mapping = &context_data.statemap;
 
-    let curr_state = match maybe_tracker_state.as_ref() {
-        Ok(state) => state.0.clone(),
-        Err(err) => {
-            bevy::log::info!("Could not find a State for AI {:?} - {:?}", ai_entity, err);
+    let curr_state = match maybe_tracker.and_then(|tracker| tracker_state_qry.get(tracker).ok()) {
+        Some(state) => state.0.clone(),
+        None => {
+            bevy::log::info!("Could not find the ActionTracker for AI {:?}", ai_entity);
             ActionState::Ready
         },
     };
@@ -106,7 +110,7 @@ fn example_action(
     bevy::log::info!("example_action for AI {:?}: Requesting state change to {:?}", ai_owner, new);
 
     state_writer.write(crate::action_state::AiActionStateChangeRequest {
-        entity: event.entity,
+        entity: maybe_tracker.unwrap_or(ai_entity),
         action: "example_action".to_string(),
         to_state: *new,
     });
@@ -208,10 +212,10 @@ fn setup_example_entity(
         )
     ];
 
-    let example_actionset = ActionSet {
-        name: "ExampleActionSet".to_string(),
-        actions: cranium::types::CraniumList::from(example_actions)
-    };
+    let example_actionset = ActionSet::new(
+        "ExampleActionSet",
+        cranium::types::CraniumList::from(example_actions),
+    );
 
     actionset_store.map_by_name.insert(example_actionset.name.to_owned(), example_actionset);
 
@@ -335,12 +339,13 @@ fn example_action_handler(
     mut commands: Commands, 
 ) {
     bevy::log::info!("Triggering a ExampleActionEvent w/ inputs {:?}...", &inputs);
-    let (ai, pawn, ctx) = inputs;
+    let (ai, pawn, ctx, tracker) = inputs;
     commands.trigger(
         ExampleActionEvent::from_context_ref(
             ctx,
             ai,
             pawn,
+            tracker,
         )
     );
 }